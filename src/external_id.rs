@@ -7,6 +7,8 @@ use std::fmt;
 use std::sync::LazyLock;
 use wikibase::*;
 
+#[cfg(feature = "property-registry")]
+use crate::property_registry::PropertyRegistry;
 #[cfg(feature = "wikidata")]
 use crate::wikidata::Wikidata;
 #[cfg(feature = "wikidata")]
@@ -144,6 +146,30 @@ impl ExternalId {
     }
 }
 
+/// References typed from property metadata, which requires the
+/// `property-registry` feature.
+#[cfg(feature = "property-registry")]
+impl ExternalId {
+    /// Like [`Self::as_reference`], but each snak takes its datatype from
+    /// `registry` where the registry knows the property, rather than the
+    /// external-ID datatype assumed for this ID's property.
+    pub fn as_reference_with(
+        &self,
+        registry: &PropertyRegistry,
+        stated_in: &str,
+        use_current_date: bool,
+    ) -> Reference {
+        let mut snaks = self
+            .as_reference(stated_in, use_current_date)
+            .snaks()
+            .to_owned();
+        for snak in &mut snaks {
+            registry.classify_snak(snak);
+        }
+        Reference::new(snaks)
+    }
+}
+
 /// Lookups that hit the live Wikidata Action API, and so require the
 /// `wikidata` feature for the [`Wikidata`] client.
 #[cfg(feature = "wikidata")]
@@ -164,6 +190,7 @@ impl ExternalId {
         query: &str,
     ) -> Option<String> {
         let api = wd.api().await.ok()?;
+        let _permit = wd.api_permit().await.ok()?;
         let j = ActionApiList::search()
             .srnamespace(&[0])
             .srsearch(query)
@@ -380,6 +407,34 @@ mod tests {
         assert!(reference.snaks().iter().any(|s| s.property() == "P813"));
     }
 
+    #[cfg(feature = "property-registry")]
+    #[test]
+    fn test_as_reference_with_registry_types_snaks() {
+        use crate::property_registry::PropertyInfo;
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P1234",
+            PropertyInfo {
+                datatype: Some(SnakDataType::String),
+                ..Default::default()
+            },
+        );
+        let reference = ExternalId::new(1234, "abc").as_reference_with(&registry, "Q54919", true);
+        let datatype = |property: &str| {
+            reference
+                .snaks()
+                .iter()
+                .find(|s| s.property() == property)
+                .map(|s| *s.datatype())
+        };
+        assert_eq!(datatype("P1234"), Some(SnakDataType::String));
+        // Properties the registry does not know keep their usual type.
+        assert_eq!(datatype("P248"), Some(SnakDataType::WikibaseItem));
+        assert_eq!(datatype("P813"), Some(SnakDataType::Time));
+        let unknown = ExternalId::new(214, "1").as_reference_with(&registry, "Q54919", false);
+        assert_eq!(*unknown.snaks()[1].datatype(), SnakDataType::ExternalId);
+    }
+
     #[test]
     fn test_as_reference_stated_in_item() {
         let ext = ExternalId::new(214, "12345");
@@ -0,0 +1,630 @@
+//! What changed between two revisions of an entity, as an [`ItemDiff`].
+//!
+//! A [`MergeDiff`] only says what to add to one entity from another. An
+//! [`ItemDiff`] compares two revisions symmetrically, for auditing edits:
+//! labels, descriptions and aliases per language, sitelinks with their
+//! badges, and statements matched by ID, down to single qualifiers and
+//! references. Snaks compare as in [`ItemMerger::is_snak_identical`], so a
+//! date only rewritten below its precision is not a change.
+//!
+//! The diff serializes with `serde`, and also reads as text, one change per
+//! line:
+//!
+//! ```text
+//! label en: "Douglas Adams" -> "Douglas Noël Adams"
+//! sitelink enwiki: added badge Q17437798
+//! statement Q42$1: P31=Q5 -> P31=Q215627
+//! statement Q42$2: added reference P854=https://example.org
+//! ```
+
+use crate::item_merger::ItemMerger;
+#[cfg(doc)]
+use crate::merge_diff::MergeDiff;
+use crate::merge_report::snak_text;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use wikibase::{EntityTrait, LocaleString, Reference, SiteLink, Snak, Statement};
+
+/// A label or description that changed in one language. `old` is `None` for
+/// an added term, `new` for a removed one.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TermChange {
+    pub language: String,
+    pub old: Option<String>,
+    pub new: Option<String>,
+}
+
+/// The aliases added and removed in one language.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AliasChange {
+    pub language: String,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed: Vec<String>,
+}
+
+/// A sitelink that changed on one site. The titles are `None` where the
+/// sitelink is missing; they are equal when only the badges changed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SitelinkChange {
+    pub site: String,
+    pub old_title: Option<String>,
+    pub new_title: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_badges: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_badges: Vec<String>,
+}
+
+/// Whether a statement was added, removed, or changed in place.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatementChangeKind {
+    Added,
+    Removed,
+    Changed,
+}
+
+/// A statement that changed. An added statement has only the `new_` fields
+/// set, a removed one only the `old_` fields. A changed one has both main
+/// snaks and both ranks only where they differ, and lists the qualifiers and
+/// references added and removed.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementChange {
+    /// The statement ID; empty for a statement that has none yet.
+    #[serde(skip_serializing_if = "String::is_empty")]
+    pub id: String,
+    pub property: String,
+    pub kind: StatementChangeKind,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_main_snak: Option<Snak>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_main_snak: Option<Snak>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_rank: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_rank: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_qualifiers: Vec<Snak>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_qualifiers: Vec<Snak>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub added_references: Vec<Reference>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub removed_references: Vec<Reference>,
+}
+
+impl StatementChange {
+    fn added(statement: &Statement) -> Self {
+        Self {
+            kind: StatementChangeKind::Added,
+            new_main_snak: Some(statement.main_snak().to_owned()),
+            new_rank: Some(statement.rank().as_str().to_string()),
+            added_qualifiers: statement.qualifiers().to_owned(),
+            added_references: statement.references().to_owned(),
+            ..Self::empty(statement)
+        }
+    }
+
+    fn removed(statement: &Statement) -> Self {
+        Self {
+            kind: StatementChangeKind::Removed,
+            old_main_snak: Some(statement.main_snak().to_owned()),
+            old_rank: Some(statement.rank().as_str().to_string()),
+            removed_qualifiers: statement.qualifiers().to_owned(),
+            removed_references: statement.references().to_owned(),
+            ..Self::empty(statement)
+        }
+    }
+
+    /// The changes from `old` to `new`, or `None` if there are none.
+    pub(crate) fn changed(old: &Statement, new: &Statement) -> Option<Self> {
+        let mut ret = Self::empty(new);
+        let (old_snak, new_snak) = (old.main_snak(), new.main_snak());
+        if !same_snak(old_snak, new_snak) {
+            ret.old_main_snak = Some(old_snak.to_owned());
+            ret.new_main_snak = Some(new_snak.to_owned());
+        }
+        if old.rank() != new.rank() {
+            ret.old_rank = Some(old.rank().as_str().to_string());
+            ret.new_rank = Some(new.rank().as_str().to_string());
+        }
+        let same_reference = |r1: &Reference, r2: &Reference| {
+            r1.snaks().len() == r2.snaks().len()
+                && unmatched(r1.snaks(), r2.snaks(), same_snak).is_empty()
+        };
+        ret.added_qualifiers = unmatched(new.qualifiers(), old.qualifiers(), same_snak);
+        ret.removed_qualifiers = unmatched(old.qualifiers(), new.qualifiers(), same_snak);
+        ret.added_references = unmatched(new.references(), old.references(), same_reference);
+        ret.removed_references = unmatched(old.references(), new.references(), same_reference);
+        let unchanged = ret.old_main_snak.is_none()
+            && ret.old_rank.is_none()
+            && ret.added_qualifiers.is_empty()
+            && ret.removed_qualifiers.is_empty()
+            && ret.added_references.is_empty()
+            && ret.removed_references.is_empty();
+        (!unchanged).then_some(ret)
+    }
+
+    fn empty(statement: &Statement) -> Self {
+        Self {
+            id: statement.id().unwrap_or_default(),
+            property: statement.property().to_string(),
+            kind: StatementChangeKind::Changed,
+            old_main_snak: None,
+            new_main_snak: None,
+            old_rank: None,
+            new_rank: None,
+            added_qualifiers: vec![],
+            removed_qualifiers: vec![],
+            added_references: vec![],
+            removed_references: vec![],
+        }
+    }
+}
+
+/// Everything that changed from one revision of an entity to another; see
+/// the module documentation.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ItemDiff {
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub labels: Vec<TermChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub descriptions: Vec<TermChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<AliasChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub sitelinks: Vec<SitelinkChange>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub statements: Vec<StatementChange>,
+}
+
+impl ItemDiff {
+    /// Compares `old` with `new`. Terms and sitelinks are listed by language
+    /// and site; statements in the order of `old`, followed by those only
+    /// `new` has.
+    pub fn new(old: &impl EntityTrait, new: &impl EntityTrait) -> Self {
+        Self {
+            labels: term_changes(old.labels(), new.labels()),
+            descriptions: term_changes(old.descriptions(), new.descriptions()),
+            aliases: alias_changes(old.aliases(), new.aliases()),
+            sitelinks: sitelink_changes(old.sitelinks(), new.sitelinks()),
+            statements: statement_changes(old.claims(), new.claims()),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+            && self.descriptions.is_empty()
+            && self.aliases.is_empty()
+            && self.sitelinks.is_empty()
+            && self.statements.is_empty()
+    }
+}
+
+pub(crate) fn term_changes(old: &[LocaleString], new: &[LocaleString]) -> Vec<TermChange> {
+    let value = |terms: &[LocaleString], language: &str| {
+        terms
+            .iter()
+            .find(|t| t.language() == language)
+            .map(|t| t.value().to_string())
+    };
+    languages(old, new)
+        .into_iter()
+        .filter_map(|language| {
+            let (old, new) = (value(old, language), value(new, language));
+            (old != new).then(|| TermChange {
+                language: language.to_string(),
+                old,
+                new,
+            })
+        })
+        .collect()
+}
+
+fn alias_changes(old: &[LocaleString], new: &[LocaleString]) -> Vec<AliasChange> {
+    let values = |aliases: &[LocaleString], language: &str| -> Vec<String> {
+        aliases
+            .iter()
+            .filter(|a| a.language() == language)
+            .map(|a| a.value().to_string())
+            .collect()
+    };
+    languages(old, new)
+        .into_iter()
+        .filter_map(|language| {
+            let (old, new) = (values(old, language), values(new, language));
+            let change = AliasChange {
+                language: language.to_string(),
+                added: new.iter().filter(|a| !old.contains(a)).cloned().collect(),
+                removed: old.iter().filter(|a| !new.contains(a)).cloned().collect(),
+            };
+            (!change.added.is_empty() || !change.removed.is_empty()).then_some(change)
+        })
+        .collect()
+}
+
+/// The languages of `old` and `new`, sorted.
+fn languages<'a>(old: &'a [LocaleString], new: &'a [LocaleString]) -> BTreeSet<&'a str> {
+    old.iter().chain(new).map(|t| t.language()).collect()
+}
+
+fn sitelink_changes(
+    old: &Option<Vec<SiteLink>>,
+    new: &Option<Vec<SiteLink>>,
+) -> Vec<SitelinkChange> {
+    let old = old.as_deref().unwrap_or_default();
+    let new = new.as_deref().unwrap_or_default();
+    let sites: BTreeSet<&str> = old.iter().chain(new).map(|s| s.site().as_str()).collect();
+    let find = |sitelinks: &'_ [SiteLink], site: &str| {
+        sitelinks.iter().find(|s| s.site() == site).cloned()
+    };
+    sites
+        .into_iter()
+        .filter_map(|site| {
+            let (old, new) = (find(old, site), find(new, site));
+            let badges = |s: &Option<SiteLink>| {
+                s.as_ref()
+                    .map(|s| s.badges().to_owned())
+                    .unwrap_or_default()
+            };
+            let (old_badges, new_badges) = (badges(&old), badges(&new));
+            let change = SitelinkChange {
+                site: site.to_string(),
+                old_title: old.as_ref().map(|s| s.title().to_owned()),
+                new_title: new.as_ref().map(|s| s.title().to_owned()),
+                added_badges: new_badges
+                    .iter()
+                    .filter(|b| !old_badges.contains(b))
+                    .cloned()
+                    .collect(),
+                removed_badges: old_badges
+                    .iter()
+                    .filter(|b| !new_badges.contains(b))
+                    .cloned()
+                    .collect(),
+            };
+            (change.old_title != change.new_title
+                || !change.added_badges.is_empty()
+                || !change.removed_badges.is_empty())
+            .then_some(change)
+        })
+        .collect()
+}
+
+/// Statements are matched by ID; a statement without one never matches.
+pub(crate) fn statement_changes(old: &[Statement], new: &[Statement]) -> Vec<StatementChange> {
+    let mut ret: Vec<StatementChange> = old
+        .iter()
+        .filter_map(|statement| match find_statement(new, statement) {
+            Some(other) => StatementChange::changed(statement, other),
+            None => Some(StatementChange::removed(statement)),
+        })
+        .collect();
+    ret.extend(
+        new.iter()
+            .filter(|statement| find_statement(old, statement).is_none())
+            .map(StatementChange::added),
+    );
+    ret
+}
+
+/// The statement among `statements` with the ID of `statement`, if it has one.
+fn find_statement<'a>(statements: &'a [Statement], statement: &Statement) -> Option<&'a Statement> {
+    let id = statement.id()?;
+    statements.iter().find(|s| s.id().as_ref() == Some(&id))
+}
+
+/// The elements of `items` left over after pairing each with a different
+/// element of `others` that is the `same`.
+/// Whether the two snaks are the same, [`ItemMerger::is_snak_identical`]
+/// and of the same snak type, so that novalue and somevalue differ.
+fn same_snak(snak1: &Snak, snak2: &Snak) -> bool {
+    ItemMerger::is_snak_identical(snak1, snak2) && snak1.snak_type() == snak2.snak_type()
+}
+
+fn unmatched<T: Clone>(items: &[T], others: &[T], same: impl Fn(&T, &T) -> bool) -> Vec<T> {
+    let mut others: Vec<&T> = others.iter().collect();
+    items
+        .iter()
+        .filter(
+            |item| match others.iter().position(|other| same(item, other)) {
+                Some(pos) => {
+                    others.swap_remove(pos);
+                    false
+                }
+                None => true,
+            },
+        )
+        .cloned()
+        .collect()
+}
+
+/// `P854=https://example.org, P813=+2024-01-01T00:00:00Z/11`.
+fn reference_text(reference: &Reference) -> String {
+    reference
+        .snaks()
+        .iter()
+        .map(snak_text)
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn term_line(f: &mut fmt::Formatter<'_>, kind: &str, change: &TermChange) -> fmt::Result {
+    write!(f, "{kind} {}: ", change.language)?;
+    match (&change.old, &change.new) {
+        (Some(old), Some(new)) => writeln!(f, "{old:?} -> {new:?}"),
+        (None, Some(new)) => writeln!(f, "added {new:?}"),
+        (Some(old), None) => writeln!(f, "removed {old:?}"),
+        (None, None) => Ok(()),
+    }
+}
+
+impl fmt::Display for StatementChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let subject = match self.id.as_str() {
+            "" => format!("statement {}", self.property),
+            id => format!("statement {id}"),
+        };
+        match (self.kind, &self.old_main_snak, &self.new_main_snak) {
+            (StatementChangeKind::Added, _, Some(snak)) => {
+                return writeln!(f, "{subject}: added {}", snak_text(snak));
+            }
+            (StatementChangeKind::Removed, Some(snak), _) => {
+                return writeln!(f, "{subject}: removed {}", snak_text(snak));
+            }
+            (_, Some(old), Some(new)) => {
+                writeln!(f, "{subject}: {} -> {}", snak_text(old), snak_text(new))?
+            }
+            _ => {}
+        }
+        if let (Some(old), Some(new)) = (&self.old_rank, &self.new_rank) {
+            writeln!(f, "{subject}: rank {old} -> {new}")?;
+        }
+        for snak in &self.added_qualifiers {
+            writeln!(f, "{subject}: added qualifier {}", snak_text(snak))?;
+        }
+        for snak in &self.removed_qualifiers {
+            writeln!(f, "{subject}: removed qualifier {}", snak_text(snak))?;
+        }
+        for reference in &self.added_references {
+            writeln!(
+                f,
+                "{subject}: added reference {}",
+                reference_text(reference)
+            )?;
+        }
+        for reference in &self.removed_references {
+            writeln!(
+                f,
+                "{subject}: removed reference {}",
+                reference_text(reference)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ItemDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for change in &self.labels {
+            term_line(f, "label", change)?;
+        }
+        for change in &self.descriptions {
+            term_line(f, "description", change)?;
+        }
+        for change in &self.aliases {
+            for alias in &change.added {
+                writeln!(f, "alias {}: added {alias:?}", change.language)?;
+            }
+            for alias in &change.removed {
+                writeln!(f, "alias {}: removed {alias:?}", change.language)?;
+            }
+        }
+        for change in &self.sitelinks {
+            let site = &change.site;
+            match (&change.old_title, &change.new_title) {
+                (Some(old), Some(new)) if old != new => {
+                    writeln!(f, "sitelink {site}: {old:?} -> {new:?}")?
+                }
+                (None, Some(new)) => writeln!(f, "sitelink {site}: added {new:?}")?,
+                (Some(old), None) => writeln!(f, "sitelink {site}: removed {old:?}")?,
+                _ => {}
+            }
+            for badge in &change.added_badges {
+                writeln!(f, "sitelink {site}: added badge {badge}")?;
+            }
+            for badge in &change.removed_badges {
+                writeln!(f, "sitelink {site}: removed badge {badge}")?;
+            }
+        }
+        for change in &self.statements {
+            write!(f, "{change}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use wikibase::{ItemEntity, SnakDataType, SnakType, StatementRank};
+
+    fn statement(id: &str, snak: Snak, references: Vec<Reference>) -> Statement {
+        let mut ret = Statement::new_normal(snak, vec![], references);
+        ret.set_id(id);
+        ret
+    }
+
+    #[test]
+    fn test_identical_items_have_empty_diff() {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut().push(LocaleString::new("en", "Foo"));
+        item.add_claim(statement("Q1$1", Snak::new_item("P31", "Q5"), vec![]));
+        let diff = ItemDiff::new(&item, &item.clone());
+        assert!(diff.is_empty());
+        assert_eq!(diff.to_string(), "");
+    }
+
+    #[test]
+    fn test_term_changes() {
+        let mut old = ItemEntity::new_empty();
+        old.labels_mut().push(LocaleString::new("en", "Foo"));
+        old.labels_mut().push(LocaleString::new("fr", "Le Foo"));
+        old.aliases_mut().push(LocaleString::new("en", "F"));
+        let mut new = ItemEntity::new_empty();
+        new.labels_mut().push(LocaleString::new("en", "Bar"));
+        new.labels_mut().push(LocaleString::new("de", "Das Foo"));
+        new.descriptions_mut()
+            .push(LocaleString::new("en", "a thing"));
+        new.aliases_mut().push(LocaleString::new("en", "B"));
+
+        let diff = ItemDiff::new(&old, &new);
+        assert_eq!(diff.labels.len(), 3);
+        assert_eq!(
+            diff.to_string(),
+            "label de: added \"Das Foo\"\n\
+             label en: \"Foo\" -> \"Bar\"\n\
+             label fr: removed \"Le Foo\"\n\
+             description en: added \"a thing\"\n\
+             alias en: added \"B\"\n\
+             alias en: removed \"F\"\n"
+        );
+    }
+
+    #[test]
+    fn test_sitelink_changes_include_badges() {
+        let mut old = ItemEntity::new_empty();
+        old.set_sitelinks(Some(vec![
+            SiteLink::new("enwiki", "Foo", vec![]),
+            SiteLink::new("dewiki", "Foo", vec!["Q17437796".to_string()]),
+        ]));
+        let mut new = ItemEntity::new_empty();
+        new.set_sitelinks(Some(vec![
+            SiteLink::new("enwiki", "Foo", vec!["Q17437798".to_string()]),
+            SiteLink::new("frwiki", "Foo", vec![]),
+        ]));
+
+        let diff = ItemDiff::new(&old, &new);
+        assert_eq!(
+            diff.to_string(),
+            "sitelink dewiki: removed \"Foo\"\n\
+             sitelink dewiki: removed badge Q17437796\n\
+             sitelink enwiki: added badge Q17437798\n\
+             sitelink frwiki: added \"Foo\"\n"
+        );
+    }
+
+    #[test]
+    fn test_statement_changes_by_id() {
+        let reference = Reference::new(vec![Snak::new_url("P854", "https://example.org")]);
+        let mut old = ItemEntity::new_empty();
+        old.add_claim(statement("Q1$1", Snak::new_item("P31", "Q5"), vec![]));
+        old.add_claim(statement(
+            "Q1$2",
+            Snak::new_string("P1476", "Title"),
+            vec![reference.clone()],
+        ));
+        old.add_claim(statement("Q1$3", Snak::new_item("P27", "Q183"), vec![]));
+        let mut new = ItemEntity::new_empty();
+        new.add_claim(statement("Q1$1", Snak::new_item("P31", "Q215627"), vec![]));
+        let mut retitled = statement("Q1$2", Snak::new_string("P1476", "Title"), vec![]);
+        retitled.set_rank(StatementRank::Preferred);
+        retitled.set_qualifier_snaks(vec![Snak::new_string("P1810", "T.")]);
+        new.add_claim(retitled);
+        new.add_claim(Statement::new_normal(
+            Snak::new_item("P21", "Q6581097"),
+            vec![],
+            vec![],
+        ));
+
+        let diff = ItemDiff::new(&old, &new);
+        let kinds: Vec<_> = diff.statements.iter().map(|s| s.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                StatementChangeKind::Changed,
+                StatementChangeKind::Changed,
+                StatementChangeKind::Removed,
+                StatementChangeKind::Added,
+            ]
+        );
+        assert_eq!(
+            diff.to_string(),
+            "statement Q1$1: P31=Q5 -> P31=Q215627\n\
+             statement Q1$2: rank normal -> preferred\n\
+             statement Q1$2: added qualifier P1810=T.\n\
+             statement Q1$2: removed reference P854=https://example.org\n\
+             statement Q1$3: removed P27=Q183\n\
+             statement P21: added P21=Q6581097\n"
+        );
+    }
+
+    #[test]
+    fn test_snak_type_of_qualifiers_and_references_is_a_change() {
+        let snak = |snak_type| Snak::new(SnakDataType::String, "P1810", snak_type, None);
+        let with = |snak_type| {
+            let mut s = statement(
+                "Q1$1",
+                Snak::new_item("P31", "Q5"),
+                vec![Reference::new(vec![snak(snak_type)])],
+            );
+            s.set_qualifier_snaks(vec![snak(snak_type)]);
+            s
+        };
+        let mut old = ItemEntity::new_empty();
+        old.add_claim(with(SnakType::NoValue));
+        let mut new = ItemEntity::new_empty();
+        new.add_claim(with(SnakType::UnknownValue));
+
+        let diff = ItemDiff::new(&old, &new);
+        assert_eq!(diff.statements.len(), 1);
+        let change = &diff.statements[0];
+        assert_eq!(change.added_qualifiers, vec![snak(SnakType::UnknownValue)]);
+        assert_eq!(change.removed_qualifiers, vec![snak(SnakType::NoValue)]);
+        assert_eq!(change.added_references.len(), 1);
+        assert_eq!(change.removed_references.len(), 1);
+        assert!(ItemDiff::new(&old, &old).is_empty());
+    }
+
+    #[test]
+    fn test_time_below_precision_is_not_a_change() {
+        let mut old = ItemEntity::new_empty();
+        old.add_claim(statement(
+            "Q1$1",
+            Snak::new_time("P569", "+1952-03-11T00:00:00Z", 9),
+            vec![],
+        ));
+        let mut new = ItemEntity::new_empty();
+        new.add_claim(statement(
+            "Q1$1",
+            Snak::new_time("P569", "+1952-01-01T00:00:00Z", 9),
+            vec![],
+        ));
+        assert!(ItemDiff::new(&old, &new).is_empty());
+    }
+
+    #[test]
+    fn test_serializes_as_json() {
+        let old = ItemEntity::new_empty();
+        let mut new = ItemEntity::new_empty();
+        new.labels_mut().push(LocaleString::new("en", "Foo"));
+        new.add_claim(statement("Q1$1", Snak::new_item("P31", "Q5"), vec![]));
+
+        let json = json!(ItemDiff::new(&old, &new));
+        assert_eq!(
+            json["labels"],
+            json!([{"language": "en", "old": null, "new": "Foo"}])
+        );
+        let statement = &json["statements"][0];
+        assert_eq!(statement["id"], "Q1$1");
+        assert_eq!(statement["kind"], "added");
+        assert_eq!(statement["new_rank"], "normal");
+        assert_eq!(statement["new_main_snak"]["property"], "P31");
+        assert!(statement.get("old_main_snak").is_none());
+        assert!(json.get("sitelinks").is_none());
+    }
+}
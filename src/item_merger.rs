@@ -1,9 +1,26 @@
 //! `ItemMerger` takes an `ItemEntity` and merges another `ItemEntity` into it.
 //! It returns the differences as a [`MergeDiff`] object, which can be sent to
-//! the `wbeditentity` API action.
+//! the `wbeditentity` API action. [`LexemeMerger`] and [`PropertyMerger`] do
+//! the same for lexemes and properties; see [`crate::merge_entity`].
 //!
-//! Note that currently, only added or altered statements appear in the diff —
-//! removed statements are intentionally not emitted.
+//! By default, only added or altered data appears in the diff — nothing the
+//! base item has is ever removed. With [`ItemMerger::set_remove_absent`], the
+//! merged-in entity is treated as authoritative instead: labels, descriptions,
+//! aliases, sitelinks, and statements it lacks are removed, and labels,
+//! descriptions, and sitelinks it has a different value for are replaced.
+//!
+//! How clashing labels and descriptions, qualifier lists, and imprecise dates
+//! are treated is set by a [`MergePolicy`]; see [`crate::merge_policy`].
+//! [`ItemMerger::merge_reported`] and [`ItemMerger::dry_run`] explain each of
+//! those decisions; see [`crate::merge_report`].
+//!
+//! Snaks from third-party JSON often lack a datatype, which hides external IDs
+//! and reference URLs from the checks below. With
+//! [`ItemMerger::set_property_registry`], every snak is typed from the
+//! registry before it is compared. With
+//! [`ItemMerger::set_constraint_checks`], each statement it would add is also
+//! checked against the registry's property constraints; see
+//! [`crate::merge_constraints`].
 //!
 //! # Stateful merger
 //!
@@ -23,11 +40,25 @@
 //! ```
 
 use crate::external_id::ExternalId;
+use crate::merge_constraints::{
+    CheckedMerge, ConstraintAction, ConstraintChecks, ConstraintKind, ConstraintViolation,
+};
 use crate::merge_diff::MergeDiff;
+use crate::merge_entity::MergeEntity;
+use crate::merge_normalize::ValueNormalization;
+use crate::merge_policy::{DescriptionConflict, LabelConflict, MergePolicy, QualifierMatch};
+use crate::merge_provenance::Provenance;
+#[cfg(doc)]
+use crate::merge_references::ReferenceConsolidation;
+use crate::merge_report::{
+    constraint_name, explain_terms, note, note_claim_parts, Decision, MergeReport, ReportEntry,
+    ReportSubject, ReportedMerge,
+};
+use crate::property_registry::PropertyRegistry;
 use regex::Regex;
 
 use std::cmp::Ordering;
-use std::sync::LazyLock;
+use std::sync::{Arc, LazyLock};
 use wikibase::*;
 
 // Literal patterns, held as `Option<Regex>` so a pattern that somehow failed to
@@ -36,30 +67,49 @@ use wikibase::*;
 static YEAR_FIX: LazyLock<Option<Regex>> = LazyLock::new(|| Regex::new(r"-\d\d-\d\dT").ok());
 static MONTH_FIX: LazyLock<Option<Regex>> = LazyLock::new(|| Regex::new(r"-\d\dT").ok());
 
+/// Merges into an item by default; see [`crate::merge_entity`] for lexemes
+/// and properties.
 #[derive(Debug, Clone)]
-pub struct ItemMerger {
-    item: ItemEntity,
-    properties_ignore_qualifier_match: Vec<String>,
+pub struct ItemMerger<E = ItemEntity> {
+    item: E,
+    policy: MergePolicy,
+    remove_absent: bool,
+    property_registry: Option<Arc<PropertyRegistry>>,
+    constraint_checks: Option<ConstraintChecks>,
+    violations: Vec<ConstraintViolation>,
+    provenance: Provenance,
+    /// Collects decisions while [`Self::merge_reported`] runs.
+    report: Option<MergeReport>,
 }
 
-impl ItemMerger {
-    pub fn new(item: ItemEntity) -> Self {
+impl<E: MergeEntity> ItemMerger<E> {
+    pub fn new(item: E) -> Self {
         Self {
             item,
-            properties_ignore_qualifier_match: vec![],
+            policy: MergePolicy::default(),
+            remove_absent: false,
+            property_registry: None,
+            constraint_checks: None,
+            violations: vec![],
+            provenance: Provenance::new(),
+            report: None,
         }
     }
 
     /// Borrow the merged-so-far entity.
-    pub fn item(&self) -> &ItemEntity {
+    pub fn item(&self) -> &E {
         &self.item
     }
 
     /// Consume the merger and return the merged entity.
-    pub fn into_item(self) -> ItemEntity {
+    pub fn into_item(self) -> E {
         self.item
     }
 
+    pub(crate) fn item_mut(&mut self) -> &mut E {
+        &mut self.item
+    }
+
     /// Merge `other` into the internal item.
     ///
     /// **Side effect:** mutates `self.item` by absorbing labels/aliases/etc.
@@ -69,35 +119,52 @@ impl ItemMerger {
     /// **Return value:** the diff for *this call only*. Callers that want a
     /// cumulative diff across multiple merges should hold their own
     /// [`MergeDiff`] and `extend` it on each call.
-    pub fn merge(&mut self, other: &ItemEntity) -> MergeDiff {
-        let mut diff = MergeDiff::new();
-        let mut new_aliases =
-            Self::merge_locale_strings(self.item.labels_mut(), other.labels(), &mut diff.labels);
+    pub fn merge(&mut self, other: &E) -> MergeDiff {
+        let mut diff = MergeDiff {
+            entity_type: Some(*self.item.entity_type()),
+            ..Default::default()
+        };
+        if self.remove_absent {
+            self.remove_absent_from(other, &mut diff);
+        }
+        let label_conflict = self.label_conflict();
+        let mut new_aliases = ItemMerger::merge_locale_strings(
+            self.item.labels_mut(),
+            other.labels(),
+            &mut diff.labels,
+            label_conflict,
+        );
 
         // Descriptions
-        let mut new_ones: Vec<LocaleString> = other
+        let replace = self.policy.description_conflict == DescriptionConflict::Replace;
+        let new_ones: Vec<LocaleString> = other
             .descriptions()
             .iter()
-            .filter_map(|x| {
+            .filter(|x| {
                 match self
                     .item
                     .descriptions()
                     .iter()
                     .find(|y| x.language() == y.language())
                 {
-                    Some(_) => None,
-                    None => Some(x.clone()),
+                    Some(y) => replace && x.value() != y.value(),
+                    None => true,
                 }
             })
             .filter(|d| !self.item.labels().contains(d))
             .filter(|d| !self.item.aliases().contains(d))
+            .cloned()
             .collect();
         diff.descriptions.append(&mut new_ones.clone());
-        self.item.descriptions_mut().append(&mut new_ones);
+        for description in new_ones {
+            let descriptions = self.item.descriptions_mut();
+            descriptions.retain(|d| d.language() != description.language());
+            descriptions.push(description);
+        }
 
         // Aliases
         new_aliases.append(&mut other.aliases().clone());
-        new_aliases.sort_by(Self::compare_locale_string);
+        new_aliases.sort_by(ItemMerger::compare_locale_string);
         new_aliases.dedup();
         diff.aliases = new_aliases
             .iter()
@@ -109,7 +176,9 @@ impl ItemMerger {
         self.item
             .aliases_mut()
             .append(&mut other.aliases().to_owned());
-        self.item.aliases_mut().sort_by(Self::compare_locale_string);
+        self.item
+            .aliases_mut()
+            .sort_by(ItemMerger::compare_locale_string);
         self.item.aliases_mut().dedup();
 
         // Sitelinks: add only
@@ -123,7 +192,7 @@ impl ItemMerger {
                 .cloned()
                 .collect();
             if !new_ones.is_empty() {
-                diff.sitelinks = new_ones.clone();
+                diff.sitelinks.extend(new_ones.iter().cloned());
                 // A fresh `ItemEntity` has no sitelink list at all, so create one
                 // rather than discarding the incoming links.
                 self.item
@@ -139,52 +208,285 @@ impl ItemMerger {
             }
         }
 
+        E::merge_specific(self, other, &mut diff);
         diff
     }
 
+    /// The policy's [`LabelConflict`], except that entities without aliases
+    /// keep their label rather than gain one.
+    fn label_conflict(&self) -> LabelConflict {
+        match self.policy.label_conflict {
+            LabelConflict::AddAsAlias if !E::HAS_ALIASES => LabelConflict::Keep,
+            conflict => conflict,
+        }
+    }
+
+    /// Like [`Self::merge`], but also tags everything the call adds to the
+    /// diff, and everything already in it that `other` backs, with `source`
+    /// in [`Self::provenance`]; see [`crate::merge_provenance`].
+    pub fn merge_from(&mut self, other: &E, source: &str) -> MergeDiff {
+        let before = self.item.claims().to_owned();
+        self.provenance
+            .corroborate(self.item.claims(), other, source, &self.policy);
+        let diff = self.merge(other);
+        self.provenance.record(&before, &diff, source);
+        diff
+    }
+
+    /// Which source contributed what, across all [`Self::merge_from`] calls.
+    pub fn provenance(&self) -> &Provenance {
+        &self.provenance
+    }
+
+    /// Like [`Self::merge`], but also explains every decision; see
+    /// [`crate::merge_report`].
+    pub fn merge_reported(&mut self, other: &E) -> ReportedMerge {
+        let before = self.item.clone();
+        self.report = Some(MergeReport::default());
+        let diff = self.merge(other);
+        let claims = self.report.take().unwrap_or_default();
+        let policy = MergePolicy {
+            label_conflict: self.label_conflict(),
+            ..self.policy.clone()
+        };
+        let mut entries = explain_terms(&before, other, &diff, &policy);
+        entries.extend(claims.entries);
+        ReportedMerge {
+            diff,
+            report: MergeReport { entries },
+        }
+    }
+
+    /// What [`Self::merge_reported`] would do, without changing the merger.
+    pub fn dry_run(&self, other: &E) -> ReportedMerge {
+        self.clone().merge_reported(other)
+    }
+
+    /// Like [`Self::merge`], but also returns the constraint violations
+    /// found during this call; see [`Self::set_constraint_checks`].
+    pub fn merge_checked(&mut self, other: &E) -> CheckedMerge {
+        let diff = self.merge(other);
+        CheckedMerge {
+            diff,
+            violations: self.take_violations(),
+        }
+    }
+
+    /// Returns and clears the constraint violations collected since the last
+    /// call, including those from [`Self::add_claim`] and [`Self::merge`].
+    pub fn take_violations(&mut self) -> Vec<ConstraintViolation> {
+        std::mem::take(&mut self.violations)
+    }
+
+    /// Brings the item in line with `other` ahead of the additive merge: removes
+    /// whatever `other` lacks and replaces single-valued terms and sitelinks
+    /// whose values differ. The additive pass that follows then only has to
+    /// add what is new.
+    ///
+    /// Only statements carrying an ID are candidates for removal, since only
+    /// those exist on the wiki; a statement counts as present in `other` under
+    /// the same main-snak and qualifier rules [`Self::add_claim`] uses.
+    fn remove_absent_from(&mut self, other: &E, diff: &mut MergeDiff) {
+        ItemMerger::replace_locale_strings(
+            self.item.labels_mut(),
+            other.labels(),
+            &mut diff.labels,
+            &mut diff.removed_labels,
+        );
+        ItemMerger::replace_locale_strings(
+            self.item.descriptions_mut(),
+            other.descriptions(),
+            &mut diff.descriptions,
+            &mut diff.removed_descriptions,
+        );
+
+        let (kept, mut removed): (Vec<LocaleString>, Vec<LocaleString>) = self
+            .item
+            .aliases()
+            .iter()
+            .cloned()
+            .partition(|a| other.aliases().contains(a));
+        *self.item.aliases_mut() = kept;
+        diff.removed_aliases.append(&mut removed);
+
+        if let Some(sitelinks) = self.item.sitelinks_mut() {
+            let other_sitelinks = other.sitelinks().as_deref().unwrap_or_default();
+            let mut kept = vec![];
+            for sitelink in sitelinks.drain(..) {
+                match other_sitelinks.iter().find(|s| s.site() == sitelink.site()) {
+                    Some(new_sitelink) if new_sitelink.title() != sitelink.title() => {
+                        diff.sitelinks.push(new_sitelink.to_owned());
+                        kept.push(new_sitelink.to_owned());
+                    }
+                    Some(_) => kept.push(sitelink),
+                    None => diff.removed_sitelinks.push(sitelink),
+                }
+            }
+            *sitelinks = kept;
+        }
+
+        let (kept, removed): (Vec<Statement>, Vec<Statement>) =
+            self.item.claims().iter().cloned().partition(|existing| {
+                existing.id().is_none()
+                    || other.claims().iter().any(|new_claim| {
+                        ItemMerger::claims_match(&self.policy, new_claim, existing)
+                    })
+            });
+        *self.item.claims_mut() = kept;
+        for statement in removed {
+            if let Some(id) = statement.id() {
+                diff.removed_statements.insert(id, statement);
+            }
+        }
+    }
+
     /// Adds a new claim to the item's claims.
     ///
-    /// If a claim with an identical main snak already exists *and* the two
-    /// qualifier lists are compatible — see [`Self::are_qualifiers_compatible`],
-    /// or the property being listed via
-    /// [`Self::set_properties_ignore_qualifier_match`] — the new claim is folded
+    /// If a claim with a matching main snak already exists *and* the two
+    /// qualifier lists match under the policy's [`QualifierMatch`] for the
+    /// property — by default, are compatible; see
+    /// [`Self::are_qualifiers_compatible`] — the new claim is folded
     /// into the existing one instead of being added: any references and
     /// qualifiers it contributes are merged in. External-ID claims are never
-    /// merged this way; a duplicate is simply dropped.
+    /// merged this way; a duplicate is simply dropped. A new reference that
+    /// matches an existing one is dropped too, unless the policy has a
+    /// [`ReferenceConsolidation`]; see [`crate::merge_references`]. Main snaks,
+    /// qualifiers and references are compared under the policy's
+    /// [`ValueNormalization`]; see [`crate::merge_normalize`].
     ///
     /// Returns `Some(claim)` if a claim was added or changed, `None` otherwise.
     pub fn add_claim(&mut self, mut new_claim: Statement) -> Option<Statement> {
-        let mut existing_claims_iter = self
-            .item
-            .claims_mut()
-            .iter_mut()
-            .filter(|existing_claim| {
-                Self::is_snak_identical(new_claim.main_snak(), existing_claim.main_snak())
-            })
-            .filter(|existing_claim| {
-                let property = existing_claim.main_snak().property().to_string();
-                self.properties_ignore_qualifier_match.contains(&property)
-                    || Self::are_qualifiers_compatible(
-                        new_claim.qualifiers(),
-                        existing_claim.qualifiers(),
-                    )
+        if let Some(registry) = &self.property_registry {
+            registry.classify_statement(&mut new_claim);
+        }
+        let policy = &self.policy;
+        let mut existing_claims_iter =
+            self.item.claims_mut().iter_mut().filter(|existing_claim| {
+                ItemMerger::claims_match(policy, &new_claim, existing_claim)
             });
         if let Some(existing_claim) = existing_claims_iter.next() {
             // At least one claim exists, use first one
-            if *new_claim.main_snak().datatype() == SnakDataType::ExternalId {
+            let main_snak = new_claim.main_snak();
+            if *main_snak.datatype() == SnakDataType::ExternalId {
+                note(&mut self.report, || {
+                    let subject = ReportSubject::claim(main_snak);
+                    ReportEntry::new(subject, Decision::Skipped, "external ID already present")
+                });
                 return None; // Claim already exists, don't add reference to external IDs
             }
             let mut new_references = existing_claim.references().clone();
             let mut reference_changed = false;
             for r in new_claim.references() {
-                if !Self::reference_exists(&new_references, r) {
-                    new_references.push(r.to_owned());
-                    reference_changed = true;
+                match ItemMerger::reference_match(&policy.normalization, &new_references, r) {
+                    Some((pos, reason)) => {
+                        let changes = match &policy.reference_consolidation {
+                            Some(consolidation) => {
+                                consolidation.consolidate(&mut new_references[pos], r)
+                            }
+                            None => vec![],
+                        };
+                        let (decision, reason) = if changes.is_empty() {
+                            (Decision::Skipped, reason)
+                        } else {
+                            reference_changed = true;
+                            (
+                                Decision::Merged,
+                                format!("{reason}; {}", changes.join(", ")),
+                            )
+                        };
+                        note(&mut self.report, || {
+                            let subject = ReportSubject::reference(main_snak, r);
+                            ReportEntry::new(subject, decision, reason)
+                        });
+                    }
+                    None => {
+                        note(&mut self.report, || {
+                            let subject = ReportSubject::reference(main_snak, r);
+                            ReportEntry::new(subject, Decision::Added, "new reference")
+                        });
+                        new_references.push(r.to_owned());
+                        reference_changed = true;
+                    }
                 }
             }
-            let qualifier_snaks =
-                Self::merge_qualifiers(new_claim.qualifiers(), existing_claim.qualifiers());
+            // Re-ordering alone is not worth an edit.
+            if let (true, Some(consolidation)) =
+                (reference_changed, &policy.reference_consolidation)
+            {
+                consolidation.rank(&mut new_references);
+            }
+            let mut offered = new_claim.qualifiers().to_owned();
+            if let (Some(registry), Some(checks)) =
+                (&self.property_registry, &self.constraint_checks)
+            {
+                // A qualifier folded into an existing statement is dropped on
+                // `Deprecate` too: deprecating data already on the item for
+                // what the new source contributes would punish the wrong side.
+                let info = registry.get(new_claim.property());
+                let action = match checks.action(ConstraintKind::AllowedQualifiers) {
+                    ConstraintAction::Flag => ConstraintAction::Flag,
+                    ConstraintAction::Deprecate | ConstraintAction::Reject => {
+                        ConstraintAction::Reject
+                    }
+                };
+                offered.retain(|qualifier| {
+                    if info.is_none_or(|i| i.allows_qualifier(qualifier.property()))
+                        || existing_claim
+                            .qualifiers()
+                            .iter()
+                            .any(|q| policy.normalization.snaks_match(q, qualifier))
+                    {
+                        return true;
+                    }
+                    self.violations.push(ConstraintViolation {
+                        kind: ConstraintKind::AllowedQualifiers,
+                        property: new_claim.property().to_string(),
+                        detail: qualifier.property().to_string(),
+                        action,
+                        claim: new_claim.clone(),
+                    });
+                    if action == ConstraintAction::Reject {
+                        note(&mut self.report, || {
+                            let subject = ReportSubject::qualifier(main_snak, qualifier);
+                            let reason = "violates allowed-qualifiers constraint";
+                            ReportEntry::new(subject, Decision::Rejected, reason)
+                        });
+                    }
+                    action == ConstraintAction::Flag
+                });
+            }
+            let qualifier_snaks = ItemMerger::merge_qualifiers(
+                &policy.normalization,
+                &offered,
+                existing_claim.qualifiers(),
+            );
             let qualifiers_changed = qualifier_snaks != *existing_claim.qualifiers();
+            if self.report.is_some() {
+                for qualifier in qualifier_snaks
+                    .iter()
+                    .skip(existing_claim.qualifiers().len())
+                {
+                    note(&mut self.report, || {
+                        let subject = ReportSubject::qualifier(main_snak, qualifier);
+                        ReportEntry::new(subject, Decision::Added, "new qualifier")
+                    });
+                }
+                let property = main_snak.property();
+                let matched = match policy.qualifier_match_for(property) {
+                    QualifierMatch::Compatible => "qualifiers compatible".to_string(),
+                    QualifierMatch::Exact => "qualifiers identical".to_string(),
+                    QualifierMatch::Ignore => format!("qualifiers ignored for {property}"),
+                };
+                let (decision, reason) = if reference_changed || qualifiers_changed {
+                    (Decision::Merged, format!("duplicate main snak, {matched}"))
+                } else {
+                    let reason = format!("duplicate main snak, {matched}; nothing new");
+                    (Decision::Skipped, reason)
+                };
+                note(&mut self.report, || {
+                    ReportEntry::new(ReportSubject::claim(main_snak), decision, reason)
+                });
+            }
 
             if reference_changed || qualifiers_changed {
                 existing_claim.set_references(new_references);
@@ -194,21 +496,232 @@ impl ItemMerger {
             return None; // Claim already exists, including references
         }
 
+        if let (Some(registry), Some(checks)) = (&self.property_registry, &self.constraint_checks) {
+            let problems = checks.problems(registry, &self.item, &new_claim);
+            let action = problems.iter().map(|(kind, _)| checks.action(*kind)).max();
+            let violated: Vec<_> = problems
+                .iter()
+                .map(|(kind, _)| constraint_name(*kind))
+                .collect();
+            for (kind, detail) in problems {
+                self.violations.push(ConstraintViolation {
+                    kind,
+                    property: new_claim.property().to_string(),
+                    detail,
+                    action: action.unwrap_or(ConstraintAction::Flag),
+                    claim: new_claim.clone(),
+                });
+            }
+            if let Some(action) = action {
+                // A flagged statement's parts are noted below, where it is added.
+                let parts = match action {
+                    ConstraintAction::Reject => Some((Decision::Rejected, "statement rejected")),
+                    ConstraintAction::Deprecate => Some((Decision::Added, "new statement")),
+                    ConstraintAction::Flag => None,
+                };
+                if let Some((decision, reason)) = parts {
+                    note_claim_parts(&mut self.report, &new_claim, decision, reason);
+                }
+                note(&mut self.report, || {
+                    let reason = format!("violates {} constraint", violated.join(", "));
+                    let decision = match action {
+                        ConstraintAction::Reject => Decision::Rejected,
+                        ConstraintAction::Deprecate => Decision::Deprecated,
+                        ConstraintAction::Flag => Decision::Added,
+                    };
+                    ReportEntry::new(
+                        ReportSubject::claim(new_claim.main_snak()),
+                        decision,
+                        reason,
+                    )
+                });
+            }
+            match action {
+                Some(ConstraintAction::Reject) => return None,
+                Some(ConstraintAction::Deprecate) => {
+                    new_claim.set_rank(StatementRank::Deprecated);
+                    self.item.add_claim(new_claim.clone());
+                    return Some(new_claim);
+                }
+                Some(ConstraintAction::Flag) | None => {}
+            }
+        }
+
+        let rank = *new_claim.rank();
         self.check_new_claim_for_dates(&mut new_claim);
 
         // Claim does not exist, adding
+        note_claim_parts(
+            &mut self.report,
+            &new_claim,
+            Decision::Added,
+            "new statement",
+        );
+        note(&mut self.report, || {
+            let subject = ReportSubject::claim(new_claim.main_snak());
+            if *new_claim.rank() != rank {
+                let reason = format!("lower precision than existing {}", new_claim.property());
+                ReportEntry::new(subject, Decision::Deprecated, reason)
+            } else {
+                let reason = "no statement with this main snak and matching qualifiers";
+                ReportEntry::new(subject, Decision::Added, reason)
+            }
+        });
         self.item.add_claim(new_claim.clone());
         Some(new_claim)
     }
 
-    fn merge_qualifiers(new_qualifiers: &Vec<Snak>, existing_qualifiers: &Vec<Snak>) -> Vec<Snak> {
+    /// [`Self::add_claim`] against `claims`, such as a lexeme form's, rather
+    /// than the entity's own statements.
+    pub(crate) fn add_claim_to(
+        &mut self,
+        claims: &mut Vec<Statement>,
+        claim: Statement,
+    ) -> Option<Statement> {
+        std::mem::swap(self.item.claims_mut(), claims);
+        let ret = self.add_claim(claim);
+        std::mem::swap(self.item.claims_mut(), claims);
+        ret
+    }
+
+    /// Adds the entry `entry` builds to the report, while
+    /// [`Self::merge_reported`] runs.
+    pub(crate) fn note(&mut self, entry: impl FnOnce() -> ReportEntry) {
+        note(&mut self.report, entry);
+    }
+
+    /// Deprecates `new_claim` if its property is listed in
+    /// [`MergePolicy::precision_deprecation`] and the item already has a more
+    /// precise time value for it.
+    pub fn check_new_claim_for_dates(&self, new_claim: &mut Statement) {
+        let prop = new_claim.property();
+        if !self.policy.deprecates_less_precise(prop) {
+            return;
+        }
+        if let Some(dv) = new_claim.main_snak().data_value() {
+            let new_claim_precision = match dv.value() {
+                Value::Time(t) => *t.precision(),
+                _ => return,
+            };
+
+            let best_existing_precision = self
+                .item
+                .claims()
+                .iter()
+                .filter(|c| c.property() == prop)
+                .filter_map(|c| c.main_snak().data_value().to_owned())
+                .filter_map(|dv| match dv.value() {
+                    Value::Time(t) => Some(*t.precision()),
+                    _ => None,
+                })
+                .max()
+                .unwrap_or(0);
+            if new_claim_precision < best_existing_precision {
+                new_claim.set_rank(StatementRank::Deprecated);
+            }
+        }
+    }
+
+    /// When set, [`Self::merge`] treats each merged-in entity as authoritative
+    /// and also emits removals and replacements; see the module documentation.
+    /// Removals are computed against the merged-so-far item, so this mode is
+    /// meant for syncing an item against a single source.
+    pub fn set_remove_absent(&mut self, remove_absent: bool) {
+        self.remove_absent = remove_absent;
+    }
+
+    /// Types every snak of the base item, and of each claim merged in later,
+    /// from `registry`; see [`PropertyRegistry::classify_snak`].
+    pub fn set_property_registry(&mut self, registry: Arc<PropertyRegistry>) {
+        registry.classify_item(&mut self.item);
+        self.property_registry = Some(registry);
+    }
+
+    /// Checks each statement [`Self::add_claim`] would add against the
+    /// property constraints in the registry set with
+    /// [`Self::set_property_registry`], and rejects, deprecates or flags
+    /// violating ones as `checks` says. Without a registry nothing is checked.
+    /// Violations are collected for [`Self::take_violations`].
+    pub fn set_constraint_checks(&mut self, checks: ConstraintChecks) {
+        self.constraint_checks = Some(checks);
+    }
+
+    /// Folds new statements of these properties into existing ones whatever
+    /// their qualifiers, replacing earlier [`QualifierMatch::Ignore`]
+    /// overrides; shorthand for [`MergePolicy::set_qualifier_match`].
+    pub fn set_properties_ignore_qualifier_match(
+        &mut self,
+        properties_ignore_qualifier_match: Vec<String>,
+    ) {
+        self.policy
+            .qualifier_match_overrides
+            .retain(|_, mode| *mode != QualifierMatch::Ignore);
+        for property in properties_ignore_qualifier_match {
+            self.policy
+                .set_qualifier_match(&property, QualifierMatch::Ignore);
+        }
+    }
+
+    pub fn policy(&self) -> &MergePolicy {
+        &self.policy
+    }
+
+    /// Replaces the rules the merger works by; see [`crate::merge_policy`].
+    pub fn set_policy(&mut self, policy: MergePolicy) {
+        self.policy = policy;
+    }
+}
+
+/// Merges into a lexeme; see [`crate::merge_entity`].
+pub type LexemeMerger = ItemMerger<LexemeEntity>;
+
+/// Merges into a property; see [`crate::merge_entity`].
+pub type PropertyMerger = ItemMerger<PropertyEntity>;
+
+// Comparisons shared by the mergers of every entity type.
+impl ItemMerger {
+    /// Whether `new_claim` would be folded into `existing_claim` by
+    /// [`Self::add_claim`] rather than added alongside it.
+    pub(crate) fn claims_match(
+        policy: &MergePolicy,
+        new_claim: &Statement,
+        existing_claim: &Statement,
+    ) -> bool {
+        let normalization = &policy.normalization;
+        normalization.snaks_match(new_claim.main_snak(), existing_claim.main_snak())
+            && match policy.qualifier_match_for(existing_claim.main_snak().property()) {
+                QualifierMatch::Compatible => {
+                    Self::is_qualifier_subset(
+                        normalization,
+                        new_claim.qualifiers(),
+                        existing_claim.qualifiers(),
+                    ) || Self::is_qualifier_subset(
+                        normalization,
+                        existing_claim.qualifiers(),
+                        new_claim.qualifiers(),
+                    )
+                }
+                QualifierMatch::Exact => Self::qualifiers_match(
+                    normalization,
+                    new_claim.qualifiers(),
+                    existing_claim.qualifiers(),
+                ),
+                QualifierMatch::Ignore => true,
+            }
+    }
+
+    fn merge_qualifiers(
+        normalization: &ValueNormalization,
+        new_qualifiers: &Vec<Snak>,
+        existing_qualifiers: &Vec<Snak>,
+    ) -> Vec<Snak> {
         // Start with existing qualifiers
         let mut qualifier_snaks = existing_qualifiers.to_owned();
         // Add new qualifiers, if they do not exist yet
         for qualifier in new_qualifiers {
             if !existing_qualifiers
                 .iter()
-                .any(|q| Self::is_snak_identical(q, qualifier))
+                .any(|q| normalization.snaks_match(q, qualifier))
             {
                 qualifier_snaks.push(qualifier.to_owned());
             }
@@ -258,36 +771,101 @@ impl ItemMerger {
     ///
     /// Note: strategies 1 and 2 are intentionally loose — a partial ID match is enough to
     /// consider the reference already covered, avoiding duplicate sourcing from the same source.
+    #[cfg(test)]
     fn reference_exists(existing_references: &[Reference], new_reference: &Reference) -> bool {
-        let ext_ids = Self::get_external_ids_from_reference(new_reference);
-        let reference_urls = Self::get_reference_urls_from_reference(new_reference);
+        Self::reference_match(
+            &ValueNormalization::none(),
+            existing_references,
+            new_reference,
+        )
+        .is_some()
+    }
 
-        // Check if any external ID matches
-        let has_external_ids = !ext_ids.is_empty()
-            && existing_references
-                .iter()
-                .flat_map(Self::get_external_ids_from_reference)
-                .any(|ext_id| ext_ids.contains(&ext_id));
+    /// The position of the reference among `existing_references` that
+    /// `new_reference` duplicates, and why, or `None` if there is none.
+    /// Values are compared under `normalization`.
+    fn reference_match(
+        normalization: &ValueNormalization,
+        existing_references: &[Reference],
+        new_reference: &Reference,
+    ) -> Option<(usize, String)> {
+        let ext_ids = Self::external_id_snaks(new_reference);
+        let reference_urls = Self::string_snaks(new_reference, SnakDataType::Url);
 
-        // Check if any reference URL matches
-        let has_reference_urls = !reference_urls.is_empty()
-            && existing_references
-                .iter()
-                .flat_map(Self::get_reference_urls_from_reference)
-                .any(|reference_url| reference_urls.contains(&reference_url));
+        // Check if any external ID matches
+        for (pos, existing) in existing_references.iter().enumerate() {
+            if let Some(ext_id) = Self::external_id_snaks(existing)
+                .into_iter()
+                .find(|ext_id| ext_ids.iter().any(|e| normalization.snaks_match(e, ext_id)))
+            {
+                let reason = format!("shares {} with an existing reference", ext_id.property());
+                return Some((pos, reason));
+            }
+        }
 
-        if has_external_ids || has_reference_urls {
-            return true;
+        // Check if any reference URL matches, whatever the property
+        for (pos, existing) in existing_references.iter().enumerate() {
+            if let Some(Value::StringValue(url)) = Self::string_snaks(existing, SnakDataType::Url)
+                .into_iter()
+                .find(|url| {
+                    reference_urls
+                        .iter()
+                        .any(|u| normalization.values_match(u, url))
+                })
+                .and_then(|snak| snak.data_value().as_ref())
+                .map(|dv| dv.value())
+            {
+                let reason = format!("shares URL {url} with an existing reference");
+                return Some((pos, reason));
+            }
         }
 
         // Fallback: if the reference has no external IDs or URLs, compare all snaks structurally
         if ext_ids.is_empty() && reference_urls.is_empty() {
-            return existing_references.iter().any(|existing| {
-                Self::are_qualifiers_identical(existing.snaks(), new_reference.snaks())
-            });
+            if let Some(pos) = existing_references.iter().position(|existing| {
+                Self::qualifiers_match(normalization, existing.snaks(), new_reference.snaks())
+            }) {
+                return Some((pos, "identical to an existing reference".to_string()));
+            }
         }
 
-        false
+        None
+    }
+
+    /// The external ID snaks of `reference`, with their values as
+    /// [`ExternalId`] stores them, e.g. an ISNI (P213) without spaces,
+    /// whatever the policy's normalization.
+    fn external_id_snaks(reference: &Reference) -> Vec<Snak> {
+        Self::string_snaks(reference, SnakDataType::ExternalId)
+            .into_iter()
+            .map(|snak| {
+                let fixed = match snak.data_value().as_ref().map(|dv| dv.value()) {
+                    Some(Value::StringValue(id)) => ExternalId::prop_numeric(snak.property())
+                        .map(|property| ExternalId::new(property, id))
+                        .filter(|fixed| fixed.id() != id),
+                    _ => None,
+                };
+                match fixed {
+                    Some(fixed) => Snak::new_external_id(snak.property(), fixed.id()),
+                    None => snak.to_owned(),
+                }
+            })
+            .collect()
+    }
+
+    /// The snaks of `reference` with `datatype` and a string value.
+    fn string_snaks(reference: &Reference, datatype: SnakDataType) -> Vec<&Snak> {
+        reference
+            .snaks()
+            .iter()
+            .filter(|snak| *snak.datatype() == datatype)
+            .filter(|snak| {
+                matches!(
+                    snak.data_value().as_ref().map(|dv| dv.value()),
+                    Some(Value::StringValue(_))
+                )
+            })
+            .collect()
     }
 
     pub fn is_snak_identical(snak1: &Snak, snak2: &Snak) -> bool {
@@ -342,13 +920,40 @@ impl ItemMerger {
     /// statement that merely carries an extra qualifier such as P1810 ("subject named as").
     /// See <https://github.com/magnusmanske/auth2wd/issues/10>.
     pub fn are_qualifiers_compatible(q1: &[Snak], q2: &[Snak]) -> bool {
-        Self::is_qualifier_subset(q1, q2) || Self::is_qualifier_subset(q2, q1)
+        let normalization = ValueNormalization::none();
+        Self::is_qualifier_subset(&normalization, q1, q2)
+            || Self::is_qualifier_subset(&normalization, q2, q1)
     }
 
-    /// Returns `true` if every snak in `sub` has an identical snak in `sup`.
-    fn is_qualifier_subset(sub: &[Snak], sup: &[Snak]) -> bool {
+    /// Returns `true` if every snak in `sub` has a matching snak in `sup`.
+    fn is_qualifier_subset(normalization: &ValueNormalization, sub: &[Snak], sup: &[Snak]) -> bool {
         sub.iter()
-            .all(|q| sup.iter().any(|e| Self::is_snak_identical(q, e)))
+            .all(|q| sup.iter().any(|e| normalization.snaks_match(q, e)))
+    }
+
+    /// [`Self::are_qualifiers_identical`] under `normalization`: each snak
+    /// of `q1` pairs up with a different matching snak of `q2`.
+    pub(crate) fn qualifiers_match(
+        normalization: &ValueNormalization,
+        q1: &[Snak],
+        q2: &[Snak],
+    ) -> bool {
+        if q1.len() != q2.len() {
+            return false;
+        }
+        let mut unpaired: Vec<&Snak> = q2.iter().collect();
+        q1.iter().all(|snak| {
+            match unpaired
+                .iter()
+                .position(|other| normalization.snaks_match(snak, other))
+            {
+                Some(pos) => {
+                    unpaired.swap_remove(pos);
+                    true
+                }
+                None => false,
+            }
+        })
     }
 
     pub fn are_qualifiers_identical(q1: &[Snak], q2: &[Snak]) -> bool {
@@ -367,35 +972,6 @@ impl ItemMerger {
             .any(|(snak1, snak2)| !Self::is_snak_identical(snak1, snak2))
     }
 
-    pub fn check_new_claim_for_dates(&self, new_claim: &mut Statement) {
-        let prop = new_claim.property();
-        if prop != "P569" && prop != "P570" {
-            return;
-        }
-        if let Some(dv) = new_claim.main_snak().data_value() {
-            let new_claim_precision = match dv.value() {
-                Value::Time(t) => *t.precision(),
-                _ => return,
-            };
-
-            let best_existing_precision = self
-                .item
-                .claims()
-                .iter()
-                .filter(|c| c.property() == prop)
-                .filter_map(|c| c.main_snak().data_value().to_owned())
-                .filter_map(|dv| match dv.value() {
-                    Value::Time(t) => Some(*t.precision()),
-                    _ => None,
-                })
-                .max()
-                .unwrap_or(0);
-            if new_claim_precision < best_existing_precision {
-                new_claim.set_rank(StatementRank::Deprecated);
-            }
-        }
-    }
-
     pub fn compare_locale_string(a: &LocaleString, b: &LocaleString) -> Ordering {
         match a.language().cmp(b.language()) {
             Ordering::Equal => a.value().cmp(b.value()),
@@ -421,8 +997,10 @@ impl ItemMerger {
         mine: &mut Vec<LocaleString>,
         other: &[LocaleString],
         diff: &mut Vec<LocaleString>,
+        conflict: LabelConflict,
     ) -> Vec<LocaleString> {
         let mut ret = vec![];
+        let mut replaced = vec![];
         let mul_label = mine
             .iter()
             .find(|x| x.language() == "mul")
@@ -433,7 +1011,12 @@ impl ItemMerger {
                 match mine.iter().find(|y| x.language() == y.language()) {
                     Some(y) => {
                         if x.value() != y.value() {
-                            ret.push(x.clone()); // Labels for which a language already exists, as aliases
+                            match conflict {
+                                // Labels for which a language already exists, as aliases
+                                LabelConflict::AddAsAlias => ret.push(x.clone()),
+                                LabelConflict::Keep => {}
+                                LabelConflict::Replace => replaced.push(x.clone()),
+                            }
                         }
                         None
                     }
@@ -448,20 +1031,43 @@ impl ItemMerger {
             .collect();
         diff.append(&mut new_ones.clone());
         mine.append(&mut new_ones);
+        for label in replaced {
+            mine.retain(|l| l.language() != label.language());
+            diff.push(label.clone());
+            mine.push(label);
+        }
         ret
     }
 
-    pub fn set_properties_ignore_qualifier_match(
-        &mut self,
-        properties_ignore_qualifier_match: Vec<String>,
+    /// One-value-per-language counterpart of [`Self::merge_locale_strings`] for
+    /// removal mode: languages `other` lacks are removed, and differing values
+    /// are replaced by `other`'s.
+    fn replace_locale_strings(
+        mine: &mut Vec<LocaleString>,
+        other: &[LocaleString],
+        added: &mut Vec<LocaleString>,
+        removed: &mut Vec<LocaleString>,
     ) {
-        self.properties_ignore_qualifier_match = properties_ignore_qualifier_match;
+        let mut kept = vec![];
+        for ls in mine.drain(..) {
+            match other.iter().find(|x| x.language() == ls.language()) {
+                Some(new_ls) if new_ls.value() != ls.value() => {
+                    added.push(new_ls.to_owned());
+                    kept.push(new_ls.to_owned());
+                }
+                Some(_) => kept.push(ls),
+                None => removed.push(ls),
+            }
+        }
+        *mine = kept;
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::merge_normalize::Normalization;
+    use crate::merge_references::ReferenceConsolidation;
 
     #[test]
     fn test_add_claim_p225_both_with_qualifiers() {
@@ -497,6 +1103,13 @@ mod tests {
         assert!(!ItemMerger::reference_exists(&references, &reference2));
     }
 
+    #[test]
+    fn test_reference_exists_by_isni_with_spaces() {
+        let reference1 = Reference::new(vec![Snak::new_external_id("P213", "0000000121849233")]);
+        let reference2 = Reference::new(vec![Snak::new_external_id("P213", "0000 0001 2184 9233")]);
+        assert!(ItemMerger::reference_exists(&[reference1], &reference2));
+    }
+
     #[test]
     fn test_reference_exists_by_reference_urls() {
         let reference1 = Reference::new(vec![Snak::new_url("P854", "http://foo.bar")]);
@@ -1750,4 +2363,641 @@ mod tests {
         assert!(YEAR_FIX.is_some());
         assert!(MONTH_FIX.is_some());
     }
+
+    // --- Removal mode ---
+
+    fn statement_with_id(snak: Snak, id: &str) -> Statement {
+        let mut statement = Statement::new_normal(snak, vec![], vec![]);
+        statement.set_id(id);
+        statement
+    }
+
+    #[test]
+    fn test_merge_without_remove_absent_keeps_everything() {
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut().push(LocaleString::new("en", "Only here"));
+        base.add_claim(statement_with_id(Snak::new_string("P1", "x"), "Q1$a"));
+
+        let mut im = ItemMerger::new(base);
+        let diff = im.merge(&ItemEntity::new_empty());
+
+        assert!(diff.is_empty());
+        assert_eq!(im.item().labels().len(), 1);
+        assert_eq!(im.item().claims().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_remove_absent_removes_terms_and_sitelinks() {
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut().push(LocaleString::new("en", "Kept"));
+        base.labels_mut().push(LocaleString::new("de", "Weg"));
+        base.descriptions_mut()
+            .push(LocaleString::new("fr", "gone"));
+        base.aliases_mut()
+            .push(LocaleString::new("en", "kept alias"));
+        base.aliases_mut()
+            .push(LocaleString::new("en", "old alias"));
+        base.set_sitelink(SiteLink::new("enwiki", "Kept", vec![]));
+        base.set_sitelink(SiteLink::new("dewiki", "Weg", vec![]));
+
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Kept"));
+        other
+            .aliases_mut()
+            .push(LocaleString::new("en", "kept alias"));
+        other.set_sitelink(SiteLink::new("enwiki", "Kept", vec![]));
+
+        let mut im = ItemMerger::new(base);
+        im.set_remove_absent(true);
+        let diff = im.merge(&other);
+
+        assert_eq!(diff.removed_labels, vec![LocaleString::new("de", "Weg")]);
+        assert_eq!(
+            diff.removed_descriptions,
+            vec![LocaleString::new("fr", "gone")]
+        );
+        assert_eq!(
+            diff.removed_aliases,
+            vec![LocaleString::new("en", "old alias")]
+        );
+        assert_eq!(diff.removed_sitelinks.len(), 1);
+        assert_eq!(diff.removed_sitelinks[0].site(), "dewiki");
+        assert!(diff.labels.is_empty());
+        assert!(diff.aliases.is_empty());
+        assert!(diff.sitelinks.is_empty());
+        assert_eq!(im.item().labels().len(), 1);
+        assert_eq!(im.item().aliases().len(), 1);
+        assert_eq!(im.item().sitelinks().as_ref().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_merge_remove_absent_replaces_conflicting_values() {
+        // In removal mode a clashing label is replaced instead of being demoted
+        // to an alias; descriptions and sitelink titles are replaced too.
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut().push(LocaleString::new("en", "Foo"));
+        base.descriptions_mut()
+            .push(LocaleString::new("en", "old desc"));
+        base.set_sitelink(SiteLink::new("enwiki", "Foo", vec![]));
+
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Bar"));
+        other
+            .descriptions_mut()
+            .push(LocaleString::new("en", "new desc"));
+        other.set_sitelink(SiteLink::new("enwiki", "Bar", vec![]));
+
+        let mut im = ItemMerger::new(base);
+        im.set_remove_absent(true);
+        let diff = im.merge(&other);
+
+        assert_eq!(diff.labels, vec![LocaleString::new("en", "Bar")]);
+        assert!(diff.aliases.is_empty());
+        assert!(diff.removed_labels.is_empty());
+        assert_eq!(diff.descriptions, vec![LocaleString::new("en", "new desc")]);
+        assert_eq!(diff.sitelinks.len(), 1);
+        assert_eq!(diff.sitelinks[0].title(), "Bar");
+        assert!(diff.removed_sitelinks.is_empty());
+        assert_eq!(im.item().labels(), &vec![LocaleString::new("en", "Bar")]);
+    }
+
+    #[test]
+    fn test_merge_remove_absent_removes_unmatched_statements() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(statement_with_id(Snak::new_string("P1", "kept"), "Q1$kept"));
+        base.add_claim(statement_with_id(Snak::new_string("P1", "gone"), "Q1$gone"));
+
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(Statement::new_normal(
+            Snak::new_string("P1", "kept"),
+            vec![],
+            vec![],
+        ));
+        other.add_claim(Statement::new_normal(
+            Snak::new_string("P2", "new"),
+            vec![],
+            vec![],
+        ));
+
+        let mut im = ItemMerger::new(base);
+        im.set_remove_absent(true);
+        let diff = im.merge(&other);
+
+        assert_eq!(diff.removed_statements.len(), 1);
+        assert!(diff.removed_statements.contains_key("Q1$gone"));
+        assert_eq!(diff.added_statements.len(), 1);
+        assert_eq!(diff.added_statements[0].property(), "P2");
+        assert_eq!(im.item().claims().len(), 2);
+
+        // The serialized payload carries both the addition and the removal.
+        let serialized = serde_json::to_value(&diff).unwrap();
+        let claims = serialized["claims"].as_array().unwrap();
+        assert_eq!(claims.len(), 2);
+        assert!(claims
+            .iter()
+            .any(|c| c["id"] == "Q1$gone" && c["remove"] == ""));
+    }
+
+    #[test]
+    fn test_merge_remove_absent_statement_match_respects_qualifier_rules() {
+        // A source statement with a qualifier subset still matches the existing
+        // one, so nothing is removed.
+        let mut base = ItemEntity::new_empty();
+        let mut existing = Statement::new_normal(
+            Snak::new_external_id("P214", "123"),
+            vec![Snak::new_string("P1810", "Named as")],
+            vec![],
+        );
+        existing.set_id("Q1$viaf");
+        base.add_claim(existing);
+
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "123"),
+            vec![],
+            vec![],
+        ));
+
+        let mut im = ItemMerger::new(base);
+        im.set_remove_absent(true);
+        let diff = im.merge(&other);
+
+        assert!(diff.is_empty(), "unexpected diff: {diff:?}");
+    }
+
+    #[test]
+    fn test_merge_remove_absent_round_trips_through_apply() {
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut().push(LocaleString::new("en", "Foo"));
+        base.labels_mut().push(LocaleString::new("de", "Foo"));
+        base.aliases_mut().push(LocaleString::new("en", "F"));
+        base.add_claim(statement_with_id(Snak::new_string("P1", "gone"), "Q1$gone"));
+
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Bar"));
+        other.aliases_mut().push(LocaleString::new("en", "B"));
+
+        let mut im = ItemMerger::new(base.clone());
+        im.set_remove_absent(true);
+        let diff = im.merge(&other);
+        diff.apply(&mut base);
+
+        assert_eq!(base.labels(), im.item().labels());
+        assert_eq!(base.aliases(), im.item().aliases());
+        assert!(base.claims().is_empty());
+    }
+
+    #[test]
+    fn test_property_registry_types_untyped_external_ids() {
+        // Without a datatype the P214 claim looks like a plain string and would
+        // collect the new reference; typed as an external ID it is left alone.
+        use crate::property_registry::PropertyInfo;
+
+        let untyped = |property: &str, value: &str| {
+            let mut snak = Snak::new_string(property, value);
+            snak.set_datatype(SnakDataType::NotSet);
+            snak
+        };
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            untyped("P214", "123456"),
+            vec![],
+            vec![],
+        ));
+        let new_claim = Statement::new_normal(
+            untyped("P214", "123456"),
+            vec![],
+            vec![Reference::new(vec![untyped("P854", "http://viaf.org")])],
+        );
+
+        let mut registry = PropertyRegistry::new();
+        for (property, datatype) in [
+            ("P214", SnakDataType::ExternalId),
+            ("P854", SnakDataType::Url),
+        ] {
+            registry.insert(
+                property,
+                PropertyInfo {
+                    datatype: Some(datatype),
+                    ..Default::default()
+                },
+            );
+        }
+
+        let mut plain = ItemMerger::new(base.clone());
+        assert!(plain.add_claim(new_claim.clone()).is_some());
+
+        let mut im = ItemMerger::new(base);
+        im.set_property_registry(Arc::new(registry));
+        assert_eq!(
+            *im.item().claims()[0].main_snak().datatype(),
+            SnakDataType::ExternalId
+        );
+        assert!(im.add_claim(new_claim).is_none());
+        assert!(im.item().claims()[0].references().is_empty());
+    }
+
+    fn constrained_merger(checks: ConstraintChecks) -> ItemMerger {
+        use crate::property_registry::PropertyInfo;
+
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P214",
+            PropertyInfo {
+                datatype: Some(SnakDataType::ExternalId),
+                format_regex: Some("\\d+".to_string()),
+                single_value: true,
+                ..Default::default()
+            },
+        );
+        registry.insert(
+            "P1476",
+            PropertyInfo {
+                datatype: Some(SnakDataType::String),
+                allowed_qualifiers: Some(vec!["P407".to_string()]),
+                ..Default::default()
+            },
+        );
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "1"),
+            vec![],
+            vec![],
+        ));
+        base.add_claim(Statement::new_normal(
+            Snak::new_string("P1476", "Title"),
+            vec![],
+            vec![],
+        ));
+        let mut im = ItemMerger::new(base);
+        im.set_property_registry(Arc::new(registry));
+        im.set_constraint_checks(checks);
+        im
+    }
+
+    #[test]
+    fn test_merge_checked_rejects_deprecates_and_flags() {
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "2"),
+            vec![],
+            vec![],
+        ));
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "x"),
+            vec![],
+            vec![],
+        ));
+
+        let mut checks = ConstraintChecks::new(ConstraintAction::Reject);
+        checks.set_action(ConstraintKind::SingleValue, ConstraintAction::Deprecate);
+        let mut im = constrained_merger(checks);
+        let CheckedMerge { diff, violations } = im.merge_checked(&other);
+        // "2" breaks single-value only and is deprecated; "x" also fails the
+        // format regex, and the strictest action wins.
+        assert_eq!(diff.added_statements.len(), 1);
+        assert_eq!(*diff.added_statements[0].rank(), StatementRank::Deprecated);
+        let found: Vec<_> = violations
+            .iter()
+            .map(|v| (v.kind, v.detail.as_str(), v.action))
+            .collect();
+        assert_eq!(
+            found,
+            vec![
+                (
+                    ConstraintKind::SingleValue,
+                    "2",
+                    ConstraintAction::Deprecate
+                ),
+                (ConstraintKind::Format, "x", ConstraintAction::Reject),
+                (ConstraintKind::SingleValue, "x", ConstraintAction::Reject),
+            ]
+        );
+        assert!(im.take_violations().is_empty());
+
+        let mut im = constrained_merger(ConstraintChecks::new(ConstraintAction::Flag));
+        let checked = im.merge_checked(&other);
+        assert_eq!(checked.diff.added_statements.len(), 2);
+        assert!(checked
+            .diff
+            .added_statements
+            .iter()
+            .all(|s| *s.rank() == StatementRank::Normal));
+        assert_eq!(checked.violations.len(), 3);
+    }
+
+    #[test]
+    fn test_constraint_checks_drop_disallowed_qualifiers_on_existing_claims() {
+        let mut im = constrained_merger(ConstraintChecks::new(ConstraintAction::Deprecate));
+        let result = im.add_claim(Statement::new_normal(
+            Snak::new_string("P1476", "Title"),
+            vec![
+                Snak::new_item("P407", "Q1860"),
+                Snak::new_string("P580", "x"),
+            ],
+            vec![],
+        ));
+        let qualifiers: Vec<_> = result
+            .unwrap()
+            .qualifiers()
+            .iter()
+            .map(|q| q.property().to_string())
+            .collect();
+        assert_eq!(qualifiers, vec!["P407"]);
+        let violations = im.take_violations();
+        assert_eq!(violations.len(), 1);
+        assert_eq!(violations[0].kind, ConstraintKind::AllowedQualifiers);
+        assert_eq!(violations[0].detail, "P580");
+        assert_eq!(violations[0].action, ConstraintAction::Reject);
+    }
+
+    #[test]
+    fn test_constraint_checks_need_a_registry() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "1"),
+            vec![],
+            vec![],
+        ));
+        let mut im = ItemMerger::new(base);
+        im.set_constraint_checks(ConstraintChecks::new(ConstraintAction::Reject));
+        let claim = Statement::new_normal(Snak::new_external_id("P214", "x"), vec![], vec![]);
+        assert!(im.add_claim(claim).is_some());
+        assert!(im.take_violations().is_empty());
+    }
+
+    // ── Merge policies ─────────────────────────────────────────────────────
+
+    fn item_with_terms(label: &str, description: &str) -> ItemEntity {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut().push(LocaleString::new("en", label));
+        item.descriptions_mut()
+            .push(LocaleString::new("en", description));
+        item
+    }
+
+    #[test]
+    fn test_policy_label_and_description_conflicts() {
+        let base = item_with_terms("Foo", "a thing");
+        let other = item_with_terms("Bar", "another thing");
+
+        let mut im = ItemMerger::new(base.clone());
+        im.set_policy(MergePolicy {
+            label_conflict: LabelConflict::Keep,
+            ..Default::default()
+        });
+        let diff = im.merge(&other);
+        assert!(diff.is_empty(), "unexpected diff: {diff:?}");
+        assert!(im.item().aliases().is_empty());
+
+        let mut im = ItemMerger::new(base);
+        im.set_policy(MergePolicy {
+            label_conflict: LabelConflict::Replace,
+            description_conflict: DescriptionConflict::Replace,
+            ..Default::default()
+        });
+        let diff = im.merge(&other);
+        assert_eq!(diff.labels, vec![LocaleString::new("en", "Bar")]);
+        assert_eq!(
+            diff.descriptions,
+            vec![LocaleString::new("en", "another thing")]
+        );
+        assert!(diff.aliases.is_empty());
+        assert_eq!(im.item().labels(), &vec![LocaleString::new("en", "Bar")]);
+        assert_eq!(
+            im.item().descriptions(),
+            &vec![LocaleString::new("en", "another thing")]
+        );
+    }
+
+    #[test]
+    fn test_policy_exact_qualifier_match_with_override() {
+        let mut base = ItemEntity::new_empty();
+        for property in ["P1", "P2"] {
+            base.add_claim(Statement::new_normal(
+                Snak::new_string(property, "x"),
+                vec![],
+                vec![],
+            ));
+        }
+        let qualified = |property: &str| {
+            Statement::new_normal(
+                Snak::new_string(property, "x"),
+                vec![Snak::new_string("P580", "q")],
+                vec![],
+            )
+        };
+
+        let mut policy = MergePolicy {
+            qualifier_match: QualifierMatch::Exact,
+            ..Default::default()
+        };
+        policy.set_qualifier_match("P2", QualifierMatch::Compatible);
+        let mut im = ItemMerger::new(base);
+        im.set_policy(policy);
+        im.add_claim(qualified("P1"));
+        im.add_claim(qualified("P2"));
+        // P1 requires identical qualifiers, so the qualified claim stands
+        // apart; P2 folds it into the existing one.
+        let count = |p: &str| {
+            im.item()
+                .claims()
+                .iter()
+                .filter(|c| c.property() == p)
+                .count()
+        };
+        assert_eq!(count("P1"), 2);
+        assert_eq!(count("P2"), 1);
+    }
+
+    #[test]
+    fn test_normalized_reference_urls_and_qualifiers_match() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            Snak::new_string("P1476", "some title"),
+            vec![Snak::new_url("P973", "https://example.org/about")],
+            vec![Reference::new(vec![Snak::new_url(
+                "P854",
+                "https://Example.org/page",
+            )])],
+        ));
+        let mut new_item = ItemEntity::new_empty();
+        new_item.add_claim(Statement::new_normal(
+            Snak::new_string("P1476", "some title"),
+            vec![Snak::new_url("P973", "http://example.org/about/")],
+            vec![Reference::new(vec![Snak::new_url(
+                "P1065",
+                "http://example.org/page/",
+            )])],
+        ));
+
+        // By default, the URLs are all different.
+        let mut im = ItemMerger::new(base.clone());
+        im.merge(&new_item);
+        assert_eq!(im.item().claims().len(), 2);
+
+        let mut im = ItemMerger::new(base);
+        im.set_policy(MergePolicy {
+            normalization: ValueNormalization::loose(),
+            ..Default::default()
+        });
+        let diff = im.merge(&new_item);
+        assert!(diff.is_empty());
+    }
+
+    #[test]
+    fn test_normalized_case_insensitive_external_id_references() {
+        let reference = |id: &str| Reference::new(vec![Snak::new_external_id("P214", id)]);
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![reference("abc12")],
+        ));
+        let mut new_item = ItemEntity::new_empty();
+        new_item.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![reference("ABC12")],
+        ));
+
+        let mut im = ItemMerger::new(base.clone());
+        im.merge(&new_item);
+        assert_eq!(im.item().claims()[0].references().len(), 2);
+
+        let mut policy = MergePolicy::default();
+        policy.normalization.set_property(
+            "P214",
+            vec![Normalization::Unicode, Normalization::CaseInsensitive],
+        );
+        let mut im = ItemMerger::new(base);
+        im.set_policy(policy);
+        let report = im.merge_reported(&new_item).report;
+        assert_eq!(im.item().claims()[0].references().len(), 1);
+        assert!(report
+            .entries
+            .iter()
+            .any(|e| e.reason == "shares P214 with an existing reference"));
+    }
+
+    #[test]
+    fn test_reference_consolidation_enriches_existing_reference() {
+        let url = Snak::new_url("P854", "https://example.org/page");
+        let mut existing = Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![
+                Reference::new(vec![Snak::new_string("P1476", "a title")]),
+                Reference::new(vec![
+                    url.clone(),
+                    Snak::new_time("P813", "+2020-01-01T00:00:00Z", 11),
+                ]),
+            ],
+        );
+        existing.set_id("Q1$1");
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(existing);
+        let mut new_item = ItemEntity::new_empty();
+        new_item.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![Reference::new(vec![
+                url.clone(),
+                Snak::new_item("P248", "Q36578"),
+                Snak::new_time("P813", "+2024-01-01T00:00:00Z", 11),
+            ])],
+        ));
+
+        // By default, the matching reference is dropped.
+        let mut im = ItemMerger::new(base.clone());
+        assert!(im.merge(&new_item).is_empty());
+
+        let policy = MergePolicy {
+            reference_consolidation: Some(ReferenceConsolidation {
+                refresh_retrieved: true,
+                rank_by_completeness: true,
+            }),
+            ..Default::default()
+        };
+        // A reference that adds nothing leaves the order alone.
+        let mut im = ItemMerger::new(base.clone());
+        im.set_policy(policy.clone());
+        let mut title_only = ItemEntity::new_empty();
+        title_only.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![Reference::new(vec![Snak::new_string("P1476", "a title")])],
+        ));
+        assert!(im.merge(&title_only).is_empty());
+
+        let mut im = ItemMerger::new(base);
+        im.set_policy(policy);
+        let ReportedMerge { diff, report } = im.merge_reported(&new_item);
+        let references = diff.altered_statements["Q1$1"].references();
+        assert_eq!(references.len(), 2);
+        // The enriched reference now ranks first.
+        assert_eq!(
+            references[0].snaks(),
+            &vec![
+                url,
+                Snak::new_time("P813", "+2024-01-01T00:00:00Z", 11),
+                Snak::new_item("P248", "Q36578"),
+            ]
+        );
+        assert!(report
+            .entries
+            .iter()
+            .any(|e| e.decision == Decision::Merged
+                && e.reason.ends_with("adds P248, refreshes P813")));
+    }
+
+    #[test]
+    fn test_policy_precision_deprecation_properties() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(make_date_claim("P569", "+1900-05-10T00:00:00Z", 11));
+        base.add_claim(make_date_claim("P571", "+1900-05-10T00:00:00Z", 11));
+        let mut im = ItemMerger::new(base);
+        im.set_policy(MergePolicy {
+            precision_deprecation: vec!["P571".to_string()],
+            ..Default::default()
+        });
+
+        let mut inception = make_date_claim("P571", "+1900-00-00T00:00:00Z", 9);
+        im.check_new_claim_for_dates(&mut inception);
+        assert_eq!(*inception.rank(), StatementRank::Deprecated);
+        let mut birth = make_date_claim("P569", "+1900-00-00T00:00:00Z", 9);
+        im.check_new_claim_for_dates(&mut birth);
+        assert_eq!(*birth.rank(), StatementRank::Normal);
+    }
+
+    #[test]
+    fn test_set_properties_ignore_qualifier_match_replaces_ignore_overrides() {
+        let mut im = ItemMerger::new(ItemEntity::new_empty());
+        let mut policy = MergePolicy::new();
+        policy.set_qualifier_match("P3", QualifierMatch::Exact);
+        im.set_policy(policy);
+        im.set_properties_ignore_qualifier_match(vec!["P1".to_string()]);
+        im.set_properties_ignore_qualifier_match(vec!["P2".to_string()]);
+        let policy = im.policy();
+        assert_eq!(policy.qualifier_match_for("P1"), QualifierMatch::Compatible);
+        assert_eq!(policy.qualifier_match_for("P2"), QualifierMatch::Ignore);
+        assert_eq!(policy.qualifier_match_for("P3"), QualifierMatch::Exact);
+    }
+
+    #[test]
+    fn test_merge_reported_explains_date_deprecation() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(make_date_claim("P569", "+1952-03-11T00:00:00Z", 11));
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(make_date_claim("P569", "+1952-00-00T00:00:00Z", 9));
+
+        let merged = ItemMerger::new(base).merge_reported(&other);
+        let entry = &merged.report.entries[0];
+        assert_eq!(entry.decision, Decision::Deprecated);
+        assert_eq!(entry.reason, "lower precision than existing P569");
+        assert_eq!(
+            *merged.diff.added_statements[0].rank(),
+            StatementRank::Deprecated
+        );
+    }
 }
@@ -20,11 +20,33 @@ pub mod date;
 #[cfg(feature = "external-id")]
 pub mod external_id;
 #[cfg(feature = "item-merger")]
+pub mod item_diff;
+#[cfg(feature = "item-merger")]
 pub mod item_merger;
 #[cfg(feature = "lat-lon")]
 pub mod lat_lon;
 #[cfg(feature = "item-merger")]
+pub mod merge_constraints;
+#[cfg(feature = "item-merger")]
 pub mod merge_diff;
+#[cfg(feature = "item-merger")]
+pub mod merge_entity;
+#[cfg(feature = "item-merger")]
+pub mod merge_normalize;
+#[cfg(feature = "item-merger")]
+pub mod merge_policy;
+#[cfg(feature = "item-merger")]
+pub mod merge_provenance;
+#[cfg(feature = "item-merger")]
+pub mod merge_references;
+#[cfg(feature = "item-merger")]
+pub mod merge_report;
+#[cfg(feature = "item-merger")]
+pub mod merge_three_way;
+#[cfg(feature = "property-registry")]
+pub mod property_registry;
+#[cfg(feature = "quickstatements")]
+pub mod quickstatements;
 #[cfg(feature = "seppuku")]
 pub mod seppuku;
 #[cfg(feature = "site-matrix")]
@@ -34,6 +56,10 @@ pub mod sparql_results;
 #[cfg(feature = "sparql-table")]
 pub mod sparql_table;
 #[cfg(feature = "sparql-table")]
+pub mod sparql_table_disk;
+#[cfg(feature = "sparql-table")]
+pub mod sparql_table_export;
+#[cfg(feature = "sparql-table")]
 pub mod sparql_table_trait;
 #[cfg(feature = "sparql-table")]
 pub mod sparql_table_vec;
@@ -47,6 +73,12 @@ pub mod toolforge_app;
 pub mod toolforge_db;
 #[cfg(feature = "wikidata")]
 pub mod wikidata;
+#[cfg(all(feature = "wikidata", feature = "item-merger"))]
+pub mod wikidata_edit;
+#[cfg(feature = "wikidata")]
+pub mod wikidata_entities;
+#[cfg(feature = "wikidata")]
+pub mod wikidata_throttle;
 
 #[cfg(test)]
 pub(crate) mod test_support;
@@ -0,0 +1,305 @@
+//! Property-constraint checks for [`ItemMerger`].
+//!
+//! Scraped data routinely adds a second value to a single-value property, or
+//! an identifier that fails its property's format regex. Given a
+//! [`PropertyRegistry`] and [`ConstraintChecks`], the merger checks every
+//! statement it would add against the registry's format regex, single-value,
+//! distinct-values, allowed-qualifiers and allowed-units data, and rejects,
+//! deprecates or merely flags each violating statement. Each violation is
+//! reported as a [`ConstraintViolation`]; [`ItemMerger::merge_checked`]
+//! returns them alongside the [`MergeDiff`].
+//!
+//! ```ignore
+//! let mut im = ItemMerger::new(target);
+//! im.set_property_registry(registry);
+//! let mut checks = ConstraintChecks::new(ConstraintAction::Reject);
+//! checks.set_action(ConstraintKind::SingleValue, ConstraintAction::Deprecate);
+//! im.set_constraint_checks(checks);
+//! let CheckedMerge { diff, violations } = im.merge_checked(&scraped);
+//! ```
+
+#[cfg(doc)]
+use crate::item_merger::ItemMerger;
+use crate::merge_diff::MergeDiff;
+use crate::property_registry::PropertyRegistry;
+use std::collections::{HashMap, HashSet};
+use wikibase::{EntityTrait, Snak, Statement, StatementRank, Value};
+
+/// What happens to a statement that violates a constraint. Ordered from
+/// mildest to strictest; a statement with several violations gets the
+/// strictest action among them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum ConstraintAction {
+    /// Add the statement unchanged; only report the violation.
+    Flag,
+    /// Add the statement with deprecated rank.
+    Deprecate,
+    /// Leave the statement out.
+    Reject,
+}
+
+/// Which constraint a statement violates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConstraintKind {
+    /// A string value does not match the property's format regex.
+    Format,
+    /// The item already has a non-deprecated statement for the property.
+    SingleValue,
+    /// The value is already used on another item; see
+    /// [`ConstraintChecks::add_values_in_use`].
+    DistinctValues,
+    /// A qualifier property is not among the allowed qualifiers.
+    AllowedQualifiers,
+    /// A quantity's unit is not among the allowed units.
+    AllowedUnits,
+}
+
+/// One constraint violation found while merging.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintViolation {
+    pub kind: ConstraintKind,
+    /// The property of the statement.
+    pub property: String,
+    /// The offending value (string or entity ID; empty for other value
+    /// types), qualifier property, or unit.
+    pub detail: String,
+    /// What was done to the statement.
+    pub action: ConstraintAction,
+    /// The statement as offered to the merger.
+    pub claim: Statement,
+}
+
+/// The result of [`ItemMerger::merge_checked`].
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CheckedMerge {
+    pub diff: MergeDiff,
+    pub violations: Vec<ConstraintViolation>,
+}
+
+/// Which action to take for which [`ConstraintKind`], plus the values other
+/// items already use for distinct-values properties.
+#[derive(Debug, Clone)]
+pub struct ConstraintChecks {
+    default_action: ConstraintAction,
+    actions: HashMap<ConstraintKind, ConstraintAction>,
+    values_in_use: HashMap<String, HashSet<String>>,
+}
+
+impl ConstraintChecks {
+    /// Checks every constraint kind, taking `default_action` on violations.
+    pub fn new(default_action: ConstraintAction) -> Self {
+        Self {
+            default_action,
+            actions: HashMap::new(),
+            values_in_use: HashMap::new(),
+        }
+    }
+
+    /// Overrides the action for one kind of violation.
+    pub fn set_action(&mut self, kind: ConstraintKind, action: ConstraintAction) {
+        self.actions.insert(kind, action);
+    }
+
+    pub fn action(&self, kind: ConstraintKind) -> ConstraintAction {
+        self.actions
+            .get(&kind)
+            .copied()
+            .unwrap_or(self.default_action)
+    }
+
+    /// Records values of `property` (strings or entity IDs) that other items
+    /// already use. The merger only sees one item, so distinct-values
+    /// violations are found only against values supplied here, e.g. from a
+    /// SPARQL query.
+    pub fn add_values_in_use(&mut self, property: &str, values: impl IntoIterator<Item = String>) {
+        self.values_in_use
+            .entry(property.to_string())
+            .or_default()
+            .extend(values);
+    }
+
+    /// The violations `claim` would cause as a new statement on `item`, as
+    /// kind and detail.
+    pub(crate) fn problems(
+        &self,
+        registry: &PropertyRegistry,
+        item: &impl EntityTrait,
+        claim: &Statement,
+    ) -> Vec<(ConstraintKind, String)> {
+        let snak = claim.main_snak();
+        let property = snak.property();
+        let Some(info) = registry.get(property) else {
+            return vec![];
+        };
+        let mut ret = vec![];
+        let value = snak.data_value().as_ref().map(|dv| dv.value());
+        let key = value_key(snak);
+        if let Some(Value::StringValue(s)) = value {
+            if registry.matches_format(property, s) == Some(false) {
+                ret.push((ConstraintKind::Format, s.to_owned()));
+            }
+        }
+        if info.single_value
+            && item
+                .claims()
+                .iter()
+                .any(|c| c.property() == property && *c.rank() != StatementRank::Deprecated)
+        {
+            ret.push((ConstraintKind::SingleValue, key.clone().unwrap_or_default()));
+        }
+        if let Some(key) = key.filter(|key| {
+            info.distinct_values
+                && self
+                    .values_in_use
+                    .get(property)
+                    .is_some_and(|values| values.contains(key))
+        }) {
+            ret.push((ConstraintKind::DistinctValues, key));
+        }
+        for qualifier in claim.qualifiers() {
+            if !info.allows_qualifier(qualifier.property()) {
+                ret.push((
+                    ConstraintKind::AllowedQualifiers,
+                    qualifier.property().to_string(),
+                ));
+            }
+        }
+        if let Some(Value::Quantity(quantity)) = value {
+            if !info.allows_unit(quantity.unit()) {
+                ret.push((ConstraintKind::AllowedUnits, quantity.unit().to_string()));
+            }
+        }
+        ret
+    }
+}
+
+/// The value of `snak` as it appears in [`ConstraintChecks::add_values_in_use`].
+fn value_key(snak: &Snak) -> Option<String> {
+    match snak.data_value().as_ref()?.value() {
+        Value::StringValue(s) => Some(s.to_owned()),
+        Value::Entity(entity) => Some(entity.id().to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::property_registry::PropertyInfo;
+    use wikibase::{DataValue, DataValueType, ItemEntity, QuantityValue, SnakDataType, SnakType};
+
+    fn registry() -> PropertyRegistry {
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P214",
+            PropertyInfo {
+                datatype: Some(SnakDataType::ExternalId),
+                format_regex: Some("\\d+".to_string()),
+                single_value: true,
+                distinct_values: true,
+                allowed_qualifiers: Some(vec!["P1810".to_string()]),
+                ..Default::default()
+            },
+        );
+        registry.insert(
+            "P2048",
+            PropertyInfo {
+                datatype: Some(SnakDataType::Quantity),
+                allowed_units: Some(vec!["Q11573".to_string()]),
+                ..Default::default()
+            },
+        );
+        registry
+    }
+
+    fn height(unit: &str) -> Statement {
+        Statement::new_normal(
+            Snak::new(
+                SnakDataType::Quantity,
+                "P2048",
+                SnakType::Value,
+                Some(DataValue::new(
+                    DataValueType::Quantity,
+                    Value::Quantity(QuantityValue::new(1.8, None, unit, None)),
+                )),
+            ),
+            vec![],
+            vec![],
+        )
+    }
+
+    #[test]
+    fn test_actions() {
+        let mut checks = ConstraintChecks::new(ConstraintAction::Flag);
+        checks.set_action(ConstraintKind::Format, ConstraintAction::Reject);
+        assert_eq!(
+            checks.action(ConstraintKind::Format),
+            ConstraintAction::Reject
+        );
+        assert_eq!(
+            checks.action(ConstraintKind::SingleValue),
+            ConstraintAction::Flag
+        );
+        assert!(ConstraintAction::Reject > ConstraintAction::Deprecate);
+        assert!(ConstraintAction::Deprecate > ConstraintAction::Flag);
+    }
+
+    #[test]
+    fn test_problems() {
+        let registry = registry();
+        let mut checks = ConstraintChecks::new(ConstraintAction::Flag);
+        checks.add_values_in_use("P214", ["42".to_string()]);
+        let mut item = ItemEntity::new_empty();
+
+        let claim = Statement::new_normal(
+            Snak::new_external_id("P214", "x1"),
+            vec![
+                Snak::new_string("P1810", "name"),
+                Snak::new_string("P580", "x"),
+            ],
+            vec![],
+        );
+        assert_eq!(
+            checks.problems(&registry, &item, &claim),
+            vec![
+                (ConstraintKind::Format, "x1".to_string()),
+                (ConstraintKind::AllowedQualifiers, "P580".to_string()),
+            ]
+        );
+
+        item.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "1"),
+            vec![],
+            vec![],
+        ));
+        let claim = Statement::new_normal(Snak::new_external_id("P214", "42"), vec![], vec![]);
+        assert_eq!(
+            checks.problems(&registry, &item, &claim),
+            vec![
+                (ConstraintKind::SingleValue, "42".to_string()),
+                (ConstraintKind::DistinctValues, "42".to_string()),
+            ]
+        );
+
+        // A deprecated value does not count against single-value.
+        item.claims_mut()[0].set_rank(StatementRank::Deprecated);
+        let claim = Statement::new_normal(Snak::new_external_id("P214", "7"), vec![], vec![]);
+        assert!(checks.problems(&registry, &item, &claim).is_empty());
+    }
+
+    #[test]
+    fn test_problems_units() {
+        let registry = registry();
+        let checks = ConstraintChecks::new(ConstraintAction::Flag);
+        let item = ItemEntity::new_empty();
+        let metre = height("http://www.wikidata.org/entity/Q11573");
+        assert!(checks.problems(&registry, &item, &metre).is_empty());
+        assert_eq!(
+            checks.problems(&registry, &item, &height("1")),
+            vec![(ConstraintKind::AllowedUnits, "1".to_string())]
+        );
+        // Properties the registry does not know are never in violation.
+        let unknown = Statement::new_normal(Snak::new_string("P1", "x"), vec![], vec![]);
+        assert!(checks.problems(&registry, &item, &unknown).is_empty());
+    }
+}
@@ -1,11 +1,20 @@
-//! This module contains the MergeDiff struct, which is used by the ItemMerger to generate the differences between two items.
+//! This module contains the MergeDiff struct, which is used by the ItemMerger to generate the differences between two entities.
 
+use crate::merge_entity::MergeEntity;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use serde_json::json;
 use std::collections::HashMap;
 use wikibase::*;
 
-/// This contains the wbeditentiry payload to ADD data to a base item, generated from a merge
+/// This contains the wbeditentiry payload to ADD data to a base item, generated from a merge.
+///
+/// The `removed_*` fields are only populated when the merger runs with
+/// [`ItemMerger::set_remove_absent`](crate::item_merger::ItemMerger::set_remove_absent),
+/// or by [`crate::merge_three_way`]; they serialize as `wbeditentity`
+/// `remove` entries.
+///
+/// Diffs for lexemes and properties also carry what only those entity types
+/// have; see [`crate::merge_entity`].
 #[derive(Debug, Clone, Default, PartialEq)]
 pub struct MergeDiff {
     pub labels: Vec<LocaleString>,
@@ -14,6 +23,97 @@ pub struct MergeDiff {
     pub sitelinks: Vec<SiteLink>,
     pub altered_statements: HashMap<String, Statement>,
     pub added_statements: Vec<Statement>,
+    pub removed_labels: Vec<LocaleString>,
+    pub removed_aliases: Vec<LocaleString>,
+    pub removed_descriptions: Vec<LocaleString>,
+    pub removed_sitelinks: Vec<SiteLink>,
+    /// Statements to delete, keyed by statement ID like `altered_statements`.
+    pub removed_statements: HashMap<String, Statement>,
+    /// The type of entity the diff is for, as set by
+    /// [`ItemMerger::merge`](crate::item_merger::ItemMerger::merge). A
+    /// lexeme's `labels` are its lemmas, and serialize as `lemmas`; `None`
+    /// serializes like an item.
+    pub entity_type: Option<EntityType>,
+    /// Lexemes only: the new language, as an item ID.
+    pub language: Option<String>,
+    /// Lexemes only: the new lexical category, as an item ID.
+    pub lexical_category: Option<String>,
+    /// Properties only: the datatype of a property that has none yet.
+    pub datatype: Option<SnakDataType>,
+    /// Lexemes only: forms to add or change.
+    pub forms: Vec<FormDiff>,
+    /// Lexemes only: senses to add or change.
+    pub senses: Vec<SenseDiff>,
+    /// Lexemes only: IDs of forms to delete.
+    pub removed_forms: Vec<String>,
+    /// Lexemes only: IDs of senses to delete.
+    pub removed_senses: Vec<String>,
+}
+
+/// A lexeme form to add, or the changes to an existing one. It serializes
+/// as its fields, for [`crate::merge_three_way::MergeConflict`]; in a
+/// [`MergeDiff`] it serializes for `wbeditentity`.
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct FormDiff {
+    /// The ID of the form to change; `None` adds a new form.
+    pub id: Option<String>,
+    /// New representations; all of them for a new form.
+    pub representations: Vec<LocaleString>,
+    /// Grammatical features as item IDs. Always the complete list, since
+    /// `wbeditentity` replaces a form's features as a whole.
+    pub grammatical_features: Vec<String>,
+    /// New or changed statements of the form; all of them for a new form.
+    pub claims: Vec<Statement>,
+    /// Representations to delete, by language.
+    pub removed_representations: Vec<LocaleString>,
+    /// Statements of the form to delete, by ID.
+    pub removed_claims: Vec<Statement>,
+}
+
+impl FormDiff {
+    /// Whether `self` and `later` both add a form, and `later` has all of
+    /// `self`'s representations and grammatical features: the same new form,
+    /// after more was folded into it.
+    fn grew_into(&self, later: &FormDiff) -> bool {
+        self.id.is_none()
+            && later.id.is_none()
+            && !self.representations.is_empty()
+            && self
+                .representations
+                .iter()
+                .all(|r| later.representations.contains(r))
+            && self
+                .grammatical_features
+                .iter()
+                .all(|f| later.grammatical_features.contains(f))
+    }
+}
+
+/// A lexeme sense to add, or the changes to an existing one; see
+/// [`FormDiff`].
+#[derive(Debug, Clone, Default, PartialEq, serde::Serialize)]
+pub struct SenseDiff {
+    /// The ID of the sense to change; `None` adds a new sense.
+    pub id: Option<String>,
+    /// New glosses; all of them for a new sense.
+    pub glosses: Vec<LocaleString>,
+    /// New or changed statements of the sense; all of them for a new sense.
+    pub claims: Vec<Statement>,
+    /// Glosses to delete, by language.
+    pub removed_glosses: Vec<LocaleString>,
+    /// Statements of the sense to delete, by ID.
+    pub removed_claims: Vec<Statement>,
+}
+
+impl SenseDiff {
+    /// Whether `self` and `later` both add a sense, and `later` has all of
+    /// `self`'s glosses; see [`FormDiff`].
+    fn grew_into(&self, later: &SenseDiff) -> bool {
+        self.id.is_none()
+            && later.id.is_none()
+            && !self.glosses.is_empty()
+            && self.glosses.iter().all(|g| later.glosses.contains(g))
+    }
 }
 
 impl MergeDiff {
@@ -24,12 +124,16 @@ impl MergeDiff {
     /// Append every field of `other` onto `self`.
     ///
     /// **No dedup.** This is a raw concatenation: labels, aliases, descriptions,
-    /// sitelinks, and added/altered statements from `other` are appended in
+    /// sitelinks, added/altered statements, and removals from `other` are appended in
     /// order, and identical entries from a prior `extend` are kept duplicate.
     /// This matches how `ItemMerger` accumulates per-call diffs that have
     /// already been deduplicated against the merger's internal item state — so
     /// the diff stream is duplicate-free *if* it comes from the same merger.
     /// Hand-built diffs should be deduplicated by the caller before extending.
+    ///
+    /// The one exception are new forms and senses: one that `ItemMerger`
+    /// folded more into is emitted again in full, and replaces its earlier
+    /// version here; see [`Self::push_form`].
     pub fn extend(&mut self, other: &MergeDiff) {
         self.labels.extend(other.labels.iter().cloned());
         self.aliases.extend(other.aliases.iter().cloned());
@@ -43,21 +147,126 @@ impl MergeDiff {
         );
         self.added_statements
             .extend(other.added_statements.iter().cloned());
+        self.removed_labels
+            .extend(other.removed_labels.iter().cloned());
+        self.removed_aliases
+            .extend(other.removed_aliases.iter().cloned());
+        self.removed_descriptions
+            .extend(other.removed_descriptions.iter().cloned());
+        self.removed_sitelinks
+            .extend(other.removed_sitelinks.iter().cloned());
+        self.removed_statements.extend(
+            other
+                .removed_statements
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone())),
+        );
+        self.entity_type = self.entity_type.or(other.entity_type);
+        if other.language.is_some() {
+            self.language.clone_from(&other.language);
+        }
+        if other.lexical_category.is_some() {
+            self.lexical_category.clone_from(&other.lexical_category);
+        }
+        if other.datatype.is_some() {
+            self.datatype = other.datatype;
+        }
+        for form in &other.forms {
+            self.push_form(form.to_owned());
+        }
+        for sense in &other.senses {
+            self.push_sense(sense.to_owned());
+        }
+        self.removed_forms
+            .extend(other.removed_forms.iter().cloned());
+        self.removed_senses
+            .extend(other.removed_senses.iter().cloned());
+    }
+
+    /// Adds `form`. A new form replaces an earlier new form whose
+    /// representations and grammatical features it all has, as that is the
+    /// same form with more folded into it.
+    pub fn push_form(&mut self, form: FormDiff) {
+        match self.forms.iter_mut().find(|f| f.grew_into(&form)) {
+            Some(earlier) => *earlier = form,
+            None => self.forms.push(form),
+        }
+    }
+
+    /// Adds `sense`, replacing an earlier new sense whose glosses it all has;
+    /// see [`Self::push_form`].
+    pub fn push_sense(&mut self, sense: SenseDiff) {
+        match self.senses.iter_mut().find(|s| s.grew_into(&sense)) {
+            Some(earlier) => *earlier = sense,
+            None => self.senses.push(sense),
+        }
+    }
+
+    /// Returns `true` if the diff contains no changes at all.
+    pub fn is_empty(&self) -> bool {
+        self.labels.is_empty()
+            && self.aliases.is_empty()
+            && self.descriptions.is_empty()
+            && self.sitelinks.is_empty()
+            && self.altered_statements.is_empty()
+            && self.added_statements.is_empty()
+            && self.removed_labels.is_empty()
+            && self.removed_aliases.is_empty()
+            && self.removed_descriptions.is_empty()
+            && self.removed_sitelinks.is_empty()
+            && self.removed_statements.is_empty()
+            && self.language.is_none()
+            && self.lexical_category.is_none()
+            && self.datatype.is_none()
+            && self.forms.is_empty()
+            && self.senses.is_empty()
+            && self.removed_forms.is_empty()
+            && self.removed_senses.is_empty()
     }
 
     /// Fold this diff into `item`.
-    pub fn apply(&self, item: &mut ItemEntity) {
-        item.labels_mut().extend(self.labels.iter().cloned());
+    ///
+    /// Removals are applied first. A label, description, or sitelink for a
+    /// language (or site) the item already has replaces the existing one, the
+    /// same way `wbeditentity` treats it. Lexeme and property parts are
+    /// applied by [`MergeEntity::apply_specific`]. Altered statements
+    /// overwrite the item's by ID; for an item edited since the diff was
+    /// made, see [`crate::merge_three_way`].
+    pub fn apply<E: MergeEntity>(&self, item: &mut E) {
+        item.labels_mut().retain(|l| {
+            !self
+                .removed_labels
+                .iter()
+                .any(|r| r.language() == l.language())
+        });
+        item.aliases_mut()
+            .retain(|a| !self.removed_aliases.contains(a));
+        item.descriptions_mut().retain(|d| {
+            !self
+                .removed_descriptions
+                .iter()
+                .any(|r| r.language() == d.language())
+        });
+        if let Some(sitelinks) = item.sitelinks_mut() {
+            sitelinks.retain(|s| !self.removed_sitelinks.iter().any(|r| r.site() == s.site()));
+        }
+        item.claims_mut().retain(|c| match c.id() {
+            Some(id) => !self.removed_statements.contains_key(&id),
+            None => true,
+        });
+
+        Self::apply_locale_strings(item.labels_mut(), &self.labels);
         item.aliases_mut().extend(self.aliases.iter().cloned());
-        item.descriptions_mut()
-            .extend(self.descriptions.iter().cloned());
+        Self::apply_locale_strings(item.descriptions_mut(), &self.descriptions);
         if !self.sitelinks.is_empty() {
             // A fresh `ItemEntity` has no sitelink list at all; create one rather
             // than discarding the diff's links. Guarded on `is_empty` so a diff
             // with no sitelinks leaves the item's `None` untouched.
-            item.sitelinks_mut()
-                .get_or_insert_with(Vec::new)
-                .extend(self.sitelinks.iter().cloned());
+            let sitelinks = item.sitelinks_mut().get_or_insert_with(Vec::new);
+            for sitelink in &self.sitelinks {
+                sitelinks.retain(|s| s.site() != sitelink.site());
+                sitelinks.push(sitelink.to_owned());
+            }
         }
         for (id, statement) in self.altered_statements.iter() {
             let existing_statement = item
@@ -70,6 +279,16 @@ impl MergeDiff {
         }
         item.claims_mut()
             .extend(self.added_statements.iter().cloned());
+        item.apply_specific(self);
+    }
+
+    /// Labels and descriptions hold one value per language, so a new one
+    /// replaces whatever the language had before.
+    fn apply_locale_strings(mine: &mut Vec<LocaleString>, new_ones: &[LocaleString]) {
+        for ls in new_ones {
+            mine.retain(|x| x.language() != ls.language());
+            mine.push(ls.to_owned());
+        }
     }
 
     pub fn add_statement(&mut self, s: Statement) {
@@ -80,19 +299,29 @@ impl MergeDiff {
         }
     }
 
-    fn serialize_labels(&self, list: &[LocaleString]) -> Option<serde_json::Value> {
-        if list.is_empty() {
+    fn serialize_labels(
+        &self,
+        list: &[LocaleString],
+        removed: &[LocaleString],
+    ) -> Option<serde_json::Value> {
+        if list.is_empty() && removed.is_empty() {
             return None;
         }
 
-        let labels: HashMap<String, serde_json::Value> = list
+        let labels: HashMap<String, serde_json::Value> = removed
             .iter()
             .map(|l| {
                 (
                     l.language().to_owned(),
-                    json!({"language":l.language(),"value":l.value(), "add": ""}),
+                    json!({"language":l.language(), "remove": ""}),
                 )
             })
+            .chain(list.iter().map(|l| {
+                (
+                    l.language().to_owned(),
+                    json!({"language":l.language(),"value":l.value(), "add": ""}),
+                )
+            }))
             .collect();
         Some(json!(labels))
     }
@@ -104,7 +333,7 @@ impl MergeDiff {
     /// Serialising them through [`Self::serialize_labels`] would silently keep
     /// only one per language.
     fn serialize_aliases(&self) -> Option<serde_json::Value> {
-        if self.aliases.is_empty() {
+        if self.aliases.is_empty() && self.removed_aliases.is_empty() {
             return None;
         }
 
@@ -115,23 +344,35 @@ impl MergeDiff {
                 .or_default()
                 .push(json!({"language":alias.language(),"value":alias.value(), "add": ""}));
         }
+        for alias in &self.removed_aliases {
+            by_language
+                .entry(alias.language().to_owned())
+                .or_default()
+                .push(json!({"language":alias.language(),"value":alias.value(), "remove": ""}));
+        }
         Some(json!(by_language))
     }
 
     fn serialize_sitelinks(&self) -> Option<serde_json::Value> {
-        if self.sitelinks.is_empty() {
+        if self.sitelinks.is_empty() && self.removed_sitelinks.is_empty() {
             return None;
         }
 
         let sitelinks: HashMap<String, serde_json::Value> = self
-            .sitelinks
+            .removed_sitelinks
             .iter()
             .map(|l| {
                 (
                     l.site().to_owned(),
-                    json!({"site":l.site(),"title":l.title()}),
+                    json!({"site":l.site(),"title":"", "remove": ""}),
                 )
             })
+            .chain(self.sitelinks.iter().map(|l| {
+                (
+                    l.site().to_owned(),
+                    json!({"site":l.site(),"title":l.title()}),
+                )
+            }))
             .collect();
         Some(json!(sitelinks))
     }
@@ -144,37 +385,152 @@ impl MergeDiff {
 
     fn serialize_claims(&self) -> Option<serde_json::Value> {
         let ret: Vec<serde_json::Value> = self
-            .added_statements
-            .iter()
-            .chain(self.altered_statements.values())
-            .map(|c| json!(c))
-            .map(|mut c| {
-                if let Some(snak) = c.get_mut("mainsnak") {
-                    self.clean_snak(snak);
-                }
+            .statements_json(
+                self.added_statements
+                    .iter()
+                    .chain(self.altered_statements.values()),
+            )
+            .chain(
+                self.removed_statements
+                    .keys()
+                    .map(|id| json!({"id": id, "remove": ""})),
+            )
+            .collect();
 
-                if let Some(references) = c.get_mut("references").and_then(|r| r.as_array_mut()) {
-                    for refgroup in references {
-                        if let Some(prop_snaks_map) = refgroup["snaks"].as_object_mut() {
-                            for (_, snaks) in prop_snaks_map.iter_mut() {
-                                if let Some(snaks_array) = snaks.as_array_mut() {
-                                    for snak in snaks_array {
-                                        self.clean_snak(snak);
-                                    }
+        if ret.is_empty() {
+            None
+        } else {
+            Some(json!(ret))
+        }
+    }
+
+    /// Statements as `wbeditentity` takes them, without snak datatypes.
+    fn statements_json<'a>(
+        &'a self,
+        statements: impl Iterator<Item = &'a Statement> + 'a,
+    ) -> impl Iterator<Item = serde_json::Value> + 'a {
+        statements.map(|c| json!(c)).map(|mut c| {
+            if let Some(snak) = c.get_mut("mainsnak") {
+                self.clean_snak(snak);
+            }
+
+            if let Some(references) = c.get_mut("references").and_then(|r| r.as_array_mut()) {
+                for refgroup in references {
+                    if let Some(prop_snaks_map) = refgroup["snaks"].as_object_mut() {
+                        for (_, snaks) in prop_snaks_map.iter_mut() {
+                            if let Some(snaks_array) = snaks.as_array_mut() {
+                                for snak in snaks_array {
+                                    self.clean_snak(snak);
                                 }
                             }
                         }
                     }
                 }
-                c
+            }
+            c
+        })
+    }
+
+    /// Representations and glosses: one plain value per language, and a
+    /// `remove` entry for each removed one.
+    fn serialize_terms(list: &[LocaleString], removed: &[LocaleString]) -> serde_json::Value {
+        let terms: HashMap<&str, serde_json::Value> = removed
+            .iter()
+            .map(|l| (l.language(), json!({"language":l.language(),"remove":""})))
+            .chain(list.iter().map(|l| {
+                (
+                    l.language(),
+                    json!({"language":l.language(),"value":l.value()}),
+                )
+            }))
+            .collect();
+        json!(terms)
+    }
+
+    /// The statements of a form or sense, with `remove` entries for removed
+    /// ones, or `None` if there are none.
+    fn serialize_part_claims(
+        &self,
+        claims: &[Statement],
+        removed: &[Statement],
+    ) -> Option<serde_json::Value> {
+        let ret: Vec<serde_json::Value> = self
+            .statements_json(claims.iter())
+            .chain(
+                removed
+                    .iter()
+                    .filter_map(|s| s.id())
+                    .map(|id| json!({"id": id, "remove": ""})),
+            )
+            .collect();
+        (!ret.is_empty()).then(|| json!(ret))
+    }
+
+    /// New forms carry the `add` marker; changed ones their ID, and removed
+    /// ones their ID and the `remove` marker.
+    fn serialize_forms(&self) -> Option<serde_json::Value> {
+        if self.forms.is_empty() && self.removed_forms.is_empty() {
+            return None;
+        }
+        let forms: Vec<serde_json::Value> = self
+            .forms
+            .iter()
+            .map(|form| {
+                let mut ret = json!({
+                    "representations": Self::serialize_terms(
+                        &form.representations,
+                        &form.removed_representations,
+                    ),
+                    "grammaticalFeatures": form.grammatical_features,
+                });
+                match &form.id {
+                    Some(id) => ret["id"] = json!(id),
+                    None => ret["add"] = json!(""),
+                }
+                if let Some(claims) = self.serialize_part_claims(&form.claims, &form.removed_claims)
+                {
+                    ret["claims"] = claims;
+                }
+                ret
             })
+            .chain(
+                self.removed_forms
+                    .iter()
+                    .map(|id| json!({"id": id, "remove": ""})),
+            )
             .collect();
+        Some(json!(forms))
+    }
 
-        if ret.is_empty() {
-            None
-        } else {
-            Some(json!(ret))
+    fn serialize_senses(&self) -> Option<serde_json::Value> {
+        if self.senses.is_empty() && self.removed_senses.is_empty() {
+            return None;
         }
+        let senses: Vec<serde_json::Value> = self
+            .senses
+            .iter()
+            .map(|sense| {
+                let mut ret = json!({
+                    "glosses": Self::serialize_terms(&sense.glosses, &sense.removed_glosses),
+                });
+                match &sense.id {
+                    Some(id) => ret["id"] = json!(id),
+                    None => ret["add"] = json!(""),
+                }
+                if let Some(claims) =
+                    self.serialize_part_claims(&sense.claims, &sense.removed_claims)
+                {
+                    ret["claims"] = claims;
+                }
+                ret
+            })
+            .chain(
+                self.removed_senses
+                    .iter()
+                    .map(|id| json!({"id": id, "remove": ""})),
+            )
+            .collect();
+        Some(json!(senses))
     }
 }
 
@@ -185,12 +541,30 @@ impl Serialize for MergeDiff {
     {
         // Build a Vec of only the fields that have content, avoiding the two-HashMap
         // allocate-then-filter pattern.
+        let labels_key = match self.entity_type {
+            Some(EntityType::Lexeme) => "lemmas",
+            _ => "labels",
+        };
         let fields: Vec<(&str, serde_json::Value)> = [
-            ("labels", self.serialize_labels(&self.labels)),
+            (
+                labels_key,
+                self.serialize_labels(&self.labels, &self.removed_labels),
+            ),
             ("aliases", self.serialize_aliases()),
-            ("descriptions", self.serialize_labels(&self.descriptions)),
+            (
+                "descriptions",
+                self.serialize_labels(&self.descriptions, &self.removed_descriptions),
+            ),
             ("sitelinks", self.serialize_sitelinks()),
             ("claims", self.serialize_claims()),
+            ("language", self.language.as_ref().map(|l| json!(l))),
+            (
+                "lexicalCategory",
+                self.lexical_category.as_ref().map(|c| json!(c)),
+            ),
+            ("datatype", self.datatype.map(|d| json!(d))),
+            ("forms", self.serialize_forms()),
+            ("senses", self.serialize_senses()),
         ]
         .into_iter()
         .filter_map(|(k, v)| v.map(|v| (k, v)))
@@ -732,4 +1106,163 @@ mod tests {
             );
         }
     }
+
+    // --- Removals ---
+
+    #[test]
+    fn test_serialize_removals_as_remove_entries() {
+        let mut diff = MergeDiff::new();
+        diff.removed_labels.push(LocaleString::new("en", "Old"));
+        diff.removed_descriptions
+            .push(LocaleString::new("de", "Alt"));
+        diff.removed_aliases.push(LocaleString::new("en", "Gone"));
+        diff.removed_sitelinks
+            .push(SiteLink::new("enwiki", "Old", vec![]));
+        let mut statement = Statement::new_normal(Snak::new_string("P1", "x"), vec![], vec![]);
+        statement.set_id("Q1$abc");
+        diff.removed_statements
+            .insert("Q1$abc".to_string(), statement);
+
+        let serialized = serde_json::to_value(&diff).unwrap();
+
+        assert_eq!(serialized["labels"]["en"]["remove"], "");
+        assert!(serialized["labels"]["en"].get("value").is_none());
+        assert_eq!(serialized["descriptions"]["de"]["remove"], "");
+        assert_eq!(serialized["aliases"]["en"][0]["value"], "Gone");
+        assert_eq!(serialized["aliases"]["en"][0]["remove"], "");
+        assert_eq!(serialized["sitelinks"]["enwiki"]["remove"], "");
+        let claims = serialized["claims"].as_array().unwrap();
+        assert_eq!(claims.len(), 1);
+        assert_eq!(claims[0], json!({"id": "Q1$abc", "remove": ""}));
+    }
+
+    #[test]
+    fn test_serialize_alias_additions_and_removals_share_a_language_list() {
+        let mut diff = MergeDiff::new();
+        diff.aliases.push(LocaleString::new("en", "New"));
+        diff.removed_aliases.push(LocaleString::new("en", "Old"));
+
+        let serialized = serde_json::to_value(&diff).unwrap();
+        let en = serialized["aliases"]["en"].as_array().unwrap();
+
+        assert_eq!(en.len(), 2);
+        assert!(en
+            .iter()
+            .any(|a| a["value"] == "New" && a.get("add").is_some()));
+        assert!(en
+            .iter()
+            .any(|a| a["value"] == "Old" && a.get("remove").is_some()));
+    }
+
+    #[test]
+    fn test_merge_diff_apply_removals() {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut().push(LocaleString::new("en", "label"));
+        item.labels_mut().push(LocaleString::new("de", "Etikett"));
+        item.aliases_mut().push(LocaleString::new("en", "a1"));
+        item.aliases_mut().push(LocaleString::new("en", "a2"));
+        item.descriptions_mut()
+            .push(LocaleString::new("en", "desc"));
+        item.set_sitelink(SiteLink::new("enwiki", "Title", vec![]));
+        let mut statement = Statement::new_normal(Snak::new_string("P1", "x"), vec![], vec![]);
+        statement.set_id("Q1$gone");
+        item.add_claim(statement.clone());
+        item.add_claim(Statement::new_normal(
+            Snak::new_string("P2", "kept"),
+            vec![],
+            vec![],
+        ));
+
+        let mut diff = MergeDiff::new();
+        diff.removed_labels.push(LocaleString::new("de", "Etikett"));
+        diff.removed_aliases.push(LocaleString::new("en", "a1"));
+        diff.removed_descriptions
+            .push(LocaleString::new("en", "desc"));
+        diff.removed_sitelinks
+            .push(SiteLink::new("enwiki", "Title", vec![]));
+        diff.removed_statements
+            .insert("Q1$gone".to_string(), statement);
+
+        diff.apply(&mut item);
+
+        assert_eq!(item.labels(), &vec![LocaleString::new("en", "label")]);
+        assert_eq!(item.aliases(), &vec![LocaleString::new("en", "a2")]);
+        assert!(item.descriptions().is_empty());
+        assert!(item.sitelinks().as_ref().unwrap().is_empty());
+        assert_eq!(item.claims().len(), 1);
+        assert_eq!(item.claims()[0].property(), "P2");
+    }
+
+    #[test]
+    fn test_merge_diff_apply_replaces_label_description_and_sitelink() {
+        // A term or sitelink for a language/site the item already has must
+        // replace it, not sit next to it.
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut().push(LocaleString::new("en", "Old"));
+        item.descriptions_mut()
+            .push(LocaleString::new("en", "old desc"));
+        item.set_sitelink(SiteLink::new("enwiki", "Old", vec![]));
+
+        let mut diff = MergeDiff::new();
+        diff.labels.push(LocaleString::new("en", "New"));
+        diff.descriptions.push(LocaleString::new("en", "new desc"));
+        diff.sitelinks.push(SiteLink::new("enwiki", "New", vec![]));
+
+        diff.apply(&mut item);
+
+        assert_eq!(item.labels(), &vec![LocaleString::new("en", "New")]);
+        assert_eq!(
+            item.descriptions(),
+            &vec![LocaleString::new("en", "new desc")]
+        );
+        let sitelinks = item.sitelinks().as_ref().unwrap();
+        assert_eq!(sitelinks.len(), 1);
+        assert_eq!(sitelinks[0].title(), "New");
+    }
+
+    #[test]
+    fn test_merge_diff_is_empty() {
+        let mut diff = MergeDiff::new();
+        assert!(diff.is_empty());
+        diff.removed_aliases.push(LocaleString::new("en", "x"));
+        assert!(!diff.is_empty());
+    }
+
+    #[test]
+    fn test_merge_diff_extend_carries_removals() {
+        let mut diff1 = MergeDiff::new();
+        diff1.removed_labels.push(LocaleString::new("en", "x"));
+        let mut diff2 = MergeDiff::new();
+        diff2.removed_labels.push(LocaleString::new("de", "y"));
+        diff2
+            .removed_sitelinks
+            .push(SiteLink::new("dewiki", "Y", vec![]));
+
+        diff1.extend(&diff2);
+
+        assert_eq!(diff1.removed_labels.len(), 2);
+        assert_eq!(diff1.removed_sitelinks.len(), 1);
+    }
+
+    #[test]
+    fn test_merge_diff_extend_carries_lexeme_parts() {
+        let mut diff1 = MergeDiff {
+            entity_type: Some(EntityType::Lexeme),
+            ..Default::default()
+        };
+        let diff2 = MergeDiff {
+            lexical_category: Some("Q1084".to_string()),
+            forms: vec![FormDiff::default()],
+            senses: vec![SenseDiff::default()],
+            ..Default::default()
+        };
+        assert!(!diff2.is_empty());
+
+        diff1.extend(&diff2);
+
+        assert_eq!(diff1.entity_type, Some(EntityType::Lexeme));
+        assert_eq!(diff1.lexical_category.as_deref(), Some("Q1084"));
+        assert_eq!(diff1.forms.len(), 1);
+        assert_eq!(diff1.senses.len(), 1);
+    }
 }
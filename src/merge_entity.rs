@@ -0,0 +1,759 @@
+//! What [`ItemMerger`] merges beyond terms, sitelinks and statements, for
+//! each Wikibase entity type.
+//!
+//! [`ItemMerger`] merges any [`MergeEntity`]: items, properties and lexemes.
+//! Labels, descriptions, aliases, sitelinks and statements are merged the
+//! same way for all of them; a lexeme's labels are its lemmas, and its diff
+//! serializes them as `lemmas`. Beyond that:
+//!
+//! - A property without a datatype takes the merged-in one's.
+//! - A lexeme without a language or lexical category takes the merged-in
+//!   one's. A different value for either is left out, like a clashing
+//!   description. Lexemes have no aliases, so a clashing lemma is left out
+//!   too rather than added as one.
+//! - A merged-in form is folded into the lexeme's form with the same ID, or
+//!   into one that shares a representation and has compatible grammatical
+//!   features (one list contains the other). Folding adds representations in
+//!   new languages, grammatical features, and statements, which are merged
+//!   like the lexeme's own. A form that matches none is added. Senses are
+//!   matched by ID or a shared gloss, and folded the same way.
+//!
+//! [`ItemMerger::set_remove_absent`] never removes forms or senses, and
+//! [`crate::merge_provenance`] does not track them.
+//!
+//! ```ignore
+//! let mut merger = LexemeMerger::new(lexeme);
+//! let diff = merger.merge(&scraped_lexeme);
+//! // `diff` serializes with `lemmas`, `forms` and `senses`, new forms and
+//! // senses marked with `add`.
+//! ```
+
+use crate::item_merger::ItemMerger;
+#[cfg(doc)]
+use crate::item_merger::LexemeMerger;
+use crate::merge_diff::{FormDiff, MergeDiff, SenseDiff};
+use crate::merge_report::{Decision, ReportEntry, ReportSubject};
+use crate::merge_three_way::{self, ThreeWayMerge};
+use std::fmt::Debug;
+use wikibase::{
+    EntityTrait, EntityType, EntityValue, ItemEntity, LexemeEntity, LexemeForm, LexemeSense,
+    LocaleString, PropertyEntity, Statement,
+};
+
+/// A Wikibase entity type [`ItemMerger`] can merge.
+pub trait MergeEntity: EntityTrait + Clone + Debug {
+    /// Whether entities of this type have aliases.
+    const HAS_ALIASES: bool = true;
+
+    /// Merges what only this entity type has from `other` into the merger's
+    /// entity, and adds the changes to `diff`. Runs after terms, sitelinks
+    /// and statements are merged.
+    fn merge_specific(merger: &mut ItemMerger<Self>, other: &Self, diff: &mut MergeDiff) {
+        let _ = (merger, other, diff);
+    }
+
+    /// Applies what only this entity type has in `diff`; see
+    /// [`MergeDiff::apply`].
+    fn apply_specific(&mut self, diff: &MergeDiff) {
+        let _ = diff;
+    }
+
+    /// Takes the changes of `ours` to what only this entity type has in
+    /// `base` into `ret`, for [`merge_three_way::merge_three_way`], and
+    /// leaves in `additions`, a copy of `ours`, only what the merger is to
+    /// add. Runs after terms, sitelinks and statements are merged.
+    fn merge_three_way_specific(
+        base: &Self,
+        ours: &Self,
+        theirs: &Self,
+        additions: &mut Self,
+        ret: &mut ThreeWayMerge,
+    ) {
+        let _ = (base, ours, theirs, additions, ret);
+    }
+}
+
+impl MergeEntity for ItemEntity {}
+
+impl MergeEntity for PropertyEntity {
+    fn merge_specific(merger: &mut ItemMerger<Self>, other: &Self, diff: &mut MergeDiff) {
+        let Some(datatype) = *other.datatype() else {
+            return;
+        };
+        let existing = merger.item().datatype().map(|d| datatype_text(&d));
+        if merge_field(merger, "datatype", existing, datatype_text(&datatype)) {
+            merger.item_mut().set_datatype(Some(datatype));
+            diff.datatype = Some(datatype);
+        }
+    }
+
+    fn apply_specific(&mut self, diff: &MergeDiff) {
+        if let Some(datatype) = diff.datatype {
+            self.set_datatype(Some(datatype));
+        }
+    }
+}
+
+impl MergeEntity for LexemeEntity {
+    const HAS_ALIASES: bool = false;
+
+    fn merge_specific(merger: &mut ItemMerger<Self>, other: &Self, diff: &mut MergeDiff) {
+        if !other.language().is_empty() {
+            let existing = Some(merger.item().language().to_owned()).filter(|l| !l.is_empty());
+            if merge_field(merger, "language", existing, other.language().to_owned()) {
+                other
+                    .language()
+                    .clone_into(merger.item_mut().language_mut());
+                diff.language = Some(other.language().to_owned());
+            }
+        }
+        if let Some(category) = other.lexical_category() {
+            let existing = merger.item().lexical_category().to_owned();
+            if merge_field(merger, "lexical category", existing, category.to_owned()) {
+                *merger.item_mut().lexical_category_mut() = Some(category.to_owned());
+                diff.lexical_category = Some(category.to_owned());
+            }
+        }
+
+        // Taken out of the lexeme while merging, so that their statements can
+        // be merged through the merger.
+        let mut forms = std::mem::take(merger.item_mut().forms_mut());
+        for new_form in other.forms() {
+            if let Some(form_diff) = merge_form(merger, &mut forms, new_form) {
+                diff.push_form(form_diff);
+            }
+        }
+        *merger.item_mut().forms_mut() = forms;
+
+        let mut senses = std::mem::take(merger.item_mut().senses_mut());
+        for new_sense in other.senses() {
+            if let Some(sense_diff) = merge_sense(merger, &mut senses, new_sense) {
+                diff.push_sense(sense_diff);
+            }
+        }
+        *merger.item_mut().senses_mut() = senses;
+    }
+
+    fn apply_specific(&mut self, diff: &MergeDiff) {
+        if let Some(language) = &diff.language {
+            language.clone_into(self.language_mut());
+        }
+        if let Some(category) = &diff.lexical_category {
+            *self.lexical_category_mut() = Some(category.to_owned());
+        }
+        self.forms_mut()
+            .retain(|f| !diff.removed_forms.iter().any(|id| id == f.id()));
+        self.senses_mut()
+            .retain(|s| !diff.removed_senses.iter().any(|id| id == s.id()));
+        for form_diff in &diff.forms {
+            let features = form_diff
+                .grammatical_features
+                .iter()
+                .map(|id| EntityValue::new(EntityType::Item, id))
+                .collect();
+            match self
+                .forms_mut()
+                .iter_mut()
+                .find(|f| Some(f.id()) == form_diff.id.as_ref())
+            {
+                Some(form) => {
+                    remove_terms(
+                        form.representations_mut(),
+                        &form_diff.removed_representations,
+                    );
+                    apply_terms(form.representations_mut(), &form_diff.representations);
+                    *form.grammatical_features_mut() = features;
+                    remove_statements(form.claims_mut(), &form_diff.removed_claims);
+                    apply_statements(form.claims_mut(), &form_diff.claims);
+                }
+                None => self.forms_mut().push(LexemeForm::new(
+                    form_diff.id.to_owned().unwrap_or_default(),
+                    form_diff.representations.to_owned(),
+                    features,
+                    form_diff.claims.to_owned(),
+                )),
+            }
+        }
+        for sense_diff in &diff.senses {
+            match self
+                .senses_mut()
+                .iter_mut()
+                .find(|s| Some(s.id()) == sense_diff.id.as_ref())
+            {
+                Some(sense) => {
+                    remove_terms(sense.glosses_mut(), &sense_diff.removed_glosses);
+                    apply_terms(sense.glosses_mut(), &sense_diff.glosses);
+                    remove_statements(sense.claims_mut(), &sense_diff.removed_claims);
+                    apply_statements(sense.claims_mut(), &sense_diff.claims);
+                }
+                None => self.senses_mut().push(LexemeSense::new(
+                    sense_diff.id.to_owned().unwrap_or_default(),
+                    sense_diff.glosses.to_owned(),
+                    sense_diff.claims.to_owned(),
+                )),
+            }
+        }
+    }
+
+    fn merge_three_way_specific(
+        base: &Self,
+        ours: &Self,
+        theirs: &Self,
+        additions: &mut Self,
+        ret: &mut ThreeWayMerge,
+    ) {
+        merge_three_way::merge_forms_and_senses(base, ours, theirs, ret);
+        let in_base = |id: &str| !id.is_empty() && base.forms().iter().any(|f| f.id() == id);
+        additions.forms_mut().retain(|f| !in_base(f.id()));
+        let in_base = |id: &str| !id.is_empty() && base.senses().iter().any(|s| s.id() == id);
+        additions.senses_mut().retain(|s| !in_base(s.id()));
+    }
+}
+
+/// Whether to take `new` for a single-valued field whose current value is
+/// `existing`: only if there is none.
+fn merge_field<E: MergeEntity>(
+    merger: &mut ItemMerger<E>,
+    field: &str,
+    existing: Option<String>,
+    new: String,
+) -> bool {
+    let (decision, reason) = match &existing {
+        None => (Decision::Added, format!("no {field} yet")),
+        Some(old) if *old == new => (Decision::Skipped, format!("already the {field}")),
+        Some(old) => (Decision::Skipped, format!("{field} is already {old}")),
+    };
+    merger.note(|| ReportEntry::new(ReportSubject::field(field, &new), decision, reason));
+    existing.is_none()
+}
+
+/// `external-id`, as the datatype appears in entity JSON.
+fn datatype_text(datatype: &wikibase::SnakDataType) -> String {
+    serde_json::to_value(datatype)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+/// Folds `new_form` into the matching form of `forms`, or adds it.
+fn merge_form(
+    merger: &mut ItemMerger<LexemeEntity>,
+    forms: &mut Vec<LexemeForm>,
+    new_form: &LexemeForm,
+) -> Option<FormDiff> {
+    let matched = forms
+        .iter_mut()
+        .find_map(|form| form_match(form, new_form).map(|reason| (form, reason)));
+    let Some((form, reason)) = matched else {
+        merger.note(|| {
+            let subject = ReportSubject::form("", new_form.representations());
+            let reason = "no form with a shared representation and compatible features";
+            ReportEntry::new(subject, Decision::Added, reason)
+        });
+        let claims = merge_statements(merger, &mut vec![], new_form.claims());
+        let features = new_form.grammatical_features().to_owned();
+        forms.push(LexemeForm::new(
+            String::new(),
+            new_form.representations().to_owned(),
+            features.clone(),
+            claims.clone(),
+        ));
+        return Some(FormDiff {
+            id: None,
+            representations: new_form.representations().to_owned(),
+            grammatical_features: feature_ids(&features),
+            claims,
+            ..Default::default()
+        });
+    };
+
+    let representations = new_terms(form.representations(), new_form.representations());
+    form.representations_mut()
+        .extend(representations.iter().cloned());
+    let features_before = form.grammatical_features().len();
+    for feature in new_form.grammatical_features() {
+        if !form
+            .grammatical_features()
+            .iter()
+            .any(|f| f.id() == feature.id())
+        {
+            form.grammatical_features_mut().push(feature.to_owned());
+        }
+    }
+    let features_changed = form.grammatical_features().len() != features_before;
+    let claims = merge_statements(merger, form.claims_mut(), new_form.claims());
+
+    let changed = !representations.is_empty() || features_changed || !claims.is_empty();
+    merger.note(|| {
+        let subject = ReportSubject::form(form.id(), form.representations());
+        match changed {
+            true => ReportEntry::new(subject, Decision::Merged, reason),
+            false => ReportEntry::new(subject, Decision::Skipped, format!("{reason}; nothing new")),
+        }
+    });
+    if !changed {
+        return None;
+    }
+    // A form added earlier, in this merge or a previous one, is not on the
+    // lexeme yet, so it is added again in full, replacing the earlier
+    // addition in the diff.
+    if form.id().is_empty() {
+        return Some(FormDiff {
+            id: None,
+            representations: form.representations().to_owned(),
+            grammatical_features: feature_ids(form.grammatical_features()),
+            claims: form.claims().to_owned(),
+            ..Default::default()
+        });
+    }
+    Some(FormDiff {
+        id: Some(form.id().to_owned()),
+        representations,
+        grammatical_features: feature_ids(form.grammatical_features()),
+        claims,
+        ..Default::default()
+    })
+}
+
+/// Folds `new_sense` into the matching sense of `senses`, or adds it.
+fn merge_sense(
+    merger: &mut ItemMerger<LexemeEntity>,
+    senses: &mut Vec<LexemeSense>,
+    new_sense: &LexemeSense,
+) -> Option<SenseDiff> {
+    let matched = senses
+        .iter_mut()
+        .find_map(|sense| sense_match(sense, new_sense).map(|reason| (sense, reason)));
+    let Some((sense, reason)) = matched else {
+        merger.note(|| {
+            let subject = ReportSubject::sense("", new_sense.glosses());
+            ReportEntry::new(subject, Decision::Added, "no sense with a shared gloss")
+        });
+        let claims = merge_statements(merger, &mut vec![], new_sense.claims());
+        senses.push(LexemeSense::new(
+            String::new(),
+            new_sense.glosses().to_owned(),
+            claims.clone(),
+        ));
+        return Some(SenseDiff {
+            id: None,
+            glosses: new_sense.glosses().to_owned(),
+            claims,
+            ..Default::default()
+        });
+    };
+
+    let glosses = new_terms(sense.glosses(), new_sense.glosses());
+    sense.glosses_mut().extend(glosses.iter().cloned());
+    let claims = merge_statements(merger, sense.claims_mut(), new_sense.claims());
+
+    let changed = !glosses.is_empty() || !claims.is_empty();
+    merger.note(|| {
+        let subject = ReportSubject::sense(sense.id(), sense.glosses());
+        match changed {
+            true => ReportEntry::new(subject, Decision::Merged, reason),
+            false => ReportEntry::new(subject, Decision::Skipped, format!("{reason}; nothing new")),
+        }
+    });
+    if !changed {
+        return None;
+    }
+    // As for forms.
+    if sense.id().is_empty() {
+        return Some(SenseDiff {
+            id: None,
+            glosses: sense.glosses().to_owned(),
+            claims: sense.claims().to_owned(),
+            ..Default::default()
+        });
+    }
+    Some(SenseDiff {
+        id: Some(sense.id().to_owned()),
+        glosses,
+        claims,
+        ..Default::default()
+    })
+}
+
+/// Why `new` is the same form as `existing`, or `None` if it is not.
+fn form_match(existing: &LexemeForm, new: &LexemeForm) -> Option<String> {
+    if !existing.id().is_empty() && existing.id() == new.id() {
+        return Some("same ID".to_string());
+    }
+    let features_compatible =
+        is_feature_subset(existing.grammatical_features(), new.grammatical_features())
+            || is_feature_subset(new.grammatical_features(), existing.grammatical_features());
+    if !features_compatible {
+        return None;
+    }
+    shared_term(existing.representations(), new.representations())
+        .map(|r| format!("shares representation {r}, features compatible"))
+}
+
+/// Why `new` is the same sense as `existing`, or `None` if it is not.
+fn sense_match(existing: &LexemeSense, new: &LexemeSense) -> Option<String> {
+    if !existing.id().is_empty() && existing.id() == new.id() {
+        return Some("same ID".to_string());
+    }
+    shared_term(existing.glosses(), new.glosses()).map(|g| format!("shares gloss {g}"))
+}
+
+fn shared_term(existing: &[LocaleString], new: &[LocaleString]) -> Option<String> {
+    new.iter()
+        .find(|t| existing.contains(t))
+        .map(|t| format!("{}:{:?}", t.language(), t.value()))
+}
+
+fn is_feature_subset(sub: &[EntityValue], sup: &[EntityValue]) -> bool {
+    sub.iter().all(|f| sup.iter().any(|g| g.id() == f.id()))
+}
+
+fn feature_ids(features: &[EntityValue]) -> Vec<String> {
+    features.iter().map(|f| f.id().to_string()).collect()
+}
+
+/// The terms of `new` in languages `existing` has none for. Representations
+/// and glosses hold one value per language; a clashing one is left out.
+fn new_terms(existing: &[LocaleString], new: &[LocaleString]) -> Vec<LocaleString> {
+    let mut ret: Vec<LocaleString> = vec![];
+    for term in new {
+        if !existing
+            .iter()
+            .chain(ret.iter())
+            .any(|t| t.language() == term.language())
+        {
+            ret.push(term.to_owned());
+        }
+    }
+    ret
+}
+
+/// Merges `new` into `claims` through the merger, and returns the statements
+/// added or changed, once each.
+fn merge_statements<E: MergeEntity>(
+    merger: &mut ItemMerger<E>,
+    claims: &mut Vec<Statement>,
+    new: &[Statement],
+) -> Vec<Statement> {
+    let mut ret: Vec<Statement> = vec![];
+    for claim in new {
+        if let Some(statement) = merger.add_claim_to(claims, claim.to_owned()) {
+            match statement.id() {
+                Some(id) => {
+                    ret.retain(|s| s.id().as_ref() != Some(&id));
+                    ret.push(statement);
+                }
+                None => ret.push(statement),
+            }
+        }
+    }
+    ret
+}
+
+fn apply_terms(mine: &mut Vec<LocaleString>, new_ones: &[LocaleString]) {
+    for term in new_ones {
+        mine.retain(|t| t.language() != term.language());
+        mine.push(term.to_owned());
+    }
+}
+
+fn remove_terms(mine: &mut Vec<LocaleString>, removed: &[LocaleString]) {
+    mine.retain(|t| !removed.iter().any(|r| r.language() == t.language()));
+}
+
+fn remove_statements(mine: &mut Vec<Statement>, removed: &[Statement]) {
+    mine.retain(|s| !removed.iter().any(|r| r.id().is_some() && r.id() == s.id()));
+}
+
+fn apply_statements(mine: &mut Vec<Statement>, statements: &[Statement]) {
+    for statement in statements {
+        match mine
+            .iter_mut()
+            .find(|s| s.id().is_some() && s.id() == statement.id())
+        {
+            Some(existing) => *existing = statement.to_owned(),
+            None => mine.push(statement.to_owned()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_merger::{LexemeMerger, PropertyMerger};
+    use wikibase::{Snak, SnakDataType};
+
+    fn form(
+        id: &str,
+        representation: &str,
+        features: &[&str],
+        claims: Vec<Statement>,
+    ) -> LexemeForm {
+        LexemeForm::new(
+            id.to_string(),
+            vec![LocaleString::new("en", representation)],
+            features
+                .iter()
+                .map(|f| EntityValue::new(EntityType::Item, *f))
+                .collect(),
+            claims,
+        )
+    }
+
+    fn ipa(value: &str) -> Statement {
+        Statement::new_normal(Snak::new_string("P898", value), vec![], vec![])
+    }
+
+    fn goose() -> LexemeEntity {
+        LexemeEntity::new(
+            "L1".to_string(),
+            vec![LocaleString::new("en", "goose")],
+            vec![],
+            String::new(),
+            None,
+            vec![form("L1-F1", "goose", &["Q110786"], vec![])],
+            vec![LexemeSense::new(
+                "L1-S1".to_string(),
+                vec![LocaleString::new("en", "bird")],
+                vec![],
+            )],
+            false,
+        )
+    }
+
+    fn scraped() -> LexemeEntity {
+        LexemeEntity::new(
+            String::new(),
+            vec![
+                LocaleString::new("en", "geese"),
+                LocaleString::new("de", "Gans"),
+            ],
+            vec![],
+            "Q1860".to_string(),
+            Some("Q1084".to_string()),
+            vec![
+                form("", "goose", &[], vec![ipa("ɡuːs")]),
+                form("", "geese", &["Q146786"], vec![ipa("ɡiːs")]),
+            ],
+            vec![
+                LexemeSense::new(
+                    String::new(),
+                    vec![
+                        LocaleString::new("en", "bird"),
+                        LocaleString::new("de", "Vogel"),
+                    ],
+                    vec![],
+                ),
+                LexemeSense::new(
+                    String::new(),
+                    vec![LocaleString::new("en", "silly person")],
+                    vec![],
+                ),
+            ],
+            false,
+        )
+    }
+
+    #[test]
+    fn test_merge_lexeme_terms() {
+        let mut merger = LexemeMerger::new(goose());
+        let diff = merger.merge(&scraped());
+        assert_eq!(diff.entity_type, Some(EntityType::Lexeme));
+        // A clashing lemma is left out: lexemes have no aliases.
+        assert_eq!(diff.labels, vec![LocaleString::new("de", "Gans")]);
+        assert!(diff.aliases.is_empty());
+        assert_eq!(diff.language.as_deref(), Some("Q1860"));
+        assert_eq!(diff.lexical_category.as_deref(), Some("Q1084"));
+        assert_eq!(merger.item().language(), "Q1860");
+
+        // A different category for a lexeme that has one is left out.
+        let mut other = scraped();
+        *other.lexical_category_mut() = Some("Q24905".to_string());
+        let diff = merger.merge(&other);
+        assert!(diff.lexical_category.is_none());
+        assert_eq!(merger.item().lexical_category().as_deref(), Some("Q1084"));
+    }
+
+    #[test]
+    fn test_merge_lexeme_forms() {
+        let mut merger = LexemeMerger::new(goose());
+        let diff = merger.merge(&scraped());
+        assert_eq!(
+            diff.forms,
+            vec![
+                FormDiff {
+                    id: Some("L1-F1".to_string()),
+                    representations: vec![],
+                    grammatical_features: vec!["Q110786".to_string()],
+                    claims: vec![ipa("ɡuːs")],
+                    ..Default::default()
+                },
+                FormDiff {
+                    id: None,
+                    representations: vec![LocaleString::new("en", "geese")],
+                    grammatical_features: vec!["Q146786".to_string()],
+                    claims: vec![ipa("ɡiːs")],
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(merger.item().forms().len(), 2);
+        assert_eq!(merger.item().forms()[0].claims().len(), 1);
+
+        // Merging the same data again changes nothing.
+        assert!(merger.merge(&scraped()).is_empty());
+    }
+
+    #[test]
+    fn test_folding_into_a_new_form_adds_it_once() {
+        let mut merger = LexemeMerger::new(goose());
+        let mut geese = form("", "geese", &["Q146786"], vec![]);
+        geese
+            .representations_mut()
+            .push(LocaleString::new("de", "Gänse"));
+        let mut other = LexemeEntity::new_empty();
+        other
+            .forms_mut()
+            .push(form("", "geese", &["Q146786"], vec![ipa("ɡiːs")]));
+        other.forms_mut().push(geese.clone());
+        let mut sense = LexemeSense::new(
+            String::new(),
+            vec![LocaleString::new("en", "silly person")],
+            vec![],
+        );
+        other.senses_mut().push(sense.clone());
+
+        let mut total = merger.merge(&other);
+        assert_eq!(total.forms.len(), 1);
+        assert_eq!(total.forms[0].representations.len(), 2);
+        assert_eq!(total.forms[0].claims, vec![ipa("ɡiːs")]);
+
+        // Across merges too, once the diffs are extended into one.
+        sense
+            .glosses_mut()
+            .push(LocaleString::new("de", "Dummkopf"));
+        let mut later = LexemeEntity::new_empty();
+        later.senses_mut().push(sense);
+        total.extend(&merger.merge(&later));
+        assert_eq!(
+            total.senses,
+            vec![SenseDiff {
+                id: None,
+                glosses: vec![
+                    LocaleString::new("en", "silly person"),
+                    LocaleString::new("de", "Dummkopf"),
+                ],
+                claims: vec![],
+                ..Default::default()
+            }]
+        );
+        let mut lexeme = goose();
+        total.apply(&mut lexeme);
+        assert_eq!(lexeme.to_json(), merger.item().to_json());
+    }
+
+    #[test]
+    fn test_merge_lexeme_form_features_must_be_compatible() {
+        let mut merger = LexemeMerger::new(goose());
+        let mut other = LexemeEntity::new_empty();
+        // Same spelling, but plural where the existing form is singular.
+        other
+            .forms_mut()
+            .push(form("", "goose", &["Q146786"], vec![]));
+        let diff = merger.merge(&other);
+        assert_eq!(diff.forms.len(), 1);
+        assert_eq!(diff.forms[0].id, None);
+    }
+
+    #[test]
+    fn test_merge_lexeme_senses() {
+        let mut merger = LexemeMerger::new(goose());
+        let diff = merger.merge(&scraped());
+        assert_eq!(
+            diff.senses,
+            vec![
+                SenseDiff {
+                    id: Some("L1-S1".to_string()),
+                    glosses: vec![LocaleString::new("de", "Vogel")],
+                    claims: vec![],
+                    ..Default::default()
+                },
+                SenseDiff {
+                    id: None,
+                    glosses: vec![LocaleString::new("en", "silly person")],
+                    claims: vec![],
+                    ..Default::default()
+                },
+            ]
+        );
+        assert_eq!(merger.item().senses()[0].glosses().len(), 2);
+    }
+
+    #[test]
+    fn test_lexeme_diff_serializes_for_wbeditentity() {
+        let diff = LexemeMerger::new(goose()).merge(&scraped());
+        let json = serde_json::to_value(&diff).unwrap();
+        assert!(json.get("labels").is_none());
+        assert_eq!(json["lemmas"]["de"]["value"], "Gans");
+        assert_eq!(json["language"], "Q1860");
+        assert_eq!(json["lexicalCategory"], "Q1084");
+
+        let forms = json["forms"].as_array().unwrap();
+        assert_eq!(forms[0]["id"], "L1-F1");
+        assert!(forms[0].get("add").is_none());
+        assert_eq!(forms[0]["grammaticalFeatures"][0], "Q110786");
+        assert_eq!(forms[0]["claims"][0]["mainsnak"]["property"], "P898");
+        assert!(forms[0]["claims"][0]["mainsnak"].get("datatype").is_none());
+        assert_eq!(forms[1]["add"], "");
+        assert_eq!(forms[1]["representations"]["en"]["value"], "geese");
+
+        let senses = json["senses"].as_array().unwrap();
+        assert_eq!(senses[0]["id"], "L1-S1");
+        assert_eq!(senses[0]["glosses"]["de"]["value"], "Vogel");
+        assert_eq!(senses[1]["add"], "");
+        assert!(senses[1].get("claims").is_none());
+    }
+
+    #[test]
+    fn test_apply_lexeme_diff() {
+        let mut merger = LexemeMerger::new(goose());
+        let diff = merger.merge(&scraped());
+        let mut lexeme = goose();
+        diff.apply(&mut lexeme);
+        assert_eq!(lexeme.to_json(), merger.item().to_json());
+    }
+
+    #[test]
+    fn test_merge_property_datatype() {
+        let mut other = PropertyEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "VIAF ID"));
+        other.set_datatype(Some(SnakDataType::ExternalId));
+
+        let diff = PropertyMerger::new(PropertyEntity::new_empty()).merge(&other);
+        assert_eq!(diff.datatype, Some(SnakDataType::ExternalId));
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["datatype"], "external-id");
+        assert_eq!(json["labels"]["en"]["value"], "VIAF ID");
+
+        let mut base = PropertyEntity::new_empty();
+        base.set_datatype(Some(SnakDataType::String));
+        let mut merger = PropertyMerger::new(base);
+        assert!(merger.merge(&other).datatype.is_none());
+        assert_eq!(*merger.item().datatype(), Some(SnakDataType::String));
+    }
+
+    #[test]
+    fn test_merge_reported_lexeme() {
+        let report = LexemeMerger::new(goose()).dry_run(&scraped()).report;
+        let lines: Vec<String> = report.entries.iter().map(|e| e.to_string()).collect();
+        for expected in [
+            "label en:\"geese\": skipped (language already has label \"goose\")",
+            "lexical category Q1084: added (no lexical category yet)",
+            "form L1-F1: merged (shares representation en:\"goose\", features compatible)",
+            "form en:\"geese\": added (no form with a shared representation and compatible features)",
+            "sense L1-S1: merged (shares gloss en:\"bird\")",
+        ] {
+            assert!(lines.iter().any(|l| l == expected), "{expected} in {lines:?}");
+        }
+    }
+}
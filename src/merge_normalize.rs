@@ -0,0 +1,397 @@
+//! How loosely [`ItemMerger`] compares snak values, as [`ValueNormalization`].
+//!
+//! Values scraped from different sources often differ only in form: a URL in
+//! `http` and `https`, or with a trailing slash; coordinates below their
+//! stated precision; text in another Unicode normalization form; an external
+//! ID in case, for a property whose IDs are case-insensitive (which has to be
+//! set by hand; see [`Normalization::CaseInsensitive`]). The merger
+//! compares main snaks, qualifiers and references through the
+//! [`ValueNormalization`] of its [`MergePolicy`], so such values count as
+//! the same. Time values always compare only to their precision; see
+//! [`ItemMerger::is_time_value_identical`].
+//!
+//! The default policy compares values exactly; [`ValueNormalization::loose`]
+//! turns the usual normalizations on. Each datatype has a list of
+//! [`Normalization`]s, which a property can replace with its own:
+//!
+//! ```ignore
+//! let mut policy = MergePolicy {
+//!     normalization: ValueNormalization::loose(),
+//!     ..Default::default()
+//! };
+//! policy
+//!     .normalization
+//!     .set_property("P214", vec![Normalization::Unicode, Normalization::CaseInsensitive]);
+//! policy.normalization.set_datatype(SnakDataType::Url, vec![]);
+//! merger.set_policy(policy);
+//! ```
+
+use crate::external_id::ExternalId;
+use crate::item_merger::ItemMerger;
+#[cfg(doc)]
+use crate::merge_policy::MergePolicy;
+#[cfg(doc)]
+use crate::property_registry::PropertyRegistry;
+use icu_normalizer::ComposingNormalizerBorrowed;
+use std::collections::HashMap;
+use wikibase::{Coordinate, QuantityValue, Snak, SnakDataType, Value};
+
+/// One way two values may differ and still count as the same. Each applies
+/// only to the value types named.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Normalization {
+    /// Strings and monolingual text: compared in Unicode normalization form
+    /// C.
+    Unicode,
+    /// Strings and monolingual text: compared regardless of case. Never
+    /// applied by default, and not derived from a [`PropertyRegistry`], which
+    /// has no notion of case-insensitive properties: set it for each such
+    /// property with [`ValueNormalization::set_property`].
+    CaseInsensitive,
+    /// Strings of external-ID properties: compared as [`ExternalId`] stores
+    /// them, e.g. an ISNI (P213) without spaces. External IDs in references
+    /// are always compared this way, whatever the normalization.
+    ExternalId,
+    /// Strings: compared as URLs, regardless of `http` or `https`, the case
+    /// of the host, and a trailing slash.
+    Url,
+    /// Quantities: amounts and bounds equal up to floating-point rounding,
+    /// and units compared by item ID rather than URI. A `+` sign or trailing
+    /// zeros never matter, since amounts are parsed into numbers.
+    Quantity,
+    /// Coordinates: on the same globe, and closer than the coarser of the two
+    /// precisions in both latitude and longitude.
+    CoordinatePrecision,
+}
+
+/// Which [`Normalization`]s apply to which snaks; see the module
+/// documentation. The default is [`Self::none`].
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ValueNormalization {
+    datatypes: Vec<(SnakDataType, Vec<Normalization>)>,
+    properties: HashMap<String, Vec<Normalization>>,
+}
+
+impl ValueNormalization {
+    /// Compares values exactly, apart from time precision.
+    pub fn none() -> Self {
+        Self::default()
+    }
+
+    /// Unicode normalization for text, URL normalization for URLs, and the
+    /// quantity and coordinate normalizations for their datatypes, and
+    /// external IDs as [`ExternalId`] stores them. Case is
+    /// never ignored unless a property asks for it.
+    pub fn loose() -> Self {
+        let mut ret = Self::none();
+        for datatype in [SnakDataType::String, SnakDataType::MonolingualText] {
+            ret.set_datatype(datatype, vec![Normalization::Unicode]);
+        }
+        ret.set_datatype(
+            SnakDataType::ExternalId,
+            vec![Normalization::ExternalId, Normalization::Unicode],
+        );
+        ret.set_datatype(SnakDataType::Url, vec![Normalization::Url]);
+        ret.set_datatype(SnakDataType::Quantity, vec![Normalization::Quantity]);
+        ret.set_datatype(
+            SnakDataType::GlobeCoordinate,
+            vec![Normalization::CoordinatePrecision],
+        );
+        ret
+    }
+
+    /// Sets the normalizations for snaks of `datatype`.
+    pub fn set_datatype(&mut self, datatype: SnakDataType, normalizations: Vec<Normalization>) {
+        self.datatypes.retain(|(d, _)| *d != datatype);
+        self.datatypes.push((datatype, normalizations));
+    }
+
+    /// Sets the normalizations for snaks of `property`, replacing those of
+    /// its datatype.
+    pub fn set_property(&mut self, property: &str, normalizations: Vec<Normalization>) {
+        self.properties.insert(property.to_string(), normalizations);
+    }
+
+    /// The normalizations that apply to `snak`.
+    pub fn normalizations(&self, snak: &Snak) -> &[Normalization] {
+        if let Some(normalizations) = self.properties.get(snak.property()) {
+            return normalizations;
+        }
+        self.datatypes
+            .iter()
+            .find(|(d, _)| d == snak.datatype())
+            .map(|(_, n)| n.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The normalizations that apply to either snak, so that comparing them
+    /// does not depend on their order.
+    fn shared_normalizations(&self, snak1: &Snak, snak2: &Snak) -> Vec<Normalization> {
+        let mut ret = self.normalizations(snak1).to_vec();
+        for normalization in self.normalizations(snak2) {
+            if !ret.contains(normalization) {
+                ret.push(*normalization);
+            }
+        }
+        ret
+    }
+
+    /// Whether the two snaks have the same property and, under the
+    /// normalizations of either, the same value.
+    pub fn snaks_match(&self, snak1: &Snak, snak2: &Snak) -> bool {
+        snak1.property() == snak2.property() && self.values_match(snak1, snak2)
+    }
+
+    /// Whether the two snaks have the same value under the normalizations of
+    /// either, whatever their properties.
+    pub fn values_match(&self, snak1: &Snak, snak2: &Snak) -> bool {
+        snak_values_match(&self.shared_normalizations(snak1, snak2), snak1, snak2)
+    }
+}
+
+fn snak_values_match(normalizations: &[Normalization], snak1: &Snak, snak2: &Snak) -> bool {
+    let (Some(dv1), Some(dv2)) = (snak1.data_value(), snak2.data_value()) else {
+        return snak1.data_value() == snak2.data_value();
+    };
+    let (p1, p2) = (snak1.property(), snak2.property());
+    match (dv1.value(), dv2.value()) {
+        (Value::Time(t1), Value::Time(t2)) => ItemMerger::is_time_value_identical(t1, t2),
+        (Value::StringValue(s1), Value::StringValue(s2)) if !normalizations.is_empty() => {
+            normalize_text(normalizations, p1, s1) == normalize_text(normalizations, p2, s2)
+        }
+        (Value::MonoLingual(m1), Value::MonoLingual(m2)) if !normalizations.is_empty() => {
+            m1.language() == m2.language()
+                && normalize_text(normalizations, p1, m1.text())
+                    == normalize_text(normalizations, p2, m2.text())
+        }
+        (Value::Quantity(q1), Value::Quantity(q2))
+            if normalizations.contains(&Normalization::Quantity) =>
+        {
+            quantities_match(q1, q2)
+        }
+        (Value::Coordinate(c1), Value::Coordinate(c2))
+            if normalizations.contains(&Normalization::CoordinatePrecision) =>
+        {
+            coordinates_match(c1, c2)
+        }
+        _ => dv1 == dv2,
+    }
+}
+
+/// `text` under `normalizations`, as a value of `property`.
+fn normalize_text(normalizations: &[Normalization], property: &str, text: &str) -> String {
+    let mut ret = text.to_string();
+    if normalizations.contains(&Normalization::ExternalId) {
+        if let Some(property) = ExternalId::prop_numeric(property) {
+            ret = ExternalId::new(property, &ret).id().to_string();
+        }
+    }
+    if normalizations.contains(&Normalization::Unicode) {
+        ret = ComposingNormalizerBorrowed::new_nfc()
+            .normalize(&ret)
+            .into_owned();
+    }
+    if normalizations.contains(&Normalization::Url) {
+        ret = normalize_url(&ret);
+    }
+    if normalizations.contains(&Normalization::CaseInsensitive) {
+        ret = ret.to_lowercase();
+    }
+    ret
+}
+
+/// `http://Example.org/a/` and `https://example.org/a` both become
+/// `https://example.org/a`.
+fn normalize_url(url: &str) -> String {
+    let rest = match url.split_once("://") {
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("http") => rest,
+        Some((scheme, rest)) if scheme.eq_ignore_ascii_case("https") => rest,
+        _ => return url.to_string(),
+    };
+    let (host, path) = match rest.find('/') {
+        Some(pos) => rest.split_at(pos),
+        None => (rest, ""),
+    };
+    format!(
+        "https://{}{}",
+        host.to_lowercase(),
+        path.strip_suffix('/').unwrap_or(path)
+    )
+}
+
+fn quantities_match(q1: &QuantityValue, q2: &QuantityValue) -> bool {
+    let bounds_match = |b1: &Option<f64>, b2: &Option<f64>| match (b1, b2) {
+        (Some(b1), Some(b2)) => numbers_match(*b1, *b2),
+        _ => b1 == b2,
+    };
+    numbers_match(*q1.amount(), *q2.amount())
+        && bounds_match(q1.lower_bound(), q2.lower_bound())
+        && bounds_match(q1.upper_bound(), q2.upper_bound())
+        && entity_id(q1.unit()) == entity_id(q2.unit())
+}
+
+fn numbers_match(n1: f64, n2: f64) -> bool {
+    (n1 - n2).abs() <= f64::EPSILON * 8.0 * n1.abs().max(n2.abs())
+}
+
+fn coordinates_match(c1: &Coordinate, c2: &Coordinate) -> bool {
+    let precision = match (c1.precision(), c2.precision()) {
+        (Some(p1), Some(p2)) => p1.max(*p2),
+        (Some(p), None) | (None, Some(p)) => *p,
+        (None, None) => return c1 == c2,
+    };
+    entity_id(c1.globe()) == entity_id(c2.globe())
+        && (c1.latitude() - c2.latitude()).abs() < precision
+        && (c1.longitude() - c2.longitude()).abs() < precision
+}
+
+/// `Q11573` for `http://www.wikidata.org/entity/Q11573`; units and globes are
+/// URIs, but some sources give bare IDs.
+fn entity_id(uri: &str) -> &str {
+    uri.rsplit('/').next().unwrap_or(uri)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wikibase::{DataValue, DataValueType, MonoLingualText, SnakType};
+
+    fn snak(
+        datatype: SnakDataType,
+        property: &str,
+        value_type: DataValueType,
+        value: Value,
+    ) -> Snak {
+        Snak::new(
+            datatype,
+            property,
+            SnakType::Value,
+            Some(DataValue::new(value_type, value)),
+        )
+    }
+
+    fn quantity(amount: f64, unit: &str) -> Snak {
+        snak(
+            SnakDataType::Quantity,
+            "P2048",
+            DataValueType::Quantity,
+            Value::Quantity(QuantityValue::new(amount, None, unit, None)),
+        )
+    }
+
+    fn coordinate(latitude: f64, longitude: f64, precision: Option<f64>) -> Snak {
+        snak(
+            SnakDataType::GlobeCoordinate,
+            "P625",
+            DataValueType::GlobeCoordinate,
+            Value::Coordinate(Coordinate::new(
+                None,
+                "http://www.wikidata.org/entity/Q2".to_string(),
+                latitude,
+                longitude,
+                precision,
+            )),
+        )
+    }
+
+    fn monolingual(text: &str) -> Snak {
+        snak(
+            SnakDataType::MonolingualText,
+            "P1476",
+            DataValueType::MonoLingualText,
+            Value::MonoLingual(MonoLingualText::new(text, "fr")),
+        )
+    }
+
+    #[test]
+    fn test_urls() {
+        let n = ValueNormalization::loose();
+        let a = Snak::new_url("P854", "http://Example.org/page/");
+        let b = Snak::new_url("P854", "https://example.org/page");
+        assert!(n.snaks_match(&a, &b));
+        // Paths stay case-sensitive.
+        assert!(!n.snaks_match(&a, &Snak::new_url("P854", "https://example.org/Page")));
+        assert!(!ValueNormalization::none().snaks_match(&a, &b));
+    }
+
+    #[test]
+    fn test_quantities() {
+        let n = ValueNormalization::loose();
+        let metre = "http://www.wikidata.org/entity/Q11573";
+        assert!(n.snaks_match(&quantity(0.1 + 0.2, metre), &quantity(0.3, "Q11573")));
+        assert!(!n.snaks_match(&quantity(0.3, metre), &quantity(0.31, metre)));
+        assert!(!n.snaks_match(&quantity(0.3, metre), &quantity(0.3, "1")));
+    }
+
+    #[test]
+    fn test_coordinates() {
+        let n = ValueNormalization::loose();
+        let a = coordinate(52.5186, 13.4081, Some(0.01));
+        assert!(n.snaks_match(&a, &coordinate(52.52, 13.41, None)));
+        assert!(!n.snaks_match(&a, &coordinate(52.53, 13.41, None)));
+        // Without a precision, coordinates must be identical.
+        assert!(!n.snaks_match(
+            &coordinate(52.5186, 13.4081, None),
+            &coordinate(52.52, 13.41, None)
+        ));
+    }
+
+    #[test]
+    fn test_unicode() {
+        let n = ValueNormalization::loose();
+        // "é" precomposed, and as "e" plus a combining acute accent.
+        let precomposed = monolingual("caf\u{e9}");
+        let combining = monolingual("cafe\u{301}");
+        assert!(n.snaks_match(&precomposed, &combining));
+        assert!(!ValueNormalization::none().snaks_match(&precomposed, &combining));
+        assert!(!n.snaks_match(&precomposed, &monolingual("CAF\u{c9}")));
+    }
+
+    #[test]
+    fn test_external_ids() {
+        let a = Snak::new_external_id("P213", "0000000121849233");
+        let b = Snak::new_external_id("P213", "0000 0001 2184 9233");
+        assert!(ValueNormalization::loose().snaks_match(&a, &b));
+        assert!(!ValueNormalization::none().snaks_match(&a, &b));
+        let mut n = ValueNormalization::none();
+        n.set_property("P213", vec![Normalization::ExternalId]);
+        assert!(n.snaks_match(&a, &b));
+    }
+
+    #[test]
+    fn test_match_is_symmetric() {
+        let n = ValueNormalization::loose();
+        // An untyped reference snak against an external ID.
+        let untyped = Snak::new_string("P213", "0000 0001 2184 9233");
+        let typed = Snak::new_external_id("P213", "0000000121849233");
+        assert!(n.snaks_match(&untyped, &typed));
+        assert!(n.snaks_match(&typed, &untyped));
+
+        let mut n = ValueNormalization::none();
+        n.set_property("P214", vec![Normalization::CaseInsensitive]);
+        let a = Snak::new_external_id("P214", "abc");
+        let b = Snak::new_external_id("P1", "ABC");
+        assert!(n.values_match(&a, &b));
+        assert!(n.values_match(&b, &a));
+    }
+
+    #[test]
+    fn test_property_overrides_datatype() {
+        let mut n = ValueNormalization::loose();
+        let a = Snak::new_external_id("P214", "abc");
+        let b = Snak::new_external_id("P214", "ABC");
+        assert!(!n.snaks_match(&a, &b));
+        n.set_property("P214", vec![Normalization::CaseInsensitive]);
+        assert!(n.snaks_match(&a, &b));
+        assert!(!n.snaks_match(
+            &Snak::new_external_id("P227", "abc"),
+            &Snak::new_external_id("P227", "ABC")
+        ));
+
+        n.set_datatype(SnakDataType::Url, vec![]);
+        assert!(!n.snaks_match(
+            &Snak::new_url("P854", "http://example.org"),
+            &Snak::new_url("P854", "https://example.org")
+        ));
+    }
+}
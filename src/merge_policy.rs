@@ -0,0 +1,157 @@
+//! The rules [`ItemMerger`] merges by, as a [`MergePolicy`].
+//!
+//! The default policy is the merger's long-standing behaviour: a clashing
+//! label becomes an alias, descriptions only fill empty languages, a new
+//! statement is folded into an existing one when their qualifier lists are
+//! compatible, and less precise dates of birth and death (P569, P570) are
+//! deprecated. References that duplicate existing ones are dropped rather than
+//! consolidated; see [`crate::merge_references`]. Values are compared
+//! exactly, unless `normalization` is set to, say,
+//! [`ValueNormalization::loose`]; see [`crate::merge_normalize`].
+//!
+//! Bots that need other rules change the fields, overriding the qualifier
+//! matching per property where needed:
+//!
+//! ```ignore
+//! let mut policy = MergePolicy {
+//!     label_conflict: LabelConflict::Keep,
+//!     precision_deprecation: vec![],
+//!     ..Default::default()
+//! };
+//! policy.set_qualifier_match("P225", QualifierMatch::Ignore);
+//! merger.set_policy(policy);
+//! ```
+
+#[cfg(doc)]
+use crate::item_merger::ItemMerger;
+use crate::merge_normalize::ValueNormalization;
+use crate::merge_references::ReferenceConsolidation;
+use std::collections::HashMap;
+
+/// What to do with a merged-in label for a language that already has a
+/// different one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LabelConflict {
+    /// Add the new label as an alias.
+    #[default]
+    AddAsAlias,
+    /// Drop the new label.
+    Keep,
+    /// Replace the existing label with the new one.
+    Replace,
+}
+
+/// What to do with a merged-in description for a language that already has a
+/// different one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DescriptionConflict {
+    /// Keep the existing description.
+    #[default]
+    Keep,
+    /// Replace the existing description with the new one.
+    Replace,
+}
+
+/// When a new statement with the same main snak as an existing one is folded
+/// into it, rather than added alongside it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QualifierMatch {
+    /// One qualifier list is a subset of the other; see
+    /// [`ItemMerger::are_qualifiers_compatible`].
+    #[default]
+    Compatible,
+    /// The qualifier lists are identical; see
+    /// [`ItemMerger::are_qualifiers_identical`].
+    Exact,
+    /// Always, whatever the qualifiers.
+    Ignore,
+}
+
+/// The rules [`ItemMerger`] merges by; see the module documentation.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MergePolicy {
+    pub label_conflict: LabelConflict,
+    pub description_conflict: DescriptionConflict,
+    /// Qualifier matching for properties without an override.
+    pub qualifier_match: QualifierMatch,
+    /// Qualifier matching for single properties, keyed by property ID.
+    pub qualifier_match_overrides: HashMap<String, QualifierMatch>,
+    /// Properties whose new time values are deprecated when the item already
+    /// has a more precise one.
+    pub precision_deprecation: Vec<String>,
+    /// How loosely values are compared; see [`crate::merge_normalize`].
+    pub normalization: ValueNormalization,
+    /// How new references are merged into matching existing ones; `None`
+    /// drops them.
+    pub reference_consolidation: Option<ReferenceConsolidation>,
+}
+
+impl Default for MergePolicy {
+    fn default() -> Self {
+        Self {
+            label_conflict: LabelConflict::default(),
+            description_conflict: DescriptionConflict::default(),
+            qualifier_match: QualifierMatch::default(),
+            qualifier_match_overrides: HashMap::new(),
+            precision_deprecation: vec!["P569".to_string(), "P570".to_string()],
+            normalization: ValueNormalization::none(),
+            reference_consolidation: None,
+        }
+    }
+}
+
+impl MergePolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Overrides qualifier matching for statements of `property`.
+    pub fn set_qualifier_match(&mut self, property: &str, mode: QualifierMatch) {
+        self.qualifier_match_overrides
+            .insert(property.to_string(), mode);
+    }
+
+    /// The qualifier matching that applies to statements of `property`.
+    pub fn qualifier_match_for(&self, property: &str) -> QualifierMatch {
+        self.qualifier_match_overrides
+            .get(property)
+            .copied()
+            .unwrap_or(self.qualifier_match)
+    }
+
+    /// Whether less precise new time values of `property` are deprecated.
+    pub fn deprecates_less_precise(&self, property: &str) -> bool {
+        self.precision_deprecation.iter().any(|p| p == property)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_policy() {
+        let policy = MergePolicy::new();
+        assert_eq!(policy.label_conflict, LabelConflict::AddAsAlias);
+        assert_eq!(policy.description_conflict, DescriptionConflict::Keep);
+        assert_eq!(
+            policy.qualifier_match_for("P31"),
+            QualifierMatch::Compatible
+        );
+        assert!(policy.deprecates_less_precise("P569"));
+        assert!(policy.deprecates_less_precise("P570"));
+        assert!(!policy.deprecates_less_precise("P571"));
+        assert_eq!(policy.normalization, ValueNormalization::none());
+    }
+
+    #[test]
+    fn test_qualifier_match_overrides() {
+        let mut policy = MergePolicy {
+            qualifier_match: QualifierMatch::Exact,
+            ..Default::default()
+        };
+        policy.set_qualifier_match("P225", QualifierMatch::Ignore);
+        assert_eq!(policy.qualifier_match_for("P225"), QualifierMatch::Ignore);
+        assert_eq!(policy.qualifier_match_for("P31"), QualifierMatch::Exact);
+    }
+}
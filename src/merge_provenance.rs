@@ -0,0 +1,611 @@
+//! Which source contributed which part of a [`MergeDiff`].
+//!
+//! Once the diffs of several [`ItemMerger::merge`] calls are `extend`ed into
+//! one, nothing says which source item a label, statement or reference came
+//! from. [`ItemMerger::merge_from`] takes a source tag (`"VIAF"`, `"GND"`, …)
+//! and records it in the merger's [`Provenance`] against every
+//! [`DiffElement`] the call produced. A later source that offers an element
+//! already in the diff adds its tag to that element too, so each element
+//! lists every source that backs it.
+//!
+//! ```ignore
+//! let mut im = ItemMerger::new(target);
+//! let mut total = MergeDiff::new();
+//! total.extend(&im.merge_from(&viaf_item, "VIAF"));
+//! total.extend(&im.merge_from(&gnd_item, "GND"));
+//! // Drop whatever only GND contributed.
+//! let reviewed = im.provenance().without_sources(&total, &["GND"]);
+//! ```
+
+use crate::item_merger::ItemMerger;
+use crate::merge_diff::MergeDiff;
+use crate::merge_policy::MergePolicy;
+use serde::Serialize;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use wikibase::{EntityTrait, LocaleString, Reference, SiteLink, Snak, Statement};
+
+/// Identifies a statement in a diff: by its ID if it has one, otherwise by
+/// its main snak. Two new statements with the same main snak but different
+/// qualifiers share a key.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementKey {
+    pub id: Option<String>,
+    pub main_snak: Snak,
+}
+
+impl StatementKey {
+    pub fn of(statement: &Statement) -> Self {
+        Self {
+            id: statement.id(),
+            main_snak: statement.main_snak().to_owned(),
+        }
+    }
+
+    /// Whether `statement` is the statement this key stands for. The main
+    /// snak of a key is copied from its statement, so it is compared exactly.
+    pub fn matches(&self, statement: &Statement) -> bool {
+        match &self.id {
+            Some(id) => statement.id().as_ref() == Some(id),
+            None => {
+                statement.id().is_none()
+                    && ItemMerger::is_snak_identical(&self.main_snak, statement.main_snak())
+            }
+        }
+    }
+}
+
+/// One part of a [`MergeDiff`] that a source can contribute.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum DiffElement {
+    Label(LocaleString),
+    Description(LocaleString),
+    Alias(LocaleString),
+    Sitelink(SiteLink),
+    /// A statement that was not on the item before.
+    Statement(StatementKey),
+    /// A qualifier added to a statement already on the item.
+    Qualifier {
+        statement: StatementKey,
+        qualifier: Snak,
+    },
+    /// A reference added to a statement already on the item.
+    Reference {
+        statement: StatementKey,
+        reference: Reference,
+    },
+    /// The removal of a label, description, alias, sitelink or statement.
+    Removal(Box<DiffElement>),
+}
+
+/// Source tags for each [`DiffElement`], in the order the elements were
+/// first recorded.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Provenance {
+    entries: Vec<(DiffElement, Vec<String>)>,
+    /// The position of each element in `entries`, by [`lookup_key`].
+    index: HashMap<String, usize>,
+}
+
+impl Provenance {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Every element with its source tags.
+    pub fn iter(&self) -> impl Iterator<Item = (&DiffElement, &[String])> {
+        self.entries.iter().map(|(e, s)| (e, s.as_slice()))
+    }
+
+    /// The sources of `element`; empty if it was never recorded.
+    pub fn sources(&self, element: &DiffElement) -> &[String] {
+        self.index
+            .get(&lookup_key(element))
+            .and_then(|&pos| self.entries.get(pos))
+            .map(|(_, s)| s.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// The elements `source` contributed or backs.
+    pub fn elements_from<'a>(&'a self, source: &'a str) -> impl Iterator<Item = &'a DiffElement> {
+        self.entries
+            .iter()
+            .filter(move |(_, s)| s.iter().any(|s| s == source))
+            .map(|(e, _)| e)
+    }
+
+    /// Adds `source` to the tags of `element`, recording it if needed.
+    pub fn tag(&mut self, element: DiffElement, source: &str) {
+        match self.index.entry(lookup_key(&element)) {
+            Entry::Occupied(pos) => {
+                if let Some((_, sources)) = self.entries.get_mut(*pos.get()) {
+                    add_source(sources, source);
+                }
+            }
+            Entry::Vacant(pos) => {
+                pos.insert(self.entries.len());
+                self.entries.push((element, vec![source.to_string()]));
+            }
+        }
+    }
+
+    /// Merges the tags of `other` into this one.
+    pub fn extend(&mut self, other: &Provenance) {
+        for (element, sources) in &other.entries {
+            for source in sources {
+                self.tag(element.clone(), source);
+            }
+        }
+    }
+
+    /// A copy of `diff` without what only `rejected` sources contributed.
+    /// Elements without any recorded source are kept. A statement loses the
+    /// rejected qualifiers and references; an altered statement left with no
+    /// accepted contribution is dropped.
+    pub fn without_sources(&self, diff: &MergeDiff, rejected: &[&str]) -> MergeDiff {
+        let keep = |element: &DiffElement| {
+            let sources = self.sources(element);
+            sources.is_empty() || sources.iter().any(|s| !rejected.contains(&s.as_str()))
+        };
+        // The qualifiers and references recorded for each statement.
+        let mut contributions: HashMap<String, Vec<&DiffElement>> = HashMap::new();
+        for (element, _) in &self.entries {
+            if let DiffElement::Qualifier { statement, .. }
+            | DiffElement::Reference { statement, .. } = element
+            {
+                contributions
+                    .entry(lookup_key(statement))
+                    .or_default()
+                    .push(element);
+            }
+        }
+        let terms = |list: &[LocaleString], element: fn(LocaleString) -> DiffElement| {
+            list.iter()
+                .filter(|ls| keep(&element((*ls).to_owned())))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let removed_terms = |list: &[LocaleString], element: fn(LocaleString) -> DiffElement| {
+            list.iter()
+                .filter(|ls| keep(&DiffElement::Removal(Box::new(element((*ls).to_owned())))))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        let statement = |statement: &Statement| -> Option<Statement> {
+            let key = StatementKey::of(statement);
+            if !keep(&DiffElement::Statement(key.clone())) {
+                return None;
+            }
+            let mut ret = statement.to_owned();
+            ret.set_qualifier_snaks(
+                statement
+                    .qualifiers()
+                    .iter()
+                    .filter(|q| {
+                        keep(&DiffElement::Qualifier {
+                            statement: key.clone(),
+                            qualifier: (*q).to_owned(),
+                        })
+                    })
+                    .cloned()
+                    .collect(),
+            );
+            ret.set_references(
+                statement
+                    .references()
+                    .iter()
+                    .filter(|r| {
+                        keep(&DiffElement::Reference {
+                            statement: key.clone(),
+                            reference: (*r).to_owned(),
+                        })
+                    })
+                    .cloned()
+                    .collect(),
+            );
+            // A statement already on the item is in the diff only for what
+            // sources added to it.
+            if !self
+                .sources(&DiffElement::Statement(key.clone()))
+                .is_empty()
+            {
+                return Some(ret);
+            }
+            if let Some(contributions) = contributions.get(&lookup_key(&key)) {
+                if contributions.iter().all(|e| !keep(e)) {
+                    return None;
+                }
+            }
+            Some(ret)
+        };
+
+        MergeDiff {
+            labels: terms(&diff.labels, DiffElement::Label),
+            aliases: terms(&diff.aliases, DiffElement::Alias),
+            descriptions: terms(&diff.descriptions, DiffElement::Description),
+            sitelinks: diff
+                .sitelinks
+                .iter()
+                .filter(|s| keep(&DiffElement::Sitelink((*s).to_owned())))
+                .cloned()
+                .collect(),
+            altered_statements: diff
+                .altered_statements
+                .iter()
+                .filter_map(|(id, s)| Some((id.to_owned(), statement(s)?)))
+                .collect(),
+            added_statements: diff.added_statements.iter().filter_map(statement).collect(),
+            removed_labels: removed_terms(&diff.removed_labels, DiffElement::Label),
+            removed_aliases: removed_terms(&diff.removed_aliases, DiffElement::Alias),
+            removed_descriptions: removed_terms(
+                &diff.removed_descriptions,
+                DiffElement::Description,
+            ),
+            removed_sitelinks: diff
+                .removed_sitelinks
+                .iter()
+                .filter(|s| {
+                    keep(&DiffElement::Removal(Box::new(DiffElement::Sitelink(
+                        (*s).to_owned(),
+                    ))))
+                })
+                .cloned()
+                .collect(),
+            removed_statements: diff
+                .removed_statements
+                .iter()
+                .filter(|(_, s)| {
+                    keep(&DiffElement::Removal(Box::new(DiffElement::Statement(
+                        StatementKey::of(s),
+                    ))))
+                })
+                .map(|(id, s)| (id.to_owned(), s.to_owned()))
+                .collect(),
+            // Lexeme and property parts carry no provenance, and are kept.
+            entity_type: diff.entity_type,
+            language: diff.language.clone(),
+            lexical_category: diff.lexical_category.clone(),
+            datatype: diff.datatype,
+            forms: diff.forms.clone(),
+            senses: diff.senses.clone(),
+            removed_forms: diff.removed_forms.clone(),
+            removed_senses: diff.removed_senses.clone(),
+        }
+    }
+
+    /// Tags everything in `diff` with `source`. `before` holds the item's
+    /// statements from before the merge, to tell new statements from new
+    /// qualifiers and references on existing ones.
+    pub(crate) fn record(&mut self, before: &[Statement], diff: &MergeDiff, source: &str) {
+        for ls in &diff.labels {
+            self.tag(DiffElement::Label(ls.to_owned()), source);
+        }
+        for ls in &diff.descriptions {
+            self.tag(DiffElement::Description(ls.to_owned()), source);
+        }
+        for ls in &diff.aliases {
+            self.tag(DiffElement::Alias(ls.to_owned()), source);
+        }
+        for sitelink in &diff.sitelinks {
+            self.tag(DiffElement::Sitelink(sitelink.to_owned()), source);
+        }
+        for statement in diff
+            .added_statements
+            .iter()
+            .chain(diff.altered_statements.values())
+        {
+            let key = StatementKey::of(statement);
+            let Some(previous) = before.iter().find(|s| key.matches(s)) else {
+                self.tag(DiffElement::Statement(key), source);
+                continue;
+            };
+            for qualifier in statement.qualifiers() {
+                if !previous.qualifiers().contains(qualifier) {
+                    let statement = key.clone();
+                    let qualifier = qualifier.to_owned();
+                    self.tag(
+                        DiffElement::Qualifier {
+                            statement,
+                            qualifier,
+                        },
+                        source,
+                    );
+                }
+            }
+            for reference in statement.references() {
+                if !previous.references().contains(reference) {
+                    let statement = key.clone();
+                    let reference = reference.to_owned();
+                    self.tag(
+                        DiffElement::Reference {
+                            statement,
+                            reference,
+                        },
+                        source,
+                    );
+                }
+            }
+        }
+        let removals = diff
+            .removed_labels
+            .iter()
+            .map(|ls| DiffElement::Label(ls.to_owned()))
+            .chain(
+                diff.removed_descriptions
+                    .iter()
+                    .map(|ls| DiffElement::Description(ls.to_owned())),
+            )
+            .chain(
+                diff.removed_aliases
+                    .iter()
+                    .map(|ls| DiffElement::Alias(ls.to_owned())),
+            )
+            .chain(
+                diff.removed_sitelinks
+                    .iter()
+                    .map(|s| DiffElement::Sitelink(s.to_owned())),
+            )
+            .chain(
+                diff.removed_statements
+                    .values()
+                    .map(|s| DiffElement::Statement(StatementKey::of(s))),
+            );
+        for element in removals.collect::<Vec<_>>() {
+            self.tag(DiffElement::Removal(Box::new(element)), source);
+        }
+    }
+
+    /// Adds `source` to every recorded element that `other` also has, as
+    /// `policy` compares them: a statement of `other` backs a recorded one if
+    /// [`ItemMerger::add_claim`] would fold it into the statement as it is in
+    /// `claims`, the merger's current statements.
+    pub(crate) fn corroborate(
+        &mut self,
+        claims: &[Statement],
+        other: &impl EntityTrait,
+        source: &str,
+        policy: &MergePolicy,
+    ) {
+        let normalization = &policy.normalization;
+        for (element, sources) in &mut self.entries {
+            let backed = match element {
+                DiffElement::Label(ls) => other.labels().contains(ls),
+                DiffElement::Description(ls) => other.descriptions().contains(ls),
+                // A clashing label is merged as an alias.
+                DiffElement::Alias(ls) => {
+                    other.aliases().contains(ls) || other.labels().contains(ls)
+                }
+                DiffElement::Sitelink(s) => other
+                    .sitelinks()
+                    .as_ref()
+                    .is_some_and(|sitelinks| sitelinks.contains(s)),
+                DiffElement::Statement(key) => {
+                    same_statement(claims, other, key, policy).next().is_some()
+                }
+                DiffElement::Qualifier {
+                    statement,
+                    qualifier,
+                } => same_statement(claims, other, statement, policy).any(|c| {
+                    c.qualifiers()
+                        .iter()
+                        .any(|q| normalization.snaks_match(q, qualifier))
+                }),
+                DiffElement::Reference {
+                    statement,
+                    reference,
+                } => same_statement(claims, other, statement, policy).any(|c| {
+                    c.references().iter().any(|r| {
+                        ItemMerger::qualifiers_match(normalization, r.snaks(), reference.snaks())
+                    })
+                }),
+                DiffElement::Removal(_) => false,
+            };
+            if backed {
+                add_source(sources, source);
+            }
+        }
+    }
+}
+
+/// A hashable stand-in for `value`, whose wikibase parts cannot be hashed:
+/// its JSON, which is the same for equal values.
+fn lookup_key(value: &(impl Serialize + Debug)) -> String {
+    serde_json::to_string(value).unwrap_or_else(|_| format!("{value:?}"))
+}
+
+fn add_source(sources: &mut Vec<String>, source: &str) {
+    if !sources.iter().any(|s| s == source) {
+        sources.push(source.to_string());
+    }
+}
+
+/// The statements of `item` that [`ItemMerger::add_claim`] would fold into
+/// the statement of `key` among `claims`.
+fn same_statement<'a>(
+    claims: &[Statement],
+    item: &'a impl EntityTrait,
+    key: &StatementKey,
+    policy: &'a MergePolicy,
+) -> impl Iterator<Item = &'a Statement> {
+    let existing = claims
+        .iter()
+        .find(|s| key.matches(s))
+        .cloned()
+        .unwrap_or_else(|| Statement::new_normal(key.main_snak.to_owned(), vec![], vec![]));
+    item.claims()
+        .iter()
+        .filter(move |c| ItemMerger::claims_match(policy, c, &existing))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::merge_normalize::ValueNormalization;
+    use wikibase::ItemEntity;
+
+    fn with_id(snak: Snak, id: &str) -> Statement {
+        let mut statement = Statement::new_normal(snak, vec![], vec![]);
+        statement.set_id(id);
+        statement
+    }
+
+    fn sources(provenance: &Provenance, element: DiffElement) -> Vec<&str> {
+        provenance
+            .sources(&element)
+            .iter()
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn test_merge_from_tags_and_corroborates() {
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut()
+            .push(LocaleString::new("en", "Douglas Adams"));
+
+        let mut viaf = ItemEntity::new_empty();
+        viaf.labels_mut()
+            .push(LocaleString::new("en", "Adams, Douglas"));
+        viaf.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "113230702"),
+            vec![],
+            vec![],
+        ));
+        let mut gnd = ItemEntity::new_empty();
+        gnd.aliases_mut()
+            .push(LocaleString::new("en", "Adams, Douglas"));
+        gnd.add_claim(Statement::new_normal(
+            Snak::new_string("P1477", "Douglas Noel Adams"),
+            vec![],
+            vec![],
+        ));
+
+        let mut im = ItemMerger::new(base);
+        let mut total = im.merge_from(&viaf, "VIAF");
+        total.extend(&im.merge_from(&gnd, "GND"));
+        let provenance = im.provenance();
+
+        let alias = DiffElement::Alias(LocaleString::new("en", "Adams, Douglas"));
+        assert_eq!(sources(provenance, alias), vec!["VIAF", "GND"]);
+        let viaf_id = StatementKey::of(&total.added_statements[0]);
+        assert_eq!(
+            sources(provenance, DiffElement::Statement(viaf_id)),
+            vec!["VIAF"]
+        );
+        assert_eq!(provenance.elements_from("GND").count(), 2);
+        assert_eq!(provenance.len(), 3);
+
+        let reviewed = provenance.without_sources(&total, &["GND"]);
+        assert!(reviewed
+            .aliases
+            .contains(&LocaleString::new("en", "Adams, Douglas")));
+        assert_eq!(reviewed.added_statements.len(), 1);
+        assert_eq!(reviewed.added_statements[0].property(), "P214");
+    }
+
+    #[test]
+    fn test_references_on_existing_statements() {
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(with_id(Snak::new_string("P1477", "Douglas"), "Q42$1"));
+        let reference = Reference::new(vec![Snak::new_url("P854", "https://d-nb.info")]);
+        let mut gnd = ItemEntity::new_empty();
+        gnd.add_claim(Statement::new_normal(
+            Snak::new_string("P1477", "Douglas"),
+            vec![],
+            vec![reference.clone()],
+        ));
+
+        let mut im = ItemMerger::new(base.clone());
+        let diff = im.merge_from(&gnd, "GND");
+        let altered = &diff.altered_statements["Q42$1"];
+        let element = DiffElement::Reference {
+            statement: StatementKey::of(altered),
+            reference,
+        };
+        assert_eq!(sources(im.provenance(), element), vec!["GND"]);
+        // The statement was already on the item; only the reference is GND's.
+        assert!(im
+            .provenance()
+            .sources(&DiffElement::Statement(StatementKey::of(altered)))
+            .is_empty());
+
+        assert!(im
+            .provenance()
+            .without_sources(&diff, &["GND"])
+            .altered_statements
+            .is_empty());
+        assert_eq!(im.provenance().without_sources(&diff, &["VIAF"]), diff);
+    }
+
+    #[test]
+    fn test_corroborates_values_the_merger_folds() {
+        let statement = |url: &str, reference: &str| {
+            Statement::new_normal(
+                Snak::new_url("P973", url),
+                vec![],
+                vec![Reference::new(vec![Snak::new_url("P854", reference)])],
+            )
+        };
+        let mut viaf = ItemEntity::new_empty();
+        viaf.add_claim(statement("http://example.org/about", "http://viaf.org/1"));
+        let mut gnd = ItemEntity::new_empty();
+        gnd.add_claim(statement(
+            "https://example.org/about/",
+            "https://viaf.org/1/",
+        ));
+
+        let mut im = ItemMerger::new(ItemEntity::new_empty());
+        im.set_policy(MergePolicy {
+            normalization: ValueNormalization::loose(),
+            ..Default::default()
+        });
+        let diff = im.merge_from(&viaf, "VIAF");
+        assert!(im.merge_from(&gnd, "GND").is_empty());
+        let key = StatementKey::of(&diff.added_statements[0]);
+        assert_eq!(
+            sources(im.provenance(), DiffElement::Statement(key)),
+            vec!["VIAF", "GND"]
+        );
+
+        // Compared exactly, GND's statement is a different one.
+        let mut im = ItemMerger::new(ItemEntity::new_empty());
+        let diff = im.merge_from(&viaf, "VIAF");
+        im.merge_from(&gnd, "GND");
+        let key = StatementKey::of(&diff.added_statements[0]);
+        assert_eq!(
+            sources(im.provenance(), DiffElement::Statement(key)),
+            vec!["VIAF"]
+        );
+    }
+
+    #[test]
+    fn test_untagged_elements_are_kept() {
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Foo"));
+        let mut im = ItemMerger::new(ItemEntity::new_empty());
+        let diff = im.merge(&other);
+        assert!(im.provenance().is_empty());
+        assert_eq!(im.provenance().without_sources(&diff, &["VIAF"]), diff);
+    }
+
+    #[test]
+    fn test_extend_merges_tags() {
+        let label = DiffElement::Label(LocaleString::new("en", "Foo"));
+        let mut a = Provenance::new();
+        a.tag(label.clone(), "VIAF");
+        let mut b = Provenance::new();
+        b.tag(label.clone(), "GND");
+        b.tag(label.clone(), "VIAF");
+        a.extend(&b);
+        assert_eq!(sources(&a, label), vec!["VIAF", "GND"]);
+    }
+}
@@ -0,0 +1,219 @@
+//! Reference quality scoring and consolidation for [`ItemMerger`].
+//!
+//! By default, a merged-in reference that matches one already on the
+//! statement — by a shared external ID or URL, or structurally — is dropped,
+//! even when it carries more than the existing one. With a
+//! [`ReferenceConsolidation`] in the [`MergePolicy`], the new reference's
+//! snaks for properties the existing one lacks, such as a retrieval date
+//! (P813) or a stated-in (P248), are merged into it instead, and the
+//! statement shows up among the diff's altered statements:
+//!
+//! ```ignore
+//! merger.set_policy(MergePolicy {
+//!     reference_consolidation: Some(ReferenceConsolidation {
+//!         refresh_retrieved: true,
+//!         rank_by_completeness: true,
+//!     }),
+//!     ..Default::default()
+//! });
+//! ```
+
+#[cfg(doc)]
+use crate::{item_merger::ItemMerger, merge_policy::MergePolicy};
+use std::cmp::Reverse;
+use wikibase::{Reference, Snak, SnakDataType, Value};
+
+/// The "retrieved" property.
+const RETRIEVED: &str = "P813";
+/// The "stated in" property.
+const STATED_IN: &str = "P248";
+
+/// How [`ItemMerger`] consolidates matching references.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ReferenceConsolidation {
+    /// Replace the retrieval date (P813) of an existing reference with a
+    /// later one from the new reference.
+    pub refresh_retrieved: bool,
+    /// Order the references of each statement the merger adds a reference to,
+    /// or consolidates one of, from most to least complete; see
+    /// [`reference_score`].
+    pub rank_by_completeness: bool,
+}
+
+/// How complete `reference` is: two points each for a stated-in (P248), an
+/// external ID and a URL, one for a retrieval date (P813), and one for each
+/// other property.
+pub fn reference_score(reference: &Reference) -> usize {
+    let mut properties: Vec<(&str, &SnakDataType)> = reference
+        .snaks()
+        .iter()
+        .map(|snak| (snak.property(), snak.datatype()))
+        .collect();
+    properties.sort_by_key(|(property, _)| *property);
+    properties.dedup_by_key(|(property, _)| *property);
+    properties
+        .iter()
+        .map(|(property, datatype)| match (*property, datatype) {
+            (STATED_IN, _) | (_, SnakDataType::ExternalId) | (_, SnakDataType::Url) => 2,
+            _ => 1,
+        })
+        .sum()
+}
+
+impl ReferenceConsolidation {
+    /// Merges the snaks of `new` into `existing`, returning what changed, for
+    /// the merge report. Snaks for properties `existing` already has are
+    /// left out, unless they are a later retrieval date and
+    /// [`Self::refresh_retrieved`] is set. Every snak of a property only
+    /// `new` has is added, so two URLs (P854) both carry over.
+    pub(crate) fn consolidate(&self, existing: &mut Reference, new: &Reference) -> Vec<String> {
+        let mut snaks = existing.snaks().to_owned();
+        let mut changes = vec![];
+        for snak in new.snaks() {
+            let property = snak.property();
+            match existing
+                .snaks()
+                .iter()
+                .position(|s| s.property() == property)
+            {
+                None => {
+                    let change = format!("adds {property}");
+                    if !changes.contains(&change) {
+                        changes.push(change);
+                    }
+                    snaks.push(snak.to_owned());
+                }
+                Some(pos) if property == RETRIEVED && self.refresh_retrieved => {
+                    if is_later(snak, &snaks[pos]) {
+                        changes.push(format!("refreshes {property}"));
+                        snaks[pos] = snak.to_owned();
+                    }
+                }
+                Some(_) => {}
+            }
+        }
+        if !changes.is_empty() {
+            existing.set_snaks(snaks);
+        }
+        changes
+    }
+
+    /// Orders `references` by [`reference_score`], most complete first, if
+    /// [`Self::rank_by_completeness`] is set. References with the same score
+    /// keep their order.
+    pub(crate) fn rank(&self, references: &mut [Reference]) {
+        if self.rank_by_completeness {
+            references.sort_by_key(|r| Reverse(reference_score(r)));
+        }
+    }
+}
+
+/// Whether the time value of `snak1` is later than that of `snak2`. Only
+/// dates in the common era are compared, as their timestamps sort as text.
+fn is_later(snak1: &Snak, snak2: &Snak) -> bool {
+    let time = |snak: &Snak| match snak.data_value().as_ref().map(|dv| dv.value()) {
+        Some(Value::Time(t)) => t.time().strip_prefix('+').map(str::to_string),
+        _ => None,
+    };
+    match (time(snak1), time(snak2)) {
+        (Some(t1), Some(t2)) => t1.len() == t2.len() && t1 > t2,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn retrieved(time: &str) -> Snak {
+        Snak::new_time(RETRIEVED, time, 11)
+    }
+
+    #[test]
+    fn test_reference_score() {
+        let url = Snak::new_url("P854", "https://example.org");
+        assert_eq!(reference_score(&Reference::new(vec![])), 0);
+        assert_eq!(reference_score(&Reference::new(vec![url.clone()])), 2);
+        let full = Reference::new(vec![
+            Snak::new_item(STATED_IN, "Q36578"),
+            Snak::new_external_id("P227", "118540238"),
+            url,
+            retrieved("+2024-01-01T00:00:00Z"),
+        ]);
+        assert_eq!(reference_score(&full), 7);
+    }
+
+    #[test]
+    fn test_consolidate_adds_missing_properties() {
+        let url = Snak::new_url("P854", "https://example.org");
+        let mut existing = Reference::new(vec![url.clone()]);
+        let new = Reference::new(vec![
+            Snak::new_url("P854", "https://example.org/other"),
+            retrieved("+2024-01-01T00:00:00Z"),
+        ]);
+        let changes = ReferenceConsolidation::default().consolidate(&mut existing, &new);
+        assert_eq!(changes, vec!["adds P813".to_string()]);
+        assert_eq!(existing.snaks().len(), 2);
+        assert_eq!(existing.snaks()[0], url);
+    }
+
+    #[test]
+    fn test_consolidate_adds_repeated_properties() {
+        let mut existing = Reference::new(vec![Snak::new_item(STATED_IN, "Q36578")]);
+        let first = Snak::new_url("P854", "https://example.org/a");
+        let second = Snak::new_url("P854", "https://example.org/b");
+        let new = Reference::new(vec![
+            first.clone(),
+            Snak::new_item(STATED_IN, "Q1"),
+            second.clone(),
+        ]);
+        let changes = ReferenceConsolidation::default().consolidate(&mut existing, &new);
+        assert_eq!(changes, vec!["adds P854".to_string()]);
+        assert_eq!(
+            existing.snaks(),
+            &vec![Snak::new_item(STATED_IN, "Q36578"), first, second]
+        );
+    }
+
+    #[test]
+    fn test_consolidate_refreshes_retrieved() {
+        let old = Reference::new(vec![retrieved("+2020-01-01T00:00:00Z")]);
+        let new = Reference::new(vec![retrieved("+2024-01-01T00:00:00Z")]);
+
+        let mut existing = old.clone();
+        assert!(ReferenceConsolidation::default()
+            .consolidate(&mut existing, &new)
+            .is_empty());
+        assert_eq!(existing, old);
+
+        let refresh = ReferenceConsolidation {
+            refresh_retrieved: true,
+            ..Default::default()
+        };
+        assert_eq!(
+            refresh.consolidate(&mut existing, &new),
+            vec!["refreshes P813".to_string()]
+        );
+        assert_eq!(existing, new);
+        // An older date never replaces a newer one.
+        assert!(refresh.consolidate(&mut existing, &old).is_empty());
+    }
+
+    #[test]
+    fn test_rank_by_completeness() {
+        let weak = Reference::new(vec![Snak::new_string("P1476", "a title")]);
+        let strong = Reference::new(vec![
+            Snak::new_item(STATED_IN, "Q36578"),
+            Snak::new_external_id("P227", "118540238"),
+        ]);
+        let mut references = vec![weak.clone(), strong.clone()];
+        ReferenceConsolidation::default().rank(&mut references);
+        assert_eq!(references, vec![weak.clone(), strong.clone()]);
+        let rank = ReferenceConsolidation {
+            rank_by_completeness: true,
+            ..Default::default()
+        };
+        rank.rank(&mut references);
+        assert_eq!(references, vec![strong, weak]);
+    }
+}
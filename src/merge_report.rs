@@ -0,0 +1,663 @@
+//! Why [`ItemMerger`] took or left each part of a merged-in item.
+//!
+//! [`ItemMerger::merge_reported`] merges like [`ItemMerger::merge`] and also
+//! returns a [`MergeReport`]: one [`ReportEntry`] per incoming label,
+//! description, alias, sitelink, claim, qualifier and reference, and for
+//! lexemes and properties per form, sense and single-valued field, saying what
+//! was decided and why, e.g. "duplicate main snak, qualifiers compatible" or
+//! "shares P214 with an existing reference". [`ItemMerger::dry_run`] does the
+//! same on a copy of the merger, leaving it untouched.
+//!
+//! The report serializes with `serde`, and each entry also reads as a line of
+//! text:
+//!
+//! ```text
+//! claim P569=+1900-00-00T00:00:00Z/9: deprecated (lower precision than existing P569)
+//! ```
+
+#[cfg(doc)]
+use crate::item_merger::ItemMerger;
+use crate::merge_constraints::ConstraintKind;
+use crate::merge_diff::MergeDiff;
+use crate::merge_policy::{LabelConflict, MergePolicy};
+use serde::Serialize;
+use std::fmt;
+use wikibase::{EntityTrait, LocaleString, Reference, Snak, SnakType, Statement, Value};
+
+/// What the merger did with one incoming part.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Decision {
+    Added,
+    /// A clashing label, added as an alias instead.
+    AddedAsAlias,
+    /// Replaced the item's value for the same language or site.
+    Replaced,
+    /// Folded into a statement already on the item.
+    Merged,
+    /// Added with deprecated rank.
+    Deprecated,
+    /// Left out because the item already has it, or something that wins over
+    /// it.
+    Skipped,
+    /// Left out because it violates a property constraint.
+    Rejected,
+    /// Removed from the item because the merged-in item lacks it.
+    Removed,
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Decision::Added => "added",
+            Decision::AddedAsAlias => "added as alias",
+            Decision::Replaced => "replaced",
+            Decision::Merged => "merged",
+            Decision::Deprecated => "deprecated",
+            Decision::Skipped => "skipped",
+            Decision::Rejected => "rejected",
+            Decision::Removed => "removed",
+        };
+        f.write_str(text)
+    }
+}
+
+/// The incoming part a [`ReportEntry`] is about. Snaks are written as
+/// `P31=Q5`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ReportSubject {
+    Label {
+        language: String,
+        value: String,
+    },
+    Description {
+        language: String,
+        value: String,
+    },
+    Alias {
+        language: String,
+        value: String,
+    },
+    Sitelink {
+        site: String,
+        title: String,
+    },
+    Claim {
+        snak: String,
+    },
+    Qualifier {
+        claim: String,
+        snak: String,
+    },
+    Reference {
+        claim: String,
+        snaks: Vec<String>,
+    },
+    /// A lexeme form, by ID, or by its representations if it has none.
+    Form {
+        form: String,
+    },
+    /// A lexeme sense, by ID, or by its glosses if it has none.
+    Sense {
+        sense: String,
+    },
+    /// A lexeme's language or lexical category, or a property's datatype.
+    Field {
+        field: String,
+        value: String,
+    },
+}
+
+impl fmt::Display for ReportSubject {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReportSubject::Label { language, value } => write!(f, "label {language}:{value:?}"),
+            ReportSubject::Description { language, value } => {
+                write!(f, "description {language}:{value:?}")
+            }
+            ReportSubject::Alias { language, value } => write!(f, "alias {language}:{value:?}"),
+            ReportSubject::Sitelink { site, title } => write!(f, "sitelink {site}:{title:?}"),
+            ReportSubject::Claim { snak } => write!(f, "claim {snak}"),
+            ReportSubject::Qualifier { claim, snak } => write!(f, "qualifier {snak} on {claim}"),
+            ReportSubject::Reference { claim, snaks } => {
+                write!(f, "reference [{}] on {claim}", snaks.join(", "))
+            }
+            ReportSubject::Form { form } => write!(f, "form {form}"),
+            ReportSubject::Sense { sense } => write!(f, "sense {sense}"),
+            ReportSubject::Field { field, value } => write!(f, "{field} {value}"),
+        }
+    }
+}
+
+impl ReportSubject {
+    pub(crate) fn label(ls: &LocaleString) -> Self {
+        Self::Label {
+            language: ls.language().to_string(),
+            value: ls.value().to_string(),
+        }
+    }
+
+    pub(crate) fn description(ls: &LocaleString) -> Self {
+        Self::Description {
+            language: ls.language().to_string(),
+            value: ls.value().to_string(),
+        }
+    }
+
+    pub(crate) fn alias(ls: &LocaleString) -> Self {
+        Self::Alias {
+            language: ls.language().to_string(),
+            value: ls.value().to_string(),
+        }
+    }
+
+    pub(crate) fn claim(main_snak: &Snak) -> Self {
+        Self::Claim {
+            snak: snak_text(main_snak),
+        }
+    }
+
+    pub(crate) fn qualifier(main_snak: &Snak, qualifier: &Snak) -> Self {
+        Self::Qualifier {
+            claim: snak_text(main_snak),
+            snak: snak_text(qualifier),
+        }
+    }
+
+    pub(crate) fn reference(main_snak: &Snak, reference: &Reference) -> Self {
+        Self::Reference {
+            claim: snak_text(main_snak),
+            snaks: reference.snaks().iter().map(snak_text).collect(),
+        }
+    }
+
+    pub(crate) fn form(id: &str, representations: &[LocaleString]) -> Self {
+        Self::Form {
+            form: part_text(id, representations),
+        }
+    }
+
+    pub(crate) fn sense(id: &str, glosses: &[LocaleString]) -> Self {
+        Self::Sense {
+            sense: part_text(id, glosses),
+        }
+    }
+
+    pub(crate) fn field(field: &str, value: &str) -> Self {
+        Self::Field {
+            field: field.to_string(),
+            value: value.to_string(),
+        }
+    }
+}
+
+/// `L1-F1`, or `en:"goose", de:"Gans"` for a form or sense without an ID.
+fn part_text(id: &str, terms: &[LocaleString]) -> String {
+    if !id.is_empty() {
+        return id.to_string();
+    }
+    terms
+        .iter()
+        .map(|t| format!("{}:{:?}", t.language(), t.value()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// One decision of the merger.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ReportEntry {
+    pub subject: ReportSubject,
+    pub decision: Decision,
+    pub reason: String,
+}
+
+impl ReportEntry {
+    pub fn new(subject: ReportSubject, decision: Decision, reason: impl Into<String>) -> Self {
+        Self {
+            subject,
+            decision,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ReportEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} ({})", self.subject, self.decision, self.reason)
+    }
+}
+
+/// Every decision of one merge, in the order made.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct MergeReport {
+    pub entries: Vec<ReportEntry>,
+}
+
+impl fmt::Display for MergeReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for entry in &self.entries {
+            writeln!(f, "{entry}")?;
+        }
+        Ok(())
+    }
+}
+
+/// The result of [`ItemMerger::merge_reported`] and [`ItemMerger::dry_run`].
+/// `diff` serializes as the `wbeditentity` payload.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ReportedMerge {
+    pub diff: MergeDiff,
+    pub report: MergeReport,
+}
+
+/// Adds the entry `entry` builds to `report`, if there is one.
+pub(crate) fn note(report: &mut Option<MergeReport>, entry: impl FnOnce() -> ReportEntry) {
+    if let Some(report) = report {
+        report.entries.push(entry());
+    }
+}
+
+/// Notes `decision` for each reference and qualifier of `claim`, which is
+/// being added or rejected as a whole.
+pub(crate) fn note_claim_parts(
+    report: &mut Option<MergeReport>,
+    claim: &Statement,
+    decision: Decision,
+    reason: &str,
+) {
+    let Some(report) = report else {
+        return;
+    };
+    let main_snak = claim.main_snak();
+    let references = claim
+        .references()
+        .iter()
+        .map(|r| ReportSubject::reference(main_snak, r));
+    let qualifiers = claim
+        .qualifiers()
+        .iter()
+        .map(|q| ReportSubject::qualifier(main_snak, q));
+    for subject in references.chain(qualifiers) {
+        report
+            .entries
+            .push(ReportEntry::new(subject, decision, reason));
+    }
+}
+
+pub(crate) fn constraint_name(kind: ConstraintKind) -> &'static str {
+    match kind {
+        ConstraintKind::Format => "format",
+        ConstraintKind::SingleValue => "single-value",
+        ConstraintKind::DistinctValues => "distinct-values",
+        ConstraintKind::AllowedQualifiers => "allowed-qualifiers",
+        ConstraintKind::AllowedUnits => "allowed-units",
+    }
+}
+
+/// `P31=Q5`, `P1476=en:"Title"`, `P569=+1900-00-00T00:00:00Z/9`, `P570=novalue`.
+pub(crate) fn snak_text(snak: &Snak) -> String {
+    let value = match snak.snak_type() {
+        SnakType::NoValue => "novalue".to_string(),
+        SnakType::UnknownValue => "somevalue".to_string(),
+        SnakType::Value => match snak.data_value().as_ref().map(|dv| dv.value()) {
+            None => "?".to_string(),
+            Some(Value::Entity(e) | Value::EntitySchema(e)) => e.id().to_string(),
+            Some(Value::StringValue(s)) => s.to_owned(),
+            Some(Value::MonoLingual(m)) => format!("{}:{:?}", m.language(), m.text()),
+            Some(Value::Time(t)) => format!("{}/{}", t.time(), t.precision()),
+            Some(Value::Quantity(q)) => {
+                let unit = q.unit().rsplit('/').next().unwrap_or_default();
+                match unit {
+                    "1" => q.amount().to_string(),
+                    unit => format!("{} {unit}", q.amount()),
+                }
+            }
+            Some(Value::Coordinate(c)) => format!("@{}/{}", c.latitude(), c.longitude()),
+        },
+    };
+    format!("{}={value}", snak.property())
+}
+
+/// Explains the term and sitelink decisions of a merge of `other` into
+/// `before` that produced `diff`. The diff is authoritative about what was
+/// taken; `before` and `policy` supply the reasons.
+pub(crate) fn explain_terms(
+    before: &impl EntityTrait,
+    other: &impl EntityTrait,
+    diff: &MergeDiff,
+    policy: &MergePolicy,
+) -> Vec<ReportEntry> {
+    let mut ret = vec![];
+    let same_language = |list: &[LocaleString], ls: &LocaleString| {
+        list.iter()
+            .find(|x| x.language() == ls.language())
+            .map(|x| x.value().to_string())
+    };
+
+    for ls in other.labels() {
+        let subject = ReportSubject::label(ls);
+        let existing = same_language(before.labels(), ls);
+        let (decision, reason) = if diff.labels.contains(ls) {
+            match existing {
+                Some(old) => (Decision::Replaced, format!("replaced label {old:?}")),
+                None => (Decision::Added, "no label in this language".to_string()),
+            }
+        } else if diff.aliases.contains(ls) {
+            let old = existing.unwrap_or_default();
+            let reason = format!("language already has label {old:?}");
+            (Decision::AddedAsAlias, reason)
+        } else {
+            let reason = match existing {
+                Some(old) if old == ls.value() => "already the label".to_string(),
+                Some(old) => match policy.label_conflict {
+                    LabelConflict::AddAsAlias => {
+                        format!("language already has label {old:?}; alias already present")
+                    }
+                    _ => format!("language already has label {old:?}"),
+                },
+                None if before
+                    .labels()
+                    .iter()
+                    .any(|l| l.language() == "mul" && l.value() == ls.value()) =>
+                {
+                    "same as the mul label".to_string()
+                }
+                None => "already present".to_string(),
+            };
+            (Decision::Skipped, reason)
+        };
+        ret.push(ReportEntry::new(subject, decision, reason));
+    }
+
+    for ls in other.descriptions() {
+        let subject = ReportSubject::description(ls);
+        let existing = same_language(before.descriptions(), ls);
+        let (decision, reason) = if diff.descriptions.contains(ls) {
+            match existing {
+                Some(old) => (Decision::Replaced, format!("replaced description {old:?}")),
+                None => (
+                    Decision::Added,
+                    "no description in this language".to_string(),
+                ),
+            }
+        } else {
+            let reason = match existing {
+                Some(old) if old == ls.value() => "already the description".to_string(),
+                Some(old) => format!("language already has description {old:?}"),
+                None => "same as a label or alias".to_string(),
+            };
+            (Decision::Skipped, reason)
+        };
+        ret.push(ReportEntry::new(subject, decision, reason));
+    }
+
+    for ls in other.aliases() {
+        let subject = ReportSubject::alias(ls);
+        let (decision, reason) = if diff.aliases.contains(ls) {
+            (Decision::Added, "new alias")
+        } else if before.aliases().contains(ls) {
+            (Decision::Skipped, "already an alias")
+        } else {
+            (Decision::Skipped, "same as a label or description")
+        };
+        ret.push(ReportEntry::new(subject, decision, reason));
+    }
+
+    for sitelink in other.sitelinks().as_deref().unwrap_or_default() {
+        let subject = ReportSubject::Sitelink {
+            site: sitelink.site().to_string(),
+            title: sitelink.title().to_string(),
+        };
+        let existing = before
+            .sitelinks()
+            .as_deref()
+            .unwrap_or_default()
+            .iter()
+            .find(|s| s.site() == sitelink.site())
+            .map(|s| s.title().to_string());
+        let (decision, reason) = if diff.sitelinks.contains(sitelink) {
+            match existing {
+                Some(old) => (Decision::Replaced, format!("replaced sitelink {old:?}")),
+                None => (Decision::Added, "no sitelink for this site".to_string()),
+            }
+        } else {
+            match existing {
+                Some(old) if old == *sitelink.title() => {
+                    (Decision::Skipped, "already present".to_string())
+                }
+                Some(old) => (
+                    Decision::Skipped,
+                    format!("site already has sitelink {old:?}"),
+                ),
+                None => (Decision::Skipped, "already present".to_string()),
+            }
+        };
+        ret.push(ReportEntry::new(subject, decision, reason));
+    }
+
+    let absent = "absent from the merged item";
+    let mut removed_statements: Vec<_> = diff.removed_statements.iter().collect();
+    removed_statements.sort_by_key(|(id, _)| *id);
+    let removals = diff
+        .removed_labels
+        .iter()
+        .map(ReportSubject::label)
+        .chain(
+            diff.removed_descriptions
+                .iter()
+                .map(ReportSubject::description),
+        )
+        .chain(diff.removed_aliases.iter().map(ReportSubject::alias))
+        .chain(
+            diff.removed_sitelinks
+                .iter()
+                .map(|s| ReportSubject::Sitelink {
+                    site: s.site().to_string(),
+                    title: s.title().to_string(),
+                }),
+        )
+        .chain(
+            removed_statements
+                .into_iter()
+                .map(|(_, s)| ReportSubject::claim(s.main_snak())),
+        );
+    ret.extend(removals.map(|subject| ReportEntry::new(subject, Decision::Removed, absent)));
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::item_merger::ItemMerger;
+    use crate::merge_constraints::{ConstraintAction, ConstraintChecks};
+    use crate::property_registry::{PropertyInfo, PropertyRegistry};
+    use std::sync::Arc;
+    use wikibase::{ItemEntity, SiteLink};
+
+    fn lines(report: &MergeReport) -> Vec<String> {
+        report.entries.iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn test_terms_and_sitelinks() {
+        let mut base = ItemEntity::new_empty();
+        base.labels_mut()
+            .push(LocaleString::new("en", "Douglas Adams"));
+        base.descriptions_mut()
+            .push(LocaleString::new("en", "English writer"));
+        base.set_sitelinks(Some(vec![SiteLink::new("enwiki", "Douglas Adams", vec![])]));
+
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Adams"));
+        other
+            .labels_mut()
+            .push(LocaleString::new("de", "Douglas Adams"));
+        other
+            .descriptions_mut()
+            .push(LocaleString::new("en", "British author"));
+        other.aliases_mut().push(LocaleString::new("en", "DNA"));
+        other
+            .aliases_mut()
+            .push(LocaleString::new("en", "Douglas Adams"));
+        other.set_sitelinks(Some(vec![
+            SiteLink::new("enwiki", "Douglas Noel Adams", vec![]),
+            SiteLink::new("dewiki", "Douglas Adams", vec![]),
+        ]));
+
+        let merged = ItemMerger::new(base).dry_run(&other);
+        assert_eq!(
+            lines(&merged.report),
+            vec![
+                r#"label en:"Adams": added as alias (language already has label "Douglas Adams")"#,
+                r#"label de:"Douglas Adams": added (no label in this language)"#,
+                r#"description en:"British author": skipped (language already has description "English writer")"#,
+                r#"alias en:"DNA": added (new alias)"#,
+                r#"alias en:"Douglas Adams": skipped (same as a label or description)"#,
+                r#"sitelink enwiki:"Douglas Noel Adams": skipped (site already has sitelink "Douglas Adams")"#,
+                r#"sitelink dewiki:"Douglas Adams": added (no sitelink for this site)"#,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_claims_qualifiers_and_references() {
+        let viaf = |id: &str| Reference::new(vec![Snak::new_external_id("P214", id)]);
+        let mut base = ItemEntity::new_empty();
+        base.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![viaf("1")],
+        ));
+        base.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "1"),
+            vec![],
+            vec![],
+        ));
+
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![Snak::new_item("P642", "Q1")],
+            vec![viaf("1"), viaf("2")],
+        ));
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "1"),
+            vec![],
+            vec![],
+        ));
+        other.add_claim(Statement::new_normal(
+            Snak::new_item("P106", "Q36180"),
+            vec![Snak::new_item("P642", "Q2")],
+            vec![viaf("3")],
+        ));
+
+        let mut im = ItemMerger::new(base.clone());
+        let merged = im.merge_reported(&other);
+        assert_eq!(
+            lines(&merged.report),
+            vec![
+                "reference [P214=1] on P31=Q5: skipped (shares P214 with an existing reference)",
+                "reference [P214=2] on P31=Q5: added (new reference)",
+                "qualifier P642=Q1 on P31=Q5: added (new qualifier)",
+                "claim P31=Q5: merged (duplicate main snak, qualifiers compatible)",
+                "claim P214=1: skipped (external ID already present)",
+                "reference [P214=3] on P106=Q36180: added (new statement)",
+                "qualifier P642=Q2 on P106=Q36180: added (new statement)",
+                "claim P106=Q36180: added (no statement with this main snak and matching qualifiers)",
+            ]
+        );
+        assert_eq!(merged.diff.added_statements.len(), 2);
+
+        // Reporting is per call; a plain merge collects nothing.
+        let again = im.merge_reported(&other);
+        assert!(again.diff.is_empty());
+        assert!(again
+            .report
+            .entries
+            .iter()
+            .all(|e| e.decision == Decision::Skipped));
+        assert!(im.merge(&other).is_empty());
+
+        // A deprecated statement brings its parts along; a rejected one
+        // leaves them out with it.
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P214",
+            PropertyInfo {
+                format_regex: Some("\\d+".to_string()),
+                single_value: true,
+                ..Default::default()
+            },
+        );
+        let mut checks = ConstraintChecks::new(ConstraintAction::Reject);
+        checks.set_action(ConstraintKind::SingleValue, ConstraintAction::Deprecate);
+        let mut im = ItemMerger::new(base);
+        im.set_property_registry(Arc::new(registry));
+        im.set_constraint_checks(checks);
+        let mut other = ItemEntity::new_empty();
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "2"),
+            vec![],
+            vec![viaf("2")],
+        ));
+        other.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "x"),
+            vec![Snak::new_item("P642", "Q1")],
+            vec![],
+        ));
+        assert_eq!(
+            lines(&im.merge_reported(&other).report),
+            vec![
+                "reference [P214=2] on P214=2: added (new statement)",
+                "claim P214=2: deprecated (violates single-value constraint)",
+                "qualifier P642=Q1 on P214=x: rejected (statement rejected)",
+                "claim P214=x: rejected (violates format, single-value constraint)",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dry_run_leaves_merger_untouched() {
+        let mut other = ItemEntity::new_empty();
+        other.labels_mut().push(LocaleString::new("en", "Foo"));
+        let im = ItemMerger::new(ItemEntity::new_empty());
+        let merged = im.dry_run(&other);
+        assert_eq!(merged.diff.labels.len(), 1);
+        assert!(im.item().labels().is_empty());
+    }
+
+    #[test]
+    fn test_report_serializes() {
+        let report = MergeReport {
+            entries: vec![ReportEntry::new(
+                ReportSubject::claim(&Snak::new_item("P31", "Q5")),
+                Decision::AddedAsAlias,
+                "why",
+            )],
+        };
+        assert_eq!(
+            serde_json::to_value(&report).unwrap(),
+            serde_json::json!({"entries": [{
+                "subject": {"kind": "claim", "snak": "P31=Q5"},
+                "decision": "added_as_alias",
+                "reason": "why"
+            }]})
+        );
+    }
+
+    #[test]
+    fn test_snak_text() {
+        assert_eq!(snak_text(&Snak::new_item("P31", "Q5")), "P31=Q5");
+        assert_eq!(snak_text(&Snak::new_string("P1477", "DNA")), "P1477=DNA");
+        assert_eq!(
+            snak_text(&Snak::new(
+                wikibase::SnakDataType::Time,
+                "P570",
+                SnakType::NoValue,
+                None
+            )),
+            "P570=novalue"
+        );
+    }
+}
@@ -0,0 +1,914 @@
+//! Three-way merges of concurrent edits, as a [`ThreeWayMerge`].
+//!
+//! A tool that edits a snapshot of an entity and saves it later races other
+//! editors: [`MergeDiff::apply`] and `wbeditentity` overwrite altered
+//! statements by ID, whatever happened to them since. [`merge_three_way`]
+//! takes the snapshot (`base`), the tool's edited version (`ours`) and the
+//! live entity (`theirs`), and returns a [`MergeDiff`] against the live
+//! entity that carries over only what `ours` changed:
+//!
+//! - Labels, descriptions, sitelinks and statements that only `ours` changed
+//!   are taken from it, including removals. Statements are matched by ID and
+//!   compared as in [`ItemDiff`].
+//! - Aliases that `ours` added or removed are added or removed.
+//! - Statements new in `ours` are merged into the live entity with
+//!   [`ItemMerger`], so they fold into live statements as in any other merge.
+//! - A lexeme's forms and senses are matched by ID, and taken from `ours`
+//!   part by part: representations and glosses by language, grammatical
+//!   features as a whole, and statements as above, except that new ones are
+//!   added as they are. Forms and senses new in `ours` go through the merger.
+//! - Where both sides changed the same label, description, sitelink,
+//!   statement, form or sense, and differently, the live value stays and a
+//!   [`MergeConflict`] records all three versions. A form or sense clashes
+//!   as a whole if one of its parts does, or if one side removed it.
+//!
+//! ```ignore
+//! let merge = merge_three_way(&snapshot, &edited, &live);
+//! for conflict in &merge.conflicts {
+//!     println!("{conflict}");
+//! }
+//! wikidata
+//!     .submit_diff(&mut api, "Q42", &merge.diff, Some(live_revision), &options)
+//!     .await?;
+//! ```
+
+use crate::item_diff::{statement_changes, term_changes, ItemDiff, StatementChange, TermChange};
+use crate::item_merger::ItemMerger;
+use crate::merge_diff::{FormDiff, MergeDiff, SenseDiff};
+use crate::merge_entity::MergeEntity;
+use serde::Serialize;
+use std::collections::BTreeSet;
+use std::fmt;
+use wikibase::{
+    EntityTrait, LexemeEntity, LexemeForm, LexemeSense, LocaleString, SiteLink, Statement,
+};
+
+/// One part of the entity that both sides changed, differently. `None`
+/// stands for a missing value, so `ours: None` is a removal by `ours`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum MergeConflict {
+    Label {
+        language: String,
+        base: Option<String>,
+        ours: Option<String>,
+        theirs: Option<String>,
+    },
+    Description {
+        language: String,
+        base: Option<String>,
+        ours: Option<String>,
+        theirs: Option<String>,
+    },
+    Sitelink {
+        site: String,
+        base: Option<SiteLink>,
+        ours: Option<SiteLink>,
+        theirs: Option<SiteLink>,
+    },
+    Statement(Box<StatementConflict>),
+    Form(Box<FormConflict>),
+    Sense(Box<SenseConflict>),
+}
+
+/// A statement both sides changed or removed, differently.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct StatementConflict {
+    pub id: String,
+    pub base: Statement,
+    pub ours: Option<Statement>,
+    pub theirs: Option<Statement>,
+}
+
+/// A lexeme form both sides changed or removed, differently. Each version
+/// has all of the form's representations, grammatical features and
+/// statements.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct FormConflict {
+    pub id: String,
+    pub base: FormDiff,
+    pub ours: Option<FormDiff>,
+    pub theirs: Option<FormDiff>,
+}
+
+/// A lexeme sense both sides changed or removed, differently; see
+/// [`FormConflict`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SenseConflict {
+    pub id: String,
+    pub base: SenseDiff,
+    pub ours: Option<SenseDiff>,
+    pub theirs: Option<SenseDiff>,
+}
+
+impl fmt::Display for MergeConflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (subject, ours, theirs) = match self {
+            Self::Label {
+                language,
+                ours,
+                theirs,
+                ..
+            } => (
+                format!("label {language}"),
+                ours.is_some(),
+                theirs.is_some(),
+            ),
+            Self::Description {
+                language,
+                ours,
+                theirs,
+                ..
+            } => (
+                format!("description {language}"),
+                ours.is_some(),
+                theirs.is_some(),
+            ),
+            Self::Sitelink {
+                site, ours, theirs, ..
+            } => (format!("sitelink {site}"), ours.is_some(), theirs.is_some()),
+            Self::Statement(conflict) => (
+                format!("statement {}", conflict.id),
+                conflict.ours.is_some(),
+                conflict.theirs.is_some(),
+            ),
+            Self::Form(conflict) => (
+                format!("form {}", conflict.id),
+                conflict.ours.is_some(),
+                conflict.theirs.is_some(),
+            ),
+            Self::Sense(conflict) => (
+                format!("sense {}", conflict.id),
+                conflict.ours.is_some(),
+                conflict.theirs.is_some(),
+            ),
+        };
+        let verb = |present: bool| if present { "changed" } else { "removed" };
+        write!(
+            f,
+            "{subject}: {} by us, {} by them",
+            verb(ours),
+            verb(theirs)
+        )
+    }
+}
+
+/// The result of [`merge_three_way`]. `diff` serializes as the
+/// `wbeditentity` payload against the live entity.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ThreeWayMerge {
+    pub diff: MergeDiff,
+    pub conflicts: Vec<MergeConflict>,
+}
+
+impl ThreeWayMerge {
+    /// Whether every change of `ours` was carried over without a conflict.
+    pub fn is_clean(&self) -> bool {
+        self.conflicts.is_empty()
+    }
+}
+
+/// Carries the changes from `base` to `ours` over to `theirs`; see the module
+/// documentation.
+pub fn merge_three_way<E: MergeEntity>(base: &E, ours: &E, theirs: &E) -> ThreeWayMerge {
+    let our_changes = ItemDiff::new(base, ours);
+    let their_changes = ItemDiff::new(base, theirs);
+    let mut ret = ThreeWayMerge::default();
+    ret.diff.entity_type = Some(*theirs.entity_type());
+
+    let conflicts = merge_terms(
+        &our_changes.labels,
+        &their_changes.labels,
+        &mut ret.diff.labels,
+        &mut ret.diff.removed_labels,
+    );
+    ret.conflicts
+        .extend(
+            conflicts
+                .into_iter()
+                .map(|(language, base, ours, theirs)| MergeConflict::Label {
+                    language,
+                    base,
+                    ours,
+                    theirs,
+                }),
+        );
+    let conflicts = merge_terms(
+        &our_changes.descriptions,
+        &their_changes.descriptions,
+        &mut ret.diff.descriptions,
+        &mut ret.diff.removed_descriptions,
+    );
+    ret.conflicts
+        .extend(conflicts.into_iter().map(|(language, base, ours, theirs)| {
+            MergeConflict::Description {
+                language,
+                base,
+                ours,
+                theirs,
+            }
+        }));
+    merge_aliases(&our_changes, theirs, &mut ret.diff);
+    merge_sitelinks(&our_changes, &their_changes, base, ours, theirs, &mut ret);
+    let statements = merge_statements(base.claims(), ours.claims(), theirs.claims());
+    ret.diff.altered_statements.extend(statements.changed);
+    ret.diff.removed_statements.extend(statements.removed);
+    ret.conflicts.extend(
+        statements
+            .conflicts
+            .into_iter()
+            .map(|conflict| MergeConflict::Statement(Box::new(conflict))),
+    );
+    let mut additions = ours.clone();
+    E::merge_three_way_specific(base, ours, theirs, &mut additions, &mut ret);
+
+    // Statements new in `ours`, through the merger, on top of the changes
+    // taken so far.
+    let mut merged = theirs.clone();
+    ret.diff.apply(&mut merged);
+    additions.labels_mut().clear();
+    additions.descriptions_mut().clear();
+    additions.aliases_mut().clear();
+    additions.set_sitelinks(None);
+    additions
+        .claims_mut()
+        .retain(|statement| is_new(base.claims(), statement));
+    let mut merger = ItemMerger::new(merged);
+    ret.diff.extend(&merger.merge(&additions));
+    ret
+}
+
+/// A conflicting term, as language and the base, our and their value.
+type TermConflict = (String, Option<String>, Option<String>, Option<String>);
+
+/// Takes the label or description changes of `ours` for languages `theirs`
+/// left alone into `added` and `removed`, and returns the conflicts.
+fn merge_terms(
+    ours: &[TermChange],
+    theirs: &[TermChange],
+    added: &mut Vec<LocaleString>,
+    removed: &mut Vec<LocaleString>,
+) -> Vec<TermConflict> {
+    let mut conflicts = vec![];
+    for change in ours {
+        let language = &change.language;
+        match theirs.iter().find(|t| t.language == *language) {
+            None => match (&change.old, &change.new) {
+                (_, Some(new)) => added.push(LocaleString::new(language, new)),
+                (Some(old), None) => removed.push(LocaleString::new(language, old)),
+                (None, None) => {}
+            },
+            Some(their_change) if their_change.new == change.new => {}
+            Some(their_change) => conflicts.push((
+                language.to_owned(),
+                change.old.to_owned(),
+                change.new.to_owned(),
+                their_change.new.to_owned(),
+            )),
+        }
+    }
+    conflicts
+}
+
+/// Adds the aliases `ours` added and `theirs` lacks, and removes those
+/// `ours` removed and `theirs` still has.
+fn merge_aliases(our_changes: &ItemDiff, theirs: &impl EntityTrait, diff: &mut MergeDiff) {
+    for change in &our_changes.aliases {
+        let alias = |value: &String| LocaleString::new(&change.language, value);
+        diff.aliases.extend(
+            change
+                .added
+                .iter()
+                .map(alias)
+                .filter(|a| !theirs.aliases().contains(a)),
+        );
+        diff.removed_aliases.extend(
+            change
+                .removed
+                .iter()
+                .map(alias)
+                .filter(|a| theirs.aliases().contains(a)),
+        );
+    }
+}
+
+fn merge_sitelinks(
+    our_changes: &ItemDiff,
+    their_changes: &ItemDiff,
+    base: &impl EntityTrait,
+    ours: &impl EntityTrait,
+    theirs: &impl EntityTrait,
+    ret: &mut ThreeWayMerge,
+) {
+    let find = |entity: &dyn EntityTrait, site: &str| {
+        entity
+            .sitelinks()
+            .as_ref()
+            .and_then(|sitelinks| sitelinks.iter().find(|s| s.site() == site))
+            .cloned()
+    };
+    for change in &our_changes.sitelinks {
+        let site = &change.site;
+        let (our_sitelink, their_sitelink) = (find(ours, site), find(theirs, site));
+        if !their_changes.sitelinks.iter().any(|t| t.site == *site) {
+            match (our_sitelink, their_sitelink) {
+                (Some(sitelink), _) => ret.diff.sitelinks.push(sitelink),
+                (None, Some(sitelink)) => ret.diff.removed_sitelinks.push(sitelink),
+                (None, None) => {}
+            }
+        } else if our_sitelink != their_sitelink {
+            ret.conflicts.push(MergeConflict::Sitelink {
+                site: site.to_owned(),
+                base: find(base, site),
+                ours: our_sitelink,
+                theirs: their_sitelink,
+            });
+        }
+    }
+}
+
+/// What [`merge_statements`] takes from `ours`, by statement ID, and the
+/// statements both sides changed differently.
+#[derive(Default)]
+struct StatementMerge {
+    changed: Vec<(String, Statement)>,
+    removed: Vec<(String, Statement)>,
+    conflicts: Vec<StatementConflict>,
+}
+
+/// Takes the changes and removals of `ours` to statements of `base` that
+/// `theirs` left alone.
+fn merge_statements(
+    base: &[Statement],
+    ours: &[Statement],
+    theirs: &[Statement],
+) -> StatementMerge {
+    let mut ret = StatementMerge::default();
+    for statement in base {
+        let Some(id) = statement.id() else {
+            continue;
+        };
+        let our_statement = find_statement(ours, &id);
+        let their_statement = find_statement(theirs, &id);
+        if !is_touched(statement, our_statement) {
+            continue;
+        }
+        if !is_touched(statement, their_statement) {
+            match our_statement {
+                Some(our_statement) => ret.changed.push((id, our_statement.to_owned())),
+                None => ret.removed.push((id, statement.to_owned())),
+            }
+        } else if !is_same_outcome(our_statement, their_statement) {
+            ret.conflicts.push(StatementConflict {
+                id,
+                base: statement.to_owned(),
+                ours: our_statement.cloned(),
+                theirs: their_statement.cloned(),
+            });
+        }
+    }
+    ret
+}
+
+/// Takes the changes and removals of `ours` to forms and senses of `base`
+/// that `theirs` left alone or changed compatibly; see the module
+/// documentation. Forms and senses new in `ours` are left to the merger.
+pub(crate) fn merge_forms_and_senses(
+    base: &LexemeEntity,
+    ours: &LexemeEntity,
+    theirs: &LexemeEntity,
+    ret: &mut ThreeWayMerge,
+) {
+    for form in base.forms().iter().filter(|f| !f.id().is_empty()) {
+        let our_form = ours.forms().iter().find(|f| f.id() == form.id());
+        let their_form = theirs.forms().iter().find(|f| f.id() == form.id());
+        let merged = match (our_form, their_form) {
+            (Some(our_form), _) if !is_form_changed(form, our_form) => continue,
+            (Some(our_form), Some(their_form)) => merge_form(form, our_form, their_form),
+            (None, Some(their_form)) if !is_form_changed(form, their_form) => {
+                ret.diff.removed_forms.push(form.id().to_owned());
+                continue;
+            }
+            (None, None) => continue,
+            (_, _) => None,
+        };
+        match merged {
+            Some(form_diff) => ret.diff.forms.extend(form_diff),
+            None => ret
+                .conflicts
+                .push(MergeConflict::Form(Box::new(FormConflict {
+                    id: form.id().to_owned(),
+                    base: form_version(form),
+                    ours: our_form.map(form_version),
+                    theirs: their_form.map(form_version),
+                }))),
+        }
+    }
+
+    for sense in base.senses().iter().filter(|s| !s.id().is_empty()) {
+        let our_sense = ours.senses().iter().find(|s| s.id() == sense.id());
+        let their_sense = theirs.senses().iter().find(|s| s.id() == sense.id());
+        let merged = match (our_sense, their_sense) {
+            (Some(our_sense), _) if !is_sense_changed(sense, our_sense) => continue,
+            (Some(our_sense), Some(their_sense)) => merge_sense(sense, our_sense, their_sense),
+            (None, Some(their_sense)) if !is_sense_changed(sense, their_sense) => {
+                ret.diff.removed_senses.push(sense.id().to_owned());
+                continue;
+            }
+            (None, None) => continue,
+            (_, _) => None,
+        };
+        match merged {
+            Some(sense_diff) => ret.diff.senses.extend(sense_diff),
+            None => ret
+                .conflicts
+                .push(MergeConflict::Sense(Box::new(SenseConflict {
+                    id: sense.id().to_owned(),
+                    base: sense_version(sense),
+                    ours: our_sense.map(sense_version),
+                    theirs: their_sense.map(sense_version),
+                }))),
+        }
+    }
+}
+
+/// The changes of `ours` to a form of `base` that `theirs` also has, as a
+/// diff against `theirs`: `None` if the two clash, `Some(None)` if there is
+/// nothing to take.
+fn merge_form(
+    base: &LexemeForm,
+    ours: &LexemeForm,
+    theirs: &LexemeForm,
+) -> Option<Option<FormDiff>> {
+    let mut diff = FormDiff {
+        id: Some(base.id().to_owned()),
+        ..Default::default()
+    };
+    let clashes = merge_terms(
+        &term_changes(base.representations(), ours.representations()),
+        &term_changes(base.representations(), theirs.representations()),
+        &mut diff.representations,
+        &mut diff.removed_representations,
+    );
+    if !clashes.is_empty() {
+        return None;
+    }
+    let (base_features, our_features, their_features) =
+        (feature_set(base), feature_set(ours), feature_set(theirs));
+    let take_features = our_features != base_features && our_features != their_features;
+    if take_features && their_features != base_features {
+        return None;
+    }
+    let features = if take_features { ours } else { theirs };
+    diff.grammatical_features = feature_ids(features);
+    (diff.claims, diff.removed_claims) =
+        merge_part_statements(base.claims(), ours.claims(), theirs.claims())?;
+    let unchanged = !take_features
+        && diff.representations.is_empty()
+        && diff.removed_representations.is_empty()
+        && diff.claims.is_empty()
+        && diff.removed_claims.is_empty();
+    Some((!unchanged).then_some(diff))
+}
+
+/// The changes of `ours` to a sense of `base`; see [`merge_form`].
+fn merge_sense(
+    base: &LexemeSense,
+    ours: &LexemeSense,
+    theirs: &LexemeSense,
+) -> Option<Option<SenseDiff>> {
+    let mut diff = SenseDiff {
+        id: Some(base.id().to_owned()),
+        ..Default::default()
+    };
+    let clashes = merge_terms(
+        &term_changes(base.glosses(), ours.glosses()),
+        &term_changes(base.glosses(), theirs.glosses()),
+        &mut diff.glosses,
+        &mut diff.removed_glosses,
+    );
+    if !clashes.is_empty() {
+        return None;
+    }
+    (diff.claims, diff.removed_claims) =
+        merge_part_statements(base.claims(), ours.claims(), theirs.claims())?;
+    let unchanged = diff.glosses.is_empty()
+        && diff.removed_glosses.is_empty()
+        && diff.claims.is_empty()
+        && diff.removed_claims.is_empty();
+    Some((!unchanged).then_some(diff))
+}
+
+/// The statements of a form or sense to change or add, and to remove, or
+/// `None` if both sides changed one differently. New statements of `ours`
+/// are added unless `theirs` has the same.
+fn merge_part_statements(
+    base: &[Statement],
+    ours: &[Statement],
+    theirs: &[Statement],
+) -> Option<(Vec<Statement>, Vec<Statement>)> {
+    let merge = merge_statements(base, ours, theirs);
+    if !merge.conflicts.is_empty() {
+        return None;
+    }
+    let mut changed: Vec<Statement> = merge.changed.into_iter().map(|(_, s)| s).collect();
+    changed.extend(
+        ours.iter()
+            .filter(|statement| is_new(base, statement))
+            .filter(|statement| {
+                !theirs
+                    .iter()
+                    .any(|s| StatementChange::changed(s, statement).is_none())
+            })
+            .cloned(),
+    );
+    Some((changed, merge.removed.into_iter().map(|(_, s)| s).collect()))
+}
+
+fn is_form_changed(old: &LexemeForm, new: &LexemeForm) -> bool {
+    !term_changes(old.representations(), new.representations()).is_empty()
+        || feature_set(old) != feature_set(new)
+        || !statement_changes(old.claims(), new.claims()).is_empty()
+}
+
+fn is_sense_changed(old: &LexemeSense, new: &LexemeSense) -> bool {
+    !term_changes(old.glosses(), new.glosses()).is_empty()
+        || !statement_changes(old.claims(), new.claims()).is_empty()
+}
+
+/// The grammatical features of `form`, as item IDs.
+fn feature_ids(form: &LexemeForm) -> Vec<String> {
+    form.grammatical_features()
+        .iter()
+        .map(|f| f.id().to_string())
+        .collect()
+}
+
+/// The grammatical features of `form`, for comparing regardless of order.
+fn feature_set(form: &LexemeForm) -> BTreeSet<String> {
+    feature_ids(form).into_iter().collect()
+}
+
+/// `form` in full, for a [`FormConflict`].
+fn form_version(form: &LexemeForm) -> FormDiff {
+    FormDiff {
+        id: Some(form.id().to_owned()),
+        representations: form.representations().to_owned(),
+        grammatical_features: feature_ids(form),
+        claims: form.claims().to_owned(),
+        ..Default::default()
+    }
+}
+
+fn sense_version(sense: &LexemeSense) -> SenseDiff {
+    SenseDiff {
+        id: Some(sense.id().to_owned()),
+        glosses: sense.glosses().to_owned(),
+        claims: sense.claims().to_owned(),
+        ..Default::default()
+    }
+}
+
+/// Whether `statement` is not one of `base`, by ID.
+fn is_new(base: &[Statement], statement: &Statement) -> bool {
+    statement
+        .id()
+        .is_none_or(|id| find_statement(base, &id).is_none())
+}
+
+fn find_statement<'a>(statements: &'a [Statement], id: &str) -> Option<&'a Statement> {
+    statements
+        .iter()
+        .find(|s| s.id().is_some_and(|other| other == id))
+}
+
+/// Whether `statement` was changed or, as `None`, removed.
+fn is_touched(original: &Statement, statement: Option<&Statement>) -> bool {
+    statement.is_none_or(|s| StatementChange::changed(original, s).is_some())
+}
+
+fn is_same_outcome(ours: Option<&Statement>, theirs: Option<&Statement>) -> bool {
+    match (ours, theirs) {
+        (Some(ours), Some(theirs)) => StatementChange::changed(ours, theirs).is_none(),
+        (ours, theirs) => ours.is_none() && theirs.is_none(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wikibase::{
+        EntityType, EntityValue, ItemEntity, Reference, Snak, SnakDataType, SnakType, StatementRank,
+    };
+
+    fn statement(id: &str, snak: Snak) -> Statement {
+        let mut ret = Statement::new_normal(snak, vec![], vec![]);
+        ret.set_id(id);
+        ret
+    }
+
+    fn base_item() -> ItemEntity {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut().push(LocaleString::new("en", "Foo"));
+        item.labels_mut().push(LocaleString::new("de", "Foo"));
+        item.add_claim(statement("Q1$1", Snak::new_item("P31", "Q5")));
+        item.add_claim(statement("Q1$2", Snak::new_string("P1476", "Title")));
+        item.add_claim(statement("Q1$3", Snak::new_item("P27", "Q183")));
+        item
+    }
+
+    fn find<'a>(item: &'a ItemEntity, id: &str) -> Option<&'a Statement> {
+        find_statement(item.claims(), id)
+    }
+
+    fn form(id: &str, representations: &[(&str, &str)], feature: &str) -> LexemeForm {
+        LexemeForm::new(
+            id.to_string(),
+            representations
+                .iter()
+                .map(|(language, value)| LocaleString::new(*language, *value))
+                .collect(),
+            vec![EntityValue::new(EntityType::Item, feature)],
+            vec![],
+        )
+    }
+
+    fn base_lexeme() -> LexemeEntity {
+        let mut lexeme = LexemeEntity::new_empty();
+        let mut goose = form("L1-F1", &[("en", "goose"), ("en-x-Q1", "gos")], "Q110786");
+        goose
+            .claims_mut()
+            .push(statement("L1-F1$1", Snak::new_string("P898", "ɡus")));
+        lexeme.forms_mut().push(goose);
+        lexeme
+            .forms_mut()
+            .push(form("L1-F2", &[("en", "geese")], "Q146786"));
+        lexeme.senses_mut().push(LexemeSense::new(
+            "L1-S1".to_string(),
+            vec![
+                LocaleString::new("en", "bird"),
+                LocaleString::new("de", "Vogel"),
+            ],
+            vec![],
+        ));
+        lexeme
+    }
+
+    #[test]
+    fn test_non_overlapping_changes_are_merged() {
+        let base = base_item();
+        let mut ours = base.clone();
+        ours.labels_mut()[0] = LocaleString::new("en", "Bar");
+        ours.claims_mut()[0] = statement("Q1$1", Snak::new_item("P31", "Q215627"));
+        ours.claims_mut()
+            .retain(|s| s.id().as_deref() != Some("Q1$3"));
+        ours.add_claim(Statement::new_normal(
+            Snak::new_item("P21", "Q6581097"),
+            vec![],
+            vec![],
+        ));
+        let mut theirs = base.clone();
+        theirs.labels_mut()[1] = LocaleString::new("de", "Baz");
+        theirs.claims_mut()[1].set_rank(StatementRank::Preferred);
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        let mut live = theirs.clone();
+        merge.diff.apply(&mut live);
+        assert_eq!(live.label_in_locale("en"), Some("Bar"));
+        assert_eq!(live.label_in_locale("de"), Some("Baz"));
+        assert_eq!(
+            find(&live, "Q1$1").map(|s| s.main_snak().to_owned()),
+            Some(Snak::new_item("P31", "Q215627"))
+        );
+        // Their rank change survives, since we left the statement alone.
+        assert_eq!(
+            find(&live, "Q1$2").map(|s| *s.rank()),
+            Some(StatementRank::Preferred)
+        );
+        assert!(find(&live, "Q1$3").is_none());
+        assert!(live.claims().iter().any(|s| s.property() == "P21"));
+        assert!(!merge.diff.altered_statements.contains_key("Q1$2"));
+    }
+
+    #[test]
+    fn test_statement_both_sides_touched_is_a_conflict() {
+        let base = base_item();
+        let mut ours = base.clone();
+        ours.claims_mut()[0] = statement("Q1$1", Snak::new_item("P31", "Q215627"));
+        ours.claims_mut()
+            .retain(|s| s.id().as_deref() != Some("Q1$2"));
+        let mut theirs = base.clone();
+        theirs.claims_mut()[0] = statement("Q1$1", Snak::new_item("P31", "Q43229"));
+        theirs.claims_mut()[1].set_references(vec![Reference::new(vec![Snak::new_url(
+            "P854",
+            "https://example.org",
+        )])]);
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.diff.altered_statements.is_empty());
+        assert!(merge.diff.removed_statements.is_empty());
+        let lines: Vec<String> = merge.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "statement Q1$1: changed by us, changed by them",
+                "statement Q1$2: removed by us, changed by them",
+            ]
+        );
+        let MergeConflict::Statement(conflict) = &merge.conflicts[0] else {
+            panic!("expected a statement conflict");
+        };
+        assert_eq!(*conflict.base.main_snak(), Snak::new_item("P31", "Q5"));
+        assert_eq!(
+            conflict.ours.as_ref().map(|s| s.main_snak().to_owned()),
+            Some(Snak::new_item("P31", "Q215627"))
+        );
+    }
+
+    #[test]
+    fn test_snak_type_only_change_is_kept() {
+        let qualifier = |snak_type| Snak::new(SnakDataType::String, "P1810", snak_type, None);
+        let mut base = base_item();
+        base.claims_mut()[1].set_qualifier_snaks(vec![qualifier(SnakType::NoValue)]);
+        let mut ours = base.clone();
+        ours.claims_mut()[1].set_qualifier_snaks(vec![qualifier(SnakType::UnknownValue)]);
+
+        let merge = merge_three_way(&base, &ours, &base);
+        assert!(merge.is_clean());
+        assert_eq!(
+            merge.diff.altered_statements["Q1$2"].qualifiers(),
+            &vec![qualifier(SnakType::UnknownValue)]
+        );
+
+        // Against a change of theirs, it is a conflict rather than lost.
+        let mut theirs = base.clone();
+        theirs.claims_mut()[1].set_rank(StatementRank::Preferred);
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert_eq!(merge.conflicts.len(), 1);
+    }
+
+    #[test]
+    fn test_same_change_on_both_sides_is_not_a_conflict() {
+        let base = base_item();
+        let mut ours = base.clone();
+        ours.labels_mut()[0] = LocaleString::new("en", "Bar");
+        ours.claims_mut()
+            .retain(|s| s.id().as_deref() != Some("Q1$3"));
+        let theirs = ours.clone();
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        assert!(merge.diff.is_empty());
+    }
+
+    #[test]
+    fn test_term_and_sitelink_conflicts() {
+        let mut base = base_item();
+        base.set_sitelinks(Some(vec![SiteLink::new("enwiki", "Foo", vec![])]));
+        let mut ours = base.clone();
+        ours.labels_mut()[0] = LocaleString::new("en", "Bar");
+        ours.set_sitelinks(Some(vec![SiteLink::new("enwiki", "Foo (band)", vec![])]));
+        let mut theirs = base.clone();
+        theirs.labels_mut()[0] = LocaleString::new("en", "Baz");
+        theirs.set_sitelinks(None);
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.diff.is_empty());
+        let lines: Vec<String> = merge.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "label en: changed by us, changed by them",
+                "sitelink enwiki: changed by us, removed by them",
+            ]
+        );
+        let json = serde_json::json!(merge.conflicts[0]);
+        assert_eq!(json["kind"], "label");
+        assert_eq!(json["theirs"], "Baz");
+    }
+
+    #[test]
+    fn test_new_statements_fold_into_live_ones() {
+        let base = base_item();
+        let reference = Reference::new(vec![Snak::new_url("P854", "https://example.org")]);
+        let mut ours = base.clone();
+        ours.add_claim(Statement::new_normal(
+            Snak::new_item("P106", "Q36180"),
+            vec![],
+            vec![reference.clone()],
+        ));
+        let mut theirs = base.clone();
+        theirs.add_claim(statement("Q1$4", Snak::new_item("P106", "Q36180")));
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        assert!(merge.diff.added_statements.is_empty());
+        assert_eq!(
+            merge.diff.altered_statements["Q1$4"].references(),
+            &vec![reference]
+        );
+    }
+
+    #[test]
+    fn test_forms_and_senses_are_merged_by_id() {
+        let base = base_lexeme();
+        let mut ours = base.clone();
+        let goose = &mut ours.forms_mut()[0];
+        goose.representations_mut().retain(|r| r.language() == "en");
+        goose.claims_mut()[0] = statement("L1-F1$1", Snak::new_string("P898", "ɡuːs"));
+        ours.forms_mut()
+            .push(form("", &[("en", "gooses")], "Q146786"));
+        let gloss = &mut ours.senses_mut()[0];
+        gloss.glosses_mut().retain(|g| g.language() == "en");
+        gloss.glosses_mut().push(LocaleString::new("fr", "oie"));
+        let mut theirs = base.clone();
+        theirs.forms_mut().retain(|f| f.id() != "L1-F2");
+        theirs.senses_mut()[0]
+            .glosses_mut()
+            .push(LocaleString::new("es", "ganso"));
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        assert!(merge.is_clean());
+        // The form they removed is not added back.
+        assert!(merge
+            .diff
+            .forms
+            .iter()
+            .all(|f| f.representations != vec![LocaleString::new("en", "geese")]));
+        assert_eq!(merge.diff.forms.len(), 2);
+        let json = serde_json::json!(merge.diff);
+        assert_eq!(json["forms"][0]["id"], "L1-F1");
+        assert_eq!(
+            json["forms"][0]["representations"]["en-x-Q1"],
+            serde_json::json!({"language": "en-x-Q1", "remove": ""})
+        );
+        assert_eq!(
+            json["senses"][0]["glosses"]["de"],
+            serde_json::json!({"language": "de", "remove": ""})
+        );
+
+        let mut live = theirs.clone();
+        merge.diff.apply(&mut live);
+        assert_eq!(live.forms().len(), 2);
+        let goose = &live.forms()[0];
+        assert_eq!(
+            goose.representations(),
+            &vec![LocaleString::new("en", "goose")]
+        );
+        assert_eq!(
+            *goose.claims()[0].main_snak(),
+            Snak::new_string("P898", "ɡuːs")
+        );
+        assert_eq!(
+            live.forms()[1].representations(),
+            &vec![LocaleString::new("en", "gooses")]
+        );
+        let glosses: Vec<&str> = live.senses()[0]
+            .glosses()
+            .iter()
+            .map(|g| g.language())
+            .collect();
+        assert_eq!(glosses, vec!["en", "es", "fr"]);
+    }
+
+    #[test]
+    fn test_removed_form_and_clashing_gloss_are_conflicts() {
+        let base = base_lexeme();
+        let mut ours = base.clone();
+        ours.forms_mut()[1]
+            .representations_mut()
+            .push(LocaleString::new("de", "Gänse"));
+        ours.forms_mut().retain(|f| f.id() != "L1-F1");
+        ours.senses_mut()[0].glosses_mut()[0] = LocaleString::new("en", "waterfowl");
+        let mut theirs = base.clone();
+        theirs.forms_mut().retain(|f| f.id() != "L1-F2");
+        theirs.senses_mut()[0].glosses_mut()[0] = LocaleString::new("en", "large bird");
+
+        let merge = merge_three_way(&base, &ours, &theirs);
+        // Our removal of the form they left alone is taken.
+        assert_eq!(merge.diff.removed_forms, vec!["L1-F1".to_string()]);
+        assert_eq!(
+            serde_json::json!(merge.diff)["forms"],
+            serde_json::json!([{"id": "L1-F1", "remove": ""}])
+        );
+        assert!(merge.diff.forms.is_empty());
+        assert!(merge.diff.senses.is_empty());
+        let lines: Vec<String> = merge.conflicts.iter().map(|c| c.to_string()).collect();
+        assert_eq!(
+            lines,
+            vec![
+                "form L1-F2: changed by us, removed by them",
+                "sense L1-S1: changed by us, changed by them",
+            ]
+        );
+        let MergeConflict::Form(conflict) = &merge.conflicts[0] else {
+            panic!("expected a form conflict");
+        };
+        assert_eq!(conflict.base.representations.len(), 1);
+        assert_eq!(
+            conflict.ours.as_ref().map(|f| f.representations.len()),
+            Some(2)
+        );
+    }
+}
@@ -0,0 +1,786 @@
+//! Per-property metadata: datatype, formatter URL, format regex, and the
+//! single-value, distinct-values, allowed-qualifiers and allowed-units
+//! constraints.
+//!
+//! Snaks from third-party JSON, or built by hand, often carry no datatype or a
+//! guessed one. A [`PropertyRegistry`] knows the real datatype of each
+//! property, so [`PropertyRegistry::classify_item`] can fix them up, and
+//! merging and export can rely on them: `ItemMerger` classifies every claim it
+//! adds once a registry is set, and `Wikidata::item2qs_export_with` reports
+//! values that do not fit their property.
+//!
+//! A registry can be filled by hand, read from a JSON snapshot written by
+//! [`PropertyRegistry::to_json_writer`], built from a SPARQL result
+//! ([`PropertyRegistry::SPARQL_QUERY`], with `sparql-table`), or loaded from
+//! the property entities themselves (with `wikidata`).
+
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::io::{Read, Write};
+use std::str::FromStr;
+use thiserror::Error;
+use wikibase::{EntityTrait, PropertyEntity, Reference, Snak, SnakDataType, Statement, Value};
+
+/// "formatter URL"
+const FORMATTER_URL: &str = "P1630";
+/// "format as a regular expression"
+const FORMAT_REGEX: &str = "P1793";
+/// "property constraint"
+const PROPERTY_CONSTRAINT: &str = "P2302";
+/// "single-value constraint"
+const SINGLE_VALUE_CONSTRAINT: &str = "Q19474404";
+/// "distinct-values constraint"
+const DISTINCT_VALUES_CONSTRAINT: &str = "Q21502410";
+/// "allowed qualifiers constraint"
+const ALLOWED_QUALIFIERS_CONSTRAINT: &str = "Q21510851";
+/// "allowed units constraint"
+const ALLOWED_UNITS_CONSTRAINT: &str = "Q21514353";
+/// "property" qualifier of a constraint statement
+const CONSTRAINT_PROPERTY: &str = "P2306";
+/// "item of property constraint" qualifier of a constraint statement
+const CONSTRAINT_ITEM: &str = "P2305";
+/// How [`PropertyInfo::allowed_units`] spells "no unit"; also the unit a
+/// unitless quantity carries.
+pub const NO_UNIT: &str = "1";
+#[cfg(feature = "sparql-table")]
+const ONTOLOGY_PREFIX: &str = "http://wikiba.se/ontology#";
+
+/// `wikibase:propertyType` local names and the datatype names they stand for.
+#[cfg(feature = "sparql-table")]
+const ONTOLOGY_TYPES: &[(&str, &str)] = &[
+    ("CommonsMedia", "commonsMedia"),
+    ("EntitySchema", "entity-schema"),
+    ("ExternalId", "external-id"),
+    ("GeoShape", "geo-shape"),
+    ("GlobeCoordinate", "globe-coordinate"),
+    ("Math", "math"),
+    ("Monolingualtext", "monolingualtext"),
+    ("MusicalNotation", "musical-notation"),
+    ("Quantity", "quantity"),
+    ("String", "string"),
+    ("TabularData", "tabular-data"),
+    ("Time", "time"),
+    ("Url", "url"),
+    ("WikibaseForm", "wikibase-form"),
+    ("WikibaseItem", "wikibase-item"),
+    ("WikibaseLexeme", "wikibase-lexeme"),
+    ("WikibaseProperty", "wikibase-property"),
+    ("WikibaseSense", "wikibase-sense"),
+];
+
+/// Failure modes of reading and writing a registry snapshot.
+#[derive(Debug, Error)]
+pub enum PropertyRegistryError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// The snapshot is not valid registry JSON.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+}
+
+/// What the registry knows about one property.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PropertyInfo {
+    #[serde(
+        default,
+        deserialize_with = "deserialize_datatype",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub datatype: Option<SnakDataType>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub formatter_url: Option<String>,
+    /// The whole value must match; the pattern is anchored when used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub format_regex: Option<String>,
+    #[serde(default)]
+    pub single_value: bool,
+    #[serde(default)]
+    pub distinct_values: bool,
+    /// Properties that may be used as qualifiers; `None` allows any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_qualifiers: Option<Vec<String>>,
+    /// Item IDs of the units a quantity may have, [`NO_UNIT`] for none;
+    /// `None` allows any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_units: Option<Vec<String>>,
+}
+
+/// The values of the `qualifier` qualifiers of a constraint statement, as
+/// entity IDs; a `novalue` snak stands for [`NO_UNIT`].
+fn constraint_parameters<'a>(
+    statement: &'a Statement,
+    qualifier: &'a str,
+) -> impl Iterator<Item = String> + 'a {
+    statement
+        .qualifiers()
+        .iter()
+        .filter(move |snak| snak.property() == qualifier)
+        .filter_map(|snak| match snak.snak_type() {
+            wikibase::SnakType::NoValue => Some(NO_UNIT.to_string()),
+            _ => match snak.data_value().as_ref()?.value() {
+                Value::Entity(e) => Some(e.id().to_string()),
+                _ => None,
+            },
+        })
+}
+
+fn deserialize_datatype<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<SnakDataType>, D::Error> {
+    match Option::<String>::deserialize(deserializer)? {
+        Some(name) => SnakDataType::from_str(&name)
+            .map(Some)
+            .map_err(serde::de::Error::custom),
+        None => Ok(None),
+    }
+}
+
+impl PropertyInfo {
+    /// Reads datatype, formatter URL, format regex and constraints off a
+    /// property entity. Deprecated statements are ignored.
+    pub fn from_property_entity(property: &PropertyEntity) -> Self {
+        let mut info = Self {
+            datatype: *property.datatype(),
+            ..Self::default()
+        };
+        for statement in property.claims() {
+            if *statement.rank() == wikibase::StatementRank::Deprecated {
+                continue;
+            }
+            let value = statement
+                .main_snak()
+                .data_value()
+                .as_ref()
+                .map(|dv| dv.value());
+            match (statement.property(), value) {
+                (FORMATTER_URL, Some(Value::StringValue(s))) if info.formatter_url.is_none() => {
+                    info.formatter_url = Some(s.to_owned());
+                }
+                (FORMAT_REGEX, Some(Value::StringValue(s))) if info.format_regex.is_none() => {
+                    info.format_regex = Some(s.to_owned());
+                }
+                (PROPERTY_CONSTRAINT, Some(Value::Entity(e))) => match e.id() {
+                    SINGLE_VALUE_CONSTRAINT => info.single_value = true,
+                    DISTINCT_VALUES_CONSTRAINT => info.distinct_values = true,
+                    ALLOWED_QUALIFIERS_CONSTRAINT => info
+                        .allowed_qualifiers
+                        .get_or_insert_with(Vec::new)
+                        .extend(constraint_parameters(statement, CONSTRAINT_PROPERTY)),
+                    ALLOWED_UNITS_CONSTRAINT => info
+                        .allowed_units
+                        .get_or_insert_with(Vec::new)
+                        .extend(constraint_parameters(statement, CONSTRAINT_ITEM)),
+                    _ => {}
+                },
+                _ => {}
+            }
+        }
+        info
+    }
+
+    /// Whether `property` may qualify a statement of this property.
+    pub fn allows_qualifier(&self, property: &str) -> bool {
+        self.allowed_qualifiers
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|p| p == property))
+    }
+
+    /// Whether a quantity with the unit `unit` (an entity URI or
+    /// [`NO_UNIT`]) fits this property.
+    pub fn allows_unit(&self, unit: &str) -> bool {
+        let unit = unit.rsplit('/').next().unwrap_or(unit);
+        self.allowed_units
+            .as_ref()
+            .is_none_or(|allowed| allowed.iter().any(|u| u == unit))
+    }
+
+    /// The URL for `value` built from the formatter URL.
+    pub fn format_url(&self, value: &str) -> Option<String> {
+        Some(self.formatter_url.as_ref()?.replace("$1", value))
+    }
+}
+
+/// Property metadata keyed by property ID (`P214`).
+#[derive(Debug, Clone, Default)]
+pub struct PropertyRegistry {
+    properties: HashMap<String, PropertyInfo>,
+    /// Anchored, compiled `format_regex` of each property that has a valid one.
+    format_regexes: HashMap<String, Regex>,
+}
+
+impl PropertyRegistry {
+    /// Lists datatype, formatter URL, format regex, and single-value,
+    /// distinct-values, allowed-qualifiers and allowed-units constraints of
+    /// every property, for [`PropertyRegistry::from_sparql_table`]. A property
+    /// appears once per combination of values; `?qualifier` and `?unit` are
+    /// the parameters of the last two constraints, with `"1"` standing for
+    /// "no unit". Deprecated constraints are left out.
+    pub const SPARQL_QUERY: &'static str =
+        "SELECT ?property ?type ?formatter ?regex ?constraint ?qualifier ?unit {
+  ?property a wikibase:Property ; wikibase:propertyType ?type .
+  OPTIONAL { ?property wdt:P1630 ?formatter }
+  OPTIONAL { ?property wdt:P1793 ?regex }
+  OPTIONAL {
+    ?property p:P2302 ?statement .
+    ?statement ps:P2302 ?constraint .
+    FILTER(?constraint IN (wd:Q19474404, wd:Q21502410, wd:Q21510851, wd:Q21514353))
+    FILTER NOT EXISTS { ?statement wikibase:rank wikibase:DeprecatedRank }
+    OPTIONAL { ?statement pq:P2306 ?qualifier }
+    OPTIONAL {
+      { ?statement pq:P2305 ?unit } UNION { ?statement a wdno:P2305 BIND(\"1\" AS ?unit) }
+    }
+  }
+}";
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds or replaces what is known about `property`.
+    pub fn insert(&mut self, property: &str, info: PropertyInfo) {
+        let regex = info
+            .format_regex
+            .as_ref()
+            .and_then(|re| Regex::new(&format!("^(?:{re})$")).ok());
+        match regex {
+            Some(regex) => self.format_regexes.insert(property.to_string(), regex),
+            None => self.format_regexes.remove(property),
+        };
+        self.properties.insert(property.to_string(), info);
+    }
+
+    pub fn get(&self, property: &str) -> Option<&PropertyInfo> {
+        self.properties.get(property)
+    }
+
+    pub fn datatype(&self, property: &str) -> Option<SnakDataType> {
+        self.get(property)?.datatype
+    }
+
+    pub fn len(&self) -> usize {
+        self.properties.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.properties.is_empty()
+    }
+
+    /// Whether `value` matches the format regex of `property`; `None` if the
+    /// property has no usable one.
+    pub fn matches_format(&self, property: &str, value: &str) -> Option<bool> {
+        Some(self.format_regexes.get(property)?.is_match(value))
+    }
+
+    /// Why `value` does not fit `property`: a value type other than its
+    /// datatype calls for, or a string that fails its format regex.
+    pub fn value_problem(&self, property: &str, value: &Value) -> Option<String> {
+        let datatype = self.datatype(property)?;
+        let fits = match datatype {
+            SnakDataType::WikibaseItem
+            | SnakDataType::WikibaseProperty
+            | SnakDataType::WikibaseLexeme
+            | SnakDataType::WikibaseForm
+            | SnakDataType::WikibaseSense => matches!(value, Value::Entity(_)),
+            SnakDataType::EntitySchema => {
+                matches!(value, Value::EntitySchema(_) | Value::Entity(_))
+            }
+            SnakDataType::String
+            | SnakDataType::ExternalId
+            | SnakDataType::Url
+            | SnakDataType::CommonsMedia
+            | SnakDataType::Math
+            | SnakDataType::TabularData
+            | SnakDataType::MusicalNotation
+            | SnakDataType::GeoShape => matches!(value, Value::StringValue(_)),
+            SnakDataType::Time => matches!(value, Value::Time(_)),
+            SnakDataType::GlobeCoordinate => matches!(value, Value::Coordinate(_)),
+            SnakDataType::MonolingualText => matches!(value, Value::MonoLingual(_)),
+            SnakDataType::Quantity => matches!(value, Value::Quantity(_)),
+            SnakDataType::NotSet | SnakDataType::NoValue | SnakDataType::SomeValue => true,
+        };
+        if !fits {
+            let name = serde_json::to_value(datatype).ok()?;
+            return Some(format!("value does not fit datatype {}", name.as_str()?));
+        }
+        match value {
+            Value::StringValue(s) if self.matches_format(property, s) == Some(false) => {
+                Some(format!("{s:?} does not match the format regex"))
+            }
+            _ => None,
+        }
+    }
+
+    /// Sets the datatype of `snak` to that of its property, if known.
+    /// Returns whether it changed.
+    pub fn classify_snak(&self, snak: &mut Snak) -> bool {
+        match self.datatype(snak.property()) {
+            Some(datatype) if *snak.datatype() != datatype => {
+                snak.set_datatype(datatype);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Classifies the main snak, qualifiers and reference snaks of
+    /// `statement`; see [`PropertyRegistry::classify_snak`].
+    pub fn classify_statement(&self, statement: &mut Statement) {
+        let mut main_snak = statement.main_snak().to_owned();
+        if self.classify_snak(&mut main_snak) {
+            statement.set_main_snak(main_snak);
+        }
+        let mut qualifiers = statement.qualifiers().to_owned();
+        if qualifiers.iter_mut().any(|q| self.classify_snak(q)) {
+            qualifiers.iter_mut().for_each(|q| {
+                self.classify_snak(q);
+            });
+            statement.set_qualifier_snaks(qualifiers);
+        }
+        let references: Vec<Reference> = statement
+            .references()
+            .iter()
+            .map(|reference| {
+                let mut snaks = reference.snaks().to_owned();
+                snaks.iter_mut().for_each(|s| {
+                    self.classify_snak(s);
+                });
+                Reference::new(snaks)
+            })
+            .collect();
+        if references != *statement.references() {
+            statement.set_references(references);
+        }
+    }
+
+    /// Classifies every statement of `entity`.
+    pub fn classify_item(&self, entity: &mut impl EntityTrait) {
+        for statement in entity.claims_mut() {
+            self.classify_statement(statement);
+        }
+    }
+
+    /// Reads a snapshot written by [`PropertyRegistry::to_json_writer`]: an
+    /// object from property ID to [`PropertyInfo`].
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, PropertyRegistryError> {
+        let properties: HashMap<String, PropertyInfo> = serde_json::from_reader(reader)?;
+        let mut ret = Self::new();
+        for (property, info) in properties {
+            ret.insert(&property, info);
+        }
+        Ok(ret)
+    }
+
+    /// Writes the registry as JSON, sorted by property ID.
+    pub fn to_json_writer<W: Write>(&self, writer: W) -> Result<(), PropertyRegistryError> {
+        let sorted: BTreeMap<&String, &PropertyInfo> = self.properties.iter().collect();
+        serde_json::to_writer(writer, &sorted)?;
+        Ok(())
+    }
+
+    /// Builds a registry from the result of
+    /// [`PropertyRegistry::SPARQL_QUERY`]. Rows without a property are
+    /// skipped.
+    #[cfg(feature = "sparql-table")]
+    pub fn from_sparql_table<T: crate::sparql_table_trait::SparqlTableTrait>(table: &T) -> Self {
+        use crate::sparql_value::SparqlValue;
+
+        let column = |var: &str| table.get_var_index(var);
+        let (property, datatype, formatter, regex, constraint, qualifier, unit) = (
+            column("property"),
+            column("type"),
+            column("formatter"),
+            column("regex"),
+            column("constraint"),
+            column("qualifier"),
+            column("unit"),
+        );
+        // Each constraint parameter shows up once per combination of the
+        // other values.
+        let add = |list: &mut Option<Vec<String>>, value: Option<String>| {
+            let list = list.get_or_insert_with(Vec::new);
+            if let Some(value) = value.filter(|v| !list.contains(v)) {
+                list.push(value);
+            }
+        };
+        let cell = |row: usize, col: Option<usize>| table.get_row_col(row, col?);
+        let mut properties: HashMap<String, PropertyInfo> = HashMap::new();
+        for row in 0..table.len() {
+            let Some(SparqlValue::Entity(id)) = cell(row, property) else {
+                continue;
+            };
+            let info = properties.entry(id).or_default();
+            if let Some(SparqlValue::Uri(uri)) = cell(row, datatype) {
+                info.datatype = ontology_datatype(&uri);
+            }
+            if let Some(SparqlValue::Literal(s)) = cell(row, formatter) {
+                info.formatter_url.get_or_insert(s);
+            }
+            if let Some(SparqlValue::Literal(s)) = cell(row, regex) {
+                info.format_regex.get_or_insert(s);
+            }
+            match cell(row, constraint) {
+                Some(SparqlValue::Entity(q)) if q == SINGLE_VALUE_CONSTRAINT => {
+                    info.single_value = true
+                }
+                Some(SparqlValue::Entity(q)) if q == DISTINCT_VALUES_CONSTRAINT => {
+                    info.distinct_values = true
+                }
+                Some(SparqlValue::Entity(q)) if q == ALLOWED_QUALIFIERS_CONSTRAINT => {
+                    let value = match cell(row, qualifier) {
+                        Some(SparqlValue::Entity(p)) => Some(p),
+                        _ => None,
+                    };
+                    add(&mut info.allowed_qualifiers, value);
+                }
+                Some(SparqlValue::Entity(q)) if q == ALLOWED_UNITS_CONSTRAINT => {
+                    let value = match cell(row, unit) {
+                        Some(SparqlValue::Entity(q)) => Some(q),
+                        Some(SparqlValue::Literal(s)) if s == NO_UNIT => Some(s),
+                        _ => None,
+                    };
+                    add(&mut info.allowed_units, value);
+                }
+                _ => {}
+            }
+        }
+        let mut ret = Self::new();
+        for (property, info) in properties {
+            ret.insert(&property, info);
+        }
+        ret
+    }
+}
+
+/// The datatype behind a `wikibase:propertyType` URI.
+#[cfg(feature = "sparql-table")]
+fn ontology_datatype(uri: &str) -> Option<SnakDataType> {
+    let local = uri.strip_prefix(ONTOLOGY_PREFIX)?;
+    let (_, name) = ONTOLOGY_TYPES.iter().find(|(l, _)| *l == local)?;
+    SnakDataType::from_str(name).ok()
+}
+
+/// Loading a registry from Wikidata, which requires the `wikidata` feature.
+#[cfg(feature = "wikidata")]
+impl crate::wikidata::Wikidata {
+    /// Builds a registry from the property entities `ids`, through the
+    /// entity cache. IDs that are missing or not properties are skipped.
+    pub async fn load_property_registry(
+        &self,
+        ids: &[&str],
+    ) -> Result<PropertyRegistry, crate::wikidata::WikidataError> {
+        let mut registry = PropertyRegistry::new();
+        for (id, lookup) in self.load_entities(ids).await? {
+            if let Some(wikibase::Entity::Property(property)) = lookup.entity() {
+                registry.insert(&id, PropertyInfo::from_property_entity(property));
+            }
+        }
+        Ok(registry)
+    }
+
+    /// Builds a registry of every property by running
+    /// [`PropertyRegistry::SPARQL_QUERY`].
+    pub async fn load_property_registry_sparql(
+        &self,
+    ) -> Result<PropertyRegistry, crate::wikidata::WikidataError> {
+        let table: crate::sparql_table::SparqlTableVec = self
+            .load_sparql_table(PropertyRegistry::SPARQL_QUERY)
+            .await?;
+        Ok(PropertyRegistry::from_sparql_table(&table))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wikibase::{DataValue, DataValueType, EntityType, EntityValue, ItemEntity, SnakType};
+
+    fn registry() -> PropertyRegistry {
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P214",
+            PropertyInfo {
+                datatype: Some(SnakDataType::ExternalId),
+                formatter_url: Some("https://viaf.org/viaf/$1/".to_string()),
+                format_regex: Some("[1-9]\\d(\\d{0,7}|\\d{17,20})".to_string()),
+                single_value: true,
+                distinct_values: true,
+                ..Default::default()
+            },
+        );
+        registry.insert(
+            "P31",
+            PropertyInfo {
+                datatype: Some(SnakDataType::WikibaseItem),
+                ..Default::default()
+            },
+        );
+        registry.insert(
+            "P854",
+            PropertyInfo {
+                datatype: Some(SnakDataType::Url),
+                format_regex: Some("(".to_string()),
+                ..Default::default()
+            },
+        );
+        registry
+    }
+
+    fn untyped_string(property: &str, value: &str) -> Snak {
+        Snak::new(
+            SnakDataType::NotSet,
+            property,
+            SnakType::Value,
+            Some(DataValue::new(
+                DataValueType::StringType,
+                Value::StringValue(value.to_string()),
+            )),
+        )
+    }
+
+    #[test]
+    fn test_lookups() {
+        let registry = registry();
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.datatype("P214"), Some(SnakDataType::ExternalId));
+        assert_eq!(registry.datatype("P1"), None);
+        assert_eq!(
+            registry.get("P214").and_then(|i| i.format_url("123")),
+            Some("https://viaf.org/viaf/123/".to_string())
+        );
+        assert_eq!(registry.matches_format("P214", "30701597"), Some(true));
+        // The pattern is anchored.
+        assert_eq!(registry.matches_format("P214", "x30701597"), Some(false));
+        // An invalid pattern is ignored rather than failing.
+        assert_eq!(registry.matches_format("P854", "http://x"), None);
+        assert_eq!(registry.matches_format("P31", "Q5"), None);
+    }
+
+    #[test]
+    fn test_value_problem() {
+        let registry = registry();
+        let string = Value::StringValue("Q5".to_string());
+        let item = Value::Entity(EntityValue::new(EntityType::Item, "Q5"));
+        assert_eq!(
+            registry.value_problem("P31", &string),
+            Some("value does not fit datatype wikibase-item".to_string())
+        );
+        assert_eq!(registry.value_problem("P31", &item), None);
+        assert!(registry
+            .value_problem("P214", &Value::StringValue("abc".to_string()))
+            .is_some_and(|p| p.contains("format regex")));
+        assert_eq!(registry.value_problem("P999", &string), None);
+    }
+
+    #[test]
+    fn test_classify_item_types_every_snak() {
+        let registry = registry();
+        let mut item = ItemEntity::new_empty();
+        item.add_claim(Statement::new_normal(
+            untyped_string("P214", "30701597"),
+            vec![untyped_string("P214", "1")],
+            vec![Reference::new(vec![
+                untyped_string("P854", "http://example.org"),
+                untyped_string("P999", "unknown"),
+            ])],
+        ));
+        registry.classify_item(&mut item);
+
+        let statement = &item.claims()[0];
+        assert_eq!(*statement.main_snak().datatype(), SnakDataType::ExternalId);
+        assert_eq!(
+            *statement.qualifiers()[0].datatype(),
+            SnakDataType::ExternalId
+        );
+        let snaks = statement.references()[0].snaks();
+        assert_eq!(*snaks[0].datatype(), SnakDataType::Url);
+        assert_eq!(*snaks[1].datatype(), SnakDataType::NotSet);
+    }
+
+    #[test]
+    fn test_json_snapshot_round_trip() {
+        let registry = registry();
+        let mut json = vec![];
+        registry.to_json_writer(&mut json).unwrap();
+        let text = String::from_utf8(json).unwrap();
+        assert!(
+            text.starts_with(r#"{"P214":{"datatype":"external-id","#),
+            "{text}"
+        );
+
+        let read = PropertyRegistry::from_json_reader(text.as_bytes()).unwrap();
+        assert_eq!(read.len(), 3);
+        for property in ["P214", "P31", "P854"] {
+            assert_eq!(read.get(property), registry.get(property));
+        }
+        assert_eq!(read.matches_format("P214", "30701597"), Some(true));
+
+        let sparse = PropertyRegistry::from_json_reader(r#"{"P1": {}}"#.as_bytes()).unwrap();
+        assert_eq!(sparse.get("P1"), Some(&PropertyInfo::default()));
+        assert!(matches!(
+            PropertyRegistry::from_json_reader(r#"{"P1": {"datatype": "nope"}}"#.as_bytes()),
+            Err(PropertyRegistryError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_property_entity() {
+        let json = serde_json::json!({
+            "type": "property",
+            "id": "P214",
+            "datatype": "external-id",
+            "labels": {}, "descriptions": {}, "aliases": {},
+            "claims": {
+                "P1630": [{
+                    "type": "statement", "rank": "normal",
+                    "mainsnak": {"snaktype": "value", "property": "P1630", "datatype": "string",
+                        "datavalue": {"type": "string", "value": "https://viaf.org/viaf/$1/"}}
+                }],
+                "P2302": [{
+                    "type": "statement", "rank": "normal",
+                    "mainsnak": {"snaktype": "value", "property": "P2302", "datatype": "wikibase-item",
+                        "datavalue": {"type": "wikibase-entityid",
+                            "value": {"entity-type": "item", "id": "Q19474404"}}}
+                }, {
+                    "type": "statement", "rank": "deprecated",
+                    "mainsnak": {"snaktype": "value", "property": "P2302", "datatype": "wikibase-item",
+                        "datavalue": {"type": "wikibase-entityid",
+                            "value": {"entity-type": "item", "id": "Q21502410"}}}
+                }, {
+                    "type": "statement", "rank": "normal",
+                    "mainsnak": {"snaktype": "value", "property": "P2302", "datatype": "wikibase-item",
+                        "datavalue": {"type": "wikibase-entityid",
+                            "value": {"entity-type": "item", "id": "Q21510851"}}},
+                    "qualifiers": {"P2306": [
+                        {"snaktype": "value", "property": "P2306", "datatype": "wikibase-property",
+                            "datavalue": {"type": "wikibase-entityid",
+                                "value": {"entity-type": "property", "id": "P580"}}}
+                    ]}
+                }, {
+                    "type": "statement", "rank": "normal",
+                    "mainsnak": {"snaktype": "value", "property": "P2302", "datatype": "wikibase-item",
+                        "datavalue": {"type": "wikibase-entityid",
+                            "value": {"entity-type": "item", "id": "Q21514353"}}},
+                    "qualifiers": {"P2305": [
+                        {"snaktype": "value", "property": "P2305", "datatype": "wikibase-item",
+                            "datavalue": {"type": "wikibase-entityid",
+                                "value": {"entity-type": "item", "id": "Q11573"}}},
+                        {"snaktype": "novalue", "property": "P2305", "datatype": "wikibase-item"}
+                    ]}
+                }]
+            }
+        });
+        let property = PropertyEntity::new_from_json(&json).unwrap();
+        let info = PropertyInfo::from_property_entity(&property);
+        assert_eq!(info.datatype, Some(SnakDataType::ExternalId));
+        assert_eq!(
+            info.formatter_url.as_deref(),
+            Some("https://viaf.org/viaf/$1/")
+        );
+        assert!(info.single_value);
+        assert!(!info.distinct_values);
+        assert_eq!(info.allowed_qualifiers, Some(vec!["P580".to_string()]));
+        assert!(info.allows_qualifier("P580") && !info.allows_qualifier("P582"));
+        assert!(info.allows_unit("http://www.wikidata.org/entity/Q11573"));
+        assert!(info.allows_unit(NO_UNIT));
+        assert!(!info.allows_unit("http://www.wikidata.org/entity/Q174728"));
+        assert!(PropertyInfo::default().allows_unit("Q1"));
+    }
+
+    #[cfg(feature = "sparql-table")]
+    #[test]
+    fn test_ontology_datatype() {
+        assert_eq!(
+            ontology_datatype("http://wikiba.se/ontology#ExternalId"),
+            Some(SnakDataType::ExternalId)
+        );
+        assert_eq!(
+            ontology_datatype("http://wikiba.se/ontology#Monolingualtext"),
+            Some(SnakDataType::MonolingualText)
+        );
+        assert_eq!(ontology_datatype("http://wikiba.se/ontology#Nope"), None);
+        assert_eq!(ontology_datatype("ExternalId"), None);
+        for (_, name) in ONTOLOGY_TYPES {
+            assert!(SnakDataType::from_str(name).is_ok(), "{name}");
+        }
+    }
+
+    #[cfg(feature = "sparql-table")]
+    #[test]
+    fn test_from_sparql_table() {
+        use crate::sparql_table::SparqlTableVec;
+
+        let text = r#"{"head": {"vars": ["property", "type", "formatter", "regex", "constraint", "qualifier", "unit"]},
+          "results": {"bindings": [
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P214"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#ExternalId"},
+             "formatter": {"type": "literal", "value": "https://viaf.org/viaf/$1/"},
+             "regex": {"type": "literal", "value": "[1-9]\\d+"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q19474404"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P214"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#ExternalId"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q21502410"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P31"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#WikibaseItem"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P2048"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#Quantity"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q21510851"},
+             "qualifier": {"type": "uri", "value": "http://www.wikidata.org/entity/P585"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P2048"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#Quantity"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q21514353"},
+             "unit": {"type": "uri", "value": "http://www.wikidata.org/entity/Q11573"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P2048"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#Quantity"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q21514353"},
+             "unit": {"type": "literal", "value": "1"}},
+            {"property": {"type": "uri", "value": "http://www.wikidata.org/entity/P2048"},
+             "type": {"type": "uri", "value": "http://wikiba.se/ontology#Quantity"},
+             "constraint": {"type": "uri", "value": "http://www.wikidata.org/entity/Q21514353"},
+             "unit": {"type": "uri", "value": "http://www.wikidata.org/entity/Q11573"}}
+          ]}}"#;
+        let table = SparqlTableVec::from_json_reader(text.as_bytes()).unwrap();
+        let registry = PropertyRegistry::from_sparql_table(&table);
+        assert_eq!(registry.len(), 3);
+        let p214 = registry.get("P214").unwrap();
+        assert_eq!(p214.datatype, Some(SnakDataType::ExternalId));
+        assert!(p214.single_value && p214.distinct_values);
+        assert_eq!(registry.matches_format("P214", "123"), Some(true));
+        assert_eq!(registry.datatype("P31"), Some(SnakDataType::WikibaseItem));
+        assert_eq!(registry.get("P31").unwrap().allowed_qualifiers, None);
+        let p2048 = registry.get("P2048").unwrap();
+        assert_eq!(p2048.allowed_qualifiers, Some(vec!["P585".to_string()]));
+        assert_eq!(
+            p2048.allowed_units,
+            Some(vec!["Q11573".to_string(), NO_UNIT.to_string()])
+        );
+    }
+
+    #[cfg(feature = "wikidata")]
+    #[tokio::test]
+    async fn test_load_property_registry() {
+        use crate::test_support::{mount_action, wikidata_for};
+        use wiremock::MockServer;
+
+        let server = MockServer::start().await;
+        mount_action(
+            &server,
+            "wbgetentities",
+            serde_json::json!({"entities": {
+                "P31": {
+                    "type": "property", "id": "P31", "lastrevid": 1,
+                    "datatype": "wikibase-item",
+                    "labels": {}, "descriptions": {}, "aliases": {}, "claims": {}
+                },
+                "Q5": {"type": "item", "id": "Q5", "lastrevid": 2,
+                    "labels": {}, "descriptions": {}, "aliases": {}, "claims": {}, "sitelinks": {}}
+            }}),
+        )
+        .await;
+        let wd = wikidata_for(&server);
+        let registry = wd.load_property_registry(&["P31", "Q5"]).await.unwrap();
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.datatype("P31"), Some(SnakDataType::WikibaseItem));
+    }
+}
@@ -0,0 +1,844 @@
+//! Parser for QuickStatements v1 batches.
+//!
+//! [`parse_v1`] reads the tab-separated command format users paste into
+//! QuickStatements and turns it into local data, so a batch can be validated
+//! and merged before anything is sent to Wikidata:
+//!
+//! - a `CREATE` line and the `LAST` lines that follow it become one
+//!   [`QsEdit::Create`] holding a new `ItemEntity`;
+//! - lines against an existing item (`Q42\t…`) are collected into one
+//!   [`QsEdit::Edit`] per item, holding a [`MergeDiff`]. Before it is sent
+//!   through `wbeditentity`, [`QsItemEdit::resolve_additions`] and
+//!   [`QsItemEdit::resolve_removals`] reconcile it with the live item.
+//!
+//! Supported commands are labels (`Len`), descriptions (`Den`), aliases
+//! (`Aen`), sitelinks (`Senwiki`) and statements (`P31`), where a statement
+//! may carry qualifier pairs (`P580\t…`) and reference pairs (`S248\t…`, with
+//! `!S248` starting a new reference group). A leading `-` on the item ID
+//! removes a statement. A trailing `/* … */` comment is ignored. Values can be
+//! entity IDs, `"strings"`, `en:"monolingual text"`, times with a precision
+//! (`+1967-01-17T00:00:00Z/11`), coordinates (`@43.26/10.92`), quantities
+//! with an optional tolerance and unit (`12~0.5U11573`), `somevalue` and
+//! `novalue`.
+//!
+//! QuickStatements does not say which datatype a property has, so a quoted
+//! string always becomes a `string` snak, and `somevalue`/`novalue` snaks get
+//! an unset datatype.
+//!
+//! Syntax errors are reported as [`QuickStatementsError::Syntax`] with a
+//! 1-based line and column.
+
+use crate::item_merger::ItemMerger;
+use crate::merge_diff::MergeDiff;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+use thiserror::Error;
+use wikibase::*;
+
+/// Failure modes of [`parse_v1`].
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum QuickStatementsError {
+    /// A command could not be parsed. `line` and `column` are 1-based, and
+    /// `column` counts characters, pointing at the start of the offending
+    /// field.
+    #[error("line {line}, column {column}: {message}")]
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+}
+
+// The patterns below are literals and cannot fail to compile in practice. They
+// are still stored as `Option<Regex>` so that a pattern which somehow did fail
+// degrades to "never matches", which surfaces as a syntax error rather than a
+// panic. `test_all_static_regexes_compile` makes sure CI catches a broken
+// literal.
+static RE_ITEM: LazyLock<Option<Regex>> = LazyLock::new(|| Regex::new(r"^Q\d+$").ok());
+static RE_PROPERTY: LazyLock<Option<Regex>> = LazyLock::new(|| Regex::new(r"^P\d+$").ok());
+static RE_REFERENCE_PROPERTY: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"^(!?)S(\d+)$").ok());
+static RE_LANGUAGE: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"^[a-z][a-z0-9-]*$").ok());
+static RE_SITE: LazyLock<Option<Regex>> = LazyLock::new(|| Regex::new(r"^[a-z][a-z0-9_]*$").ok());
+static RE_ENTITY_VALUE: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"^(?:[QPLE]\d+|L\d+-[FS]\d+)$").ok());
+static RE_MONOLINGUAL: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^([a-z][a-z0-9-]*):"(.*)"$"#).ok());
+static RE_TIME: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"^([+-]?)(\d+-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z)/(\d{1,2})$").ok());
+static RE_COORDINATE: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r"^@([+-]?\d+(?:\.\d+)?)/([+-]?\d+(?:\.\d+)?)$").ok());
+static RE_QUANTITY: LazyLock<Option<Regex>> = LazyLock::new(|| {
+    Regex::new(r"^([+-]?\d+(?:\.\d+)?(?:[eE][+-]?\d+)?)(?:~(\d+(?:\.\d+)?))?(?:U(\d+))?$").ok()
+});
+
+fn is_match(re: &Option<Regex>, text: &str) -> bool {
+    re.as_ref().is_some_and(|re| re.is_match(text))
+}
+
+fn captures<'t>(re: &Option<Regex>, text: &'t str) -> Option<regex::Captures<'t>> {
+    re.as_ref()?.captures(text)
+}
+
+const GREGORIAN: &str = "http://www.wikidata.org/entity/Q1985727";
+const ENTITY_PREFIX: &str = "http://www.wikidata.org/entity/";
+
+/// One item touched by a batch.
+#[derive(Debug, Clone)]
+pub enum QsEdit {
+    /// A `CREATE` and everything its `LAST` lines add.
+    Create(ItemEntity),
+    /// Everything the batch does to one existing item.
+    Edit(Box<QsItemEdit>),
+}
+
+/// The changes a batch makes to an existing item.
+#[derive(Debug, Clone, PartialEq)]
+pub struct QsItemEdit {
+    pub id: String,
+    /// Labels, descriptions, aliases, sitelinks and statements to add. Lines
+    /// repeating a statement value are folded into one statement here; see
+    /// [`QsItemEdit::resolve_additions`] for folding them into the item's.
+    pub diff: MergeDiff,
+    /// Statements from `-` lines. QuickStatements removes by value but a
+    /// [`MergeDiff`] removes by statement ID, so these are moved into
+    /// `diff.removed_statements` by [`QsItemEdit::resolve_removals`].
+    pub removals: Vec<Statement>,
+}
+
+impl QsItemEdit {
+    fn new(id: &str) -> Self {
+        Self {
+            id: id.to_string(),
+            diff: MergeDiff::new(),
+            removals: vec![],
+        }
+    }
+
+    /// Looks up every pending removal among `live`'s statements by main snak
+    /// and moves the matches, keyed by their statement ID, into
+    /// `diff.removed_statements`. Removals with no match stay in `removals`.
+    pub fn resolve_removals(&mut self, live: &ItemEntity) {
+        let mut unresolved = vec![];
+        for removal in self.removals.drain(..) {
+            let found = live
+                .claims()
+                .iter()
+                .find(|claim| snaks_match(claim.main_snak(), removal.main_snak()));
+            match found.and_then(|claim| Some((claim.id()?, claim))) {
+                Some((id, claim)) => {
+                    self.diff.removed_statements.insert(id, claim.to_owned());
+                }
+                None => unresolved.push(removal),
+            }
+        }
+        self.removals = unresolved;
+    }
+
+    /// Merges the statements in `diff.added_statements` into `live` with
+    /// [`ItemMerger`], as QuickStatements does: a statement `live` already
+    /// has gains the line's qualifiers and references, through
+    /// `diff.altered_statements`, instead of being added a second time. Only
+    /// statements `live` lacks stay in `diff.added_statements`.
+    pub fn resolve_additions(&mut self, live: &ItemEntity) {
+        let mut additions = ItemEntity::new_empty();
+        additions.set_claims(std::mem::take(&mut self.diff.added_statements));
+        let merged = ItemMerger::new(live.to_owned()).merge(&additions);
+        self.diff.added_statements = merged.added_statements;
+        self.diff
+            .altered_statements
+            .extend(merged.altered_statements);
+    }
+}
+
+/// Parses a QuickStatements v1 batch. See the module documentation for the
+/// supported syntax.
+pub fn parse_v1(text: &str) -> Result<Vec<QsEdit>, QuickStatementsError> {
+    let mut parser = Parser::default();
+    for (line_idx, line) in text.lines().enumerate() {
+        parser.parse_line(line_idx + 1, line)?;
+    }
+    Ok(parser.edits)
+}
+
+/// A tab-separated field and the 1-based character column it starts at.
+#[derive(Debug, Clone, Copy)]
+struct Field<'a> {
+    text: &'a str,
+    column: usize,
+}
+
+#[derive(Debug, Default)]
+struct Parser {
+    edits: Vec<QsEdit>,
+    /// Index into `edits` of the item `LAST` refers to.
+    last: Option<usize>,
+    /// Index into `edits` of each existing item seen so far.
+    existing: HashMap<String, usize>,
+}
+
+/// The entity a line applies to.
+enum Target {
+    Create,
+    Last,
+    Item(String),
+}
+
+impl Parser {
+    fn parse_line(&mut self, line: usize, text: &str) -> Result<(), QuickStatementsError> {
+        let text = strip_comment(text).trim_end();
+        if text.trim().is_empty() {
+            return Ok(());
+        }
+        let fields = split_fields(text);
+        let error = |field: &Field, message: String| QuickStatementsError::Syntax {
+            line,
+            column: field.column,
+            message,
+        };
+        let Some((first, rest)) = fields.split_first() else {
+            return Ok(());
+        };
+
+        let (remove, target_text) = match first.text.strip_prefix('-') {
+            Some(target) => (true, target),
+            None => (false, first.text),
+        };
+        let target = match target_text {
+            "CREATE" if !remove => Target::Create,
+            "LAST" if !remove => Target::Last,
+            id if is_match(&RE_ITEM, id) => Target::Item(id.to_string()),
+            "MERGE" => {
+                return Err(error(first, "MERGE is not supported".to_string()));
+            }
+            other => {
+                return Err(error(
+                    first,
+                    format!("expected CREATE, LAST or an item ID, found '{other}'"),
+                ));
+            }
+        };
+
+        let idx = match target {
+            Target::Create => {
+                if let Some(extra) = rest.first() {
+                    return Err(error(extra, "CREATE takes no arguments".to_string()));
+                }
+                self.edits.push(QsEdit::Create(ItemEntity::new_empty()));
+                self.last = Some(self.edits.len() - 1);
+                return Ok(());
+            }
+            Target::Last => self
+                .last
+                .ok_or_else(|| error(first, "LAST used before any CREATE".to_string()))?,
+            Target::Item(id) => match self.existing.get(&id) {
+                Some(&idx) => idx,
+                None => {
+                    self.edits
+                        .push(QsEdit::Edit(Box::new(QsItemEdit::new(&id))));
+                    let idx = self.edits.len() - 1;
+                    self.existing.insert(id, idx);
+                    idx
+                }
+            },
+        };
+
+        let Some((command, args)) = rest.split_first() else {
+            return Err(error(first, "missing command after the item".to_string()));
+        };
+        let change = parse_command(command, args, remove, &error)?;
+        if let Some(edit) = self.edits.get_mut(idx) {
+            change.apply(edit);
+        }
+        Ok(())
+    }
+}
+
+/// Cuts a trailing `/* … */` comment.
+fn strip_comment(text: &str) -> &str {
+    match (text.trim_end().ends_with("*/"), text.rfind("/*")) {
+        (true, Some(start)) => text.get(..start).unwrap_or(text).trim_end(),
+        _ => text,
+    }
+}
+
+fn split_fields(text: &str) -> Vec<Field<'_>> {
+    let mut column = 1;
+    text.split('\t')
+        .map(|part| {
+            let leading = part.chars().take_while(|c| c.is_whitespace()).count();
+            let field = Field {
+                text: part.trim(),
+                column: column + leading,
+            };
+            column += part.chars().count() + 1;
+            field
+        })
+        .collect()
+}
+
+/// What one line does to its item.
+enum Change {
+    Label(LocaleString),
+    Description(LocaleString),
+    Alias(LocaleString),
+    SiteLink(SiteLink),
+    Statement(Statement),
+    Removal(Statement),
+}
+
+impl Change {
+    fn apply(self, edit: &mut QsEdit) {
+        match (edit, self) {
+            (QsEdit::Create(item), Change::Label(label)) => {
+                set_locale_string(item.labels_mut(), label)
+            }
+            (QsEdit::Create(item), Change::Description(description)) => {
+                set_locale_string(item.descriptions_mut(), description)
+            }
+            (QsEdit::Create(item), Change::Alias(alias)) => add_alias(item.aliases_mut(), alias),
+            (QsEdit::Create(item), Change::SiteLink(sitelink)) => item.set_sitelink(sitelink),
+            (QsEdit::Create(item), Change::Statement(statement)) => {
+                add_statement(item.claims_mut(), statement)
+            }
+            (QsEdit::Edit(edit), Change::Label(label)) => {
+                set_locale_string(&mut edit.diff.labels, label)
+            }
+            (QsEdit::Edit(edit), Change::Description(description)) => {
+                set_locale_string(&mut edit.diff.descriptions, description)
+            }
+            (QsEdit::Edit(edit), Change::Alias(alias)) => add_alias(&mut edit.diff.aliases, alias),
+            (QsEdit::Edit(edit), Change::SiteLink(sitelink)) => {
+                edit.diff
+                    .sitelinks
+                    .retain(|sl| sl.site() != sitelink.site());
+                edit.diff.sitelinks.push(sitelink);
+            }
+            (QsEdit::Edit(edit), Change::Statement(statement)) => {
+                add_statement(&mut edit.diff.added_statements, statement)
+            }
+            (QsEdit::Edit(edit), Change::Removal(statement)) => edit.removals.push(statement),
+            // `parse_line` only produces removals for existing items.
+            (QsEdit::Create(_), Change::Removal(_)) => {}
+        }
+    }
+}
+
+fn set_locale_string(list: &mut Vec<LocaleString>, new: LocaleString) {
+    list.retain(|ls| ls.language() != new.language());
+    list.push(new);
+}
+
+fn add_alias(list: &mut Vec<LocaleString>, alias: LocaleString) {
+    if !list.contains(&alias) {
+        list.push(alias);
+    }
+}
+
+/// Adds `statement`, or folds its qualifiers and references into an earlier
+/// statement with the same value, as QuickStatements does.
+fn add_statement(statements: &mut Vec<Statement>, statement: Statement) {
+    let Some(existing) = statements
+        .iter_mut()
+        .find(|s| snaks_match(s.main_snak(), statement.main_snak()))
+    else {
+        statements.push(statement);
+        return;
+    };
+    for qualifier in statement.qualifiers() {
+        if !existing.qualifiers().contains(qualifier) {
+            existing.add_qualifier_snak(qualifier.to_owned());
+        }
+    }
+    let mut references = existing.references().to_owned();
+    for reference in statement.references() {
+        if !references.contains(reference) {
+            references.push(reference.to_owned());
+        }
+    }
+    existing.set_references(references);
+}
+
+/// [`ItemMerger::is_snak_identical`], but also telling `somevalue` and
+/// `novalue` apart.
+fn snaks_match(a: &Snak, b: &Snak) -> bool {
+    a.snak_type() == b.snak_type() && ItemMerger::is_snak_identical(a, b)
+}
+
+fn parse_command<E>(
+    command: &Field,
+    args: &[Field],
+    remove: bool,
+    error: &E,
+) -> Result<Change, QuickStatementsError>
+where
+    E: Fn(&Field, String) -> QuickStatementsError,
+{
+    if is_match(&RE_PROPERTY, command.text) {
+        return parse_statement(command, args, remove, error);
+    }
+    if remove {
+        return Err(error(command, "only statements can be removed".to_string()));
+    }
+
+    let mut chars = command.text.chars();
+    let kind = chars.next();
+    let code = chars.as_str();
+    let valid_code = match kind {
+        Some('L' | 'D' | 'A') => is_match(&RE_LANGUAGE, code),
+        Some('S') => is_match(&RE_SITE, code),
+        _ => false,
+    };
+    if !valid_code {
+        return Err(error(
+            command,
+            format!(
+                "expected a property, or L/D/A plus a language or S plus a site, found '{}'",
+                command.text
+            ),
+        ));
+    }
+
+    let value = match args {
+        [value] => value,
+        [] => return Err(error(command, "missing value".to_string())),
+        [_, extra, ..] => {
+            return Err(error(
+                extra,
+                "qualifiers and references only apply to statements".to_string(),
+            ))
+        }
+    };
+    let text =
+        unquote(value.text).ok_or_else(|| error(value, "expected a quoted string".to_string()))?;
+    Ok(match kind {
+        Some('L') => Change::Label(LocaleString::new(code, text)),
+        Some('D') => Change::Description(LocaleString::new(code, text)),
+        Some('A') => Change::Alias(LocaleString::new(code, text)),
+        _ => Change::SiteLink(SiteLink::new(code, text, vec![])),
+    })
+}
+
+fn parse_statement<E>(
+    property: &Field,
+    args: &[Field],
+    remove: bool,
+    error: &E,
+) -> Result<Change, QuickStatementsError>
+where
+    E: Fn(&Field, String) -> QuickStatementsError,
+{
+    let Some((value, pairs)) = args.split_first() else {
+        return Err(error(property, "missing value".to_string()));
+    };
+    let main_snak = parse_snak(property.text, value, error)?;
+    if remove {
+        if let Some(extra) = pairs.first() {
+            return Err(error(
+                extra,
+                "a removal takes no qualifiers or references".to_string(),
+            ));
+        }
+        return Ok(Change::Removal(Statement::new_normal(
+            main_snak,
+            vec![],
+            vec![],
+        )));
+    }
+
+    let mut qualifiers = vec![];
+    let mut references: Vec<Vec<Snak>> = vec![];
+    for pair in pairs.chunks(2) {
+        let [key, value] = pair else {
+            let key = pair.first().unwrap_or(property);
+            return Err(error(key, format!("missing value for '{}'", key.text)));
+        };
+        if is_match(&RE_PROPERTY, key.text) {
+            qualifiers.push(parse_snak(key.text, value, error)?);
+            continue;
+        }
+        let caps = captures(&RE_REFERENCE_PROPERTY, key.text).ok_or_else(|| {
+            error(
+                key,
+                format!(
+                    "expected a qualifier or reference property, found '{}'",
+                    key.text
+                ),
+            )
+        })?;
+        let new_group = caps.get(1).is_some_and(|m| !m.as_str().is_empty());
+        let reference_property = format!("P{}", caps.get(2).map_or("", |m| m.as_str()));
+        let snak = parse_snak(&reference_property, value, error)?;
+        match references.last_mut() {
+            Some(group) if !new_group => group.push(snak),
+            _ => references.push(vec![snak]),
+        }
+    }
+    let references = references.into_iter().map(Reference::new).collect();
+    Ok(Change::Statement(Statement::new_normal(
+        main_snak, qualifiers, references,
+    )))
+}
+
+/// The text between the surrounding double quotes, if there are any.
+fn unquote(text: &str) -> Option<&str> {
+    text.strip_prefix('"')?.strip_suffix('"')
+}
+
+fn parse_snak<E>(property: &str, value: &Field, error: &E) -> Result<Snak, QuickStatementsError>
+where
+    E: Fn(&Field, String) -> QuickStatementsError,
+{
+    let text = value.text;
+    let snak = match text {
+        "somevalue" => Some(Snak::new_unknown_value(property, SnakDataType::NotSet)),
+        "novalue" => Some(Snak::new_no_value(property, SnakDataType::NotSet)),
+        _ if is_match(&RE_ENTITY_VALUE, text) => Some(entity_snak(property, text)),
+        _ => None,
+    };
+    if let Some(snak) = snak {
+        return Ok(snak);
+    }
+    if let Some(s) = unquote(text) {
+        return Ok(Snak::new_string(property, s));
+    }
+    if let Some(caps) = captures(&RE_MONOLINGUAL, text) {
+        let language = caps.get(1).map_or("", |m| m.as_str());
+        let s = caps.get(2).map_or("", |m| m.as_str());
+        return Ok(Snak::new_monolingual_text(property, language, s));
+    }
+    if let Some(caps) = captures(&RE_TIME, text) {
+        let sign = match caps.get(1).map_or("", |m| m.as_str()) {
+            "-" => "-",
+            _ => "+",
+        };
+        let time = format!("{sign}{}", caps.get(2).map_or("", |m| m.as_str()));
+        let precision: u64 = caps
+            .get(3)
+            .and_then(|m| m.as_str().parse().ok())
+            .filter(|p| *p <= 14)
+            .ok_or_else(|| error(value, "time precision must be 0 to 14".to_string()))?;
+        return Ok(time_snak(property, &time, precision));
+    }
+    if let Some(caps) = captures(&RE_COORDINATE, text) {
+        let number = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<f64>().ok());
+        if let (Some(latitude), Some(longitude)) = (number(1), number(2)) {
+            return Ok(Snak::new_coordinate(property, latitude, longitude));
+        }
+    }
+    if let Some(caps) = captures(&RE_QUANTITY, text) {
+        let number = |i: usize| caps.get(i).and_then(|m| m.as_str().parse::<f64>().ok());
+        if let Some(amount) = number(1) {
+            let tolerance = number(2);
+            let unit = caps.get(3).map_or("1".to_string(), |m| {
+                format!("{ENTITY_PREFIX}Q{}", m.as_str())
+            });
+            let quantity = QuantityValue::new(
+                amount,
+                tolerance.map(|t| amount - t),
+                unit,
+                tolerance.map(|t| amount + t),
+            );
+            return Ok(Snak::new(
+                SnakDataType::Quantity,
+                property,
+                SnakType::Value,
+                Some(DataValue::new(
+                    DataValueType::Quantity,
+                    Value::Quantity(quantity),
+                )),
+            ));
+        }
+    }
+    Err(error(value, format!("unrecognised value '{text}'")))
+}
+
+fn entity_snak(property: &str, id: &str) -> Snak {
+    let (snak_type, entity_type) = if id.contains("-F") {
+        (SnakDataType::WikibaseForm, EntityType::LexemeForm)
+    } else if id.contains("-S") {
+        (SnakDataType::WikibaseSense, EntityType::LexemeSense)
+    } else {
+        match id.chars().next() {
+            Some('P') => (SnakDataType::WikibaseProperty, EntityType::Property),
+            Some('L') => (SnakDataType::WikibaseLexeme, EntityType::Lexeme),
+            Some('E') => (SnakDataType::EntitySchema, EntityType::EntitySchema),
+            _ => (SnakDataType::WikibaseItem, EntityType::Item),
+        }
+    };
+    let value = EntityValue::new(entity_type, id);
+    let data_value = if entity_type == EntityType::EntitySchema {
+        DataValue::new(DataValueType::EntitySchemaId, Value::EntitySchema(value))
+    } else {
+        DataValue::new(DataValueType::EntityId, Value::Entity(value))
+    };
+    Snak::new(snak_type, property, SnakType::Value, Some(data_value))
+}
+
+fn time_snak(property: &str, time: &str, precision: u64) -> Snak {
+    Snak::new(
+        SnakDataType::Time,
+        property,
+        SnakType::Value,
+        Some(DataValue::new(
+            DataValueType::Time,
+            Value::Time(TimeValue::new(0, 0, GREGORIAN, precision, time, 0)),
+        )),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn syntax_error(text: &str) -> (usize, usize, String) {
+        match parse_v1(text) {
+            Err(QuickStatementsError::Syntax {
+                line,
+                column,
+                message,
+            }) => (line, column, message),
+            Ok(edits) => panic!("expected a syntax error, got {edits:?}"),
+        }
+    }
+
+    fn created(text: &str) -> ItemEntity {
+        match parse_v1(text).unwrap().as_slice() {
+            [QsEdit::Create(item)] => item.to_owned(),
+            other => panic!("expected one created item, got {other:?}"),
+        }
+    }
+
+    fn edited(text: &str) -> QsItemEdit {
+        match parse_v1(text).unwrap().as_slice() {
+            [QsEdit::Edit(edit)] => edit.as_ref().to_owned(),
+            other => panic!("expected one edited item, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_create_with_terms_and_sitelink() {
+        let item = created(
+            "CREATE\nLAST\tLen\t\"Douglas Adams\"\nLAST\tDen\t\"writer\"\n\
+             LAST\tAen\t\"DNA\"\nLAST\tSenwiki\t\"Douglas Adams\"\nLAST\tLen\t\"D. Adams\"",
+        );
+        assert_eq!(item.labels(), &vec![LocaleString::new("en", "D. Adams")]);
+        assert_eq!(
+            item.descriptions(),
+            &vec![LocaleString::new("en", "writer")]
+        );
+        assert_eq!(item.aliases(), &vec![LocaleString::new("en", "DNA")]);
+        assert_eq!(
+            item.sitelinks(),
+            &Some(vec![SiteLink::new("enwiki", "Douglas Adams", vec![])])
+        );
+    }
+
+    #[test]
+    fn test_statement_with_qualifiers_and_reference_groups() {
+        let item = created(
+            "CREATE\nLAST\tP69\tQ691283\tP580\t+1971-00-00T00:00:00Z/9\t\
+             S248\tQ36578\tS813\t+2020-01-02T00:00:00Z/11\t!S854\t\"https://example.org\"",
+        );
+        let statement = &item.claims()[0];
+        assert_eq!(statement.main_snak(), &Snak::new_item("P69", "Q691283"));
+        assert_eq!(
+            statement.qualifiers(),
+            &vec![time_snak("P580", "+1971-00-00T00:00:00Z", 9)]
+        );
+        assert_eq!(
+            statement.references(),
+            &vec![
+                Reference::new(vec![
+                    Snak::new_item("P248", "Q36578"),
+                    time_snak("P813", "+2020-01-02T00:00:00Z", 11),
+                ]),
+                Reference::new(vec![Snak::new_string("P854", "https://example.org")]),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_repeated_statement_value_is_folded() {
+        let item = created(
+            "CREATE\nLAST\tP31\tQ5\nLAST\tP31\tQ5\tP642\tQ1\nLAST\tP31\tQ5\tS248\tQ2\nLAST\tP31\tQ6",
+        );
+        assert_eq!(item.claims().len(), 2);
+        assert_eq!(
+            item.claims()[0].qualifiers(),
+            &vec![Snak::new_item("P642", "Q1")]
+        );
+        assert_eq!(item.claims()[0].references().len(), 1);
+    }
+
+    #[test]
+    fn test_value_kinds() {
+        let item = created(
+            "CREATE\nLAST\tP1476\ten:\"Hello\"\nLAST\tP625\t@43.26/-10.92\n\
+             LAST\tP1082\t1200~5U11573\nLAST\tP1114\t-3\nLAST\tP5137\tL1-S2\n\
+             LAST\tP570\tsomevalue\nLAST\tP40\tnovalue\nLAST\tP1813\tE10\n\
+             LAST\tP571\t-0500-00-00T00:00:00Z/9",
+        );
+        let values: Vec<_> = item
+            .claims()
+            .iter()
+            .map(|s| s.main_snak().to_owned())
+            .collect();
+        assert_eq!(
+            values[0],
+            Snak::new_monolingual_text("P1476", "en", "Hello")
+        );
+        assert_eq!(values[1], Snak::new_coordinate("P625", 43.26, -10.92));
+        let Some(Value::Quantity(q)) = values[2].data_value().as_ref().map(|dv| dv.value()) else {
+            panic!("not a quantity: {:?}", values[2]);
+        };
+        assert_eq!(*q.amount(), 1200.0);
+        assert_eq!(*q.lower_bound(), Some(1195.0));
+        assert_eq!(*q.upper_bound(), Some(1205.0));
+        assert_eq!(q.unit(), "http://www.wikidata.org/entity/Q11573");
+        let Some(Value::Quantity(q)) = values[3].data_value().as_ref().map(|dv| dv.value()) else {
+            panic!("not a quantity: {:?}", values[3]);
+        };
+        assert_eq!((*q.amount(), q.unit()), (-3.0, "1"));
+        assert_eq!(*values[4].datatype(), SnakDataType::WikibaseSense);
+        assert_eq!(*values[5].snak_type(), SnakType::UnknownValue);
+        assert_eq!(*values[6].snak_type(), SnakType::NoValue);
+        assert_eq!(*values[7].datatype(), SnakDataType::EntitySchema);
+        assert_eq!(values[8], time_snak("P571", "-0500-00-00T00:00:00Z", 9));
+    }
+
+    #[test]
+    fn test_existing_items_become_diffs() {
+        let edits = parse_v1(
+            "Q42\tLen\t\"Douglas Adams\"\nCREATE\nLAST\tP31\tQ5\n\
+             Q42\tP31\tQ5\nQ1\tAde\t\"Universum\"\nQ42\tSenwiki\t\"Douglas Adams\"",
+        )
+        .unwrap();
+        assert_eq!(edits.len(), 3);
+        let QsEdit::Edit(q42) = &edits[0] else {
+            panic!("expected an edit: {:?}", edits[0]);
+        };
+        assert_eq!(q42.id, "Q42");
+        assert_eq!(
+            q42.diff.labels,
+            vec![LocaleString::new("en", "Douglas Adams")]
+        );
+        assert_eq!(q42.diff.added_statements.len(), 1);
+        assert_eq!(q42.diff.sitelinks.len(), 1);
+        assert!(matches!(&edits[1], QsEdit::Create(_)));
+        assert!(matches!(&edits[2], QsEdit::Edit(e) if e.id == "Q1"));
+    }
+
+    #[test]
+    fn test_removals_resolve_against_live_item() {
+        let mut edit = edited("-Q42\tP31\tQ5\n-Q42\tP31\tQ6\n/* unrelated */");
+        assert_eq!(edit.removals.len(), 2);
+
+        let mut live = ItemEntity::new_empty();
+        let mut statement = Statement::new_normal(Snak::new_item("P31", "Q5"), vec![], vec![]);
+        statement.set_id("Q42$abc");
+        live.add_claim(statement.clone());
+
+        edit.resolve_removals(&live);
+        assert_eq!(
+            edit.diff.removed_statements,
+            HashMap::from([("Q42$abc".to_string(), statement)])
+        );
+        assert_eq!(edit.removals.len(), 1);
+        assert_eq!(edit.removals[0].main_snak(), &Snak::new_item("P31", "Q6"));
+    }
+
+    #[test]
+    fn test_additions_resolve_against_live_item() {
+        let mut edit = edited("Q42\tP31\tQ5\tP642\tQ1\nQ42\tP31\tQ6\nQ42\tP106\tQ36180");
+        assert_eq!(edit.diff.added_statements.len(), 3);
+
+        let mut live = ItemEntity::new_empty();
+        for (id, snak) in [
+            ("Q42$abc", Snak::new_item("P31", "Q5")),
+            ("Q42$def", Snak::new_item("P106", "Q36180")),
+        ] {
+            let mut statement = Statement::new_normal(snak, vec![], vec![]);
+            statement.set_id(id);
+            live.add_claim(statement);
+        }
+
+        edit.resolve_additions(&live);
+        // P31=Q5 gains the qualifier, P106 is already there, P31=Q6 is new.
+        assert_eq!(edit.diff.altered_statements.len(), 1);
+        assert_eq!(
+            edit.diff.altered_statements["Q42$abc"].qualifiers(),
+            &vec![Snak::new_item("P642", "Q1")]
+        );
+        assert_eq!(edit.diff.added_statements.len(), 1);
+        assert_eq!(
+            edit.diff.added_statements[0].main_snak(),
+            &Snak::new_item("P31", "Q6")
+        );
+    }
+
+    #[test]
+    fn test_comments_crlf_and_blank_lines() {
+        let item = created("CREATE\r\n\r\nLAST\tP31\tQ5\t/* an edit summary */\r\n");
+        assert_eq!(item.claims().len(), 1);
+    }
+
+    #[test]
+    fn test_syntax_errors_report_position() {
+        assert_eq!(
+            syntax_error("LAST\tP31\tQ5"),
+            (1, 1, "LAST used before any CREATE".to_string())
+        );
+        assert_eq!(
+            syntax_error("CREATE\nLAST\tP31\tfoo"),
+            (2, 10, "unrecognised value 'foo'".to_string())
+        );
+        assert_eq!(
+            syntax_error("CREATE\nLAST\tLen\tunquoted"),
+            (2, 10, "expected a quoted string".to_string())
+        );
+        assert_eq!(
+            syntax_error("Q1\tP31\tQ5\tP580"),
+            (1, 11, "missing value for 'P580'".to_string())
+        );
+        assert_eq!(
+            syntax_error("Q1\tP31\tQ5\tX1\tQ2"),
+            (
+                1,
+                11,
+                "expected a qualifier or reference property, found 'X1'".to_string()
+            )
+        );
+        assert_eq!(
+            syntax_error("-Q1\tLen\t\"x\""),
+            (1, 5, "only statements can be removed".to_string())
+        );
+        assert_eq!(
+            syntax_error("Q1\tP31\t+2020-01-01T00:00:00Z/15"),
+            (1, 8, "time precision must be 0 to 14".to_string())
+        );
+        assert_eq!(syntax_error("Q1\tP31\tQ5\nfoo").0, 2);
+        assert_eq!(syntax_error("CREATE\tQ1").1, 8);
+        assert_eq!(syntax_error("Q1").2, "missing command after the item");
+    }
+
+    #[test]
+    fn test_all_static_regexes_compile() {
+        for re in [
+            &RE_ITEM,
+            &RE_PROPERTY,
+            &RE_REFERENCE_PROPERTY,
+            &RE_LANGUAGE,
+            &RE_SITE,
+            &RE_ENTITY_VALUE,
+            &RE_MONOLINGUAL,
+            &RE_TIME,
+            &RE_COORDINATE,
+            &RE_QUANTITY,
+        ] {
+            assert!(re.is_some());
+        }
+    }
+}
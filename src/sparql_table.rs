@@ -2,15 +2,24 @@
 //!
 //! [`SparqlTable`] is generic over its row container via [`RowStorage`],
 //! implemented here for `Vec<SparqlRow>`. [`SparqlTableVec`] is the
-//! ready-to-use in-memory alias; other backends can implement [`RowStorage`]
-//! without changing [`SparqlTable`] itself.
+//! ready-to-use in-memory alias; [`crate::sparql_table_disk::SparqlTableDisk`]
+//! keeps its rows in a temporary file instead. Other backends can implement
+//! [`RowStorage`] without changing [`SparqlTable`] itself.
+//!
+//! A table is built either from an already deserialised [`SparqlApiResult`]
+//! ([`SparqlTable::from_api_result`]) or by streaming a SPARQL JSON response
+//! straight into the row storage ([`SparqlTable::from_json_reader`]), which
+//! never holds more than one binding outside the storage at a time.
 
 use crate::{
-    sparql_results::{SparqlApiResult, SparqlRow},
+    sparql_results::{SparqlApiResult, SparqlResultRow, SparqlRow},
     sparql_table_trait::SparqlTableTrait,
     sparql_value::SparqlValue,
 };
+use serde::de::{DeserializeSeed, Deserializer, IgnoredAny, MapAccess, SeqAccess, Visitor};
 use std::collections::HashMap;
+use std::fmt;
+use std::io::Read;
 use thiserror::Error;
 
 /// Failure modes of the tabular SPARQL result types.
@@ -20,6 +29,28 @@ pub enum SparqlTableError {
     /// cannot be assigned to columns.
     #[error("header not set")]
     HeaderNotSet,
+
+    /// A table operation named a variable the table has no column for.
+    #[error("unknown variable '{0}'")]
+    UnknownVariable(String),
+
+    /// A table operation needs the main variable, but none is set.
+    #[error("main variable not set")]
+    MainVariableNotSet,
+
+    /// A streamed SPARQL JSON response could not be parsed, or a row could
+    /// not be encoded for disk storage.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// Reading or writing a disk-backed row storage, or an export target,
+    /// failed.
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    /// Reading or writing CSV/TSV failed.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
 }
 
 /// Row-container abstraction used by [`SparqlTable`]. Implemented here for
@@ -27,10 +58,15 @@ pub enum SparqlTableError {
 /// [`SparqlTable`] itself.
 ///
 /// `push` returns `Result` so backends that can fail (e.g. on I/O) are
-/// supported. The in-memory `Vec` impl always returns `Ok(())`.
+/// supported. The in-memory `Vec` impl always returns `Ok(())`. Such backends
+/// should also override `try_get`, which by default cannot fail.
 pub trait RowStorage: Default {
     fn push(&mut self, row: SparqlRow) -> Result<(), SparqlTableError>;
     fn get(&self, idx: usize) -> Option<SparqlRow>;
+    /// Like `get`, but reports a row that could not be read as `Err`.
+    fn try_get(&self, idx: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        Ok(self.get(idx))
+    }
     fn len(&self) -> usize;
     fn is_empty(&self) -> bool {
         self.len() == 0
@@ -78,11 +114,12 @@ impl<S: RowStorage> SparqlTable<S> {
     }
 
     /// Create a new table that copies the headers and main variable from `other` but no rows.
-    pub fn from_table<T: RowStorage>(other: &SparqlTable<T>) -> Self {
+    /// `other` can be any table, whatever its [`RowStorage`].
+    pub fn from_table<T: SparqlTableTrait + ?Sized>(other: &T) -> Self {
         Self {
-            headers: other.headers.clone(),
+            headers: other.headers().to_vec(),
             rows: S::default(),
-            main_variable: other.main_variable.clone(),
+            main_variable: other.main_variable().cloned(),
         }
     }
 
@@ -122,6 +159,17 @@ impl<S: RowStorage> SparqlTable<S> {
         self.rows.get(row_id)
     }
 
+    /// Like [`Self::get`], but fails with [`SparqlTableError::Io`] for a row
+    /// in range that the [`RowStorage`] cannot read.
+    pub fn try_get(&self, row_id: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        match self.rows.try_get(row_id)? {
+            None if row_id < self.len() => Err(SparqlTableError::Io(std::io::Error::other(
+                format!("row {row_id} could not be read"),
+            ))),
+            row => Ok(row),
+        }
+    }
+
     fn push_sparql_result_row(
         &mut self,
         row: &HashMap<String, SparqlValue>,
@@ -158,6 +206,11 @@ impl<S: RowStorage> SparqlTable<S> {
         self.headers = headers;
     }
 
+    /// The variable names, in column order.
+    pub fn headers(&self) -> &[String] {
+        &self.headers
+    }
+
     /// Build a table from a deserialised SPARQL API result. Consumes `result`.
     pub fn from_api_result(result: SparqlApiResult) -> Result<Self, SparqlTableError> {
         let mut table = Self::new();
@@ -172,6 +225,174 @@ impl<S: RowStorage> SparqlTable<S> {
         }
         Ok(table)
     }
+
+    /// Build a table by streaming a SPARQL JSON response from `reader`.
+    ///
+    /// Each binding is pushed into the row storage as soon as it has been
+    /// parsed, so apart from what the storage itself keeps, memory use does
+    /// not grow with the number of rows. `head` normally precedes `results`;
+    /// should it not, bindings are held back until the headers are known.
+    ///
+    /// Errors from the storage's `push` are returned as-is; anything wrong
+    /// with the JSON itself is [`SparqlTableError::Json`].
+    pub fn from_json_reader<R: Read>(reader: R) -> Result<Self, SparqlTableError> {
+        let mut state = StreamState {
+            table: Self::new(),
+            pending: vec![],
+            error: None,
+        };
+        let mut de = serde_json::Deserializer::from_reader(reader);
+        let parsed = ResponseSeed(&mut state)
+            .deserialize(&mut de)
+            .and_then(|()| de.end());
+        if let Some(error) = state.error {
+            return Err(error);
+        }
+        parsed?;
+        if !state.pending.is_empty() {
+            return Err(SparqlTableError::HeaderNotSet);
+        }
+        Ok(state.table)
+    }
+}
+
+/// Shared state of the streaming parser behind [`SparqlTable::from_json_reader`].
+///
+/// A storage failure cannot travel through serde's error type without losing
+/// its variant, so it is parked in `error` and the parse is aborted with a
+/// placeholder error that the caller then discards.
+struct StreamState<S: RowStorage> {
+    table: SparqlTable<S>,
+    pending: Vec<SparqlResultRow>,
+    error: Option<SparqlTableError>,
+}
+
+impl<S: RowStorage> StreamState<S> {
+    fn set_headers(&mut self, headers: Vec<String>) -> bool {
+        self.table.set_headers(headers);
+        let pending = std::mem::take(&mut self.pending);
+        pending.iter().all(|row| self.push_row(row))
+    }
+
+    fn accept_row(&mut self, row: SparqlResultRow) -> bool {
+        if self.table.headers.is_empty() {
+            self.pending.push(row);
+            return true;
+        }
+        self.push_row(&row)
+    }
+
+    /// Returns `false` once the storage has failed.
+    fn push_row(&mut self, row: &SparqlResultRow) -> bool {
+        match self.table.push_sparql_result_row(row) {
+            Ok(()) => true,
+            Err(error) => {
+                self.error = Some(error);
+                false
+            }
+        }
+    }
+}
+
+fn storage_failed<E: serde::de::Error>() -> E {
+    E::custom("row storage failed")
+}
+
+/// The top-level `{"head": …, "results": …}` object.
+struct ResponseSeed<'a, S: RowStorage>(&'a mut StreamState<S>);
+
+impl<'de, S: RowStorage> DeserializeSeed<'de> for ResponseSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, S: RowStorage> Visitor<'de> for ResponseSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a SPARQL JSON result object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "head" => {
+                    let mut head: HashMap<String, Vec<String>> = map.next_value()?;
+                    let headers = head.remove("vars").unwrap_or_default();
+                    if !self.0.set_headers(headers) {
+                        return Err(storage_failed());
+                    }
+                }
+                "results" => map.next_value_seed(ResultsSeed(&mut *self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `{"bindings": […]}` object.
+struct ResultsSeed<'a, S: RowStorage>(&'a mut StreamState<S>);
+
+impl<'de, S: RowStorage> DeserializeSeed<'de> for ResultsSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de, S: RowStorage> Visitor<'de> for ResultsSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a SPARQL JSON results object")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "bindings" => map.next_value_seed(BindingsSeed(&mut *self.0))?,
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The `bindings` array, pushed into the table one row at a time.
+struct BindingsSeed<'a, S: RowStorage>(&'a mut StreamState<S>);
+
+impl<'de, S: RowStorage> DeserializeSeed<'de> for BindingsSeed<'_, S> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_seq(self)
+    }
+}
+
+impl<'de, S: RowStorage> Visitor<'de> for BindingsSeed<'_, S> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an array of SPARQL bindings")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while let Some(row) = seq.next_element::<SparqlResultRow>()? {
+            if !self.0.accept_row(row) {
+                return Err(storage_failed());
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<S: RowStorage> SparqlTableTrait for SparqlTable<S> {
@@ -195,6 +416,10 @@ impl<S: RowStorage> SparqlTableTrait for SparqlTable<S> {
         self.get(row_id)
     }
 
+    fn try_get(&self, row_id: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        self.try_get(row_id)
+    }
+
     fn main_variable(&self) -> Option<&String> {
         self.main_variable()
     }
@@ -210,6 +435,10 @@ impl<S: RowStorage> SparqlTableTrait for SparqlTable<S> {
     fn set_headers(&mut self, headers: Vec<String>) {
         self.set_headers(headers);
     }
+
+    fn headers(&self) -> &[String] {
+        self.headers()
+    }
 }
 
 #[cfg(test)]
@@ -259,6 +488,11 @@ mod tests {
         assert_table_behaviour::<Vec<SparqlRow>>();
     }
 
+    #[test]
+    fn test_table_behaviour_disk_backend() {
+        assert_table_behaviour::<crate::sparql_table_disk::DiskRowStorage>();
+    }
+
     #[test]
     fn test_get_var_index_case_insensitive() {
         let mut table = SparqlTableVec::new();
@@ -371,4 +605,106 @@ mod tests {
         assert!(vec_table.is_empty());
         assert!(vec_table.main_variable().is_none());
     }
+
+    // ── from_json_reader ─────────────────────────────────────────────────────
+
+    /// A storage that fails on the second push, to check that storage errors
+    /// come back unchanged from the streaming parser.
+    #[derive(Default)]
+    struct FailingStorage(Vec<SparqlRow>);
+
+    impl RowStorage for FailingStorage {
+        fn push(&mut self, row: SparqlRow) -> Result<(), SparqlTableError> {
+            if !self.0.is_empty() {
+                return Err(SparqlTableError::HeaderNotSet);
+            }
+            self.0.push(row);
+            Ok(())
+        }
+        fn get(&self, idx: usize) -> Option<SparqlRow> {
+            self.0.as_slice().get(idx).cloned()
+        }
+        fn len(&self) -> usize {
+            self.0.len()
+        }
+    }
+
+    #[test]
+    fn test_from_json_reader_matches_from_api_result() {
+        let json = serde_json::json!({
+            "head": {"vars": ["item", "label"]},
+            "results": {"bindings": [
+                {
+                    "item":  {"type": "uri",     "value": "http://www.wikidata.org/entity/Q42"},
+                    "label": {"type": "literal", "value": "Douglas Adams"}
+                },
+                {"item": {"type": "uri", "value": "http://www.wikidata.org/entity/Q1"}}
+            ]}
+        });
+        let text = json.to_string();
+
+        let streamed = SparqlTableVec::from_json_reader(text.as_bytes()).unwrap();
+        let buffered = SparqlTableVec::from_api_result(make_api_result(json)).unwrap();
+
+        assert_eq!(streamed.headers, buffered.headers);
+        assert_eq!(streamed.rows, buffered.rows);
+        assert_eq!(streamed.len(), 2);
+    }
+
+    #[test]
+    fn test_from_json_reader_ignores_unknown_keys() {
+        let text = r#"{
+            "head": {"vars": ["x"], "link": []},
+            "extra": {"anything": [1, 2, 3]},
+            "results": {"distinct": false, "ordered": true, "bindings": [
+                {"x": {"type": "literal", "value": "v"}}
+            ]}
+        }"#;
+        let table = SparqlTableVec::from_json_reader(text.as_bytes()).unwrap();
+        assert_eq!(table.len(), 1);
+        assert_eq!(
+            table.get_row_col(0, 0),
+            Some(SparqlValue::Literal("v".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_from_json_reader_results_before_head() {
+        let text = r#"{
+            "results": {"bindings": [{"x": {"type": "literal", "value": "v"}}]},
+            "head": {"vars": ["x"]}
+        }"#;
+        let table = SparqlTableVec::from_json_reader(text.as_bytes()).unwrap();
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn test_from_json_reader_no_vars_in_head_is_err() {
+        let text = r#"{"head": {}, "results": {"bindings": [
+            {"x": {"type": "literal", "value": "v"}}
+        ]}}"#;
+        assert!(matches!(
+            SparqlTableVec::from_json_reader(text.as_bytes()),
+            Err(SparqlTableError::HeaderNotSet)
+        ));
+    }
+
+    #[test]
+    fn test_from_json_reader_malformed_json_is_json_err() {
+        let text = r#"{"head": {"vars": ["x"]}, "results": {"bindings": [{"#;
+        assert!(matches!(
+            SparqlTableVec::from_json_reader(text.as_bytes()),
+            Err(SparqlTableError::Json(_))
+        ));
+    }
+
+    #[test]
+    fn test_from_json_reader_returns_storage_error() {
+        let text = r#"{"head": {"vars": ["x"]}, "results": {"bindings": [
+            {"x": {"type": "literal", "value": "a"}},
+            {"x": {"type": "literal", "value": "b"}}
+        ]}}"#;
+        let result = SparqlTable::<FailingStorage>::from_json_reader(text.as_bytes());
+        assert!(matches!(result, Err(SparqlTableError::HeaderNotSet)));
+    }
 }
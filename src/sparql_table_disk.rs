@@ -0,0 +1,162 @@
+//! Disk-backed [`RowStorage`] for SPARQL result sets too large to keep in
+//! memory.
+//!
+//! [`DiskRowStorage`] appends each row as one JSON line to an anonymous
+//! temporary file and keeps only the byte offset of every row in memory, so
+//! [`RowStorage::get`] is a single seek-and-read. Cells are written in the W3C
+//! binding shape produced by `SparqlValue`'s `Serialize` impl and read back
+//! through its `Deserialize` impl. The file disappears when the storage is
+//! dropped.
+
+use crate::sparql_results::SparqlRow;
+use crate::sparql_table::{RowStorage, SparqlTable, SparqlTableError};
+use std::fs::File;
+use std::io::{BufWriter, Read, Seek, SeekFrom, Write};
+use std::sync::Mutex;
+use tempfile::tempfile;
+
+/// Append-only JSON Lines temp file plus an in-memory offset index.
+///
+/// The file is created on the first `push`, because [`RowStorage`] requires
+/// an infallible `Default`.
+#[derive(Debug, Default)]
+pub struct DiskRowStorage {
+    file: Option<Mutex<BufWriter<File>>>,
+    /// Start offset of every row; a row ends where the next one starts, or at `end`.
+    offsets: Vec<u64>,
+    end: u64,
+}
+
+/// Disk-backed alias for [`SparqlTable`].
+pub type SparqlTableDisk = SparqlTable<DiskRowStorage>;
+
+fn lock_poisoned() -> std::io::Error {
+    std::io::Error::other("disk row storage lock poisoned")
+}
+
+impl DiskRowStorage {
+    fn read_row(&self, idx: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        let (Some(file), Some(&start)) = (&self.file, self.offsets.get(idx)) else {
+            return Ok(None);
+        };
+        let end = self.offsets.get(idx + 1).copied().unwrap_or(self.end);
+        let mut buffer = vec![0; usize::try_from(end.saturating_sub(start)).unwrap_or(0)];
+
+        let mut writer = file.lock().map_err(|_| lock_poisoned())?;
+        writer.flush()?;
+        let f = writer.get_mut();
+        f.seek(SeekFrom::Start(start))?;
+        f.read_exact(&mut buffer)?;
+        // Appends go wherever the cursor is, so put it back at the end.
+        f.seek(SeekFrom::Start(self.end))?;
+        Ok(Some(serde_json::from_slice(&buffer)?))
+    }
+}
+
+impl RowStorage for DiskRowStorage {
+    fn push(&mut self, row: SparqlRow) -> Result<(), SparqlTableError> {
+        let mut line = serde_json::to_vec(&row)?;
+        line.push(b'\n');
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(Mutex::new(BufWriter::new(tempfile()?))),
+        };
+        file.get_mut()
+            .map_err(|_| lock_poisoned())?
+            .write_all(&line)?;
+        self.offsets.push(self.end);
+        self.end += line.len() as u64;
+        Ok(())
+    }
+
+    /// Returns `None` both for an out-of-range index and for a row that could
+    /// not be read back; `try_get` tells the two apart.
+    fn get(&self, idx: usize) -> Option<SparqlRow> {
+        self.read_row(idx).ok().flatten()
+    }
+
+    fn try_get(&self, idx: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        self.read_row(idx)
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lat_lon::LatLon;
+    use crate::sparql_value::{SparqlTime, SparqlValue};
+
+    #[test]
+    fn test_disk_storage_round_trips_every_value_kind() {
+        let row: SparqlRow = vec![
+            Some(SparqlValue::Entity("Q42".to_string())),
+            Some(SparqlValue::File("Some file.jpg".to_string())),
+            Some(SparqlValue::Uri("https://example.org/x".to_string())),
+            Some(SparqlValue::Time(SparqlTime::parse("1952-03-11").unwrap())),
+            Some(SparqlValue::Location(LatLon::new(51.5, -0.1))),
+            Some(SparqlValue::Literal("multi\nline \"quoted\"".to_string())),
+            Some(SparqlValue::Integer(7)),
+            Some(SparqlValue::Decimal("0.10".to_string())),
+            Some(SparqlValue::LangLiteral {
+                value: "Londres".to_string(),
+                language: "fr".to_string(),
+            }),
+            Some(SparqlValue::Form("L1-F2".to_string())),
+            Some(SparqlValue::GlobeLocation {
+                globe: "http://www.wikidata.org/entity/Q405".to_string(),
+                location: LatLon::new(1.0, 2.0),
+            }),
+            None,
+        ];
+        let mut storage = DiskRowStorage::default();
+        storage.push(row.clone()).unwrap();
+        assert_eq!(storage.get(0), Some(row));
+    }
+
+    #[test]
+    fn test_disk_storage_random_access_interleaved_with_pushes() {
+        let mut storage = DiskRowStorage::default();
+        for i in 0..100 {
+            storage
+                .push(vec![Some(SparqlValue::Literal(format!("row {i}")))])
+                .unwrap();
+            // Reading must not disturb where the next append lands.
+            assert_eq!(
+                storage.get(i / 2),
+                Some(vec![Some(SparqlValue::Literal(format!("row {}", i / 2)))])
+            );
+        }
+        assert_eq!(storage.len(), 100);
+        assert_eq!(
+            storage.get(99),
+            Some(vec![Some(SparqlValue::Literal("row 99".to_string()))])
+        );
+        assert_eq!(storage.get(100), None);
+    }
+
+    #[test]
+    fn test_disk_storage_default_creates_no_file() {
+        let storage = DiskRowStorage::default();
+        assert!(storage.file.is_none());
+        assert!(storage.is_empty());
+        assert_eq!(storage.get(0), None);
+    }
+
+    #[test]
+    fn test_sparql_table_disk_from_json_reader() {
+        let text = r#"{"head": {"vars": ["item"]}, "results": {"bindings": [
+            {"item": {"type": "uri", "value": "http://www.wikidata.org/entity/Q1"}},
+            {"item": {"type": "uri", "value": "http://www.wikidata.org/entity/Q2"}}
+        ]}}"#;
+        let table = SparqlTableDisk::from_json_reader(text.as_bytes()).unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get_row_col(1, 0),
+            Some(SparqlValue::Entity("Q2".to_string()))
+        );
+    }
+}
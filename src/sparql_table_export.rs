@@ -0,0 +1,479 @@
+//! Writing a [`SparqlTable`] out, and reading one back from CSV.
+//!
+//! Every writer walks the table row by row through [`RowStorage::get`], so a
+//! disk-backed table is exported without loading it into memory first.
+//!
+//! - [`SparqlTable::write_sparql_json`]: W3C SPARQL JSON results, readable
+//!   again through [`crate::sparql_results::SparqlApiResult`] or
+//!   [`SparqlTable::from_json_reader`].
+//! - [`SparqlTable::write_json_lines`]: one binding object per line.
+//! - [`SparqlTable::write_csv`] / [`SparqlTable::write_tsv`]: one header row
+//!   plus one row per result, with cells rendered per [`CellFormat`].
+//! - [`SparqlTable::from_csv_reader`]: the reverse of plain CSV, e.g. for the
+//!   reader returned by `Wikidata::load_sparql_csv`.
+
+use crate::sparql_results::SparqlRow;
+use crate::sparql_table::{RowStorage, SparqlTable, SparqlTableError};
+use crate::sparql_value::SparqlValue;
+use regex::Regex;
+use serde_json::json;
+use std::io::{Read, Write};
+use std::sync::LazyLock;
+
+// Literal patterns, held as `Option<Regex>` so a pattern that somehow failed to
+// compile degrades to reading the cell as a plain literal rather than
+// panicking. `test_all_static_regexes_compile` makes sure CI catches that.
+static RE_CSV_POINT: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^(<[^>]+> )?Point\(-?\d+[\.0-9]* -?\d+[\.0-9]*\)$"#).ok());
+static RE_CSV_DATE_TIME: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^[+-]?\d+-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}Z$"#).ok());
+
+/// Well-known WDQS prefixes used by [`CellFormat::Prefixed`], longest
+/// namespace first so that e.g. `prop/statement/` wins over `prop/`.
+const PREFIXES: &[(&str, &str)] = &[
+    ("psv", "http://www.wikidata.org/prop/statement/value/"),
+    ("pqv", "http://www.wikidata.org/prop/qualifier/value/"),
+    ("prv", "http://www.wikidata.org/prop/reference/value/"),
+    (
+        "psn",
+        "http://www.wikidata.org/prop/statement/value-normalized/",
+    ),
+    (
+        "pqn",
+        "http://www.wikidata.org/prop/qualifier/value-normalized/",
+    ),
+    (
+        "prn",
+        "http://www.wikidata.org/prop/reference/value-normalized/",
+    ),
+    ("wdtn", "http://www.wikidata.org/prop/direct-normalized/"),
+    ("ps", "http://www.wikidata.org/prop/statement/"),
+    ("pq", "http://www.wikidata.org/prop/qualifier/"),
+    ("pr", "http://www.wikidata.org/prop/reference/"),
+    ("wdt", "http://www.wikidata.org/prop/direct/"),
+    ("wdno", "http://www.wikidata.org/prop/novalue/"),
+    ("p", "http://www.wikidata.org/prop/"),
+    ("wds", "http://www.wikidata.org/entity/statement/"),
+    ("wdref", "http://www.wikidata.org/reference/"),
+    ("wdv", "http://www.wikidata.org/value/"),
+    ("wd", "http://www.wikidata.org/entity/"),
+    ("schema", "http://schema.org/"),
+];
+
+/// How [`SparqlTable::write_csv`] and [`SparqlTable::write_tsv`] render a cell.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CellFormat {
+    /// The lexical value, as WDQS itself writes CSV: full URIs, `Point(…)`
+    /// for coordinates.
+    #[default]
+    Plain,
+    /// Like `Plain`, but URIs in a well-known Wikidata namespace are
+    /// shortened to their prefixed form, e.g. `wd:Q42`.
+    Prefixed,
+}
+
+impl CellFormat {
+    fn render(self, value: &SparqlValue) -> String {
+        let plain = value.value_string();
+        match (self, value) {
+            (
+                CellFormat::Prefixed,
+                SparqlValue::Entity(_)
+                | SparqlValue::Uri(_)
+                | SparqlValue::Form(_)
+                | SparqlValue::Sense(_)
+                | SparqlValue::Statement(_)
+                | SparqlValue::Reference(_)
+                | SparqlValue::ValueNode(_),
+            ) => PREFIXES
+                .iter()
+                .find_map(|(prefix, namespace)| {
+                    let local = plain.strip_prefix(namespace)?;
+                    Some(format!("{prefix}:{local}"))
+                })
+                .unwrap_or(plain),
+            _ => plain,
+        }
+    }
+}
+
+impl<S: RowStorage> SparqlTable<S> {
+    /// Write the table as W3C SPARQL JSON results. Cells that are `None` are
+    /// left out of their binding, as in a real result set.
+    pub fn write_sparql_json<W: Write>(&self, mut writer: W) -> Result<(), SparqlTableError> {
+        let head = json!({"vars": self.headers()});
+        write!(writer, "{{\"head\":{head},\"results\":{{\"bindings\":[")?;
+        for row_id in 0..self.len() {
+            if row_id > 0 {
+                writer.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut writer, &self.binding(row_id)?)?;
+        }
+        writer.write_all(b"]}}")?;
+        Ok(())
+    }
+
+    /// Write the table as JSON Lines, one binding object (the shape of a
+    /// `bindings` entry in SPARQL JSON) per row.
+    pub fn write_json_lines<W: Write>(&self, mut writer: W) -> Result<(), SparqlTableError> {
+        for row_id in 0..self.len() {
+            serde_json::to_writer(&mut writer, &self.binding(row_id)?)?;
+            writer.write_all(b"\n")?;
+        }
+        Ok(())
+    }
+
+    /// Write the table as CSV with a header row. Empty cells stay empty.
+    pub fn write_csv<W: Write>(
+        &self,
+        writer: W,
+        format: CellFormat,
+    ) -> Result<(), SparqlTableError> {
+        self.write_delimited(writer, b',', format)
+    }
+
+    /// Write the table as tab-separated values with a header row.
+    pub fn write_tsv<W: Write>(
+        &self,
+        writer: W,
+        format: CellFormat,
+    ) -> Result<(), SparqlTableError> {
+        self.write_delimited(writer, b'\t', format)
+    }
+
+    /// Build a typed table from CSV as returned by WDQS, i.e. with a header row
+    /// and plain cells. Entity and Commons file URIs, other URIs, `Point(…)`
+    /// coordinates on any globe and `xsd:dateTime` values are recognised the same way
+    /// [`SparqlValue::new_from_json`] recognises them; everything else becomes
+    /// a literal, and an empty cell becomes `None`.
+    pub fn from_csv_reader<R: Read>(reader: &mut csv::Reader<R>) -> Result<Self, SparqlTableError> {
+        let mut table = Self::new();
+        table.set_headers(reader.headers()?.iter().map(|h| h.to_string()).collect());
+        for record in reader.records() {
+            let record = record?;
+            let row: SparqlRow = (0..table.headers().len())
+                .map(|col| record.get(col).and_then(Self::value_from_csv))
+                .collect();
+            table.push(row)?;
+        }
+        Ok(table)
+    }
+
+    fn value_from_csv(cell: &str) -> Option<SparqlValue> {
+        if cell.is_empty() {
+            return None;
+        }
+        let is_match = |re: &Option<Regex>| re.as_ref().is_some_and(|re| re.is_match(cell));
+        let j = if cell.starts_with("http://") || cell.starts_with("https://") {
+            json!({"type": "uri", "value": cell})
+        } else if is_match(&RE_CSV_POINT) {
+            json!({
+                "type": "literal",
+                "datatype": "http://www.opengis.net/ont/geosparql#wktLiteral",
+                "value": cell
+            })
+        } else if is_match(&RE_CSV_DATE_TIME) {
+            json!({
+                "type": "literal",
+                "datatype": "http://www.w3.org/2001/XMLSchema#dateTime",
+                "value": cell
+            })
+        } else {
+            json!({"type": "literal", "value": cell})
+        };
+        SparqlValue::new_from_json(&j).or_else(|| Some(SparqlValue::Literal(cell.to_string())))
+    }
+
+    fn binding(
+        &self,
+        row_id: usize,
+    ) -> Result<serde_json::Map<String, serde_json::Value>, SparqlTableError> {
+        let row = self.try_get(row_id)?.unwrap_or_default();
+        Ok(self
+            .headers()
+            .iter()
+            .zip(row)
+            .filter_map(|(name, cell)| Some((name.to_owned(), json!(cell?))))
+            .collect())
+    }
+
+    fn write_delimited<W: Write>(
+        &self,
+        writer: W,
+        delimiter: u8,
+        format: CellFormat,
+    ) -> Result<(), SparqlTableError> {
+        let mut writer = csv::WriterBuilder::new()
+            .delimiter(delimiter)
+            .from_writer(writer);
+        writer.write_record(self.headers())?;
+        for row_id in 0..self.len() {
+            let row = self.try_get(row_id)?.unwrap_or_default();
+            let cells = (0..self.headers().len()).map(|col| {
+                row.get(col)
+                    .and_then(|cell| cell.as_ref())
+                    .map(|value| format.render(value))
+                    .unwrap_or_default()
+            });
+            writer.write_record(cells)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lat_lon::LatLon;
+    use crate::sparql_results::SparqlApiResult;
+    use crate::sparql_table::SparqlTableVec;
+    use crate::sparql_value::SparqlTime;
+
+    fn sample_table() -> SparqlTableVec {
+        let mut table = SparqlTableVec::new();
+        table.set_headers(vec![
+            "item".to_string(),
+            "label".to_string(),
+            "coord".to_string(),
+            "date".to_string(),
+            "image".to_string(),
+        ]);
+        table
+            .push(vec![
+                Some(SparqlValue::Entity("Q42".to_string())),
+                Some(SparqlValue::Literal("Douglas \"DNA\", Adams".to_string())),
+                Some(SparqlValue::Location(LatLon::new(51.5, -0.1))),
+                Some(SparqlValue::Time(
+                    SparqlTime::parse("1952-03-11T12:00:00Z").unwrap(),
+                )),
+                Some(SparqlValue::File("Douglas adams portrait.jpg".to_string())),
+            ])
+            .unwrap();
+        table
+            .push(vec![
+                Some(SparqlValue::Uri(
+                    "http://www.wikidata.org/prop/direct/P31".to_string(),
+                )),
+                None,
+                None,
+                None,
+                None,
+            ])
+            .unwrap();
+        table
+    }
+
+    fn rows(table: &SparqlTableVec) -> Vec<SparqlRow> {
+        (0..table.len()).filter_map(|i| table.get(i)).collect()
+    }
+
+    #[test]
+    fn test_write_sparql_json_round_trips() {
+        let table = sample_table();
+        let mut out = vec![];
+        table.write_sparql_json(&mut out).unwrap();
+
+        let result: SparqlApiResult = serde_json::from_slice(&out).unwrap();
+        assert_eq!(result.head()["vars"], table.headers());
+        // `None` cells are omitted from their binding.
+        assert_eq!(result.bindings()[1].len(), 1);
+
+        let back = SparqlTableVec::from_api_result(result).unwrap();
+        assert_eq!(rows(&back), rows(&table));
+    }
+
+    #[test]
+    fn test_write_sparql_json_empty_table() {
+        let mut table = SparqlTableVec::new();
+        table.set_headers(vec!["x".to_string()]);
+        let mut out = vec![];
+        table.write_sparql_json(&mut out).unwrap();
+        let back = SparqlTableVec::from_json_reader(out.as_slice()).unwrap();
+        assert!(back.is_empty());
+        assert_eq!(back.headers(), table.headers());
+    }
+
+    #[test]
+    fn test_write_json_lines() {
+        let table = sample_table();
+        let mut out = vec![];
+        table.write_json_lines(&mut out).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 2);
+        let first: serde_json::Value = serde_json::from_str(lines[0]).unwrap();
+        assert_eq!(first["item"]["value"], "http://www.wikidata.org/entity/Q42");
+        let second: serde_json::Value = serde_json::from_str(lines[1]).unwrap();
+        assert!(second.get("label").is_none());
+    }
+
+    #[test]
+    fn test_write_csv_plain_round_trips_through_reader() {
+        let table = sample_table();
+        let mut out = vec![];
+        table.write_csv(&mut out, CellFormat::Plain).unwrap();
+
+        let text = String::from_utf8(out.clone()).unwrap();
+        assert!(text.starts_with("item,label,coord,date,image\n"));
+        assert!(text.contains("http://www.wikidata.org/entity/Q42"));
+
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        let back = SparqlTableVec::from_csv_reader(&mut reader).unwrap();
+        assert_eq!(back.headers(), table.headers());
+        assert_eq!(rows(&back), rows(&table));
+    }
+
+    #[test]
+    fn test_write_csv_prefixed() {
+        let table = sample_table();
+        let mut out = vec![];
+        table.write_csv(&mut out, CellFormat::Prefixed).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert!(lines[1].starts_with("wd:Q42,"));
+        assert_eq!(lines[2], "wdt:P31,,,,");
+    }
+
+    #[test]
+    fn test_write_tsv() {
+        let table = sample_table();
+        let mut out = vec![];
+        table.write_tsv(&mut out, CellFormat::Prefixed).unwrap();
+        let text = String::from_utf8(out).unwrap();
+        assert!(text.starts_with("item\tlabel\tcoord\tdate\timage\n"));
+        // Cells containing quotes are quoted, as in CSV.
+        assert!(text.contains("wd:Q42\t\"Douglas \"\"DNA\"\", Adams\"\t"));
+    }
+
+    #[test]
+    fn test_from_csv_reader_types_wdqs_output() {
+        // The shape `Wikidata::load_sparql_csv` hands back.
+        let csv = "\
+item,itemLabel,dob,img\r\n\
+http://www.wikidata.org/entity/Q1,one,2001-01-01T00:00:00Z,\r\n\
+http://example.org/x,,,http://commons.wikimedia.org/wiki/Special:FilePath/A%20B.jpg\r\n";
+        let mut reader = csv::ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(csv.as_bytes());
+        let table = SparqlTableVec::from_csv_reader(&mut reader).unwrap();
+
+        assert_eq!(table.len(), 2);
+        assert_eq!(
+            table.get_row_col(0, 0),
+            Some(SparqlValue::Entity("Q1".to_string()))
+        );
+        assert_eq!(
+            table.get_row_col(0, 1),
+            Some(SparqlValue::Literal("one".to_string()))
+        );
+        assert_eq!(
+            table.get_row_col(0, 2),
+            Some(SparqlValue::Time(SparqlTime::parse("2001-01-01").unwrap()))
+        );
+        assert_eq!(table.get_row_col(0, 3), None);
+        assert_eq!(
+            table.get_row_col(1, 0),
+            Some(SparqlValue::Uri("http://example.org/x".to_string()))
+        );
+        assert_eq!(table.get_row_col(1, 1), None);
+        assert_eq!(
+            table.get_row_col(1, 3),
+            Some(SparqlValue::File("A B.jpg".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_prefixed_format_shortens_lexeme_parts_and_nodes() {
+        let render = |value: SparqlValue| CellFormat::Prefixed.render(&value);
+        assert_eq!(render(SparqlValue::Form("L1-F2".to_string())), "wd:L1-F2");
+        assert_eq!(
+            render(SparqlValue::Statement("Q1-abc".to_string())),
+            "wds:Q1-abc"
+        );
+        assert_eq!(
+            render(SparqlValue::Reference("00ff".to_string())),
+            "wdref:00ff"
+        );
+        assert_eq!(
+            render(SparqlValue::ValueNode("00ff".to_string())),
+            "wdv:00ff"
+        );
+        assert_eq!(render(SparqlValue::Integer(3)), "3");
+    }
+
+    #[test]
+    fn test_prefixed_format_shortens_normalized_namespaces() {
+        let render = |uri: &str| CellFormat::Prefixed.render(&SparqlValue::Uri(uri.to_string()));
+        assert_eq!(
+            render("http://www.wikidata.org/prop/direct-normalized/P31"),
+            "wdtn:P31"
+        );
+        assert_eq!(
+            render("http://www.wikidata.org/prop/statement/value-normalized/P2048"),
+            "psn:P2048"
+        );
+        assert_eq!(
+            render("http://www.wikidata.org/prop/qualifier/value-normalized/P2048"),
+            "pqn:P2048"
+        );
+        assert_eq!(
+            render("http://www.wikidata.org/prop/reference/value-normalized/P2048"),
+            "prn:P2048"
+        );
+    }
+
+    #[test]
+    fn test_from_csv_reader_reads_globe_coordinates() {
+        let csv = "loc\r\n<http://www.wikidata.org/entity/Q405> Point(1.5 -2)\r\n";
+        let mut reader = csv::Reader::from_reader(csv.as_bytes());
+        let table = SparqlTableVec::from_csv_reader(&mut reader).unwrap();
+        assert_eq!(
+            table.get_row_col(0, 0),
+            Some(SparqlValue::GlobeLocation {
+                globe: "http://www.wikidata.org/entity/Q405".to_string(),
+                location: LatLon::new(-2.0, 1.5),
+            })
+        );
+    }
+
+    /// A storage that accepts rows but cannot read any back.
+    #[derive(Default)]
+    struct UnreadableStorage(usize);
+
+    impl RowStorage for UnreadableStorage {
+        fn push(&mut self, _row: SparqlRow) -> Result<(), SparqlTableError> {
+            self.0 += 1;
+            Ok(())
+        }
+        fn get(&self, _idx: usize) -> Option<SparqlRow> {
+            None
+        }
+        fn len(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_export_fails_on_unreadable_row() {
+        let mut table = SparqlTable::<UnreadableStorage>::new();
+        table.set_headers(vec!["item".to_string()]);
+        table
+            .push(vec![Some(SparqlValue::Entity("Q42".to_string()))])
+            .unwrap();
+        assert!(matches!(
+            table.write_csv(vec![], CellFormat::Plain),
+            Err(SparqlTableError::Io(_))
+        ));
+        assert!(matches!(
+            table.write_json_lines(vec![]),
+            Err(SparqlTableError::Io(_))
+        ));
+    }
+
+    #[test]
+    fn test_all_static_regexes_compile() {
+        assert!(RE_CSV_POINT.is_some());
+        assert!(RE_CSV_DATE_TIME.is_some());
+    }
+}
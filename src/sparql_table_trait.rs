@@ -1,9 +1,42 @@
 //! Shared trait implemented by [`crate::sparql_table::SparqlTable`] for every
 //! [`crate::sparql_table::RowStorage`] backend it is instantiated with.
+//!
+//! Besides cell and row access, the trait provides table operations —
+//! [`SparqlTableTrait::select`], [`SparqlTableTrait::filter`],
+//! [`SparqlTableTrait::sort_by_vars`], [`SparqlTableTrait::dedup`],
+//! [`SparqlTableTrait::group_by_main_variable`] and [`SparqlTableTrait::join`]
+//! — that leave the source tables untouched and return new tables, built with
+//! [`SparqlTable::from_table`], in whichever [`RowStorage`] the caller asks
+//! for. A row the source storage cannot read fails the operation.
 
 use crate::sparql_results::SparqlRow;
-use crate::sparql_table::SparqlTableError;
+use crate::sparql_table::{RowStorage, SparqlTable, SparqlTableError};
 use crate::sparql_value::SparqlValue;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+
+/// One group from [`SparqlTableTrait::group_by_main_variable`]: the main
+/// variable's value and the rows that share it.
+pub type SparqlTableGroup<T> = (Option<SparqlValue>, SparqlTable<T>);
+
+/// Which rows [`SparqlTableTrait::join`] keeps.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JoinKind {
+    /// One output row per pair of rows with matching keys.
+    Inner,
+    /// Like `Inner`, plus every left row without a match, padded with empty cells.
+    Left,
+    /// Only the left rows without a match, with the left columns only.
+    Anti,
+}
+
+/// Direction of one sort key in [`SparqlTableTrait::sort_by_vars`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum SortOrder {
+    #[default]
+    Ascending,
+    Descending,
+}
 
 /// Common interface implemented by [`crate::sparql_table::SparqlTable`]
 /// regardless of its [`crate::sparql_table::RowStorage`] backend.
@@ -31,6 +64,11 @@ pub trait SparqlTableTrait {
     /// Get a row by index. Returns `None` if out of range.
     fn get(&self, row_id: usize) -> Option<SparqlRow>;
 
+    /// Like [`Self::get`], but fails for a row in range that cannot be read.
+    fn try_get(&self, row_id: usize) -> Result<Option<SparqlRow>, SparqlTableError> {
+        Ok(self.get(row_id))
+    }
+
     /// Return the main variable name, if set.
     fn main_variable(&self) -> Option<&String>;
 
@@ -42,6 +80,275 @@ pub trait SparqlTableTrait {
 
     /// Replace the header list.
     fn set_headers(&mut self, headers: Vec<String>);
+
+    /// The variable names, in column order.
+    fn headers(&self) -> &[String];
+
+    /// A new table with only the columns for `vars`, in the order given.
+    /// The main variable is kept if it is among them.
+    fn select<T: RowStorage>(&self, vars: &[&str]) -> Result<SparqlTable<T>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let columns = var_indices(self, vars)?;
+        let mut table = SparqlTable::<T>::from_table(self);
+        table.set_headers(
+            columns
+                .iter()
+                .filter_map(|&col| self.headers().get(col).cloned())
+                .collect(),
+        );
+        if table.main_column().is_none() {
+            table.set_main_variable(None);
+        }
+        for row in rows(self) {
+            let row = row?;
+            table.push(
+                columns
+                    .iter()
+                    .map(|&col| row.get(col).cloned().flatten())
+                    .collect(),
+            )?;
+        }
+        Ok(table)
+    }
+
+    /// A new table with only the rows for which `predicate` returns `true`.
+    fn filter<T: RowStorage, F: FnMut(&SparqlRow) -> bool>(
+        &self,
+        mut predicate: F,
+    ) -> Result<SparqlTable<T>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let mut table = SparqlTable::<T>::from_table(self);
+        for row in rows(self) {
+            let row = row?;
+            if predicate(&row) {
+                table.push(row)?;
+            }
+        }
+        Ok(table)
+    }
+
+    /// A new table sorted by one or more variables, compared with
+    /// [`SparqlValue::typed_cmp`]. Empty cells sort after all values in either
+    /// direction. The sort is stable, so rows that tie keep their order.
+    fn sort_by_vars<T: RowStorage>(
+        &self,
+        keys: &[(&str, SortOrder)],
+    ) -> Result<SparqlTable<T>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let vars: Vec<&str> = keys.iter().map(|(var, _)| *var).collect();
+        let columns = var_indices(self, &vars)?;
+        // Only the key cells are held in memory; rows are fetched again in
+        // sorted order, which keeps disk-backed tables cheap to sort.
+        let mut order: Vec<(usize, Vec<Option<SparqlValue>>)> = vec![];
+        for row_id in 0..self.len() {
+            if let Some(row) = self.try_get(row_id)? {
+                let key = columns
+                    .iter()
+                    .map(|&col| row.get(col).cloned().flatten())
+                    .collect();
+                order.push((row_id, key));
+            }
+        }
+        order.sort_by(|(_, a), (_, b)| {
+            a.iter()
+                .zip(b)
+                .zip(keys)
+                .map(|((x, y), (_, direction))| match (x, y) {
+                    (Some(x), Some(y)) => match direction {
+                        SortOrder::Ascending => x.typed_cmp(y),
+                        SortOrder::Descending => y.typed_cmp(x),
+                    },
+                    (Some(_), None) => Ordering::Less,
+                    (None, Some(_)) => Ordering::Greater,
+                    (None, None) => Ordering::Equal,
+                })
+                .find(|ordering| *ordering != Ordering::Equal)
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let mut table = SparqlTable::<T>::from_table(self);
+        for (row_id, _) in order {
+            if let Some(row) = self.try_get(row_id)? {
+                table.push(row)?;
+            }
+        }
+        Ok(table)
+    }
+
+    /// A new table without duplicate rows; the first occurrence is kept.
+    fn dedup<T: RowStorage>(&self) -> Result<SparqlTable<T>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let mut seen = HashSet::new();
+        let mut table = SparqlTable::<T>::from_table(self);
+        for row in rows(self) {
+            let row = row?;
+            if seen.insert(row_key(&row)?) {
+                table.push(row)?;
+            }
+        }
+        Ok(table)
+    }
+
+    /// Splits the table into one table per distinct value of the main
+    /// variable, in order of first appearance. Rows without a main value are
+    /// grouped under `None`.
+    fn group_by_main_variable<T: RowStorage>(
+        &self,
+    ) -> Result<Vec<SparqlTableGroup<T>>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let main_column = self
+            .main_column()
+            .ok_or(SparqlTableError::MainVariableNotSet)?;
+        let mut group_index: HashMap<String, usize> = HashMap::new();
+        let mut groups: Vec<SparqlTableGroup<T>> = vec![];
+        for row in rows(self) {
+            let row = row?;
+            let key = row.get(main_column).cloned().flatten();
+            let idx = *group_index
+                .entry(row_key(std::slice::from_ref(&key))?)
+                .or_insert_with(|| {
+                    groups.push((key.clone(), SparqlTable::from_table(self)));
+                    groups.len() - 1
+                });
+            if let Some((_, table)) = groups.get_mut(idx) {
+                table.push(row)?;
+            }
+        }
+        Ok(groups)
+    }
+
+    /// Joins this table with `other` on the variable `var`, which both tables
+    /// must have. Rows whose key cell is empty never match.
+    ///
+    /// The output has this table's columns, followed (except for
+    /// [`JoinKind::Anti`]) by `other`'s columns minus its key column. A name
+    /// from `other` that is already taken, compared case-insensitively like
+    /// [`Self::get_var_index`] does, gets a `_2`, `_3`, … suffix. The main
+    /// variable is this table's.
+    fn join<T: RowStorage, O: SparqlTableTrait>(
+        &self,
+        other: &O,
+        var: &str,
+        kind: JoinKind,
+    ) -> Result<SparqlTable<T>, SparqlTableError>
+    where
+        Self: Sized,
+    {
+        let unknown = || SparqlTableError::UnknownVariable(var.to_string());
+        let left_key = self.get_var_index(var).ok_or_else(unknown)?;
+        let right_key = other.get_var_index(var).ok_or_else(unknown)?;
+
+        // Only row IDs are indexed; matching rows are fetched when emitted.
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for row_id in 0..other.len() {
+            let key = other
+                .try_get(row_id)?
+                .and_then(|row| row.get(right_key).cloned().flatten());
+            if let Some(key) = key {
+                index
+                    .entry(row_key(&[Some(key)])?)
+                    .or_default()
+                    .push(row_id);
+            }
+        }
+
+        let right_columns: Vec<usize> = (0..other.headers().len())
+            .filter(|&col| col != right_key)
+            .collect();
+        let mut headers = self.headers().to_vec();
+        if kind != JoinKind::Anti {
+            for &col in &right_columns {
+                let name = other.headers().get(col).cloned().unwrap_or_default();
+                headers.push(unique_header(&headers, name));
+            }
+        }
+        let mut table = SparqlTable::<T>::from_table(self);
+        table.set_headers(headers);
+
+        for row in rows(self) {
+            let row = row?;
+            let matches = match row.get(left_key).cloned().flatten() {
+                Some(key) => index.get(&row_key(&[Some(key)])?),
+                None => None,
+            };
+            match (kind, matches) {
+                (JoinKind::Anti, Some(_)) => {}
+                (JoinKind::Anti, None) => table.push(row)?,
+                (JoinKind::Inner, None) => {}
+                (JoinKind::Left, None) => {
+                    let mut new_row = row;
+                    new_row.resize(self.headers().len(), None);
+                    new_row.extend(right_columns.iter().map(|_| None));
+                    table.push(new_row)?;
+                }
+                (JoinKind::Inner | JoinKind::Left, Some(right_rows)) => {
+                    for &right_id in right_rows {
+                        let right = other.try_get(right_id)?.unwrap_or_default();
+                        let mut new_row = row.clone();
+                        new_row.resize(self.headers().len(), None);
+                        new_row.extend(
+                            right_columns
+                                .iter()
+                                .map(|&col| right.get(col).cloned().flatten()),
+                        );
+                        table.push(new_row)?;
+                    }
+                }
+            }
+        }
+        Ok(table)
+    }
+}
+
+/// `name`, or `name_2`, `name_3`, … if a header already has that name.
+fn unique_header(headers: &[String], name: String) -> String {
+    let taken = |candidate: &str| {
+        let candidate = candidate.to_lowercase();
+        headers.iter().any(|h| h.to_lowercase() == candidate)
+    };
+    if !taken(&name) {
+        return name;
+    }
+    (2..)
+        .map(|n| format!("{name}_{n}"))
+        .find(|candidate| !taken(candidate))
+        .unwrap_or(name)
+}
+
+/// Resolves variable names to column indices, failing on the first unknown one.
+fn var_indices<S: SparqlTableTrait + ?Sized>(
+    table: &S,
+    vars: &[&str],
+) -> Result<Vec<usize>, SparqlTableError> {
+    vars.iter()
+        .map(|var| {
+            table
+                .get_var_index(var)
+                .ok_or_else(|| SparqlTableError::UnknownVariable(var.to_string()))
+        })
+        .collect()
+}
+
+/// Every row of `table`, in order, or the error reading it.
+fn rows<S: SparqlTableTrait + ?Sized>(
+    table: &S,
+) -> impl Iterator<Item = Result<SparqlRow, SparqlTableError>> + '_ {
+    (0..table.len()).filter_map(|row_id| table.try_get(row_id).transpose())
+}
+
+/// A hashable stand-in for a row, since [`SparqlValue`] is not `Hash`.
+fn row_key(row: &[Option<SparqlValue>]) -> Result<String, SparqlTableError> {
+    Ok(serde_json::to_string(row)?)
 }
 
 #[cfg(test)]
@@ -96,6 +403,12 @@ mod tests {
         assert_table_behaviour(&mut table);
     }
 
+    #[test]
+    fn test_sparql_table_trait_disk_backend() {
+        let mut table = crate::sparql_table_disk::SparqlTableDisk::new();
+        assert_table_behaviour(&mut table);
+    }
+
     #[test]
     fn test_dyn_dispatch() {
         // The trait must remain object-safe so callers can hold a `&dyn SparqlTableTrait`.
@@ -105,4 +418,293 @@ mod tests {
         let v = SparqlTableVec::new();
         assert_eq!(count(&v), 0);
     }
+
+    // ── table operations ─────────────────────────────────────────────────────
+
+    fn entity(id: &str) -> Option<SparqlValue> {
+        Some(SparqlValue::Entity(id.to_string()))
+    }
+
+    fn literal(s: &str) -> Option<SparqlValue> {
+        Some(SparqlValue::Literal(s.to_string()))
+    }
+
+    /// item / label / year, with `item` as the main variable.
+    fn people() -> SparqlTableVec {
+        let mut table = SparqlTableVec::new();
+        table.set_headers(vec![
+            "item".to_string(),
+            "label".to_string(),
+            "year".to_string(),
+        ]);
+        table.set_main_variable(Some("item".to_string()));
+        for row in [
+            vec![entity("Q10"), literal("b"), literal("1990")],
+            vec![entity("Q2"), literal("a"), literal("2001")],
+            vec![entity("Q10"), literal("c"), None],
+            vec![entity("Q2"), literal("a"), literal("2001")],
+            vec![None, literal("orphan"), literal("5")],
+        ] {
+            table.push(row).unwrap();
+        }
+        table
+    }
+
+    fn column(table: &impl SparqlTableTrait, var: &str) -> Vec<Option<SparqlValue>> {
+        let col = table.get_var_index(var).unwrap();
+        (0..table.len())
+            .map(|row| table.get_row_col(row, col))
+            .collect()
+    }
+
+    #[test]
+    fn test_select_reorders_and_drops_columns() {
+        let table = people();
+        let selected: SparqlTableVec = table.select(&["year", "item"]).unwrap();
+        assert_eq!(selected.headers(), ["year", "item"]);
+        assert_eq!(selected.main_variable(), Some(&"item".to_string()));
+        assert_eq!(selected.get(0), Some(vec![literal("1990"), entity("Q10")]));
+        assert_eq!(selected.len(), table.len());
+
+        let without_main: SparqlTableVec = table.select(&["label"]).unwrap();
+        assert_eq!(without_main.main_variable(), None);
+    }
+
+    #[test]
+    fn test_select_unknown_variable_is_err() {
+        let result: Result<SparqlTableVec, _> = people().select(&["nope"]);
+        assert!(matches!(result, Err(SparqlTableError::UnknownVariable(v)) if v == "nope"));
+    }
+
+    #[test]
+    fn test_filter() {
+        let table = people();
+        let label = table.get_var_index("label").unwrap();
+        let filtered: SparqlTableVec = table
+            .filter(|row| row.get(label).cloned().flatten() == literal("a"))
+            .unwrap();
+        assert_eq!(filtered.len(), 2);
+        assert_eq!(filtered.headers(), table.headers());
+    }
+
+    #[test]
+    fn test_sort_by_vars_typed_and_multi_key() {
+        let table = people();
+        let sorted: SparqlTableVec = table
+            .sort_by_vars(&[
+                ("item", SortOrder::Ascending),
+                ("label", SortOrder::Descending),
+            ])
+            .unwrap();
+        // Q2 before Q10 numerically; no-item row last.
+        assert_eq!(
+            column(&sorted, "item"),
+            vec![
+                entity("Q2"),
+                entity("Q2"),
+                entity("Q10"),
+                entity("Q10"),
+                None
+            ]
+        );
+        assert_eq!(column(&sorted, "label")[2..4], [literal("c"), literal("b")]);
+    }
+
+    #[test]
+    fn test_sort_by_vars_descending_numbers_empty_last() {
+        let sorted: SparqlTableVec = people()
+            .sort_by_vars(&[("year", SortOrder::Descending)])
+            .unwrap();
+        assert_eq!(
+            column(&sorted, "year"),
+            vec![
+                literal("2001"),
+                literal("2001"),
+                literal("1990"),
+                literal("5"),
+                None
+            ]
+        );
+    }
+
+    #[test]
+    fn test_dedup_keeps_first_occurrence() {
+        let deduped: SparqlTableVec = people().dedup().unwrap();
+        assert_eq!(deduped.len(), 4);
+        assert_eq!(
+            column(&deduped, "label"),
+            vec![literal("b"), literal("a"), literal("c"), literal("orphan")]
+        );
+    }
+
+    #[test]
+    fn test_group_by_main_variable() {
+        let groups: Vec<SparqlTableGroup<Vec<SparqlRow>>> =
+            people().group_by_main_variable().unwrap();
+        let keys: Vec<Option<SparqlValue>> = groups.iter().map(|(k, _)| k.clone()).collect();
+        assert_eq!(keys, vec![entity("Q10"), entity("Q2"), None]);
+        assert_eq!(groups[0].1.len(), 2);
+        assert_eq!(groups[1].1.len(), 2);
+        assert_eq!(groups[2].1.len(), 1);
+        assert_eq!(groups[0].1.main_variable(), Some(&"item".to_string()));
+    }
+
+    #[test]
+    fn test_group_by_main_variable_requires_main_variable() {
+        let mut table = people();
+        table.set_main_variable(None);
+        let result: Result<Vec<SparqlTableGroup<Vec<SparqlRow>>>, _> =
+            table.group_by_main_variable();
+        assert!(matches!(result, Err(SparqlTableError::MainVariableNotSet)));
+    }
+
+    #[test]
+    fn test_operations_across_backends() {
+        use crate::sparql_table_disk::SparqlTableDisk;
+        let on_disk: SparqlTableDisk = people().filter(|_| true).unwrap();
+        let back: SparqlTableVec = on_disk
+            .sort_by_vars(&[("label", SortOrder::Ascending)])
+            .unwrap();
+        assert_eq!(back.len(), 5);
+        assert_eq!(column(&back, "label")[0], literal("a"));
+    }
+
+    /// A storage that accepts rows but cannot read any back.
+    #[derive(Default)]
+    struct UnreadableStorage(usize);
+
+    impl RowStorage for UnreadableStorage {
+        fn push(&mut self, _row: SparqlRow) -> Result<(), SparqlTableError> {
+            self.0 += 1;
+            Ok(())
+        }
+        fn get(&self, _idx: usize) -> Option<SparqlRow> {
+            None
+        }
+        fn len(&self) -> usize {
+            self.0
+        }
+    }
+
+    #[test]
+    fn test_operations_fail_on_unreadable_rows() {
+        let unreadable: SparqlTable<UnreadableStorage> = people().filter(|_| true).unwrap();
+        assert_eq!(unreadable.len(), 5);
+        let is_io = |result: Result<SparqlTableVec, SparqlTableError>| {
+            matches!(result, Err(SparqlTableError::Io(_)))
+        };
+        assert!(is_io(unreadable.select(&["item"])));
+        assert!(is_io(unreadable.filter(|_| true)));
+        assert!(is_io(
+            unreadable.sort_by_vars(&[("label", SortOrder::Ascending)])
+        ));
+        assert!(is_io(unreadable.dedup()));
+        assert!(unreadable
+            .group_by_main_variable::<Vec<SparqlRow>>()
+            .is_err());
+    }
+
+    // ── joins ────────────────────────────────────────────────────────────────
+
+    /// item / birth, from a second query.
+    fn births() -> SparqlTableVec {
+        let mut table = SparqlTableVec::new();
+        table.set_headers(vec![
+            "item".to_string(),
+            "Label".to_string(),
+            "born".to_string(),
+        ]);
+        for row in [
+            vec![entity("Q2"), literal("A"), literal("1900")],
+            vec![entity("Q2"), literal("A2"), literal("1901")],
+            vec![entity("Q99"), literal("Z"), literal("1800")],
+            vec![None, literal("none"), literal("0")],
+        ] {
+            table.push(row).unwrap();
+        }
+        table
+    }
+
+    #[test]
+    fn test_join_inner() {
+        let joined: SparqlTableVec = people().join(&births(), "item", JoinKind::Inner).unwrap();
+        // `Label` collides with `label` case-insensitively and is renamed.
+        assert_eq!(
+            joined.headers(),
+            ["item", "label", "year", "Label_2", "born"]
+        );
+        assert_eq!(joined.main_variable(), Some(&"item".to_string()));
+        // Two Q2 rows on the left times two on the right.
+        assert_eq!(joined.len(), 4);
+        assert_eq!(
+            joined.get(0),
+            Some(vec![
+                entity("Q2"),
+                literal("a"),
+                literal("2001"),
+                literal("A"),
+                literal("1900")
+            ])
+        );
+    }
+
+    #[test]
+    fn test_join_left_pads_unmatched_rows() {
+        let joined: SparqlTableVec = people().join(&births(), "item", JoinKind::Left).unwrap();
+        // Q10 twice, Q2 twice × 2, and the key-less row.
+        assert_eq!(joined.len(), 7);
+        assert_eq!(
+            joined.get(0),
+            Some(vec![
+                entity("Q10"),
+                literal("b"),
+                literal("1990"),
+                None,
+                None
+            ])
+        );
+        assert_eq!(
+            joined.get(6),
+            Some(vec![None, literal("orphan"), literal("5"), None, None])
+        );
+    }
+
+    #[test]
+    fn test_join_anti() {
+        let joined: SparqlTableVec = people().join(&births(), "item", JoinKind::Anti).unwrap();
+        assert_eq!(joined.headers(), people().headers());
+        assert_eq!(
+            column(&joined, "item"),
+            vec![entity("Q10"), entity("Q10"), None]
+        );
+    }
+
+    #[test]
+    fn test_join_unknown_variable_is_err() {
+        let result: Result<SparqlTableVec, _> = people().join(&births(), "year", JoinKind::Inner);
+        assert!(matches!(result, Err(SparqlTableError::UnknownVariable(v)) if v == "year"));
+    }
+
+    #[test]
+    fn test_join_fails_on_unreadable_rows() {
+        let right: SparqlTable<UnreadableStorage> = births().filter(|_| true).unwrap();
+        let result: Result<SparqlTableVec, _> = people().join(&right, "item", JoinKind::Left);
+        assert!(matches!(result, Err(SparqlTableError::Io(_))));
+    }
+
+    #[test]
+    fn test_join_across_backends() {
+        use crate::sparql_table_disk::SparqlTableDisk;
+        let right: SparqlTableDisk = births().filter(|_| true).unwrap();
+        let joined: SparqlTableDisk = people().join(&right, "ITEM", JoinKind::Inner).unwrap();
+        assert_eq!(joined.len(), 4);
+    }
+
+    #[test]
+    fn test_unique_header() {
+        let headers = vec!["a".to_string(), "b".to_string(), "b_2".to_string()];
+        assert_eq!(unique_header(&headers, "c".to_string()), "c");
+        assert_eq!(unique_header(&headers, "A".to_string()), "A_2");
+        assert_eq!(unique_header(&headers, "b".to_string()), "b_3");
+    }
 }
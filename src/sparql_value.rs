@@ -4,6 +4,7 @@ use regex::Regex;
 use serde::ser::{Serialize, SerializeStruct, Serializer};
 use serde::Deserializer;
 use serde_json::Value;
+use std::cmp::Ordering;
 use std::sync::LazyLock;
 
 // The patterns below are literals and cannot fail to compile in practice. They
@@ -13,12 +14,32 @@ use std::sync::LazyLock;
 // `test_all_static_regexes_compile` makes sure CI catches a broken literal.
 static RE_ENTITY: LazyLock<Option<Regex>> =
     LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/entity/([A-Z]\d+)$"#).ok());
+static RE_LEXEME_PART: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/entity/(L\d+-([FS])\d+)$"#).ok());
+static RE_STATEMENT: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/entity/statement/(.+)$"#).ok());
+static RE_REFERENCE: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/reference/([0-9a-f]+)$"#).ok());
+static RE_VALUE_NODE: LazyLock<Option<Regex>> =
+    LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/value/([0-9a-f]+)$"#).ok());
 static RE_FILE: LazyLock<Option<Regex>> =
     LazyLock::new(|| Regex::new(r#"^https{0,1}://[^/]+/wiki/Special:FilePath/(.+?)$"#).ok());
 static RE_POINT: LazyLock<Option<Regex>> =
     LazyLock::new(|| Regex::new(r#"^Point\((-{0,1}\d+[\.0-9]*) (-{0,1}\d+[\.0-9]*)\)$"#).ok());
-static RE_DATE: LazyLock<Option<Regex>> =
-    LazyLock::new(|| Regex::new(r#"^([+-]{0,1}\d+-\d{2}-\d{2})T00:00:00Z$"#).ok());
+static RE_GLOBE_POINT: LazyLock<Option<Regex>> = LazyLock::new(|| {
+    Regex::new(r#"^<([^>]+)> Point\((-{0,1}\d+[\.0-9]*) (-{0,1}\d+[\.0-9]*)\)$"#).ok()
+});
+static RE_DATE_TIME: LazyLock<Option<Regex>> = LazyLock::new(|| {
+    Regex::new(r#"^([+-]{0,1}\d+)-(\d{2})-(\d{2})(?:T(\d{2}):(\d{2}):(\d{2})Z?)?$"#).ok()
+});
+
+const XSD_DATE_TIME: &str = "http://www.w3.org/2001/XMLSchema#dateTime";
+const XSD_INTEGER: &str = "http://www.w3.org/2001/XMLSchema#integer";
+const XSD_DECIMAL: &str = "http://www.w3.org/2001/XMLSchema#decimal";
+const XSD_DOUBLE: &str = "http://www.w3.org/2001/XMLSchema#double";
+const XSD_BOOLEAN: &str = "http://www.w3.org/2001/XMLSchema#boolean";
+const WKT_LITERAL: &str = "http://www.opengis.net/ont/geosparql#wktLiteral";
+const WIKIDATA: &str = "http://www.wikidata.org";
 
 /// Captures from one of the statics above, treating an unavailable regex as a
 /// plain non-match.
@@ -26,123 +47,488 @@ fn captures<'t>(re: &Option<Regex>, text: &'t str) -> Option<regex::Captures<'t>
     re.as_ref()?.captures(text)
 }
 
+/// Reads a WKT point's longitude from group `lon_group` and its latitude from
+/// the group after it.
+fn point_from_captures(caps: &regex::Captures, lon_group: usize) -> Option<LatLon> {
+    let lon: f64 = caps.get(lon_group)?.as_str().parse().ok()?;
+    let lat: f64 = caps.get(lon_group + 1)?.as_str().parse().ok()?;
+    Some(LatLon::new(lat, lon))
+}
+
+/// An `xsd:dateTime` value, covering everything Wikidata stores: negative
+/// (BCE) and more-than-four-digit years, and a month or day of 0 for values
+/// coming from a reduced-precision Wikibase time. The derived order is
+/// chronological.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SparqlTime {
+    pub year: i64,
+    pub month: u8,
+    pub day: u8,
+    pub hour: u8,
+    pub minute: u8,
+    pub second: u8,
+}
+
+impl SparqlTime {
+    /// Parses `[+-]YYYY…-MM-DD`, optionally followed by `Thh:mm:ss` and `Z`.
+    /// Both the `xsd:dateTime` form WDQS emits and the Wikibase form
+    /// (`+1990-05-00T00:00:00Z`) are accepted.
+    pub fn parse(s: &str) -> Option<Self> {
+        let caps = captures(&RE_DATE_TIME, s)?;
+        let part = |i: usize| -> Option<u8> {
+            match caps.get(i) {
+                Some(m) => m.as_str().parse().ok(),
+                None => Some(0),
+            }
+        };
+        let time = Self {
+            year: caps.get(1)?.as_str().parse().ok()?,
+            month: part(2)?,
+            day: part(3)?,
+            hour: part(4)?,
+            minute: part(5)?,
+            second: part(6)?,
+        };
+        let valid = time.month <= 12
+            && time.day <= 31
+            && time.hour <= 23
+            && time.minute <= 59
+            && time.second <= 60;
+        valid.then_some(time)
+    }
+
+    /// The Wikibase time string, e.g. `+1990-05-17T00:00:00Z`: always signed,
+    /// with the year padded to at least four digits.
+    pub fn wikibase_time(&self) -> String {
+        let sign = if self.year < 0 { '-' } else { '+' };
+        format!("{sign}{}", self.unsigned_time())
+    }
+
+    fn unsigned_time(&self) -> String {
+        format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+            self.year.unsigned_abs(),
+            self.month,
+            self.day,
+            self.hour,
+            self.minute,
+            self.second
+        )
+    }
+
+    /// A copy with every component finer than the Wikibase `precision`
+    /// zeroed, as Wikibase stores it: precision 9 (year) blanks month and
+    /// day, 10 (month) blanks the day, and the time of day is always dropped.
+    pub fn truncated_to_precision(&self, precision: u64) -> Self {
+        Self {
+            year: self.year,
+            month: if precision >= 10 { self.month } else { 0 },
+            day: if precision >= 11 { self.day } else { 0 },
+            hour: 0,
+            minute: 0,
+            second: 0,
+        }
+    }
+
+    /// Reads the `time` of a Wikibase time value.
+    #[cfg(feature = "wikibase")]
+    pub fn from_time_value(time_value: &wikibase::TimeValue) -> Option<Self> {
+        Self::parse(time_value.time())
+    }
+
+    /// A Wikibase time value at `precision` in `calendarmodel` (an entity URI
+    /// such as `http://www.wikidata.org/entity/Q1985727`), truncated as
+    /// [`truncated_to_precision`](Self::truncated_to_precision) describes so
+    /// it compares equal to what Wikibase itself would store.
+    #[cfg(feature = "wikibase")]
+    pub fn to_time_value(&self, precision: u64, calendarmodel: &str) -> wikibase::TimeValue {
+        let time = self.truncated_to_precision(precision).wikibase_time();
+        wikibase::TimeValue::new(0, 0, calendarmodel.to_string(), precision, time, 0)
+    }
+
+    /// Reads the time of a [`Date`](crate::date::Date).
+    #[cfg(feature = "date")]
+    pub fn from_date(date: &crate::date::Date) -> Option<Self> {
+        Self::parse(date.time())
+    }
+
+    /// A [`Date`](crate::date::Date) at `precision` (9, 10 or 11). Subject to
+    /// the restrictions [`Date::from_str`](crate::date::Date) documents, so a
+    /// BCE or future year is an error.
+    #[cfg(feature = "date")]
+    pub fn to_date(&self, precision: u64) -> Result<crate::date::Date, crate::date::DateError> {
+        let time = self.truncated_to_precision(precision);
+        let time = if self.year < 0 {
+            time.wikibase_time()
+        } else {
+            time.unsigned_time()
+        };
+        format!("{time}/{precision}").parse()
+    }
+}
+
+impl std::fmt::Display for SparqlTime {
+    /// The `xsd:dateTime` lexical form: a sign only for negative years.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.year < 0 {
+            f.write_str("-")?;
+        }
+        f.write_str(&self.unsigned_time())
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum SparqlValue {
+    /// An item, property, lexeme or entity schema ID, e.g. `Q42` or `L7`.
     Entity(String),
     File(String),
     Uri(String),
-    Time(String),
+    Time(SparqlTime),
+    /// A point on Earth.
     Location(LatLon),
+    /// A point on another globe; `globe` is the globe's entity URI as given in
+    /// the WKT literal, e.g. `http://www.wikidata.org/entity/Q405` for the Moon.
+    GlobeLocation {
+        globe: String,
+        location: LatLon,
+    },
     Literal(String),
+    /// A literal with an `xml:lang` tag, e.g. a label.
+    LangLiteral {
+        value: String,
+        language: String,
+    },
+    /// An `xsd:integer`.
+    Integer(i64),
+    /// An `xsd:decimal`, kept in its lexical form because it has arbitrary
+    /// precision. An `xsd:integer` too large for `i64` also ends up here.
+    Decimal(String),
+    /// An `xsd:double`.
+    Double(f64),
+    /// An `xsd:boolean`.
+    Boolean(bool),
+    /// A lexeme form ID, e.g. `L1-F2`.
+    Form(String),
+    /// A lexeme sense ID, e.g. `L1-S1`.
+    Sense(String),
+    /// A statement node, by its local name, e.g. `Q42-8F3E…`.
+    Statement(String),
+    /// A reference node, by its hash.
+    Reference(String),
+    /// A full-value node (`wdv:`), by its hash.
+    ValueNode(String),
 }
 
 impl SparqlValue {
     pub fn new_from_json(j: &Value) -> Option<Self> {
         let value = j["value"].as_str()?;
         match j["type"].as_str() {
-            Some("uri") => match captures(&RE_ENTITY, value) {
-                Some(caps) => caps
-                    .get(1)
-                    .map(|caps1| SparqlValue::Entity(caps1.as_str().to_string())),
-                None => match captures(&RE_FILE, value) {
-                    Some(caps) => match caps.get(1) {
-                        Some(caps1) => {
-                            let file = caps1.as_str().to_string();
-                            let file = urlencoding::decode(&file).ok()?;
-                            let file = file.replace('_', " ");
-                            Some(SparqlValue::File(file))
-                        }
-                        None => None,
-                    },
-                    None => Some(SparqlValue::Uri(value.to_string())),
-                },
+            Some("uri") => Self::from_uri(value),
+            Some("literal") => match j["xml:lang"].as_str() {
+                Some(language) => Some(SparqlValue::LangLiteral {
+                    value: value.to_string(),
+                    language: language.to_string(),
+                }),
+                None => Self::from_literal(value, j["datatype"].as_str()),
             },
-            Some("literal") => match j["datatype"].as_str() {
-                Some("http://www.opengis.net/ont/geosparql#wktLiteral") => {
-                    match captures(&RE_POINT, value) {
-                        Some(caps) => {
-                            let lat: f64 = caps.get(2)?.as_str().parse().ok()?;
-                            let lon: f64 = caps.get(1)?.as_str().parse().ok()?;
-                            Some(SparqlValue::Location(LatLon::new(lat, lon)))
-                        }
-                        None => None,
-                    }
-                }
-                Some("http://www.w3.org/2001/XMLSchema#dateTime") => {
-                    let time = value.to_string();
-                    let time = match captures(&RE_DATE, value) {
-                        Some(caps) => {
-                            let date: String = caps.get(1)?.as_str().to_string();
-                            date
-                        }
-                        None => time,
-                    };
-                    Some(SparqlValue::Time(time))
+            Some("bnode") => Some(SparqlValue::Literal(value.to_string())),
+            _ => None,
+        }
+    }
+
+    fn from_uri(value: &str) -> Option<Self> {
+        if let Some(caps) = captures(&RE_ENTITY, value) {
+            return Some(SparqlValue::Entity(caps.get(1)?.as_str().to_string()));
+        }
+        if let Some(caps) = captures(&RE_LEXEME_PART, value) {
+            let id = caps.get(1)?.as_str().to_string();
+            return match caps.get(2)?.as_str() {
+                "F" => Some(SparqlValue::Form(id)),
+                _ => Some(SparqlValue::Sense(id)),
+            };
+        }
+        if let Some(caps) = captures(&RE_STATEMENT, value) {
+            return Some(SparqlValue::Statement(caps.get(1)?.as_str().to_string()));
+        }
+        if let Some(caps) = captures(&RE_REFERENCE, value) {
+            return Some(SparqlValue::Reference(caps.get(1)?.as_str().to_string()));
+        }
+        if let Some(caps) = captures(&RE_VALUE_NODE, value) {
+            return Some(SparqlValue::ValueNode(caps.get(1)?.as_str().to_string()));
+        }
+        match captures(&RE_FILE, value) {
+            Some(caps) => {
+                let file = caps.get(1)?.as_str().to_string();
+                let file = urlencoding::decode(&file).ok()?;
+                let file = file.replace('_', " ");
+                Some(SparqlValue::File(file))
+            }
+            None => Some(SparqlValue::Uri(value.to_string())),
+        }
+    }
+
+    fn from_literal(value: &str, datatype: Option<&str>) -> Option<Self> {
+        match datatype {
+            Some(WKT_LITERAL) => {
+                if let Some(caps) = captures(&RE_POINT, value) {
+                    return point_from_captures(&caps, 1).map(SparqlValue::Location);
                 }
+                let caps = captures(&RE_GLOBE_POINT, value)?;
+                Some(SparqlValue::GlobeLocation {
+                    globe: caps.get(1)?.as_str().to_string(),
+                    location: point_from_captures(&caps, 2)?,
+                })
+            }
+            Some(XSD_DATE_TIME) => match SparqlTime::parse(value) {
+                Some(time) => Some(SparqlValue::Time(time)),
+                None => Some(SparqlValue::Literal(value.to_string())),
+            },
+            Some(XSD_INTEGER) => match value.parse::<i64>() {
+                Ok(i) => Some(SparqlValue::Integer(i)),
+                Err(_) => Some(SparqlValue::Decimal(value.to_string())),
+            },
+            Some(XSD_DECIMAL) => Some(SparqlValue::Decimal(value.to_string())),
+            Some(XSD_DOUBLE) => match value.parse::<f64>() {
+                Ok(d) => Some(SparqlValue::Double(d)),
+                Err(_) => Some(SparqlValue::Literal(value.to_string())),
+            },
+            Some(XSD_BOOLEAN) => match value {
+                "true" | "1" => Some(SparqlValue::Boolean(true)),
+                "false" | "0" => Some(SparqlValue::Boolean(false)),
                 _ => Some(SparqlValue::Literal(value.to_string())),
             },
-            Some("bnode") => j["value"]
-                .as_str()
-                .map(|value| SparqlValue::Literal(value.to_string())),
-            _ => None,
+            _ => Some(SparqlValue::Literal(value.to_string())),
         }
     }
-}
 
-impl Serialize for SparqlValue {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: Serializer,
-    {
+    /// The lexical `value` of this binding as it appears in SPARQL JSON and
+    /// CSV results, e.g. the full entity URI for [`SparqlValue::Entity`].
+    pub fn value_string(&self) -> String {
         match self {
-            SparqlValue::Entity(entity) => {
-                let mut s = serializer.serialize_struct("Entity", 2)?;
-                s.serialize_field("type", "uri")?;
-                s.serialize_field("value", &format!("http://www.wikidata.org/entity/{entity}"))?;
-                s.end()
+            SparqlValue::Entity(id) | SparqlValue::Form(id) | SparqlValue::Sense(id) => {
+                format!("{WIKIDATA}/entity/{id}")
             }
+            SparqlValue::Statement(id) => format!("{WIKIDATA}/entity/statement/{id}"),
+            SparqlValue::Reference(hash) => format!("{WIKIDATA}/reference/{hash}"),
+            SparqlValue::ValueNode(hash) => format!("{WIKIDATA}/value/{hash}"),
             SparqlValue::File(file) => {
-                let mut s = serializer.serialize_struct("File", 2)?;
-                s.serialize_field("type", "uri")?;
-                s.serialize_field(
-                    "value",
-                    &format!("http://commons.wikimedia.org/wiki/Special:FilePath/{file}"),
-                )?;
-                s.end()
+                format!("http://commons.wikimedia.org/wiki/Special:FilePath/{file}")
             }
-            SparqlValue::Uri(uri) => {
-                let mut s = serializer.serialize_struct("Uri", 2)?;
-                s.serialize_field("type", "uri")?;
-                s.serialize_field("value", uri)?;
-                s.end()
+            SparqlValue::Location(location) => {
+                format!("Point({lon} {lat})", lat = location.lat, lon = location.lon)
             }
-            SparqlValue::Time(time) => {
-                let mut s = serializer.serialize_struct("Time", 3)?;
-                s.serialize_field("type", "literal")?;
-                s.serialize_field("datatype", "http://www.w3.org/2001/XMLSchema#dateTime")?;
-                s.serialize_field("value", time)?;
-                s.end()
+            SparqlValue::GlobeLocation { globe, location } => format!(
+                "<{globe}> Point({lon} {lat})",
+                lat = location.lat,
+                lon = location.lon
+            ),
+            SparqlValue::Integer(i) => i.to_string(),
+            SparqlValue::Double(d) => match d {
+                d if d.is_nan() => "NaN".to_string(),
+                d if d.is_infinite() && d.is_sign_positive() => "INF".to_string(),
+                d if d.is_infinite() => "-INF".to_string(),
+                d => format!("{d:?}"),
+            },
+            SparqlValue::Boolean(b) => b.to_string(),
+            SparqlValue::Time(time) => time.to_string(),
+            SparqlValue::LangLiteral { value, .. } => value.to_owned(),
+            SparqlValue::Uri(s) | SparqlValue::Literal(s) | SparqlValue::Decimal(s) => s.to_owned(),
+        }
+    }
+
+    /// The numeric value of an [`Integer`](SparqlValue::Integer),
+    /// [`Decimal`](SparqlValue::Decimal) or [`Double`](SparqlValue::Double).
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            SparqlValue::Integer(i) => Some(*i as f64),
+            SparqlValue::Decimal(d) => d.parse().ok(),
+            SparqlValue::Double(d) => Some(*d),
+            _ => None,
+        }
+    }
+
+    /// The SPARQL JSON `type`, `datatype` and `xml:lang` of this binding.
+    fn json_kind(&self) -> (&'static str, Option<&'static str>, Option<&str>) {
+        match self {
+            SparqlValue::Entity(_)
+            | SparqlValue::File(_)
+            | SparqlValue::Uri(_)
+            | SparqlValue::Form(_)
+            | SparqlValue::Sense(_)
+            | SparqlValue::Statement(_)
+            | SparqlValue::Reference(_)
+            | SparqlValue::ValueNode(_) => ("uri", None, None),
+            SparqlValue::Time(_) => ("literal", Some(XSD_DATE_TIME), None),
+            SparqlValue::Location(_) | SparqlValue::GlobeLocation { .. } => {
+                ("literal", Some(WKT_LITERAL), None)
             }
-            SparqlValue::Location(location) => {
-                let mut s = serializer.serialize_struct("Location", 3)?;
-                s.serialize_field("type", "literal")?;
-                s.serialize_field(
-                    "datatype",
-                    "http://www.opengis.net/ont/geosparql#wktLiteral",
-                )?;
-                s.serialize_field(
-                    "value",
-                    &format!("Point({lon} {lat})", lat = location.lat, lon = location.lon),
-                )?;
-                s.end()
+            SparqlValue::Integer(_) => ("literal", Some(XSD_INTEGER), None),
+            SparqlValue::Decimal(_) => ("literal", Some(XSD_DECIMAL), None),
+            SparqlValue::Double(_) => ("literal", Some(XSD_DOUBLE), None),
+            SparqlValue::Boolean(_) => ("literal", Some(XSD_BOOLEAN), None),
+            SparqlValue::LangLiteral { language, .. } => ("literal", None, Some(language)),
+            SparqlValue::Literal(_) => ("literal", None, None),
+        }
+    }
+
+    /// A total order that respects each variant's type, for sorting result
+    /// tables:
+    ///
+    /// - entity, form and sense IDs compare by their numbers, so `Q2` sorts
+    ///   before `Q10`;
+    /// - times compare chronologically, so BCE dates sort before CE ones;
+    /// - integers, decimals and doubles compare numerically with each other;
+    /// - plain literals that parse as numbers compare numerically and sort
+    ///   before all other plain literals, which compare as strings;
+    /// - locations compare by latitude, then longitude, after the globe;
+    /// - language-tagged literals compare by text, then language;
+    /// - everything else compares by its natural order or as a string.
+    ///
+    /// Values of different variants are ordered by variant, in declaration
+    /// order, except that the numeric variants share one rank.
+    pub fn typed_cmp(&self, other: &SparqlValue) -> Ordering {
+        match (self, other) {
+            (SparqlValue::Entity(a), SparqlValue::Entity(b))
+            | (SparqlValue::Form(a), SparqlValue::Form(b))
+            | (SparqlValue::Sense(a), SparqlValue::Sense(b)) => natural_cmp(a, b),
+            (SparqlValue::Time(a), SparqlValue::Time(b)) => a.cmp(b),
+            (SparqlValue::Location(a), SparqlValue::Location(b)) => lat_lon_cmp(a, b),
+            (
+                SparqlValue::GlobeLocation {
+                    globe: globe_a,
+                    location: a,
+                },
+                SparqlValue::GlobeLocation {
+                    globe: globe_b,
+                    location: b,
+                },
+            ) => natural_cmp(globe_a, globe_b).then_with(|| lat_lon_cmp(a, b)),
+            (SparqlValue::Literal(a), SparqlValue::Literal(b)) => {
+                numbers_first_cmp(a.parse().ok(), b.parse().ok(), a, b)
             }
-            SparqlValue::Literal(literal) => {
-                let mut s = serializer.serialize_struct("Literal", 2)?;
-                s.serialize_field("type", "literal")?;
-                s.serialize_field("value", literal)?;
-                s.end()
+            (
+                SparqlValue::LangLiteral {
+                    value: a,
+                    language: lang_a,
+                },
+                SparqlValue::LangLiteral {
+                    value: b,
+                    language: lang_b,
+                },
+            ) => a.cmp(b).then_with(|| lang_a.cmp(lang_b)),
+            (SparqlValue::Boolean(a), SparqlValue::Boolean(b)) => a.cmp(b),
+            (SparqlValue::File(a), SparqlValue::File(b))
+            | (SparqlValue::Uri(a), SparqlValue::Uri(b))
+            | (SparqlValue::Statement(a), SparqlValue::Statement(b))
+            | (SparqlValue::Reference(a), SparqlValue::Reference(b))
+            | (SparqlValue::ValueNode(a), SparqlValue::ValueNode(b)) => a.cmp(b),
+            _ => match self.variant_rank().cmp(&other.variant_rank()) {
+                // Integers, decimals and doubles; a decimal that does not
+                // parse sorts after all numbers.
+                Ordering::Equal => numbers_first_cmp(
+                    self.as_f64(),
+                    other.as_f64(),
+                    &self.value_string(),
+                    &other.value_string(),
+                ),
+                ordering => ordering,
+            },
+        }
+    }
+
+    fn variant_rank(&self) -> u8 {
+        match self {
+            SparqlValue::Entity(_) => 0,
+            SparqlValue::File(_) => 1,
+            SparqlValue::Uri(_) => 2,
+            SparqlValue::Time(_) => 3,
+            SparqlValue::Location(_) => 4,
+            SparqlValue::GlobeLocation { .. } => 5,
+            SparqlValue::Literal(_) => 6,
+            SparqlValue::LangLiteral { .. } => 7,
+            SparqlValue::Integer(_) | SparqlValue::Decimal(_) | SparqlValue::Double(_) => 8,
+            SparqlValue::Boolean(_) => 9,
+            SparqlValue::Form(_) => 10,
+            SparqlValue::Sense(_) => 11,
+            SparqlValue::Statement(_) => 12,
+            SparqlValue::Reference(_) => 13,
+            SparqlValue::ValueNode(_) => 14,
+        }
+    }
+}
+
+/// Orders numbers `x` and `y` numerically, before any value that is not a
+/// number; two of those compare as the strings `a` and `b`. Ranking the
+/// numbers first keeps the order total, which comparing numbers numerically
+/// and everything else as strings would not be: `"9" < "10" < "1a" < "9"`.
+fn numbers_first_cmp(x: Option<f64>, y: Option<f64>, a: &str, b: &str) -> Ordering {
+    match (x, y) {
+        (Some(x), Some(y)) => x.total_cmp(&y),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+fn lat_lon_cmp(a: &LatLon, b: &LatLon) -> Ordering {
+    a.lat
+        .total_cmp(&b.lat)
+        .then_with(|| a.lon.total_cmp(&b.lon))
+}
+
+/// Compares runs of digits by numeric value and everything else character by
+/// character, so `Q9 < Q10` and `L1-F2 < L1-F10`.
+fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut a = a.chars().peekable();
+    let mut b = b.chars().peekable();
+    loop {
+        match (a.peek().copied(), b.peek().copied()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+                let digits = |it: &mut std::iter::Peekable<std::str::Chars>| {
+                    let mut run = String::new();
+                    while let Some(c) = it.next_if(|c| c.is_ascii_digit()) {
+                        run.push(c);
+                    }
+                    run
+                };
+                let (x, y) = (digits(&mut a), digits(&mut b));
+                let (x, y) = (x.trim_start_matches('0'), y.trim_start_matches('0'));
+                let ordering = x.len().cmp(&y.len()).then_with(|| x.cmp(y));
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
             }
+            (Some(x), Some(y)) => {
+                if x != y {
+                    return x.cmp(&y);
+                }
+                a.next();
+                b.next();
+            }
+        }
+    }
+}
+
+impl Serialize for SparqlValue {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        let (kind, datatype, language) = self.json_kind();
+        let len = 2 + usize::from(datatype.is_some()) + usize::from(language.is_some());
+        let mut s = serializer.serialize_struct("SparqlValue", len)?;
+        s.serialize_field("type", kind)?;
+        if let Some(datatype) = datatype {
+            s.serialize_field("datatype", datatype)?;
         }
+        if let Some(language) = language {
+            s.serialize_field("xml:lang", language)?;
+        }
+        s.serialize_field("value", &self.value_string())?;
+        s.end()
     }
 }
 
@@ -174,19 +560,14 @@ mod tests {
 
     #[test]
     fn test_serialize_time() {
-        let dt = "+2020-01-01T:01:23:45Z";
-        let time = SparqlValue::Time(dt.to_string());
+        let time = SparqlValue::Time(SparqlTime::parse("+2020-01-01T01:23:45Z").unwrap());
         let json = serde_json::to_string(&time).unwrap();
         assert_eq!(
             json,
-            r#"{"type":"literal","datatype":"http://www.w3.org/2001/XMLSchema#dateTime","value":""#
-                .to_string()
-                + dt
-                + r#""}"#
+            r#"{"type":"literal","datatype":"http://www.w3.org/2001/XMLSchema#dateTime","value":"2020-01-01T01:23:45Z"}"#
         );
         let time2: SparqlValue = serde_json::from_str(&json).unwrap();
         assert_eq!(time, time2);
-        assert_eq!(time2, SparqlValue::Time(dt.to_string()));
     }
 
     #[test]
@@ -268,7 +649,7 @@ mod tests {
             SparqlValue::Entity("Q1234".to_string()),
             SparqlValue::File("File:Example.jpg".to_string()),
             SparqlValue::Uri("http://example.com".to_string()),
-            SparqlValue::Time("+2020-01-01T:01:23:45Z".to_string()),
+            SparqlValue::Time(SparqlTime::parse("+2020-01-01T01:23:45Z").unwrap()),
             SparqlValue::Location(LatLon::new(1.0, -2.0)),
         ];
         for value in values {
@@ -278,6 +659,89 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_value_string_matches_serialized_value() {
+        let values = vec![
+            SparqlValue::Literal("GB-CAM".to_string()),
+            SparqlValue::Entity("Q1234".to_string()),
+            SparqlValue::File("Example.jpg".to_string()),
+            SparqlValue::Uri("http://example.com".to_string()),
+            SparqlValue::Time(SparqlTime::parse("2020-01-01").unwrap()),
+            SparqlValue::Location(LatLon::new(1.0, -2.0)),
+        ];
+        for value in values {
+            let json = serde_json::to_value(&value).unwrap();
+            assert_eq!(json["value"], value.value_string());
+        }
+    }
+
+    #[test]
+    fn test_typed_cmp_entities_numerically() {
+        let q2 = SparqlValue::Entity("Q2".to_string());
+        let q10 = SparqlValue::Entity("Q10".to_string());
+        assert_eq!(q2.typed_cmp(&q10), Ordering::Less);
+        assert_eq!(q10.typed_cmp(&q2), Ordering::Greater);
+        assert_eq!(q2.typed_cmp(&q2), Ordering::Equal);
+        assert_eq!(
+            SparqlValue::Entity("P10".to_string()).typed_cmp(&q2),
+            Ordering::Less
+        );
+    }
+
+    #[test]
+    fn test_typed_cmp_times_by_signed_year() {
+        let bce = SparqlValue::Time(SparqlTime::parse("-0500-01-01").unwrap());
+        let early = SparqlValue::Time(SparqlTime::parse("0900-06-01").unwrap());
+        let late = SparqlValue::Time(SparqlTime::parse("+1952-03-11").unwrap());
+        let later = SparqlValue::Time(SparqlTime::parse("1952-03-12").unwrap());
+        assert_eq!(bce.typed_cmp(&early), Ordering::Less);
+        assert_eq!(early.typed_cmp(&late), Ordering::Less);
+        assert_eq!(late.typed_cmp(&later), Ordering::Less);
+    }
+
+    #[test]
+    fn test_typed_cmp_numeric_literals() {
+        let nine = SparqlValue::Literal("9".to_string());
+        let ten = SparqlValue::Literal("10.5".to_string());
+        let text = SparqlValue::Literal("abc".to_string());
+        assert_eq!(nine.typed_cmp(&ten), Ordering::Less);
+        assert_eq!(ten.typed_cmp(&text), Ordering::Less); // numbers sort first
+    }
+
+    #[test]
+    fn test_typed_cmp_mixed_literals_is_a_total_order() {
+        let literal = |s: &str| SparqlValue::Literal(s.to_string());
+        let mut values = vec![
+            literal("1a"),
+            literal("10"),
+            literal("b"),
+            literal("9"),
+            literal("-1"),
+            literal("1a"),
+        ];
+        values.sort_by(SparqlValue::typed_cmp);
+        let sorted: Vec<String> = values.iter().map(|v| v.value_string()).collect();
+        assert_eq!(sorted, vec!["-1", "9", "10", "1a", "1a", "b"]);
+        // Transitive across every triple.
+        for a in &values {
+            for b in &values {
+                for c in &values {
+                    if a.typed_cmp(b).is_le() && b.typed_cmp(c).is_le() {
+                        assert!(a.typed_cmp(c).is_le());
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_typed_cmp_mixed_variants_by_declaration_order() {
+        let entity = SparqlValue::Entity("Q99".to_string());
+        let literal = SparqlValue::Literal("a".to_string());
+        assert_eq!(entity.typed_cmp(&literal), Ordering::Less);
+        assert_eq!(literal.typed_cmp(&entity), Ordering::Greater);
+    }
+
     #[test]
     fn test_new_from_json_entity() {
         let json = r#"{"type":"uri","value":"http://www.wikidata.org/entity/Q21"}"#;
@@ -340,7 +804,20 @@ mod tests {
     fn test_new_from_json_datetime() {
         let json = r#"{"type":"literal","datatype":"http://www.w3.org/2001/XMLSchema#dateTime","value":"2020-01-15T00:00:00Z"}"#;
         let value = SparqlValue::new_from_json(&serde_json::from_str(json).unwrap());
-        assert_eq!(value, Some(SparqlValue::Time("2020-01-15".to_string())));
+        let expected = SparqlTime {
+            year: 2020,
+            month: 1,
+            day: 15,
+            hour: 0,
+            minute: 0,
+            second: 0,
+        };
+        assert_eq!(value, Some(SparqlValue::Time(expected)));
+        // Midnight is kept, not folded into a bare date.
+        assert_eq!(
+            value.unwrap().value_string(),
+            "2020-01-15T00:00:00Z".to_string()
+        );
     }
 
     #[test]
@@ -349,10 +826,102 @@ mod tests {
         let value = SparqlValue::new_from_json(&serde_json::from_str(json).unwrap());
         assert_eq!(
             value,
-            Some(SparqlValue::Time("2020-01-15T12:30:45Z".to_string()))
+            Some(SparqlValue::Time(
+                SparqlTime::parse("2020-01-15T12:30:45Z").unwrap()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new_from_json_datetime_unparsable_is_a_literal() {
+        let json = r#"{"type":"literal","datatype":"http://www.w3.org/2001/XMLSchema#dateTime","value":"sometime"}"#;
+        let value = SparqlValue::new_from_json(&serde_json::from_str(json).unwrap());
+        assert_eq!(value, Some(SparqlValue::Literal("sometime".to_string())));
+    }
+
+    #[test]
+    fn test_sparql_time_parse_and_display() {
+        let bce = SparqlTime::parse("-0500-01-01T00:00:00Z").unwrap();
+        assert_eq!(bce.year, -500);
+        assert_eq!(bce.to_string(), "-0500-01-01T00:00:00Z");
+        assert_eq!(bce.wikibase_time(), "-0500-01-01T00:00:00Z");
+
+        let far = SparqlTime::parse("+13798000000-00-00T00:00:00Z").unwrap();
+        assert_eq!(far.year, 13_798_000_000);
+        assert_eq!((far.month, far.day), (0, 0));
+        assert_eq!(far.to_string(), "13798000000-00-00T00:00:00Z");
+
+        let early = SparqlTime::parse("0033-04-03T15:00:00Z").unwrap();
+        assert_eq!((early.hour, early.minute, early.second), (15, 0, 0));
+        assert_eq!(early.wikibase_time(), "+0033-04-03T15:00:00Z");
+
+        assert_eq!(SparqlTime::parse("2020-13-01"), None);
+        assert_eq!(SparqlTime::parse("2020-01-01T25:00:00Z"), None);
+        assert_eq!(SparqlTime::parse("2020-01-01T:01:23:45Z"), None);
+    }
+
+    #[test]
+    fn test_sparql_time_truncated_to_precision() {
+        let time = SparqlTime::parse("1990-05-17T12:34:56Z").unwrap();
+        assert_eq!(
+            time.truncated_to_precision(9).wikibase_time(),
+            "+1990-00-00T00:00:00Z"
+        );
+        assert_eq!(
+            time.truncated_to_precision(10).wikibase_time(),
+            "+1990-05-00T00:00:00Z"
+        );
+        assert_eq!(
+            time.truncated_to_precision(11).wikibase_time(),
+            "+1990-05-17T00:00:00Z"
         );
     }
 
+    #[cfg(feature = "wikibase")]
+    #[test]
+    fn test_sparql_time_time_value_round_trip() {
+        let gregorian = "http://www.wikidata.org/entity/Q1985727";
+        let time = SparqlTime::parse("1990-05-17T00:00:00Z").unwrap();
+        let time_value = time.to_time_value(11, gregorian);
+        assert_eq!(
+            time_value,
+            wikibase::TimeValue::new(0, 0, gregorian, 11, "+1990-05-17T00:00:00Z", 0)
+        );
+        assert_eq!(SparqlTime::from_time_value(&time_value), Some(time));
+
+        let bce = SparqlTime::parse("-0044-03-15T00:00:00Z").unwrap();
+        let bce_value = bce.to_time_value(9, gregorian);
+        assert_eq!(bce_value.time(), "-0044-00-00T00:00:00Z");
+        assert_eq!(
+            SparqlTime::from_time_value(&bce_value),
+            Some(bce.truncated_to_precision(9))
+        );
+    }
+
+    #[cfg(feature = "date")]
+    #[test]
+    fn test_sparql_time_date_round_trip() {
+        use std::str::FromStr;
+        let time = SparqlTime::parse("1952-03-11T00:00:00Z").unwrap();
+        let date = time.to_date(11).unwrap();
+        assert_eq!(date.as_qs(), "+1952-03-11T00:00:00Z/11");
+        assert_eq!(SparqlTime::from_date(&date), Some(time));
+
+        assert_eq!(time.to_date(9).unwrap().as_qs(), "+1952-00-00T00:00:00Z/9");
+
+        let month = crate::date::Date::from_str("1952-03").unwrap();
+        assert_eq!(
+            SparqlTime::from_date(&month),
+            Some(time.truncated_to_precision(10))
+        );
+
+        // `Date` does not take BCE years.
+        assert!(SparqlTime::parse("-0044-03-15")
+            .unwrap()
+            .to_date(11)
+            .is_err());
+    }
+
     #[test]
     fn test_new_from_json_bnode() {
         let json = r#"{"type":"bnode","value":"b0"}"#;
@@ -391,6 +960,162 @@ mod tests {
         assert!(RE_ENTITY.is_some());
         assert!(RE_FILE.is_some());
         assert!(RE_POINT.is_some());
-        assert!(RE_DATE.is_some());
+        assert!(RE_DATE_TIME.is_some());
+        assert!(RE_LEXEME_PART.is_some());
+        assert!(RE_STATEMENT.is_some());
+        assert!(RE_REFERENCE.is_some());
+        assert!(RE_VALUE_NODE.is_some());
+        assert!(RE_GLOBE_POINT.is_some());
+    }
+
+    fn typed_values() -> Vec<SparqlValue> {
+        vec![
+            SparqlValue::Integer(-42),
+            SparqlValue::Decimal("1.50".to_string()),
+            SparqlValue::Decimal("123456789012345678901234567890".to_string()),
+            SparqlValue::Double(1.5e300),
+            SparqlValue::Double(f64::NEG_INFINITY),
+            SparqlValue::Boolean(true),
+            SparqlValue::Boolean(false),
+            SparqlValue::LangLiteral {
+                value: "Douglas Adams".to_string(),
+                language: "en-gb".to_string(),
+            },
+            SparqlValue::Entity("L7".to_string()),
+            SparqlValue::Form("L1-F2".to_string()),
+            SparqlValue::Sense("L1-S1".to_string()),
+            SparqlValue::Statement("Q42-8f3e2c7a-4d5b-4e4b-9e54-2b1c0f1a2b3c".to_string()),
+            SparqlValue::Reference("a4d108601216cffd2ff1819ccf12b483486b62e7".to_string()),
+            SparqlValue::ValueNode("382603eaa501e15688076291fc47ae54".to_string()),
+            SparqlValue::GlobeLocation {
+                globe: "http://www.wikidata.org/entity/Q405".to_string(),
+                location: LatLon::new(-12.5, 97.25),
+            },
+        ]
+    }
+
+    #[test]
+    fn test_serialize_deserialize_typed_values() {
+        for value in typed_values() {
+            let json = serde_json::to_value(&value).unwrap();
+            assert_eq!(json["value"], value.value_string());
+            let value2: SparqlValue = serde_json::from_value(json).unwrap();
+            assert_eq!(value, value2);
+        }
+    }
+
+    #[test]
+    fn test_new_from_json_numbers() {
+        let parse = |datatype: &str, value: &str| {
+            SparqlValue::new_from_json(&serde_json::json!({
+                "type": "literal",
+                "datatype": format!("http://www.w3.org/2001/XMLSchema#{datatype}"),
+                "value": value
+            }))
+        };
+        assert_eq!(parse("integer", "17"), Some(SparqlValue::Integer(17)));
+        assert_eq!(
+            parse("integer", "99999999999999999999"),
+            Some(SparqlValue::Decimal("99999999999999999999".to_string()))
+        );
+        assert_eq!(
+            parse("decimal", "+3.10"),
+            Some(SparqlValue::Decimal("+3.10".to_string()))
+        );
+        assert_eq!(parse("double", "1.0E3"), Some(SparqlValue::Double(1000.0)));
+        assert_eq!(
+            parse("double", "INF"),
+            Some(SparqlValue::Double(f64::INFINITY))
+        );
+        assert_eq!(parse("boolean", "1"), Some(SparqlValue::Boolean(true)));
+        assert_eq!(parse("boolean", "false"), Some(SparqlValue::Boolean(false)));
+        assert_eq!(
+            parse("boolean", "maybe"),
+            Some(SparqlValue::Literal("maybe".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_serialize_lang_literal() {
+        let value = SparqlValue::LangLiteral {
+            value: "Berlin".to_string(),
+            language: "de".to_string(),
+        };
+        assert_eq!(
+            serde_json::to_string(&value).unwrap(),
+            r#"{"type":"literal","xml:lang":"de","value":"Berlin"}"#
+        );
+    }
+
+    #[test]
+    fn test_new_from_json_lexeme_parts_and_nodes() {
+        let parse = |uri: &str| {
+            SparqlValue::new_from_json(&serde_json::json!({"type": "uri", "value": uri}))
+        };
+        assert_eq!(
+            parse("http://www.wikidata.org/entity/L99"),
+            Some(SparqlValue::Entity("L99".to_string()))
+        );
+        assert_eq!(
+            parse("http://www.wikidata.org/entity/L99-F3"),
+            Some(SparqlValue::Form("L99-F3".to_string()))
+        );
+        assert_eq!(
+            parse("https://www.wikidata.org/entity/L99-S1"),
+            Some(SparqlValue::Sense("L99-S1".to_string()))
+        );
+        assert_eq!(
+            parse("http://www.wikidata.org/entity/statement/Q1-abc"),
+            Some(SparqlValue::Statement("Q1-abc".to_string()))
+        );
+        assert_eq!(
+            parse("http://www.wikidata.org/reference/00ff"),
+            Some(SparqlValue::Reference("00ff".to_string()))
+        );
+        assert_eq!(
+            parse("http://www.wikidata.org/value/00ff"),
+            Some(SparqlValue::ValueNode("00ff".to_string()))
+        );
+        // Not a hash, so just a URI.
+        assert_eq!(
+            parse("http://www.wikidata.org/value/xyz"),
+            Some(SparqlValue::Uri(
+                "http://www.wikidata.org/value/xyz".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_new_from_json_globe_location() {
+        let json = r#"{"type":"literal",
+                       "datatype":"http://www.opengis.net/ont/geosparql#wktLiteral",
+                       "value":"<http://www.wikidata.org/entity/Q111> Point(137.4 -4.6)"}"#;
+        let value = SparqlValue::new_from_json(&serde_json::from_str(json).unwrap());
+        assert_eq!(
+            value,
+            Some(SparqlValue::GlobeLocation {
+                globe: "http://www.wikidata.org/entity/Q111".to_string(),
+                location: LatLon::new(-4.6, 137.4),
+            })
+        );
+    }
+
+    #[test]
+    fn test_typed_cmp_numbers_across_numeric_variants() {
+        let two = SparqlValue::Integer(2);
+        let two_and_a_half = SparqlValue::Decimal("2.5".to_string());
+        let ten = SparqlValue::Double(10.0);
+        assert_eq!(two.typed_cmp(&two_and_a_half), Ordering::Less);
+        assert_eq!(two_and_a_half.typed_cmp(&ten), Ordering::Less);
+        assert_eq!(ten.typed_cmp(&two), Ordering::Greater);
+        assert_eq!(
+            SparqlValue::Form("L1-F2".to_string())
+                .typed_cmp(&SparqlValue::Form("L1-F10".to_string())),
+            Ordering::Less
+        );
+        assert_eq!(
+            SparqlValue::Boolean(false).typed_cmp(&SparqlValue::Boolean(true)),
+            Ordering::Less
+        );
     }
 }
@@ -3,7 +3,8 @@
 //! client preconfigured to talk to it.
 //!
 //! Lets the suite cover HTTP-driven paths (`SiteMatrix::new`, `Wikidata::api`,
-//! `Wikidata::load_sparql_csv`, `ExternalId::*`) deterministically and offline.
+//! `Wikidata::load_sparql_csv`, `Wikidata::load_sparql_table`,
+//! `ExternalId::*`, the `wbeditentity` edit path) deterministically and offline.
 //!
 //! Which helpers are actually reachable depends on the enabled features, so the
 //! module tolerates unused ones rather than gating each on a feature list that
@@ -18,14 +19,22 @@ pub const API_PATH: &str = "/w/api.php";
 pub const SPARQL_PATH: &str = "/sparql";
 
 /// Build a [`crate::wikidata::Wikidata`] whose API and SPARQL endpoints point
-/// at `server`.
+/// at `server`, backing off for milliseconds rather than seconds between
+/// retries.
 #[cfg(feature = "wikidata")]
 pub fn wikidata_for(server: &MockServer) -> crate::wikidata::Wikidata {
     use crate::wikidata::Wikidata;
+    use crate::wikidata_throttle::RetryPolicy;
+    use std::time::Duration;
 
     let mut wd = Wikidata::new();
     wd.set_api_url(&format!("{}{}", server.uri(), API_PATH));
     wd.set_sparql_url(&format!("{}{}", server.uri(), SPARQL_PATH));
+    wd.set_retry_policy(RetryPolicy {
+        initial_backoff: Duration::from_millis(1),
+        max_backoff: Duration::from_millis(10),
+        ..RetryPolicy::default()
+    });
     wd
 }
 
@@ -68,6 +77,20 @@ pub async fn mount_action(server: &MockServer, action: &str, body: Value) {
         .await;
 }
 
+/// Mount the `action=query&meta=tokens` response that
+/// [`mediawiki::Api::get_edit_token`] expects, handing out `token`.
+pub async fn mount_csrf_token(server: &MockServer, token: &str) {
+    Mock::given(method("GET"))
+        .and(path(API_PATH))
+        .and(query_param("meta", "tokens"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "batchcomplete": "",
+            "query": { "tokens": { "csrftoken": token } }
+        })))
+        .mount(server)
+        .await;
+}
+
 /// Mount a JSON response for a `action=query&list=…` Action API request.
 pub async fn mount_list(server: &MockServer, list: &str, body: Value) {
     Mock::given(method("GET"))
@@ -90,3 +113,17 @@ pub async fn mount_sparql_csv(server: &MockServer, csv: &str) {
         .mount(server)
         .await;
 }
+
+/// Mount a SPARQL JSON (`application/sparql-results+json`) response for the
+/// SPARQL endpoint.
+pub async fn mount_sparql_json(server: &MockServer, body: Value) {
+    Mock::given(method("GET"))
+        .and(path(SPARQL_PATH))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .set_body_json(body)
+                .insert_header("content-type", "application/sparql-results+json"),
+        )
+        .mount(server)
+        .await;
+}
@@ -6,19 +6,36 @@
 //! through this struct rather than building a `reqwest::Client` directly, so
 //! that the project's UA and timeout policy stay uniform.
 //!
-//! Also provides [`Wikidata::item2qs`] for converting an `ItemEntity` into a
-//! QuickStatements-compatible line list.
+//! SPARQL queries can be run either as raw CSV ([`Wikidata::load_sparql_csv`])
+//! or as a typed [`SparqlTable`] ([`Wikidata::load_sparql_table`]). Entities
+//! are loaded and cached by the `wikidata_entities` module. Requests are
+//! retried and rate-limited as set up in the `wikidata_throttle` module.
+//!
+//! Also provides [`Wikidata::item2qs`] and [`Wikidata::item2qs_export`] for
+//! converting an `ItemEntity` into QuickStatements v1 or CSV commands, with a
+//! list of anything QuickStatements cannot express. With the `item-merger`
+//! feature, [`Wikidata::diff2qs`] does the same for a `MergeDiff` against an
+//! existing item.
 
 use crate::mediawiki::reqwest::{Client, ClientBuilder};
+#[cfg(feature = "item-merger")]
+use crate::merge_diff::MergeDiff;
+use crate::property_registry::PropertyRegistry;
+use crate::sparql_table::{RowStorage, SparqlTable, SparqlTableError};
+use crate::wikidata_entities::EntityCache;
+use crate::wikidata_throttle::{AttemptError, Endpoint, Throttle};
 use std::{
     fs::File,
-    io::{Seek, Write},
+    io::{BufReader, Seek, Write},
     time::Duration,
 };
 use tempfile::tempfile;
 use thiserror::Error;
 use wikibase::mediawiki::media_wiki_error::MediaWikiError;
-use wikibase::{mediawiki::Api, EntityTrait, ItemEntity, SnakType};
+use wikibase::{
+    mediawiki::Api, Coordinate, EntityTrait, ItemEntity, LocaleString, QuantityValue, Reference,
+    SiteLink, Snak, SnakType, Statement, StatementRank, TimeValue, Value,
+};
 
 /// Failure modes of [`Wikidata`].
 #[derive(Debug, Error)]
@@ -28,6 +45,11 @@ pub enum WikidataError {
     #[error("item2qs: item ID is not empty")]
     ItemIdNotEmpty,
 
+    /// [`Wikidata::diff2qs`] was given an item without an ID to apply the
+    /// diff to.
+    #[error("diff2qs: item ID is empty")]
+    ItemIdEmpty,
+
     /// Buffering the SPARQL response to a temporary file failed.
     #[error(transparent)]
     Io(#[from] std::io::Error),
@@ -36,6 +58,55 @@ pub enum WikidataError {
     #[error(transparent)]
     Http(#[from] reqwest::Error),
 
+    /// The server answered with a non-success HTTP status.
+    #[error("HTTP status {0}")]
+    HttpStatus(reqwest::StatusCode),
+
+    /// The request did not complete within the configured timeout.
+    #[error("request timed out")]
+    Timeout,
+
+    /// The response body was not the JSON that was expected.
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    /// Building the result table failed, e.g. a row arrived without headers.
+    #[error(transparent)]
+    SparqlTable(#[from] SparqlTableError),
+
+    /// [`Wikidata::item2qs`] met constructs QuickStatements cannot express.
+    #[error("item2qs: {} construct(s) cannot be expressed in QuickStatements", .0.len())]
+    QsUnrepresentable(Vec<QsIssue>),
+
+    /// Writing QuickStatements CSV failed.
+    #[error(transparent)]
+    Csv(#[from] csv::Error),
+
+    /// An edit kept running into edit conflicts; see
+    /// `Wikidata::edit_item` (with `item-merger`).
+    #[error("edit conflict on {id} after {attempts} attempt(s)")]
+    EditConflict { id: String, attempts: usize },
+
+    /// The Action API answered with an error.
+    #[error("API error {code}: {info}")]
+    Api { code: String, info: String },
+
+    /// The requested entity does not exist.
+    #[error("entity {0} not found")]
+    MissingEntity(String),
+
+    /// The Action API answered without a field that was expected.
+    #[error("malformed API response: {0}")]
+    MalformedResponse(String),
+
+    /// The concurrency limiter stopped handing out request slots.
+    #[error("request limiter closed")]
+    LimiterClosed,
+
+    /// Entity JSON could not be parsed.
+    #[error(transparent)]
+    Wikibase(#[from] wikibase::WikibaseError),
+
     /// The `Accept` header could not be constructed.
     #[error(transparent)]
     InvalidHeader(#[from] reqwest::header::InvalidHeaderValue),
@@ -56,6 +127,8 @@ pub struct Wikidata {
     timeout: Duration,
     api_url: String,
     sparql_url: String,
+    pub(crate) entity_cache: EntityCache,
+    pub(crate) throttle: Throttle,
 }
 
 impl Default for Wikidata {
@@ -71,11 +144,35 @@ impl Wikidata {
             timeout: WIKIDATA_SPARQL_TIMEOUT,
             api_url: DEFAULT_API_URL.to_string(),
             sparql_url: DEFAULT_SPARQL_URL.to_string(),
+            entity_cache: EntityCache::default(),
+            throttle: Throttle::default(),
         }
     }
 
+    /// Builds an Action API client, in an Action API slot since building it
+    /// fetches the site info. It retries maxlag errors as often as the retry
+    /// policy allows.
+    ///
+    /// # Limitation
+    ///
+    /// The returned client is a plain [`Api`], which sends its own requests
+    /// and knows nothing of the concurrency limit. Callers must hold a
+    /// [`Wikidata::api_permit`] around each request they send through it, as
+    /// the methods of [`Wikidata`] that take an `Api` do; requests made
+    /// without one bypass the limit.
+    ///
+    /// ```ignore
+    /// let api = wikidata.api().await?;
+    /// let result = {
+    ///     let _permit = wikidata.api_permit().await?;
+    ///     api.get_query_api_json(&params).await?
+    /// };
+    /// ```
     pub async fn api(&self) -> Result<Api, WikidataError> {
-        let api = Api::new_from_builder(&self.api_url, self.client_builder()).await?;
+        let _permit = self.api_permit().await?;
+        let mut api = Api::new_from_builder(&self.api_url, self.client_builder()).await?;
+        let retries = self.throttle.policy.max_attempts.saturating_sub(1);
+        api.set_max_retry_attempts(u64::from(retries));
         Ok(api)
     }
 
@@ -92,6 +189,9 @@ impl Wikidata {
 
     #[cfg(not(doctest))]
     /// Queries SPARQL and returns the result as a CSV reader over a temp file.
+    /// A `429` or gateway error that outlasts the retry policy is reported as
+    /// [`WikidataError::HttpStatus`], a timeout as [`WikidataError::Timeout`];
+    /// any other status is left to the caller.
     /// USAGE:
     /// ```rust
     /// let mut reader = wikidata.load_sparql_csv(&sparql).await?;
@@ -101,21 +201,7 @@ impl Wikidata {
     /// }
     /// ```
     pub async fn load_sparql_csv(&self, sparql: &str) -> Result<csv::Reader<File>, WikidataError> {
-        let mut f = tempfile()?;
-        let mut res = self
-            .reqwest_client()?
-            .get(&self.sparql_url)
-            .query(&[("query", sparql)])
-            .header(
-                reqwest::header::ACCEPT,
-                reqwest::header::HeaderValue::from_str("text/csv")?,
-            )
-            .send()
-            .await?;
-        while let Some(chunk) = res.chunk().await? {
-            f.write_all(chunk.as_ref())?;
-        }
-        f.seek(std::io::SeekFrom::Start(0))?;
+        let f = self.fetch_sparql(sparql, "text/csv", false).await?;
         Ok(csv::ReaderBuilder::new()
             .flexible(true)
             .has_headers(true)
@@ -123,6 +209,75 @@ impl Wikidata {
             .from_reader(f))
     }
 
+    /// Queries SPARQL as `application/sparql-results+json` and returns the
+    /// result as a typed [`SparqlTable`] over any [`RowStorage`].
+    ///
+    /// The response is buffered to a temporary file and then streamed into
+    /// the table with [`SparqlTable::from_json_reader`], so only the storage
+    /// holds the rows in memory.
+    ///
+    /// Unlike [`Self::load_sparql_csv`], every non-success HTTP status is
+    /// reported as [`WikidataError::HttpStatus`] rather than being left to the
+    /// caller.
+    pub async fn load_sparql_table<S: RowStorage>(
+        &self,
+        sparql: &str,
+    ) -> Result<SparqlTable<S>, WikidataError> {
+        let f = self
+            .fetch_sparql(sparql, "application/sparql-results+json", true)
+            .await?;
+        SparqlTable::from_json_reader(BufReader::new(f)).map_err(|error| match error {
+            SparqlTableError::Json(error) => WikidataError::Json(error),
+            error => WikidataError::SparqlTable(error),
+        })
+    }
+
+    /// Runs `sparql` asking for `accept`, retrying under the retry policy,
+    /// and buffers the response to a temporary file. With `require_success`,
+    /// any other non-success status is an error too.
+    async fn fetch_sparql(
+        &self,
+        sparql: &str,
+        accept: &str,
+        require_success: bool,
+    ) -> Result<File, WikidataError> {
+        self.with_retry(Endpoint::Sparql, || async move {
+            let mut res = self
+                .reqwest_client()?
+                .get(&self.sparql_url)
+                .query(&[("query", sparql)])
+                .header(
+                    reqwest::header::ACCEPT,
+                    reqwest::header::HeaderValue::from_str(accept).map_err(WikidataError::from)?,
+                )
+                .send()
+                .await
+                .map_err(Self::map_http_error)?;
+            AttemptError::check_status(&res, self.retry_policy())?;
+            if require_success && !res.status().is_success() {
+                return Err(AttemptError::Fatal(WikidataError::HttpStatus(res.status())));
+            }
+            // Buffer to disk rather than memory.
+            let mut f = tempfile().map_err(WikidataError::from)?;
+            while let Some(chunk) = res.chunk().await.map_err(Self::map_http_error)? {
+                f.write_all(chunk.as_ref()).map_err(WikidataError::from)?;
+            }
+            f.seek(std::io::SeekFrom::Start(0))
+                .map_err(WikidataError::from)?;
+            Ok(f)
+        })
+        .await
+    }
+
+    /// Separates timeouts from other transport failures.
+    pub(crate) fn map_http_error(error: reqwest::Error) -> WikidataError {
+        if error.is_timeout() {
+            WikidataError::Timeout
+        } else {
+            WikidataError::Http(error)
+        }
+    }
+
     pub fn set_user_agent(&mut self, user_agent: &str) {
         self.user_agent = user_agent.to_string();
     }
@@ -138,7 +293,8 @@ impl Wikidata {
         self.api_url = api_url.to_string();
     }
 
-    /// Overrides the SPARQL endpoint used by [`Self::load_sparql_csv`].
+    /// Overrides the SPARQL endpoint used by [`Self::load_sparql_csv`] and
+    /// [`Self::load_sparql_table`].
     /// Default: `https://query.wikidata.org/sparql`. Primarily a test seam
     /// for pointing at a local mock server.
     pub fn set_sparql_url(&mut self, sparql_url: &str) {
@@ -153,112 +309,616 @@ impl Wikidata {
         &self.sparql_url
     }
 
+    /// Converts a new item into QuickStatements v1 commands, starting with
+    /// `CREATE`. Fails with [`WikidataError::QsUnrepresentable`] if a value
+    /// would be left out; values that can only be approximated, such as a
+    /// coordinate precision or a rank, are exported as they come. Use
+    /// [`Wikidata::item2qs_export`] to see those issues too, or for an item
+    /// that already exists.
     pub fn item2qs(item: &ItemEntity) -> Result<Vec<String>, WikidataError> {
         if !item.id().is_empty() {
             return Err(WikidataError::ItemIdNotEmpty);
         }
+        let export = Self::item2qs_export(item, QsFormat::V1)?;
+        if export.issues.iter().any(|issue| issue.dropped) {
+            return Err(WikidataError::QsUnrepresentable(export.issues));
+        }
+        Ok(export.commands)
+    }
 
-        let mut ret = vec!["CREATE".to_string()];
+    /// Converts an item into QuickStatements commands in `format`. An item
+    /// without an ID is created; one with an ID is edited, so every command
+    /// adds to it. Whatever cannot be expressed is left out, or approximated
+    /// where the issue says so, and listed in [`QsExport::issues`].
+    pub fn item2qs_export(item: &ItemEntity, format: QsFormat) -> Result<QsExport, WikidataError> {
+        Self::export_item(item, QsWriter::new(format))
+    }
 
-        // Add labels
-        ret.extend(
-            item.labels()
-                .iter()
-                .map(|ls| format!("LAST\tL{}\t\"{}\"", ls.language(), ls.value())),
-        );
+    /// Like [`Wikidata::item2qs_export`], but also checks each value against
+    /// `registry`. A value that does not fit its property's datatype, or fails
+    /// its format regex, is still exported and listed in
+    /// [`QsExport::issues`].
+    pub fn item2qs_export_with(
+        item: &ItemEntity,
+        format: QsFormat,
+        registry: &PropertyRegistry,
+    ) -> Result<QsExport, WikidataError> {
+        let mut writer = QsWriter::new(format);
+        writer.registry = Some(registry);
+        Self::export_item(item, writer)
+    }
 
-        // Add descriptions
-        ret.extend(
-            item.descriptions()
-                .iter()
-                .map(|ls| format!("LAST\tD{}\t\"{}\"", ls.language(), ls.value())),
-        );
+    fn export_item(item: &ItemEntity, mut writer: QsWriter) -> Result<QsExport, WikidataError> {
+        let mut commands = vec![];
+        for ls in item.labels() {
+            commands.extend(writer.term('L', ls));
+        }
+        for ls in item.descriptions() {
+            commands.extend(writer.term('D', ls));
+        }
+        for ls in item.aliases() {
+            commands.extend(writer.term('A', ls));
+        }
+        for sitelink in item.sitelinks().as_deref().unwrap_or_default() {
+            commands.extend(writer.sitelink(sitelink));
+        }
+        for statement in item.claims() {
+            commands.extend(writer.statement(statement));
+        }
+        writer.finish(item.id(), commands)
+    }
 
-        // Add aliases
-        ret.extend(
-            item.aliases()
+    /// Converts a [`MergeDiff`] into QuickStatements commands in `format` that
+    /// apply it to `item`, the existing item it was made against, rendering
+    /// values as [`Wikidata::item2qs_export`] does.
+    ///
+    /// Altered statements are written in full: QuickStatements adds their
+    /// qualifiers and references to the statement with the same property and
+    /// value, skipping those already present. What it cannot do is compared
+    /// against `item` and listed in [`QsExport::issues`]: changing a
+    /// statement's value or rank, or removing its qualifiers and references,
+    /// such as one replaced by a refreshed copy. Removed statements become v1
+    /// `-` commands or `-P…` CSV columns. Removed terms and sitelinks cannot
+    /// be expressed and are listed as well.
+    #[cfg(feature = "item-merger")]
+    pub fn diff2qs(
+        item: &ItemEntity,
+        diff: &MergeDiff,
+        format: QsFormat,
+    ) -> Result<QsExport, WikidataError> {
+        let id = item.id();
+        if id.is_empty() {
+            return Err(WikidataError::ItemIdEmpty);
+        }
+        let mut writer = QsWriter::new(format);
+        let mut commands = vec![];
+        for ls in &diff.labels {
+            commands.extend(writer.term('L', ls));
+        }
+        for ls in &diff.descriptions {
+            commands.extend(writer.term('D', ls));
+        }
+        for ls in &diff.aliases {
+            commands.extend(writer.term('A', ls));
+        }
+        for sitelink in &diff.sitelinks {
+            commands.extend(writer.sitelink(sitelink));
+        }
+        for statement in &diff.added_statements {
+            commands.extend(writer.statement(statement));
+        }
+        // Sorted by statement ID so the output does not depend on hash order.
+        let mut altered: Vec<_> = diff.altered_statements.iter().collect();
+        altered.sort_by_key(|(statement_id, _)| *statement_id);
+        for (statement_id, statement) in altered {
+            let original = item
+                .claims()
                 .iter()
-                .map(|ls| format!("LAST\tA{}\t\"{}\"", ls.language(), ls.value())),
-        );
+                .find(|s| s.id().is_some_and(|other| other == *statement_id));
+            match original {
+                Some(original) => commands.extend(writer.alteration(original, statement)),
+                None => commands.extend(writer.statement(statement)),
+            }
+        }
+        let mut removed: Vec<_> = diff.removed_statements.iter().collect();
+        removed.sort_by_key(|(statement_id, _)| *statement_id);
+        for (_, statement) in removed {
+            commands.extend(writer.removal(statement));
+        }
+        let removed_terms = [
+            ("label", &diff.removed_labels),
+            ("description", &diff.removed_descriptions),
+            ("alias", &diff.removed_aliases),
+        ];
+        for (name, list) in removed_terms {
+            for ls in list {
+                writer.dropped(
+                    &format!("{name} [{}]", ls.language()),
+                    "removal cannot be expressed; dropped",
+                );
+            }
+        }
+        for sitelink in &diff.removed_sitelinks {
+            writer.dropped(
+                &format!("sitelink {}", sitelink.site()),
+                "removal cannot be expressed; dropped",
+            );
+        }
+        writer.finish(id, commands)
+    }
+}
 
-        // Add sitelinks
-        ret.extend(
-            item.sitelinks()
-                .as_ref()
-                .map(|sl| sl.as_slice())
-                .unwrap_or(&[])
-                .iter()
-                .map(|sl| format!("LAST\tS{}\t\"{}\"", sl.site(), sl.title())),
-        );
+/// Which QuickStatements syntax [`Wikidata::item2qs_export`] writes.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum QsFormat {
+    /// Tab-separated commands, one per line.
+    #[default]
+    V1,
+    /// The CSV import format: a header row, then one row for the item.
+    V2Csv,
+}
 
-        // Add statements
-        for statement in item.claims() {
-            let main_value = match Self::snak2qs(statement.main_snak()) {
-                Some(value) => value,
-                None => continue,
-            };
+/// Something [`Wikidata::item2qs_export`] could not express.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QsIssue {
+    /// Where on the item, e.g. `P31 qualifier P580` or `label [en]`.
+    pub location: String,
+    /// What could not be expressed, and whether it was dropped or
+    /// approximated.
+    pub reason: String,
+    /// Whether a whole value, term or sitelink was left out, rather than
+    /// exported without some detail.
+    pub dropped: bool,
+}
 
-            let qualifiers: Vec<String> = statement
-                .qualifiers()
-                .iter()
-                .filter_map(|snak| {
-                    let v = Self::snak2qs(snak)?;
-                    Some(vec![snak.property().to_string(), v])
-                })
-                .flatten()
-                .collect();
-
-            let references: Vec<String> = statement
-                .references()
-                .iter()
-                .flat_map(|r| {
-                    let mut parts: Vec<String> = r
-                        .snaks()
-                        .iter()
-                        .filter_map(|snak| {
-                            let v = Self::snak2qs(snak)?;
-                            let p = snak.property().replace('P', "S");
-                            Some(vec![p, v])
-                        })
-                        .flatten()
-                        .collect();
-                    if !parts.is_empty() {
-                        parts[0] = format!("!{}", parts[0]); // Start new reference section
-                    }
-                    parts
-                })
-                .collect();
-
-            let mut parts = vec![main_value];
-            parts.extend(qualifiers);
-            parts.extend(references);
-            ret.push(format!(
-                "LAST\t{}\t{}",
+impl std::fmt::Display for QsIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.location, self.reason)
+    }
+}
+
+/// The result of [`Wikidata::item2qs_export`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct QsExport {
+    /// One v1 command per entry, or for [`QsFormat::V2Csv`] the header record
+    /// followed by the item's record. A CSV record may contain quoted line
+    /// breaks, so join with `\n` rather than splitting on it.
+    pub commands: Vec<String>,
+    pub issues: Vec<QsIssue>,
+}
+
+const ENTITY_PREFIX: &str = "http://www.wikidata.org/entity/";
+const EARTH: &str = "http://www.wikidata.org/entity/Q2";
+const GREGORIAN: &str = "http://www.wikidata.org/entity/Q1985727";
+const JULIAN: &str = "http://www.wikidata.org/entity/Q1985786";
+/// The precision QuickStatements gives every coordinate it creates.
+const QS_COORDINATE_PRECISION: f64 = 0.000_001;
+
+/// The key of one value in a command: a v1 column, or a v2 CSV header.
+#[derive(Debug, Clone, PartialEq)]
+enum QsColumn {
+    /// `Len`, `Den`, `Aen` or `Senwiki`.
+    Term(String),
+    Statement(String),
+    /// Removes the statement with this property and the following value.
+    #[cfg_attr(not(feature = "item-merger"), allow(dead_code))]
+    Removal(String),
+    Qualifier(String),
+    Reference {
+        property: String,
+        new_group: bool,
+    },
+}
+
+/// One command: its key/value pairs in order, the first being the term or
+/// statement it sets.
+type QsCommand = Vec<(QsColumn, String)>;
+
+/// Renders item parts into [`QsCommand`]s, collecting [`QsIssue`]s.
+struct QsWriter<'a> {
+    format: QsFormat,
+    issues: Vec<QsIssue>,
+    registry: Option<&'a PropertyRegistry>,
+}
+
+impl QsWriter<'_> {
+    fn new(format: QsFormat) -> Self {
+        Self {
+            format,
+            issues: vec![],
+            registry: None,
+        }
+    }
+
+    /// Records something exported without some detail.
+    fn issue(&mut self, location: &str, reason: impl Into<String>) {
+        self.issues.push(QsIssue {
+            location: location.to_string(),
+            reason: reason.into(),
+            dropped: false,
+        });
+    }
+
+    /// Records something left out entirely.
+    fn dropped(&mut self, location: &str, reason: impl Into<String>) {
+        self.issues.push(QsIssue {
+            location: location.to_string(),
+            reason: reason.into(),
+            dropped: true,
+        });
+    }
+
+    /// A double-quoted string value. QuickStatements takes everything between
+    /// the outer quotes literally, so inner quotes need no escaping; in v1 a
+    /// tab or line break would end the command, so such text is dropped.
+    fn quoted(&mut self, location: &str, text: &str) -> Option<String> {
+        if self.format == QsFormat::V1 && text.contains(['\t', '\n', '\r']) {
+            self.dropped(location, "contains a tab or line break; dropped");
+            return None;
+        }
+        Some(format!("\"{text}\""))
+    }
+
+    /// Label, description or alias text: quoted in v1, a plain cell in CSV.
+    fn term_text(&mut self, location: &str, text: &str) -> Option<String> {
+        match self.format {
+            QsFormat::V1 => self.quoted(location, text),
+            QsFormat::V2Csv => Some(text.to_string()),
+        }
+    }
+
+    fn term(&mut self, kind: char, ls: &LocaleString) -> Option<QsCommand> {
+        let name = match kind {
+            'L' => "label",
+            'D' => "description",
+            _ => "alias",
+        };
+        let location = format!("{name} [{}]", ls.language());
+        let value = self.term_text(&location, ls.value())?;
+        Some(vec![(
+            QsColumn::Term(format!("{kind}{}", ls.language())),
+            value,
+        )])
+    }
+
+    fn sitelink(&mut self, sitelink: &SiteLink) -> Option<QsCommand> {
+        let location = format!("sitelink {}", sitelink.site());
+        if !sitelink.badges().is_empty() {
+            self.issue(&location, "badges cannot be set; dropped the badges");
+        }
+        let value = self.term_text(&location, sitelink.title())?;
+        Some(vec![(
+            QsColumn::Term(format!("S{}", sitelink.site())),
+            value,
+        )])
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Option<QsCommand> {
+        let command = self.statement_body(statement)?;
+        if *statement.rank() != StatementRank::Normal {
+            self.issue(
                 statement.property(),
-                parts.join("\t")
-            ));
+                format!(
+                    "rank {} cannot be set; exported as normal",
+                    statement.rank().as_str()
+                ),
+            );
+        }
+        Some(command)
+    }
+
+    /// The statement's main value, qualifiers and references, ignoring rank.
+    fn statement_body(&mut self, statement: &Statement) -> Option<QsCommand> {
+        let property = statement.property().to_string();
+        let value = self.snak(&property, statement.main_snak())?;
+        let mut command = vec![(QsColumn::Statement(property.clone()), value)];
+        command.extend(self.qualifiers_and_references(
+            &property,
+            statement.qualifiers(),
+            statement.references(),
+        ));
+        Some(command)
+    }
+
+    /// The statement as [`Self::statement_body`] writes it, for altering
+    /// `original`. QuickStatements can only add qualifiers and references to
+    /// it, so a changed value or rank, and removed qualifiers and references,
+    /// are reported as dropped.
+    #[cfg(feature = "item-merger")]
+    fn alteration(&mut self, original: &Statement, altered: &Statement) -> Option<QsCommand> {
+        let property = altered.property();
+        if original.main_snak() != altered.main_snak() {
+            self.dropped(property, "value change cannot be expressed; dropped");
+            return None;
+        }
+        if original.rank() != altered.rank() {
+            self.dropped(
+                property,
+                format!(
+                    "rank change from {} to {} cannot be expressed; dropped",
+                    original.rank().as_str(),
+                    altered.rank().as_str()
+                ),
+            );
+        }
+        for qualifier in original.qualifiers() {
+            if !altered.qualifiers().contains(qualifier) {
+                self.dropped(
+                    &format!("{property} qualifier {}", qualifier.property()),
+                    "removal cannot be expressed; dropped",
+                );
+            }
+        }
+        for (group, reference) in original.references().iter().enumerate() {
+            if !altered.references().contains(reference) {
+                self.dropped(
+                    &format!("{property} reference {}", group + 1),
+                    "removal or replacement cannot be expressed; the old reference stays",
+                );
+            }
+        }
+        self.statement_body(altered)
+    }
+
+    #[cfg(feature = "item-merger")]
+    fn removal(&mut self, statement: &Statement) -> Option<QsCommand> {
+        let property = statement.property().to_string();
+        let location = format!("{property} removal");
+        let value = self.snak(&location, statement.main_snak())?;
+        Some(vec![(QsColumn::Removal(property), value)])
+    }
+
+    fn qualifiers_and_references(
+        &mut self,
+        property: &str,
+        qualifiers: &[Snak],
+        references: &[Reference],
+    ) -> QsCommand {
+        let mut command = vec![];
+        for qualifier in qualifiers {
+            let location = format!("{property} qualifier {}", qualifier.property());
+            if let Some(value) = self.snak(&location, qualifier) {
+                command.push((QsColumn::Qualifier(qualifier.property().to_string()), value));
+            }
+        }
+        for (group, reference) in references.iter().enumerate() {
+            if group > 0 && self.format == QsFormat::V2Csv {
+                self.dropped(
+                    &format!("{property} reference {}", group + 1),
+                    "CSV holds one reference group per statement; dropped",
+                );
+                continue;
+            }
+            let mut new_group = true;
+            for snak in reference.snaks() {
+                let location = format!("{property} reference {}", snak.property());
+                if let Some(value) = self.snak(&location, snak) {
+                    let column = QsColumn::Reference {
+                        property: snak.property().to_string(),
+                        new_group,
+                    };
+                    command.push((column, value));
+                    new_group = false;
+                }
+            }
+        }
+        command
+    }
+
+    fn snak(&mut self, location: &str, snak: &Snak) -> Option<String> {
+        match snak.snak_type() {
+            SnakType::NoValue => return Some("novalue".to_string()),
+            SnakType::UnknownValue => return Some("somevalue".to_string()),
+            SnakType::Value => {}
+        }
+        let Some(dv) = snak.data_value() else {
+            self.dropped(location, "value snak without a value; dropped");
+            return None;
+        };
+        if let Some(problem) = self
+            .registry
+            .and_then(|r| r.value_problem(snak.property(), dv.value()))
+        {
+            self.issue(location, format!("{problem}; exported anyway"));
+        }
+        match dv.value() {
+            Value::Coordinate(c) => self.coordinate(location, c),
+            Value::MonoLingual(m) => {
+                let text = self.quoted(location, m.text())?;
+                Some(format!("{}:{text}", m.language()))
+            }
+            Value::Entity(entity) | Value::EntitySchema(entity) => Some(entity.id().to_string()),
+            Value::Quantity(quantity) => self.quantity(location, quantity),
+            Value::StringValue(s) => self.quoted(location, s),
+            Value::Time(time) => self.time(location, time),
         }
-        Ok(ret)
     }
 
-    fn snak2qs(snak: &wikibase::Snak) -> Option<String> {
-        if *snak.snak_type() != SnakType::Value {
+    fn coordinate(&mut self, location: &str, c: &Coordinate) -> Option<String> {
+        if c.globe() != EARTH {
+            self.dropped(
+                location,
+                format!("globe {} is not Earth; dropped", c.globe()),
+            );
             return None;
         }
-        let dv = snak.data_value().as_ref()?;
-        let value = match dv.value() {
-            wikibase::Value::Coordinate(c) => format!("@{}/{}", c.latitude(), c.longitude()),
-            wikibase::Value::MonoLingual(m) => format!("{}:\"{}\"", m.language(), m.text()),
-            wikibase::Value::Entity(entity) => format!("\"{}\"", entity.id()),
-            wikibase::Value::Quantity(quantity) => quantity.amount().to_string(),
-            wikibase::Value::StringValue(s) => format!("\"{s}\""),
-            wikibase::Value::Time(time) => format!("{}/{}", time.time(), time.precision()),
-            _ => return None,
+        if let Some(precision) = c.precision() {
+            if (precision - QS_COORDINATE_PRECISION).abs() > f64::EPSILON {
+                self.issue(
+                    location,
+                    format!("precision {precision} cannot be set; QuickStatements uses 0.000001"),
+                );
+            }
+        }
+        if c.altitude().is_some() {
+            self.issue(location, "altitude cannot be set; dropped the altitude");
+        }
+        Some(format!("@{}/{}", c.latitude(), c.longitude()))
+    }
+
+    fn quantity(&mut self, location: &str, q: &QuantityValue) -> Option<String> {
+        let amount = *q.amount();
+        let unit = match q.unit() {
+            "1" => String::new(),
+            unit => match unit
+                .strip_prefix(ENTITY_PREFIX)
+                .and_then(|id| id.strip_prefix('Q'))
+                .filter(|n| !n.is_empty() && n.chars().all(|c| c.is_ascii_digit()))
+            {
+                Some(number) => format!("U{number}"),
+                None => {
+                    self.dropped(location, format!("unit {unit} is not an item; dropped"));
+                    return None;
+                }
+            },
+        };
+        let tolerance = match (q.lower_bound(), q.upper_bound()) {
+            (None, None) => String::new(),
+            (Some(lower), Some(upper)) => {
+                let below = amount - lower;
+                let above = upper - amount;
+                let scale = amount.abs().max(1.0);
+                if (below - above).abs() > 1e-9 * scale {
+                    self.dropped(
+                        location,
+                        "asymmetric bounds cannot be written as a tolerance; dropped",
+                    );
+                    return None;
+                }
+                let decimals = [amount, *lower, *upper]
+                    .iter()
+                    .map(|x| decimal_places(*x))
+                    .max()
+                    .unwrap_or(0);
+                format!("~{}", trim_decimal(&format!("{above:.decimals$}")))
+            }
+            _ => {
+                self.dropped(location, "a one-sided bound cannot be written; dropped");
+                return None;
+            }
+        };
+        Some(format!("{amount}{tolerance}{unit}"))
+    }
+
+    fn time(&mut self, location: &str, t: &TimeValue) -> Option<String> {
+        let calendar = match t.calendarmodel() {
+            GREGORIAN => "",
+            JULIAN => "/J",
+            other => {
+                self.dropped(
+                    location,
+                    format!("calendar {other} is not supported; dropped"),
+                );
+                return None;
+            }
         };
-        Some(value)
+        if *t.before() != 0 || *t.after() != 0 || *t.timezone() != 0 {
+            self.issue(
+                location,
+                "before/after/timezone cannot be set; exported as 0",
+            );
+        }
+        Some(format!("{}/{}{calendar}", t.time(), t.precision()))
+    }
+
+    /// Lays the commands out for `id` (empty for a new item).
+    fn finish(self, id: &str, commands: Vec<QsCommand>) -> Result<QsExport, WikidataError> {
+        let commands = match self.format {
+            QsFormat::V1 => {
+                let target = if id.is_empty() { "LAST" } else { id };
+                let lines = commands.into_iter().map(|command| {
+                    let target = match command.first() {
+                        Some((QsColumn::Removal(_), _)) => format!("-{target}"),
+                        _ => target.to_string(),
+                    };
+                    let mut fields = vec![target];
+                    for (column, value) in command {
+                        fields.push(Self::v1_key(&column));
+                        fields.push(value);
+                    }
+                    fields.join("\t")
+                });
+                match id.is_empty() {
+                    true => std::iter::once("CREATE".to_string()).chain(lines).collect(),
+                    false => lines.collect(),
+                }
+            }
+            QsFormat::V2Csv => {
+                let (mut headers, mut cells) = (vec!["qid".to_string()], vec![id.to_string()]);
+                for (column, value) in commands.into_iter().flatten() {
+                    headers.push(Self::csv_header(&column));
+                    cells.push(value);
+                }
+                vec![csv_record(&headers)?, csv_record(&cells)?]
+            }
+        };
+        Ok(QsExport {
+            commands,
+            issues: self.issues,
+        })
+    }
+
+    fn v1_key(column: &QsColumn) -> String {
+        match column {
+            QsColumn::Term(key)
+            | QsColumn::Statement(key)
+            | QsColumn::Removal(key)
+            | QsColumn::Qualifier(key) => key.to_owned(),
+            QsColumn::Reference {
+                property,
+                new_group,
+            } => {
+                let bang = if *new_group { "!" } else { "" };
+                format!("{bang}{}", property.replacen('P', "S", 1))
+            }
+        }
+    }
+
+    fn csv_header(column: &QsColumn) -> String {
+        match column {
+            QsColumn::Term(key) | QsColumn::Statement(key) => key.to_owned(),
+            QsColumn::Removal(property) => format!("-{property}"),
+            QsColumn::Qualifier(property) => format!("qal{}", property.trim_start_matches('P')),
+            QsColumn::Reference { property, .. } => property.replacen('P', "S", 1),
+        }
     }
 }
 
+/// Turns an Action API `error` object into [`WikidataError::Api`].
+pub(crate) fn api_error(result: &serde_json::Value) -> Result<(), WikidataError> {
+    match result.get("error") {
+        Some(error) => Err(WikidataError::Api {
+            code: error["code"].as_str().unwrap_or_default().to_string(),
+            info: error["info"].as_str().unwrap_or_default().to_string(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Digits after the decimal point in `x`'s shortest representation.
+fn decimal_places(x: f64) -> usize {
+    let s = x.to_string();
+    s.split_once('.').map_or(0, |(_, fraction)| fraction.len())
+}
+
+fn trim_decimal(s: &str) -> &str {
+    match s.contains('.') {
+        true => s.trim_end_matches('0').trim_end_matches('.'),
+        false => s,
+    }
+}
+
+/// One CSV record, without its line terminator.
+fn csv_record(fields: &[String]) -> Result<String, WikidataError> {
+    let mut writer = csv::WriterBuilder::new()
+        .terminator(csv::Terminator::Any(b'\n'))
+        .from_writer(vec![]);
+    writer.write_record(fields)?;
+    let bytes = writer.into_inner().map_err(|e| e.into_error())?;
+    let record = String::from_utf8_lossy(&bytes);
+    Ok(record.strip_suffix('\n').unwrap_or(&record).to_string())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -314,6 +974,136 @@ http://www.wikidata.org/entity/Q5,five\r\n";
         // expect(1) + drop runs the verifier and panics if the request did not arrive.
     }
 
+    #[tokio::test]
+    async fn test_load_sparql_table() {
+        use crate::sparql_table::SparqlTableVec;
+        use crate::sparql_value::SparqlValue;
+        use crate::test_support::{mount_sparql_json, wikidata_for};
+        use wiremock::MockServer;
+
+        let server = MockServer::start().await;
+        mount_sparql_json(
+            &server,
+            serde_json::json!({
+                "head": {"vars": ["item", "itemLabel"]},
+                "results": {"bindings": [
+                    {
+                        "item": {"type": "uri", "value": "http://www.wikidata.org/entity/Q42"},
+                        "itemLabel": {"type": "literal", "value": "Douglas Adams"}
+                    },
+                    {"item": {"type": "uri", "value": "http://www.wikidata.org/entity/Q1"}}
+                ]}
+            }),
+        )
+        .await;
+
+        let wd = wikidata_for(&server);
+        let table: SparqlTableVec = wd
+            .load_sparql_table("SELECT ?item ?itemLabel {}")
+            .await
+            .unwrap();
+        assert_eq!(table.len(), 2);
+        assert_eq!(table.get_var_index("itemLabel"), Some(1));
+        assert_eq!(
+            table.get_row_col(0, 0),
+            Some(SparqlValue::Entity("Q42".to_string()))
+        );
+        assert_eq!(table.get_row_col(1, 1), None);
+    }
+
+    #[tokio::test]
+    async fn test_load_sparql_table_sends_json_accept_header() {
+        use crate::sparql_table::SparqlTableVec;
+        use crate::test_support::{wikidata_for, SPARQL_PATH};
+        use wiremock::matchers::{header, method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        let sparql = "SELECT ?x WHERE { ?x ?y ?z } LIMIT 1";
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .and(query_param("query", sparql))
+            .and(header("accept", "application/sparql-results+json"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "head": {"vars": ["x"]},
+                "results": {"bindings": []}
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let wd = wikidata_for(&server);
+        let table: SparqlTableVec = wd.load_sparql_table(sparql).await.unwrap();
+        assert!(table.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_load_sparql_table_reports_http_status() {
+        use crate::sparql_table::SparqlTableVec;
+        use crate::test_support::{wikidata_for, SPARQL_PATH};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(429))
+            .mount(&server)
+            .await;
+
+        let wd = wikidata_for(&server);
+        let result: Result<SparqlTableVec, _> = wd.load_sparql_table("SELECT").await;
+        match result {
+            Err(WikidataError::HttpStatus(status)) => assert_eq!(status.as_u16(), 429),
+            other => panic!("expected HttpStatus, got {other:?}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_load_sparql_table_reports_timeout() {
+        use crate::sparql_table::SparqlTableVec;
+        use crate::test_support::{wikidata_for, SPARQL_PATH};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(5)))
+            .mount(&server)
+            .await;
+
+        let mut wd = wikidata_for(&server);
+        wd.set_timeout(Duration::from_millis(100));
+        let result: Result<SparqlTableVec, _> = wd.load_sparql_table("SELECT").await;
+        assert!(
+            matches!(result, Err(WikidataError::Timeout)),
+            "expected Timeout, got {result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_sparql_table_reports_malformed_json() {
+        use crate::sparql_table::SparqlTableVec;
+        use crate::test_support::{wikidata_for, SPARQL_PATH};
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_string("{\"head\": "))
+            .mount(&server)
+            .await;
+
+        let wd = wikidata_for(&server);
+        let result: Result<SparqlTableVec, _> = wd.load_sparql_table("SELECT").await;
+        assert!(
+            matches!(result, Err(WikidataError::Json(_))),
+            "expected Json, got {result:?}"
+        );
+    }
+
     #[test]
     fn test_item2qs_rejects_item_with_id() {
         let mut item = ItemEntity::new_empty();
@@ -380,7 +1170,7 @@ http://www.wikidata.org/entity/Q5,five\r\n";
         ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        assert!(qs.contains(&"LAST\tP31\t\"Q5\"".to_string()));
+        assert!(qs.contains(&"LAST\tP31\tQ5".to_string()));
     }
 
     #[test]
@@ -437,18 +1227,24 @@ http://www.wikidata.org/entity/Q5,five\r\n";
     }
 
     #[test]
-    fn test_item2qs_skips_novalue_snak() {
+    fn test_item2qs_novalue_snak() {
         let mut item = ItemEntity::new_empty();
-        // A novalue snak — snak2qs should return None, so this claim is skipped
         item.add_claim(Statement::new_normal(
             Snak::new(SnakDataType::WikibaseItem, "P31", SnakType::NoValue, None),
             vec![],
             vec![],
         ));
+        item.add_claim(Statement::new_normal(
+            Snak::new(SnakDataType::Time, "P570", SnakType::UnknownValue, None),
+            vec![],
+            vec![],
+        ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        // Only "CREATE" — the novalue statement must be skipped
-        assert!(!qs.iter().any(|s| s.contains("P31")));
+        assert_eq!(
+            qs,
+            vec!["CREATE", "LAST\tP31\tnovalue", "LAST\tP570\tsomevalue"]
+        );
     }
 
     #[test]
@@ -461,7 +1257,7 @@ http://www.wikidata.org/entity/Q5,five\r\n";
                 SnakType::Value,
                 Some(DataValue::new(
                     DataValueType::Quantity,
-                    Value::Quantity(QuantityValue::new(42.0, None, "+42", None)),
+                    Value::Quantity(QuantityValue::new(42.0, None, "1", None)),
                 )),
             ),
             vec![],
@@ -469,7 +1265,7 @@ http://www.wikidata.org/entity/Q5,five\r\n";
         ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        assert!(qs.iter().any(|s| s.contains("P1082") && s.contains("42")));
+        assert!(qs.contains(&"LAST\tP1082\t42".to_string()));
     }
 
     #[test]
@@ -570,9 +1366,7 @@ http://www.wikidata.org/entity/Q5,five\r\n";
     }
 
     #[test]
-    fn test_item2qs_novalue_qualifier_is_skipped() {
-        // A qualifier whose snak_type is NoValue has no concrete value and must be
-        // silently skipped by snak2qs / the qualifier filter_map.
+    fn test_item2qs_novalue_qualifier() {
         let mut item = ItemEntity::new_empty();
         item.add_claim(Statement::new_normal(
             Snak::new_string("P1476", "title"),
@@ -586,40 +1380,25 @@ http://www.wikidata.org/entity/Q5,five\r\n";
         ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        let line = qs.iter().find(|s| s.contains("P1476")).unwrap();
-        // The NoValue qualifier must not appear in the output.
-        assert!(
-            !line.contains("P31"),
-            "NoValue qualifier must be omitted from QS output, got: {line}"
-        );
+        assert!(qs.contains(&"LAST\tP1476\t\"title\"\tP31\tnovalue".to_string()));
     }
 
     #[test]
-    fn test_item2qs_novalue_reference_snak_is_skipped() {
-        // A reference snak with NoValue produces no value string and must be
-        // omitted from the QS reference section.
+    fn test_item2qs_novalue_reference_snak() {
         let mut item = ItemEntity::new_empty();
         item.add_claim(Statement::new_normal(
             Snak::new_string("P1476", "title"),
             vec![],
             vec![Reference::new(vec![
-                Snak::new_url("P854", "http://example.com"), // valid — must appear
-                Snak::new(SnakDataType::WikibaseItem, "P248", SnakType::NoValue, None), // must be skipped
+                Snak::new_url("P854", "http://example.com"),
+                Snak::new(SnakDataType::WikibaseItem, "P248", SnakType::NoValue, None),
             ])],
         ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        let line = qs.iter().find(|s| s.contains("P1476")).unwrap();
-        // The valid URL reference must be present.
-        assert!(
-            line.contains("S854") && line.contains("http://example.com"),
-            "URL reference snak must be present, got: {line}"
-        );
-        // The NoValue reference snak must not appear.
-        assert!(
-            !line.contains("S248"),
-            "NoValue reference snak must be omitted, got: {line}"
-        );
+        assert!(qs.contains(
+            &"LAST\tP1476\t\"title\"\t!S854\t\"http://example.com\"\tS248\tnovalue".to_string()
+        ));
     }
 
     #[test]
@@ -690,18 +1469,15 @@ http://www.wikidata.org/entity/Q5,five\r\n";
     }
 
     #[test]
-    fn test_item2qs_skips_unsupported_value_type() {
-        // `EntitySchema` is the one Value variant snak2qs has no QuickStatements
-        // representation for, so such a claim must be skipped rather than
-        // emitted malformed.
+    fn test_item2qs_entity_schema() {
         let mut item = ItemEntity::new_empty();
         item.add_claim(Statement::new_normal(
             Snak::new(
-                SnakDataType::WikibaseItem,
-                "P31",
+                SnakDataType::EntitySchema,
+                "P12861",
                 SnakType::Value,
                 Some(DataValue::new(
-                    DataValueType::EntityId,
+                    DataValueType::EntitySchemaId,
                     wikibase::Value::EntitySchema(EntityValue::new(
                         EntityType::EntitySchema,
                         "E123",
@@ -713,6 +1489,395 @@ http://www.wikidata.org/entity/Q5,five\r\n";
         ));
 
         let qs = Wikidata::item2qs(&item).unwrap();
-        assert_eq!(qs, vec!["CREATE".to_string()], "got: {qs:?}");
+        assert_eq!(qs, vec!["CREATE", "LAST\tP12861\tE123"]);
+    }
+
+    fn quantity_snak(property: &str, q: QuantityValue) -> Snak {
+        Snak::new(
+            SnakDataType::Quantity,
+            property,
+            SnakType::Value,
+            Some(DataValue::new(DataValueType::Quantity, Value::Quantity(q))),
+        )
+    }
+
+    fn time_snak(calendar: &str) -> Snak {
+        Snak::new(
+            SnakDataType::Time,
+            "P569",
+            SnakType::Value,
+            Some(DataValue::new(
+                DataValueType::Time,
+                Value::Time(TimeValue::new(
+                    0,
+                    0,
+                    calendar,
+                    9,
+                    "+1500-00-00T00:00:00Z",
+                    0,
+                )),
+            )),
+        )
+    }
+
+    #[test]
+    fn test_item2qs_quantity_units_and_tolerance() {
+        let mut item = ItemEntity::new_empty();
+        let unit = "http://www.wikidata.org/entity/Q11573";
+        for q in [
+            QuantityValue::new(1.2, Some(1.1), unit, Some(1.3)),
+            QuantityValue::new(-5.0, None, unit, None),
+            QuantityValue::new(10.0, Some(9.0), "1", Some(12.0)),
+            QuantityValue::new(10.0, None, "http://example.org/unit", None),
+        ] {
+            item.add_claim(Statement::new_normal(
+                quantity_snak("P2043", q),
+                vec![],
+                vec![],
+            ));
+        }
+        let export = Wikidata::item2qs_export(&item, QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec![
+                "CREATE",
+                "LAST\tP2043\t1.2~0.1U11573",
+                "LAST\tP2043\t-5U11573"
+            ]
+        );
+        assert_eq!(export.issues.len(), 2, "{:?}", export.issues);
+    }
+
+    #[test]
+    fn test_item2qs_calendars() {
+        let mut item = ItemEntity::new_empty();
+        item.add_claim(Statement::new_normal(
+            time_snak("http://www.wikidata.org/entity/Q1985786"),
+            vec![],
+            vec![],
+        ));
+        item.add_claim(Statement::new_normal(
+            time_snak("http://www.wikidata.org/entity/Q12345"),
+            vec![],
+            vec![],
+        ));
+        let export = Wikidata::item2qs_export(&item, QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec!["CREATE", "LAST\tP569\t+1500-00-00T00:00:00Z/9/J"]
+        );
+        assert_eq!(export.issues.len(), 1);
+    }
+
+    #[test]
+    fn test_item2qs_reports_globe_rank_badges_and_line_breaks() {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut()
+            .push(LocaleString::new("en", "two\nlines"));
+        item.set_sitelink(SiteLink::new(
+            "enwiki",
+            "Title",
+            vec!["Q17437796".to_string()],
+        ));
+        item.add_claim(Statement::new_normal(
+            Snak::new(
+                SnakDataType::GlobeCoordinate,
+                "P625",
+                SnakType::Value,
+                Some(DataValue::new(
+                    DataValueType::GlobeCoordinate,
+                    Value::Coordinate(Coordinate::new(
+                        None,
+                        "http://www.wikidata.org/entity/Q405".to_string(),
+                        1.0,
+                        2.0,
+                        None,
+                    )),
+                )),
+            ),
+            vec![],
+            vec![],
+        ));
+        let mut preferred = Statement::new_normal(Snak::new_item("P31", "Q5"), vec![], vec![]);
+        preferred.set_rank(StatementRank::Preferred);
+        item.add_claim(preferred);
+
+        let export = Wikidata::item2qs_export(&item, QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec!["CREATE", "LAST\tSenwiki\t\"Title\"", "LAST\tP31\tQ5"]
+        );
+        let locations: Vec<_> = export.issues.iter().map(|i| i.location.as_str()).collect();
+        assert_eq!(
+            locations,
+            vec!["label [en]", "sitelink enwiki", "P625", "P31"]
+        );
+        let dropped: Vec<_> = export.issues.iter().map(|i| i.dropped).collect();
+        assert_eq!(dropped, vec![true, false, true, false]);
+        assert!(matches!(
+            Wikidata::item2qs(&item),
+            Err(WikidataError::QsUnrepresentable(issues)) if issues == export.issues
+        ));
+
+        // Badges and rank are only approximated, which item2qs accepts.
+        item.labels_mut().clear();
+        item.claims_mut().retain(|s| s.property() != "P625");
+        assert_eq!(
+            Wikidata::item2qs(&item).unwrap(),
+            vec!["CREATE", "LAST\tSenwiki\t\"Title\"", "LAST\tP31\tQ5"]
+        );
+    }
+
+    #[test]
+    fn test_item2qs_quotes_are_kept_literally() {
+        let mut item = ItemEntity::new_empty();
+        item.labels_mut()
+            .push(LocaleString::new("en", "The \"Best\" Item"));
+        let qs = Wikidata::item2qs(&item).unwrap();
+        assert_eq!(qs, vec!["CREATE", "LAST\tLen\t\"The \"Best\" Item\""]);
+    }
+
+    #[test]
+    fn test_item2qs_export_existing_item() {
+        let mut item = ItemEntity::new_empty();
+        item.set_id("Q42".to_string());
+        item.labels_mut()
+            .push(LocaleString::new("en", "Douglas Adams"));
+        item.add_claim(Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![],
+            vec![],
+        ));
+        let export = Wikidata::item2qs_export(&item, QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec!["Q42\tLen\t\"Douglas Adams\"", "Q42\tP31\tQ5"]
+        );
+        assert!(export.issues.is_empty());
+        assert!(matches!(
+            Wikidata::item2qs(&item),
+            Err(WikidataError::ItemIdNotEmpty)
+        ));
+    }
+
+    #[test]
+    fn test_item2qs_export_with_registry() {
+        use crate::property_registry::PropertyInfo;
+
+        let mut registry = PropertyRegistry::new();
+        registry.insert(
+            "P31",
+            PropertyInfo {
+                datatype: Some(SnakDataType::WikibaseItem),
+                ..Default::default()
+            },
+        );
+        registry.insert(
+            "P214",
+            PropertyInfo {
+                datatype: Some(SnakDataType::ExternalId),
+                format_regex: Some("\\d+".to_string()),
+                ..Default::default()
+            },
+        );
+        let mut item = ItemEntity::new_empty();
+        item.set_id("Q42".to_string());
+        item.add_claim(Statement::new_normal(
+            Snak::new_string("P31", "Q5"),
+            vec![],
+            vec![],
+        ));
+        item.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "abc"),
+            vec![],
+            vec![],
+        ));
+        item.add_claim(Statement::new_normal(
+            Snak::new_external_id("P214", "113230702"),
+            vec![],
+            vec![],
+        ));
+        assert!(Wikidata::item2qs_export(&item, QsFormat::V1)
+            .unwrap()
+            .issues
+            .is_empty());
+
+        let export = Wikidata::item2qs_export_with(&item, QsFormat::V1, &registry).unwrap();
+        assert_eq!(export.commands.len(), 3);
+        let reasons: Vec<_> = export
+            .issues
+            .iter()
+            .map(|i| (i.location.as_str(), i.reason.as_str()))
+            .collect();
+        assert_eq!(
+            reasons,
+            vec![
+                (
+                    "P31",
+                    "value does not fit datatype wikibase-item; exported anyway"
+                ),
+                (
+                    "P214",
+                    "\"abc\" does not match the format regex; exported anyway"
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_item2qs_export_csv() {
+        let mut item = ItemEntity::new_empty();
+        item.set_id("Q42".to_string());
+        item.labels_mut()
+            .push(LocaleString::new("en", "Douglas \"DNA\" Adams"));
+        item.add_claim(Statement::new_normal(
+            Snak::new_string("P1476", "a, b\nc"),
+            vec![Snak::new_item("P407", "Q1860")],
+            vec![
+                Reference::new(vec![Snak::new_item("P248", "Q36578")]),
+                Reference::new(vec![Snak::new_item("P143", "Q328")]),
+            ],
+        ));
+        let export = Wikidata::item2qs_export(&item, QsFormat::V2Csv).unwrap();
+        assert_eq!(
+            export.commands,
+            vec![
+                "qid,Len,P1476,qal407,S248".to_string(),
+                "Q42,\"Douglas \"\"DNA\"\" Adams\",\"\"\"a, b\nc\"\"\",Q1860,Q36578".to_string(),
+            ]
+        );
+        // Only one reference group fits a CSV row.
+        assert_eq!(export.issues.len(), 1);
+        assert_eq!(export.issues[0].location, "P1476 reference 2");
+    }
+
+    /// The item [`sample_diff`] was made against.
+    #[cfg(feature = "item-merger")]
+    fn sample_item() -> ItemEntity {
+        let mut item = ItemEntity::new_empty();
+        item.set_id("Q42".to_string());
+        let mut original = Statement::new_normal(Snak::new_item("P31", "Q5"), vec![], vec![]);
+        original.set_id("Q42$altered");
+        original.set_rank(StatementRank::Preferred);
+        item.add_claim(original);
+        item
+    }
+
+    #[cfg(feature = "item-merger")]
+    fn sample_diff() -> MergeDiff {
+        let mut altered = Statement::new_normal(
+            Snak::new_item("P31", "Q5"),
+            vec![Snak::new_item("P642", "Q1")],
+            vec![Reference::new(vec![Snak::new_item("P248", "Q36578")])],
+        );
+        altered.set_id("Q42$altered");
+        altered.set_rank(StatementRank::Preferred);
+        let mut removed = Statement::new_normal(Snak::new_string("P1476", "Old"), vec![], vec![]);
+        removed.set_id("Q42$removed");
+        let mut diff = MergeDiff::new();
+        diff.labels.push(LocaleString::new("en", "Douglas Adams"));
+        diff.aliases.push(LocaleString::new("en", "DNA"));
+        diff.sitelinks
+            .push(SiteLink::new("dewiki", "Douglas Adams", vec![]));
+        diff.added_statements.push(Statement::new_normal(
+            Snak::new_string("P214", "113230702"),
+            vec![],
+            vec![],
+        ));
+        diff.altered_statements
+            .insert("Q42$altered".to_string(), altered);
+        diff.removed_statements
+            .insert("Q42$removed".to_string(), removed);
+        diff
+    }
+
+    #[cfg(feature = "item-merger")]
+    #[test]
+    fn test_diff2qs_v1() {
+        let export = Wikidata::diff2qs(&sample_item(), &sample_diff(), QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec![
+                "Q42\tLen\t\"Douglas Adams\"",
+                "Q42\tAen\t\"DNA\"",
+                "Q42\tSdewiki\t\"Douglas Adams\"",
+                "Q42\tP214\t\"113230702\"",
+                "Q42\tP31\tQ5\tP642\tQ1\t!S248\tQ36578",
+                "-Q42\tP1476\t\"Old\"",
+            ]
+        );
+        // The altered statement only gains a qualifier and a reference.
+        assert!(export.issues.is_empty(), "{:?}", export.issues);
+    }
+
+    #[cfg(feature = "item-merger")]
+    #[test]
+    fn test_diff2qs_csv() {
+        let export = Wikidata::diff2qs(&sample_item(), &sample_diff(), QsFormat::V2Csv).unwrap();
+        assert_eq!(
+            export.commands,
+            vec![
+                "qid,Len,Aen,Sdewiki,P214,P31,qal642,S248,-P1476",
+                "Q42,Douglas Adams,DNA,Douglas Adams,\"\"\"113230702\"\"\",Q5,Q1,Q36578,\"\"\"Old\"\"\"",
+            ]
+        );
+    }
+
+    #[cfg(feature = "item-merger")]
+    #[test]
+    fn test_diff2qs_reports_term_removals_and_needs_an_id() {
+        let mut diff = MergeDiff::new();
+        diff.removed_labels.push(LocaleString::new("de", "Alt"));
+        diff.removed_sitelinks
+            .push(SiteLink::new("enwiki", "Old", vec![]));
+        let export = Wikidata::diff2qs(&sample_item(), &diff, QsFormat::V1).unwrap();
+        assert!(export.commands.is_empty());
+        let locations: Vec<_> = export.issues.iter().map(|i| i.location.as_str()).collect();
+        assert_eq!(locations, vec!["label [de]", "sitelink enwiki"]);
+        assert!(matches!(
+            Wikidata::diff2qs(&ItemEntity::new_empty(), &diff, QsFormat::V1),
+            Err(WikidataError::ItemIdEmpty)
+        ));
+    }
+
+    #[cfg(feature = "item-merger")]
+    #[test]
+    fn test_diff2qs_reports_rank_changes_and_replaced_references() {
+        let retrieved = |date: &str| Reference::new(vec![Snak::new_time("P813", date, 11)]);
+        let mut item = ItemEntity::new_empty();
+        item.set_id("Q42".to_string());
+        let mut born = Statement::new_normal(
+            Snak::new_time("P569", "+1952-03-11T00:00:00Z", 11),
+            vec![],
+            vec![],
+        );
+        born.set_id("Q42$born");
+        item.add_claim(born.clone());
+        let mut described = Statement::new_normal(
+            Snak::new_url("P973", "https://example.org"),
+            vec![],
+            vec![retrieved("+2020-01-01T00:00:00Z")],
+        );
+        described.set_id("Q42$described");
+        item.add_claim(described.clone());
+
+        let mut diff = MergeDiff::new();
+        born.set_rank(StatementRank::Deprecated);
+        diff.altered_statements.insert("Q42$born".to_string(), born);
+        described.set_references(vec![retrieved("+2024-01-01T00:00:00Z")]);
+        diff.altered_statements
+            .insert("Q42$described".to_string(), described);
+
+        let export = Wikidata::diff2qs(&item, &diff, QsFormat::V1).unwrap();
+        assert_eq!(
+            export.commands,
+            vec![
+                "Q42\tP569\t+1952-03-11T00:00:00Z/11",
+                "Q42\tP973\t\"https://example.org\"\t!S813\t+2024-01-01T00:00:00Z/11",
+            ]
+        );
+        let locations: Vec<_> = export.issues.iter().map(|i| i.location.as_str()).collect();
+        assert_eq!(locations, vec!["P569", "P973 reference 1"]);
+        assert!(export.issues.iter().all(|i| i.dropped));
     }
 }
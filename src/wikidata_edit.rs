@@ -0,0 +1,453 @@
+//! Writing merges back to Wikidata through `wbeditentity`.
+//!
+//! [`Wikidata::merge_into_item`] loads the live item, merges another item into
+//! it with [`ItemMerger`], and submits the resulting [`MergeDiff`] against the
+//! revision it was computed from. If someone else edited the item in the
+//! meantime, the item is reloaded and the merge re-run, up to
+//! [`EditOptions::max_attempts`] times. [`Wikidata::edit_item`] does the same
+//! for any function that turns the live item into a diff.
+//!
+//! Edits go through a caller-supplied, logged-in [`Api`], holding one of the
+//! client's Action API slots. Its maxlag handling is used: an edit rejected
+//! for lag is retried after the reported lag, and a `429 Too Many Requests`
+//! after its `Retry-After` delay.
+
+use crate::item_merger::ItemMerger;
+use crate::merge_diff::MergeDiff;
+use crate::wikidata::{api_error, Wikidata, WikidataError};
+use serde_json::Value;
+use wikibase::mediawiki::Api;
+use wikibase::ItemEntity;
+
+/// How [`Wikidata::submit_diff`] and friends edit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EditOptions {
+    /// The edit summary; none by default.
+    pub summary: Option<String>,
+    /// Flag the edit as a bot edit; requires the bot right.
+    pub bot: bool,
+    /// The `maxlag` to send, in seconds; `None` disables it. Defaults to 5.
+    pub maxlag: Option<u64>,
+    /// How often to compute and submit the diff before giving up on edit
+    /// conflicts. Defaults to 3.
+    pub max_attempts: usize,
+}
+
+impl Default for EditOptions {
+    fn default() -> Self {
+        Self {
+            summary: None,
+            bot: false,
+            maxlag: Some(5),
+            max_attempts: 3,
+        }
+    }
+}
+
+impl Wikidata {
+    /// Loads item `id` and the ID of its latest revision.
+    pub async fn load_item_revision(
+        &self,
+        api: &Api,
+        id: &str,
+    ) -> Result<(ItemEntity, u64), WikidataError> {
+        let params = api.params_into(&[("action", "wbgetentities"), ("ids", id)]);
+        let result = {
+            let _permit = self.api_permit().await?;
+            api.get_query_api_json(&params).await?
+        };
+        api_error(&result)?;
+        let json = &result["entities"][id];
+        if json.is_null() || json.get("missing").is_some() {
+            return Err(WikidataError::MissingEntity(id.to_string()));
+        }
+        let revision = last_revision(json)?;
+        Ok((ItemEntity::new_from_json(json)?, revision))
+    }
+
+    /// Submits `diff` to item `id` and returns the new revision ID, or the
+    /// current one if the diff changed nothing.
+    ///
+    /// With `base_revision`, the edit fails with
+    /// [`WikidataError::EditConflict`] if the item has been edited since that
+    /// revision. A single attempt is made; see [`Wikidata::edit_item`] for
+    /// retrying.
+    pub async fn submit_diff(
+        &self,
+        api: &mut Api,
+        id: &str,
+        diff: &MergeDiff,
+        base_revision: Option<u64>,
+        options: &EditOptions,
+    ) -> Result<u64, WikidataError> {
+        let data = serde_json::to_string(diff)?;
+        let _permit = self.api_permit().await?;
+        let token = api.get_edit_token().await?;
+        let mut params = api.params_into(&[
+            ("action", "wbeditentity"),
+            ("id", id),
+            ("data", &data),
+            ("token", &token),
+        ]);
+        if let Some(revision) = base_revision {
+            params.insert("baserevid".to_string(), revision.to_string());
+        }
+        if let Some(summary) = &options.summary {
+            params.insert("summary".to_string(), summary.to_owned());
+        }
+        if options.bot {
+            params.insert("bot".to_string(), "1".to_string());
+        }
+
+        let previous_maxlag = *api.maxlag();
+        api.set_maxlag(options.maxlag);
+        let result = api.post_query_api_json_mut(&params).await;
+        api.set_maxlag(previous_maxlag);
+        let result = result?;
+
+        if is_edit_conflict(&result) {
+            return Err(WikidataError::EditConflict {
+                id: id.to_string(),
+                attempts: 1,
+            });
+        }
+        api_error(&result)?;
+        last_revision(&result["entity"])
+    }
+
+    /// Loads item `id`, turns it into a diff with `make_diff`, and submits the
+    /// diff against the loaded revision. On an edit conflict the item is
+    /// reloaded and `make_diff` called again, up to
+    /// [`EditOptions::max_attempts`] times in all.
+    ///
+    /// Returns the new revision ID, or `None` if `make_diff` found nothing to
+    /// change.
+    pub async fn edit_item<F>(
+        &self,
+        api: &mut Api,
+        id: &str,
+        options: &EditOptions,
+        mut make_diff: F,
+    ) -> Result<Option<u64>, WikidataError>
+    where
+        F: FnMut(&ItemEntity) -> MergeDiff,
+    {
+        let max_attempts = options.max_attempts.max(1);
+        for _ in 0..max_attempts {
+            let (item, revision) = self.load_item_revision(api, id).await?;
+            let diff = make_diff(&item);
+            if diff.is_empty() {
+                return Ok(None);
+            }
+            match self
+                .submit_diff(api, id, &diff, Some(revision), options)
+                .await
+            {
+                Err(WikidataError::EditConflict { .. }) => continue,
+                result => return result.map(Some),
+            }
+        }
+        Err(WikidataError::EditConflict {
+            id: id.to_string(),
+            attempts: max_attempts,
+        })
+    }
+
+    /// Merges `other` into item `id` with a default [`ItemMerger`] and submits
+    /// the result; see [`Wikidata::edit_item`].
+    pub async fn merge_into_item(
+        &self,
+        api: &mut Api,
+        id: &str,
+        other: &ItemEntity,
+        options: &EditOptions,
+    ) -> Result<Option<u64>, WikidataError> {
+        self.edit_item(api, id, options, |live| {
+            ItemMerger::new(live.clone()).merge(other)
+        })
+        .await
+    }
+}
+
+fn is_edit_conflict(result: &Value) -> bool {
+    matches!(
+        result["error"]["code"].as_str(),
+        Some("editconflict" | "edit-conflict")
+    )
+}
+
+fn last_revision(entity: &Value) -> Result<u64, WikidataError> {
+    entity["lastrevid"]
+        .as_u64()
+        .ok_or_else(|| WikidataError::MalformedResponse("no lastrevid".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{
+        mount_action, mount_csrf_token, mount_siteinfo, wikidata_for, API_PATH,
+    };
+    use serde_json::json;
+    use wikibase::{EntityTrait, LocaleString};
+    use wiremock::matchers::{body_string_contains, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn entity(revision: u64, label: Option<&str>) -> Value {
+        let labels = match label {
+            Some(label) => json!({"en": {"language": "en", "value": label}}),
+            None => json!({}),
+        };
+        json!({"entities": {"Q42": {
+            "type": "item",
+            "id": "Q42",
+            "lastrevid": revision,
+            "labels": labels,
+            "descriptions": {},
+            "aliases": {},
+            "claims": {},
+            "sitelinks": {}
+        }}})
+    }
+
+    fn edited(revision: u64) -> ResponseTemplate {
+        ResponseTemplate::new(200)
+            .set_body_json(json!({"entity": {"id": "Q42", "lastrevid": revision}, "success": 1}))
+    }
+
+    fn other_with_alias() -> ItemEntity {
+        let mut other = ItemEntity::new_empty();
+        other.aliases_mut().push(LocaleString::new("en", "DNA"));
+        other
+    }
+
+    async fn setup(server: &MockServer) -> (Wikidata, Api) {
+        mount_siteinfo(server).await;
+        mount_csrf_token(server, "tok+\\").await;
+        let wd = wikidata_for(server);
+        let api = wd.api().await.unwrap();
+        (wd, api)
+    }
+
+    async fn edit_requests(server: &MockServer) -> Vec<String> {
+        server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|r| r.method.as_str() == "POST")
+            .map(|r| String::from_utf8_lossy(&r.body).to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_merge_into_item_submits_against_loaded_revision() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        mount_action(&server, "wbgetentities", entity(100, None)).await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .and(body_string_contains("action=wbeditentity"))
+            .respond_with(edited(101))
+            .mount(&server)
+            .await;
+
+        let options = EditOptions {
+            summary: Some("merge".to_string()),
+            bot: true,
+            ..Default::default()
+        };
+        let revision = wd
+            .merge_into_item(&mut api, "Q42", &other_with_alias(), &options)
+            .await
+            .unwrap();
+        assert_eq!(revision, Some(101));
+
+        let bodies = edit_requests(&server).await;
+        assert_eq!(bodies.len(), 1);
+        for expected in [
+            "baserevid=100",
+            "summary=merge",
+            "bot=1",
+            "maxlag=5",
+            "token=tok%2B%5C",
+            "id=Q42",
+            "DNA",
+        ] {
+            assert!(bodies[0].contains(expected), "{expected} in {}", bodies[0]);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_edit_item_retries_after_edit_conflict() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(wiremock::matchers::query_param("action", "wbgetentities"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(entity(100, None)))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        mount_action(&server, "wbgetentities", entity(105, Some("Someone else"))).await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .and(body_string_contains("baserevid=100"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "editconflict", "info": "Edit conflict."}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .and(body_string_contains("baserevid=105"))
+            .respond_with(edited(106))
+            .mount(&server)
+            .await;
+
+        let mut seen_labels = vec![];
+        let revision = wd
+            .edit_item(&mut api, "Q42", &EditOptions::default(), |live| {
+                seen_labels.push(live.labels().len());
+                ItemMerger::new(live.clone()).merge(&other_with_alias())
+            })
+            .await
+            .unwrap();
+        assert_eq!(revision, Some(106));
+        // The second diff was computed from the reloaded item.
+        assert_eq!(seen_labels, vec![0, 1]);
+    }
+
+    #[tokio::test]
+    async fn test_edit_item_gives_up_after_max_attempts() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        mount_action(&server, "wbgetentities", entity(100, None)).await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "editconflict", "info": "Edit conflict."}
+            })))
+            .mount(&server)
+            .await;
+
+        let options = EditOptions {
+            max_attempts: 2,
+            ..Default::default()
+        };
+        let result = wd
+            .merge_into_item(&mut api, "Q42", &other_with_alias(), &options)
+            .await;
+        assert!(
+            matches!(
+                &result,
+                Err(WikidataError::EditConflict { id, attempts: 2 }) if id == "Q42"
+            ),
+            "{result:?}"
+        );
+        assert_eq!(edit_requests(&server).await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_edit_item_skips_empty_diff() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        mount_action(&server, "wbgetentities", entity(100, None)).await;
+        let revision = wd
+            .merge_into_item(
+                &mut api,
+                "Q42",
+                &ItemEntity::new_empty(),
+                &EditOptions::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(revision, None);
+        assert!(edit_requests(&server).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_submit_diff_waits_out_maxlag_and_retry_after() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "maxlag", "info": "Waiting for a database server", "lag": 0}
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .respond_with(edited(7))
+            .mount(&server)
+            .await;
+
+        let mut diff = MergeDiff::new();
+        diff.labels.push(LocaleString::new("en", "Douglas Adams"));
+        let options = EditOptions {
+            maxlag: Some(3),
+            ..Default::default()
+        };
+        let revision = wd
+            .submit_diff(&mut api, "Q42", &diff, None, &options)
+            .await
+            .unwrap();
+        assert_eq!(revision, 7);
+        let bodies = edit_requests(&server).await;
+        assert_eq!(bodies.len(), 3);
+        assert!(bodies.iter().all(|b| b.contains("maxlag=3")));
+        assert!(!bodies[0].contains("baserevid"));
+        // The caller's maxlag setting is left as it was.
+        assert_eq!(*api.maxlag(), Some(5));
+    }
+
+    #[tokio::test]
+    async fn test_submit_diff_reports_api_errors() {
+        let server = MockServer::start().await;
+        let (wd, mut api) = setup(&server).await;
+        Mock::given(method("POST"))
+            .and(path(API_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "permissiondenied", "info": "No."}
+            })))
+            .mount(&server)
+            .await;
+        let result = wd
+            .submit_diff(
+                &mut api,
+                "Q42",
+                &MergeDiff::new(),
+                None,
+                &EditOptions::default(),
+            )
+            .await;
+        assert!(
+            matches!(&result, Err(WikidataError::Api { code, info }) if code == "permissiondenied" && info == "No."),
+            "{result:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_load_item_revision_missing_item() {
+        let server = MockServer::start().await;
+        let (wd, api) = setup(&server).await;
+        mount_action(
+            &server,
+            "wbgetentities",
+            json!({"entities": {"Q42": {"id": "Q42", "missing": ""}}}),
+        )
+        .await;
+        assert!(matches!(
+            wd.load_item_revision(&api, "Q42").await,
+            Err(WikidataError::MissingEntity(id)) if id == "Q42"
+        ));
+    }
+}
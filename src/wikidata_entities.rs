@@ -0,0 +1,597 @@
+//! Loading items, properties and lexemes into an in-memory cache.
+//!
+//! [`Wikidata::load_entities`] asks `wbgetentities` for up to 50 IDs per
+//! request and answers repeat requests from the cache. When `wbgetentities`
+//! rejects a batch as a whole for one unknown or malformed ID, that ID is
+//! dropped and the rest of the batch is asked for again. Rejected IDs, and any
+//! ID `wbgetentities` leaves out of its answer, are fetched one at a time from
+//! `Special:EntityData` instead. Any other failure is returned.
+//! [`Wikidata::load_entity_revision`] pins an entity to an old revision, which
+//! only `Special:EntityData` can serve.
+//!
+//! Every ID comes back as an [`EntityLookup`], so redirects and missing
+//! entities are explicit rather than absent from the result. The cache is
+//! shared by clones of a [`Wikidata`]; latest-revision entries stay until
+//! [`Wikidata::forget_entity`] or [`Wikidata::clear_entity_cache`].
+
+use crate::wikidata::{api_error, Wikidata, WikidataError};
+use crate::wikidata_throttle::{AttemptError, Endpoint};
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use wikibase::{Entity, ItemEntity};
+
+/// How many IDs `wbgetentities` accepts per request without bot rights.
+const WBGETENTITIES_BATCH_SIZE: usize = 50;
+
+/// `wbgetentities` error codes that reject a whole batch for one of its IDs.
+const REJECTED_ID_CODES: &[&str] = &["no-such-entity", "invalid-entity-id"];
+
+/// What loading one entity ID found.
+#[derive(Debug, Clone)]
+pub enum EntityLookup {
+    Found {
+        entity: Entity,
+        revision: u64,
+    },
+    /// The ID redirects to `target`, whose entity this is.
+    Redirected {
+        target: String,
+        entity: Entity,
+        revision: u64,
+    },
+    Missing,
+}
+
+impl EntityLookup {
+    /// The entity, following a redirect.
+    pub fn entity(&self) -> Option<&Entity> {
+        match self {
+            Self::Found { entity, .. } | Self::Redirected { entity, .. } => Some(entity),
+            Self::Missing => None,
+        }
+    }
+
+    /// The entity, if it is an item.
+    pub fn item(&self) -> Option<&ItemEntity> {
+        match self.entity() {
+            Some(Entity::Item(item)) => Some(item),
+            _ => None,
+        }
+    }
+
+    /// The revision the entity was loaded at.
+    pub fn revision(&self) -> Option<u64> {
+        match self {
+            Self::Found { revision, .. } | Self::Redirected { revision, .. } => Some(*revision),
+            Self::Missing => None,
+        }
+    }
+}
+
+/// Cache key: the requested ID, and the pinned revision if any.
+type CacheKey = (String, Option<u64>);
+
+/// Loaded entities, shared by clones of the owning [`Wikidata`].
+#[derive(Debug, Clone, Default)]
+pub(crate) struct EntityCache {
+    entries: Arc<Mutex<HashMap<CacheKey, EntityLookup>>>,
+}
+
+impl EntityCache {
+    /// Nothing in the map can be left half-updated, so a poisoned lock is
+    /// still safe to use.
+    fn lock(&self) -> MutexGuard<'_, HashMap<CacheKey, EntityLookup>> {
+        self.entries.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn get(&self, id: &str, revision: Option<u64>) -> Option<EntityLookup> {
+        self.lock().get(&(id.to_string(), revision)).cloned()
+    }
+
+    fn insert(&self, id: &str, revision: Option<u64>, lookup: EntityLookup) {
+        self.lock().insert((id.to_string(), revision), lookup);
+    }
+}
+
+impl Wikidata {
+    /// Loads the latest revision of each of `ids` (items, properties or
+    /// lexemes), from the cache where possible. Every requested ID is a key
+    /// of the result.
+    pub async fn load_entities(
+        &self,
+        ids: &[&str],
+    ) -> Result<HashMap<String, EntityLookup>, WikidataError> {
+        let mut ret = HashMap::new();
+        let mut to_load = vec![];
+        for id in ids {
+            match self.entity_cache.get(id, None) {
+                Some(lookup) => {
+                    ret.insert(id.to_string(), lookup);
+                }
+                None if !to_load.contains(id) => to_load.push(*id),
+                None => {}
+            }
+        }
+        for chunk in to_load.chunks(WBGETENTITIES_BATCH_SIZE) {
+            let mut pending = chunk.to_vec();
+            let mut loaded = HashMap::new();
+            while !pending.is_empty() {
+                match self.wbgetentities(&pending).await? {
+                    Batch::Loaded(found) => {
+                        loaded = found;
+                        break;
+                    }
+                    // Without a culprit to drop, the batch cannot shrink.
+                    Batch::Rejected(Some(bad)) if pending.contains(&bad.as_str()) => {
+                        pending.retain(|id| *id != bad);
+                    }
+                    Batch::Rejected(_) => break,
+                }
+            }
+            for id in chunk {
+                let lookup = match loaded.remove(*id) {
+                    Some(lookup) => lookup,
+                    None => self.entity_data(id, None).await?,
+                };
+                self.entity_cache.insert(id, None, lookup.clone());
+                ret.insert(id.to_string(), lookup);
+            }
+        }
+        Ok(ret)
+    }
+
+    /// Loads the latest revision of one entity; see
+    /// [`Wikidata::load_entities`].
+    pub async fn load_entity(&self, id: &str) -> Result<EntityLookup, WikidataError> {
+        let mut loaded = self.load_entities(&[id]).await?;
+        Ok(loaded.remove(id).unwrap_or(EntityLookup::Missing))
+    }
+
+    /// Loads an item, following a redirect. `None` if it does not exist or
+    /// `id` is not an item.
+    pub async fn load_item(&self, id: &str) -> Result<Option<ItemEntity>, WikidataError> {
+        Ok(self.load_entity(id).await?.item().cloned())
+    }
+
+    /// Loads an entity as it was at `revision`. Such entries never go stale,
+    /// so they are cached until [`Wikidata::clear_entity_cache`].
+    pub async fn load_entity_revision(
+        &self,
+        id: &str,
+        revision: u64,
+    ) -> Result<EntityLookup, WikidataError> {
+        if let Some(lookup) = self.entity_cache.get(id, Some(revision)) {
+            return Ok(lookup);
+        }
+        let lookup = self.entity_data(id, Some(revision)).await?;
+        self.entity_cache.insert(id, Some(revision), lookup.clone());
+        Ok(lookup)
+    }
+
+    /// Drops the cached latest revision of `id`, e.g. after editing it.
+    pub fn forget_entity(&self, id: &str) {
+        self.entity_cache.lock().remove(&(id.to_string(), None));
+    }
+
+    pub fn clear_entity_cache(&self) {
+        self.entity_cache.lock().clear();
+    }
+
+    /// One `wbgetentities` request. IDs it did not answer for are left out.
+    async fn wbgetentities(&self, ids: &[&str]) -> Result<Batch, WikidataError> {
+        let joined = ids.join("|");
+        let json: Value = self
+            .with_retry(Endpoint::Api, || async {
+                let res = self
+                    .reqwest_client()?
+                    .get(self.api_url())
+                    .query(&[
+                        ("action", "wbgetentities"),
+                        ("ids", joined.as_str()),
+                        ("format", "json"),
+                    ])
+                    .send()
+                    .await
+                    .map_err(Self::map_http_error)?;
+                AttemptError::check_status(&res, self.retry_policy())?;
+                if !res.status().is_success() {
+                    return Err(AttemptError::Fatal(WikidataError::HttpStatus(res.status())));
+                }
+                let json = res.json().await.map_err(Self::map_http_error)?;
+                AttemptError::check_maxlag(&json, self.retry_policy())?;
+                Ok(json)
+            })
+            .await?;
+        if let Some(bad) = rejected_id(&json) {
+            return Ok(Batch::Rejected(bad));
+        }
+        api_error(&json)?;
+        let mut ret = HashMap::new();
+        for id in ids {
+            let entity = &json["entities"][id];
+            if entity.is_null() {
+                continue;
+            }
+            let lookup = if entity.get("missing").is_some() {
+                EntityLookup::Missing
+            } else {
+                lookup_from_json(id, entity)?
+            };
+            ret.insert(id.to_string(), lookup);
+        }
+        Ok(Batch::Loaded(ret))
+    }
+
+    /// `Special:EntityData` JSON for one ID, optionally at `revision`.
+    async fn entity_data(
+        &self,
+        id: &str,
+        revision: Option<u64>,
+    ) -> Result<EntityLookup, WikidataError> {
+        let mut query = vec![("title", format!("Special:EntityData/{id}.json"))];
+        if let Some(revision) = revision {
+            query.push(("revision", revision.to_string()));
+        }
+        let index_url = self.index_url();
+        let json: Option<Value> = self
+            .with_retry(Endpoint::Api, || async {
+                let res = self
+                    .reqwest_client()?
+                    .get(&index_url)
+                    .query(&query)
+                    .send()
+                    .await
+                    .map_err(Self::map_http_error)?;
+                AttemptError::check_status(&res, self.retry_policy())?;
+                if res.status() == reqwest::StatusCode::NOT_FOUND {
+                    return Ok(None);
+                }
+                if !res.status().is_success() {
+                    return Err(AttemptError::Fatal(WikidataError::HttpStatus(res.status())));
+                }
+                Ok(Some(res.json().await.map_err(Self::map_http_error)?))
+            })
+            .await?;
+        let Some(json) = json else {
+            return Ok(EntityLookup::Missing);
+        };
+        // Keyed by the redirect target if `id` is a redirect.
+        match json["entities"].as_object().and_then(|e| e.values().next()) {
+            Some(entity) => lookup_from_json(id, entity),
+            None => Err(WikidataError::MalformedResponse(format!(
+                "no entity for {id} in Special:EntityData"
+            ))),
+        }
+    }
+
+    /// `index.php` next to the configured `api.php`.
+    fn index_url(&self) -> String {
+        let api_url = self.api_url();
+        match api_url.strip_suffix("api.php") {
+            Some(base) => format!("{base}index.php"),
+            None => api_url.to_string(),
+        }
+    }
+}
+
+/// What one `wbgetentities` request answered.
+enum Batch {
+    Loaded(HashMap<String, EntityLookup>),
+    /// The whole batch was rejected, naming the offending ID if it could be
+    /// told.
+    Rejected(Option<String>),
+}
+
+/// If `json` rejects a whole batch for one of its IDs, the ID it names, taken
+/// from the error's `id` or else the quoted ID in its `info`.
+fn rejected_id(json: &Value) -> Option<Option<String>> {
+    let error = json.get("error")?;
+    if !REJECTED_ID_CODES.contains(&error["code"].as_str()?) {
+        return None;
+    }
+    let named = error["id"].as_str().map(str::to_string).or_else(|| {
+        let info = error["info"].as_str()?;
+        let (_, rest) = info.split_once('"')?;
+        let (id, _) = rest.split_once('"')?;
+        Some(id.to_string())
+    });
+    Some(named)
+}
+
+/// Parses the entity JSON returned for the requested `id`.
+fn lookup_from_json(id: &str, json: &Value) -> Result<EntityLookup, WikidataError> {
+    let revision = json["lastrevid"]
+        .as_u64()
+        .ok_or_else(|| WikidataError::MalformedResponse(format!("no lastrevid for {id}")))?;
+    let entity = Entity::new_from_json(json)?;
+    let entity_id = json["id"].as_str().unwrap_or(id);
+    Ok(match entity_id == id {
+        true => EntityLookup::Found { entity, revision },
+        false => EntityLookup::Redirected {
+            target: entity_id.to_string(),
+            entity,
+            revision,
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{wikidata_for, API_PATH};
+    use serde_json::json;
+    use wikibase::EntityTrait;
+    use wiremock::matchers::{method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    const INDEX_PATH: &str = "/w/index.php";
+
+    fn item_json(id: &str, revision: u64) -> Value {
+        json!({
+            "type": "item",
+            "id": id,
+            "lastrevid": revision,
+            "labels": {"en": {"language": "en", "value": format!("label of {id}")}},
+            "descriptions": {},
+            "aliases": {},
+            "claims": {},
+            "sitelinks": {}
+        })
+    }
+
+    async fn request_count(server: &MockServer, path_: &str) -> usize {
+        server
+            .received_requests()
+            .await
+            .unwrap_or_default()
+            .iter()
+            .filter(|r| r.url.path() == path_)
+            .count()
+    }
+
+    #[tokio::test]
+    async fn test_load_entities_batches_and_caches() {
+        let server = MockServer::start().await;
+        let ids: Vec<String> = (1..=120).map(|n| format!("Q{n}")).collect();
+        for chunk in ids.chunks(50) {
+            let entities: serde_json::Map<String, Value> = chunk
+                .iter()
+                .map(|id| (id.clone(), item_json(id, 7)))
+                .collect();
+            Mock::given(method("GET"))
+                .and(path(API_PATH))
+                .and(query_param("action", "wbgetentities"))
+                .and(query_param("ids", chunk.join("|")))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(json!({ "entities": entities })),
+                )
+                .mount(&server)
+                .await;
+        }
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .and(query_param("ids", "Q42"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"entities": {"Q42": item_json("Q42", 8)}})),
+            )
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+        let ids: Vec<&str> = ids.iter().map(String::as_str).collect();
+
+        let loaded = wd.load_entities(&ids).await.unwrap();
+        assert_eq!(loaded.len(), 120);
+        assert_eq!(loaded["Q120"].revision(), Some(7));
+        assert_eq!(request_count(&server, API_PATH).await, 3);
+
+        // A clone shares the cache, so nothing is requested again.
+        let item = wd.clone().load_item("Q42").await.unwrap().unwrap();
+        assert_eq!(item.label_in_locale("en"), Some("label of Q42"));
+        assert_eq!(request_count(&server, API_PATH).await, 3);
+
+        wd.forget_entity("Q42");
+        assert_eq!(wd.load_entity("Q42").await.unwrap().revision(), Some(8));
+        assert_eq!(request_count(&server, API_PATH).await, 4);
+    }
+
+    #[tokio::test]
+    async fn test_load_entities_reports_redirects_and_missing() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"entities": {
+                    "Q1": item_json("Q2", 5),
+                    "Q3": {"id": "Q3", "missing": ""},
+                    "P31": {
+                        "type": "property",
+                        "id": "P31",
+                        "lastrevid": 9,
+                        "datatype": "wikibase-item",
+                        "labels": {}, "descriptions": {}, "aliases": {}, "claims": {}
+                    },
+                    "L7": {
+                        "type": "lexeme",
+                        "id": "L7",
+                        "lastrevid": 11,
+                        "lemmas": {"en": {"language": "en", "value": "run"}},
+                        "language": "Q1860",
+                        "lexicalCategory": "Q24905",
+                        "claims": {}, "forms": [], "senses": []
+                    }
+                }})),
+            )
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+        let loaded = wd.load_entities(&["Q1", "Q3", "P31", "L7"]).await.unwrap();
+
+        assert!(matches!(
+            &loaded["Q1"],
+            EntityLookup::Redirected { target, revision: 5, .. } if target == "Q2"
+        ));
+        assert_eq!(
+            loaded["Q1"].item().map(|i| i.id().to_string()),
+            Some("Q2".to_string())
+        );
+        assert!(matches!(loaded["Q3"], EntityLookup::Missing));
+        assert!(matches!(loaded["P31"].entity(), Some(Entity::Property(_))));
+        assert!(matches!(loaded["L7"].entity(), Some(Entity::Lexeme(_))));
+        assert!(loaded["P31"].item().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_load_entities_falls_back_to_entity_data() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "no-such-entity", "info": "Could not find an entity with the ID \"Qx\"."}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(INDEX_PATH))
+            .and(query_param("title", "Special:EntityData/Q42.json"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"entities": {"Q42": item_json("Q42", 3)}})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(INDEX_PATH))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        let loaded = wd.load_entities(&["Q42", "Qx"]).await.unwrap();
+        assert!(matches!(
+            loaded["Q42"],
+            EntityLookup::Found { revision: 3, .. }
+        ));
+        assert!(matches!(loaded["Qx"], EntityLookup::Missing));
+        assert_eq!(request_count(&server, INDEX_PATH).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_load_entities_drops_rejected_id_and_retries_batch() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .and(query_param("ids", "Q1|Qx|Q2|Qy"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "no-such-entity", "id": "Qx", "info": "Could not find an entity with the ID \"Qx\"."}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .and(query_param("ids", "Q1|Q2|Qy"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "invalid-entity-id", "info": "Invalid entity ID: \"Qy\"."}
+            })))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .and(query_param("ids", "Q1|Q2"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"entities": {
+                    "Q1": item_json("Q1", 4),
+                    "Q2": item_json("Q2", 5)
+                }})),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(INDEX_PATH))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        let loaded = wd.load_entities(&["Q1", "Qx", "Q2", "Qy"]).await.unwrap();
+        assert_eq!(loaded["Q1"].revision(), Some(4));
+        assert_eq!(loaded["Q2"].revision(), Some(5));
+        assert!(matches!(loaded["Qx"], EntityLookup::Missing));
+        assert!(matches!(loaded["Qy"], EntityLookup::Missing));
+        // Only the two rejected IDs go to Special:EntityData.
+        assert_eq!(request_count(&server, API_PATH).await, 3);
+        assert_eq!(request_count(&server, INDEX_PATH).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_load_entities_returns_other_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .and(query_param("action", "wbgetentities"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "readapidenied", "info": "You need read permission."}
+            })))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        assert!(matches!(
+            wd.load_entities(&["Q42"]).await,
+            Err(WikidataError::Api { code, .. }) if code == "readapidenied"
+        ));
+        assert_eq!(request_count(&server, INDEX_PATH).await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_load_entity_revision_is_pinned_and_cached() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(INDEX_PATH))
+            .and(query_param("title", "Special:EntityData/Q1.json"))
+            .and(query_param("revision", "100"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(json!({"entities": {"Q2": item_json("Q2", 100)}})),
+            )
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        for _ in 0..2 {
+            let lookup = wd.load_entity_revision("Q1", 100).await.unwrap();
+            assert!(matches!(
+                &lookup,
+                EntityLookup::Redirected { target, revision: 100, .. } if target == "Q2"
+            ));
+        }
+        assert_eq!(request_count(&server, INDEX_PATH).await, 1);
+        // Pinned entries are not mistaken for the latest revision.
+        wd.forget_entity("Q1");
+        assert!(wd.entity_cache.get("Q1", Some(100)).is_some());
+        wd.clear_entity_cache();
+        assert!(wd.entity_cache.get("Q1", Some(100)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_entity_data_reports_server_errors() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(INDEX_PATH))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+        assert!(matches!(
+            wd.load_entity_revision("Q1", 1).await,
+            Err(WikidataError::HttpStatus(status)) if status.as_u16() == 503
+        ));
+    }
+}
@@ -0,0 +1,579 @@
+//! Retrying and rate-limiting requests to Wikidata.
+//!
+//! Every request [`Wikidata`] sends itself is retried under its
+//! [`RetryPolicy`] when WDQS or the Action API asks it to slow down (`429`,
+//! `503` and other gateway errors, or an Action API `maxlag` error), or when
+//! it times out or cannot connect. A `Retry-After` header or `maxlag` lag is
+//! waited out as given, up to the policy's `max_backoff`; otherwise the wait
+//! grows exponentially, with jitter so that parallel jobs do not retry in
+//! lockstep.
+//!
+//! Requests also queue for one of a limited number of slots per endpoint, one
+//! pool for SPARQL and one for the Action API. The pools are shared by clones
+//! of a [`Wikidata`], so a batch job fanning out over cloned clients still
+//! keeps to the limit.
+//!
+//! The limit has one gap: a [`mediawiki::Api`](crate::mediawiki::Api) from
+//! [`Wikidata::api`] sends its own requests, which the limiter cannot see.
+//! This crate does not wrap that client, so callers must hold a
+//! [`Wikidata::api_permit`] around each of its requests, or they bypass the
+//! limit.
+
+use crate::wikidata::{Wikidata, WikidataError};
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// WDQS allows five concurrent queries per client IP.
+const DEFAULT_SPARQL_CONCURRENCY: usize = 5;
+const DEFAULT_API_CONCURRENCY: usize = 4;
+
+/// When and how often [`Wikidata`] retries a request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Attempts in total, including the first; at least one is always made.
+    pub max_attempts: u32,
+    /// The wait before the first retry, doubled for every further one.
+    pub initial_backoff: Duration,
+    /// Cap on every wait, including one a `Retry-After` header or `maxlag`
+    /// lag asks for.
+    pub max_backoff: Duration,
+    /// Give up rather than wait past this much time since the first attempt.
+    pub deadline: Option<Duration>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 5,
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(60),
+            deadline: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// A single attempt, never retried.
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// The wait after failed attempt `attempt` (0-based): the exponential
+    /// backoff, scaled into its upper half by `jitter` in `[0, 1)`.
+    fn backoff(&self, attempt: u32, jitter: f64) -> Duration {
+        let factor = 2_u32.saturating_pow(attempt.min(31));
+        let full = self
+            .initial_backoff
+            .saturating_mul(factor)
+            .min(self.max_backoff);
+        full.mul_f64(0.5 + jitter.clamp(0.0, 1.0) / 2.0)
+    }
+}
+
+/// Which concurrency pool a request draws from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Endpoint {
+    Sparql,
+    Api,
+}
+
+/// The request slots of one endpoint, resizable in place so that every
+/// clone holding it sees a new limit.
+#[derive(Debug)]
+struct Pool {
+    semaphore: Arc<Semaphore>,
+    sizes: Mutex<PoolSizes>,
+}
+
+#[derive(Debug)]
+struct PoolSizes {
+    limit: usize,
+    /// Permits still to be retired after a shrink, because they were in use
+    /// at the time; see [`Pool::acquire`].
+    debt: usize,
+}
+
+impl Pool {
+    fn new(limit: usize) -> Self {
+        Self {
+            semaphore: Arc::new(Semaphore::new(limit)),
+            sizes: Mutex::new(PoolSizes { limit, debt: 0 }),
+        }
+    }
+
+    fn sizes(&self) -> MutexGuard<'_, PoolSizes> {
+        self.sizes.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Changes the number of slots to `limit` (at least one). Slots in use
+    /// when the pool shrinks are retired as they come back.
+    fn resize(&self, limit: usize) {
+        let limit = limit.max(1);
+        let mut sizes = self.sizes();
+        if limit > sizes.limit {
+            let grow = limit - sizes.limit;
+            let repaid = grow.min(sizes.debt);
+            sizes.debt -= repaid;
+            self.semaphore.add_permits(grow - repaid);
+        } else {
+            let shrink = sizes.limit - limit;
+            sizes.debt += shrink - self.semaphore.forget_permits(shrink);
+        }
+        sizes.limit = limit;
+    }
+
+    /// Waits for a free slot, retiring any that a shrink still owes.
+    async fn acquire(&self) -> Result<OwnedSemaphorePermit, WikidataError> {
+        loop {
+            let permit = Arc::clone(&self.semaphore)
+                .acquire_owned()
+                .await
+                .map_err(|_| WikidataError::LimiterClosed)?;
+            let mut sizes = self.sizes();
+            if sizes.debt == 0 {
+                return Ok(permit);
+            }
+            sizes.debt -= 1;
+            permit.forget();
+        }
+    }
+}
+
+/// The per-endpoint request slots, shared by clones of a [`Wikidata`].
+#[derive(Debug, Clone)]
+pub(crate) struct Throttle {
+    pub(crate) policy: RetryPolicy,
+    sparql: Arc<Pool>,
+    api: Arc<Pool>,
+}
+
+impl Default for Throttle {
+    fn default() -> Self {
+        Self {
+            policy: RetryPolicy::default(),
+            sparql: Arc::new(Pool::new(DEFAULT_SPARQL_CONCURRENCY)),
+            api: Arc::new(Pool::new(DEFAULT_API_CONCURRENCY)),
+        }
+    }
+}
+
+/// How one attempt at a request failed.
+#[derive(Debug)]
+pub(crate) enum AttemptError {
+    /// Worth trying again, after `retry_after` if the server said so.
+    Retry {
+        error: WikidataError,
+        retry_after: Option<Duration>,
+    },
+    Fatal(WikidataError),
+}
+
+impl From<WikidataError> for AttemptError {
+    /// Timeouts and connection failures are retried; anything else is not.
+    fn from(error: WikidataError) -> Self {
+        let transient = match &error {
+            WikidataError::Timeout => true,
+            WikidataError::Http(error) => error.is_connect(),
+            _ => false,
+        };
+        match transient {
+            true => Self::Retry {
+                error,
+                retry_after: None,
+            },
+            false => Self::Fatal(error),
+        }
+    }
+}
+
+impl AttemptError {
+    /// Fails the attempt if `response` is a status that asks for a retry:
+    /// `429`, or `502`/`503`/`504` from an overloaded backend. A `Retry-After`
+    /// wait is capped at the `policy`'s `max_backoff`.
+    pub(crate) fn check_status(
+        response: &reqwest::Response,
+        policy: &RetryPolicy,
+    ) -> Result<(), Self> {
+        let status = response.status();
+        let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            || matches!(status.as_u16(), 502..=504);
+        if !retryable {
+            return Ok(());
+        }
+        let retry_after = response
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| parse_retry_after(value, SystemTime::now(), policy.max_backoff));
+        Err(Self::Retry {
+            error: WikidataError::HttpStatus(status),
+            retry_after,
+        })
+    }
+
+    /// Fails the attempt on an Action API `maxlag` error, waiting out the
+    /// reported lag, up to the `policy`'s `max_backoff`.
+    pub(crate) fn check_maxlag(json: &serde_json::Value, policy: &RetryPolicy) -> Result<(), Self> {
+        if json["error"]["code"].as_str() != Some("maxlag") {
+            return Ok(());
+        }
+        let lag = json["error"]["lag"].as_f64().unwrap_or_default();
+        // A lag too large for a `Duration` is as good as an infinite one.
+        let wait = Duration::try_from_secs_f64(lag.max(0.0)).unwrap_or(Duration::MAX);
+        Err(Self::Retry {
+            error: WikidataError::Api {
+                code: "maxlag".to_string(),
+                info: json["error"]["info"]
+                    .as_str()
+                    .unwrap_or_default()
+                    .to_string(),
+            },
+            retry_after: Some(wait.min(policy.max_backoff)),
+        })
+    }
+}
+
+impl Wikidata {
+    pub fn retry_policy(&self) -> &RetryPolicy {
+        &self.throttle.policy
+    }
+
+    pub fn set_retry_policy(&mut self, policy: RetryPolicy) {
+        self.throttle.policy = policy;
+    }
+
+    /// Allows at most `limit` concurrent SPARQL queries (at least one), for
+    /// this client and all its clones, whenever they were made. Queries
+    /// already running are not interrupted when the limit drops.
+    pub fn set_max_concurrent_sparql(&mut self, limit: usize) {
+        self.throttle.sparql.resize(limit);
+    }
+
+    /// Allows at most `limit` concurrent Action API requests (at least one);
+    /// see [`Wikidata::set_max_concurrent_sparql`].
+    pub fn set_max_concurrent_api(&mut self, limit: usize) {
+        self.throttle.api.resize(limit);
+    }
+
+    /// Waits for a free Action API slot, held until the permit is dropped.
+    /// Required around every request sent through [`Wikidata::api`]'s client.
+    pub async fn api_permit(&self) -> Result<OwnedSemaphorePermit, WikidataError> {
+        self.permit(Endpoint::Api).await
+    }
+
+    /// Waits for a free SPARQL slot, held until the permit is dropped.
+    pub async fn sparql_permit(&self) -> Result<OwnedSemaphorePermit, WikidataError> {
+        self.permit(Endpoint::Sparql).await
+    }
+
+    async fn permit(&self, endpoint: Endpoint) -> Result<OwnedSemaphorePermit, WikidataError> {
+        match endpoint {
+            Endpoint::Sparql => self.throttle.sparql.acquire().await,
+            Endpoint::Api => self.throttle.api.acquire().await,
+        }
+    }
+
+    /// Runs `attempt` in an `endpoint` slot until it succeeds, fails fatally,
+    /// or the retry policy runs out; then returns the last error.
+    pub(crate) async fn with_retry<T, F, Fut>(
+        &self,
+        endpoint: Endpoint,
+        mut attempt: F,
+    ) -> Result<T, WikidataError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, AttemptError>>,
+    {
+        let policy = self.throttle.policy;
+        let start = Instant::now();
+        let mut attempts = 0;
+        loop {
+            let result = {
+                let _permit = self.permit(endpoint).await?;
+                attempt().await
+            };
+            attempts += 1;
+            let (error, retry_after) = match result {
+                Ok(value) => return Ok(value),
+                Err(AttemptError::Fatal(error)) => return Err(error),
+                Err(AttemptError::Retry { error, retry_after }) => (error, retry_after),
+            };
+            if attempts >= policy.max_attempts {
+                return Err(error);
+            }
+            let wait =
+                retry_after.unwrap_or_else(|| policy.backoff(attempts - 1, random_fraction()));
+            if let Some(deadline) = policy.deadline {
+                if start.elapsed() + wait > deadline {
+                    return Err(error);
+                }
+            }
+            tokio::time::sleep(wait).await;
+        }
+    }
+}
+
+/// A `Retry-After` value, either delay seconds or an HTTP date, capped at
+/// `max`; a date in the past means no wait.
+fn parse_retry_after(value: &str, now: SystemTime, max: Duration) -> Option<Duration> {
+    let value = value.trim();
+    let wait = match value.parse() {
+        Ok(seconds) => Duration::from_secs(seconds),
+        Err(_) => {
+            let date = httpdate::parse_http_date(value).ok()?;
+            date.duration_since(now).unwrap_or_default()
+        }
+    };
+    Some(wait.min(max))
+}
+
+/// A number in `[0, 1)` that differs between calls and processes; good
+/// enough to spread retries, without a dependency on `rand`.
+fn random_fraction() -> f64 {
+    let mut hasher = RandomState::new().build_hasher();
+    let nanos = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map_or(0, |d| d.subsec_nanos());
+    hasher.write_u32(nanos);
+    (hasher.finish() >> 11) as f64 / (1_u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{wikidata_for, API_PATH, SPARQL_PATH};
+    use serde_json::json;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn request_count(server: &MockServer) -> usize {
+        server.received_requests().await.unwrap_or_default().len()
+    }
+
+    #[test]
+    fn test_backoff_grows_exponentially_within_jitter_and_cap() {
+        let policy = RetryPolicy {
+            initial_backoff: Duration::from_secs(1),
+            max_backoff: Duration::from_secs(10),
+            ..Default::default()
+        };
+        assert_eq!(policy.backoff(0, 0.0), Duration::from_millis(500));
+        assert_eq!(policy.backoff(0, 1.0), Duration::from_secs(1));
+        assert_eq!(policy.backoff(2, 1.0), Duration::from_secs(4));
+        assert_eq!(policy.backoff(3, 0.5), Duration::from_secs(6));
+        assert_eq!(policy.backoff(10, 1.0), Duration::from_secs(10));
+        assert_eq!(policy.backoff(u32::MAX, 1.0), Duration::from_secs(10));
+        for _ in 0..100 {
+            let fraction = random_fraction();
+            assert!((0.0..1.0).contains(&fraction));
+        }
+    }
+
+    #[test]
+    fn test_parse_retry_after() {
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(784_111_777);
+        let max = Duration::from_secs(60);
+        assert_eq!(
+            parse_retry_after(" 120 ", now, Duration::from_secs(600)),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:47 GMT", now, max),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:49:27 GMT", now, max),
+            Some(Duration::ZERO)
+        );
+        assert_eq!(parse_retry_after("soon", now, max), None);
+        // Capped at `max`, for delays and dates alike.
+        assert_eq!(parse_retry_after("120", now, max), Some(max));
+        assert_eq!(
+            parse_retry_after("Sun, 06 Nov 1994 08:59:37 GMT", now, max),
+            Some(max)
+        );
+    }
+
+    #[test]
+    fn test_maxlag_wait_is_capped() {
+        let policy = RetryPolicy::default();
+        let wait = |lag: f64| {
+            let json = json!({"error": {"code": "maxlag", "info": "", "lag": lag}});
+            match AttemptError::check_maxlag(&json, &policy) {
+                Err(AttemptError::Retry { retry_after, .. }) => retry_after,
+                _ => None,
+            }
+        };
+        assert_eq!(wait(2.5), Some(Duration::from_millis(2500)));
+        assert_eq!(wait(1e300), Some(policy.max_backoff));
+        assert_eq!(wait(-1.0), Some(Duration::ZERO));
+        assert_eq!(
+            AttemptError::check_maxlag(&json!({"entities": {}}), &policy).ok(),
+            Some(())
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sparql_honours_retry_after() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "0"))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_string("item\nQ1\n"))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        let mut reader = wd.load_sparql_csv("SELECT").await.unwrap();
+        assert_eq!(reader.records().count(), 1);
+        assert_eq!(request_count(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_max_attempts() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let mut wd = wikidata_for(&server);
+        wd.set_retry_policy(RetryPolicy {
+            max_attempts: 3,
+            ..*wd.retry_policy()
+        });
+
+        let result = wd.load_sparql_csv("SELECT").await;
+        assert!(
+            matches!(&result, Err(WikidataError::HttpStatus(s)) if s.as_u16() == 503),
+            "{:?}",
+            result.err()
+        );
+        assert_eq!(request_count(&server).await, 3);
+    }
+
+    #[tokio::test]
+    async fn test_retries_stop_at_deadline() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "3600"))
+            .mount(&server)
+            .await;
+        let mut wd = wikidata_for(&server);
+        wd.set_retry_policy(RetryPolicy {
+            max_backoff: Duration::from_secs(3600),
+            deadline: Some(Duration::from_secs(10)),
+            ..*wd.retry_policy()
+        });
+
+        let result = wd.load_sparql_csv("SELECT").await;
+        assert!(matches!(result, Err(WikidataError::HttpStatus(_))));
+        assert_eq!(request_count(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_client_errors_are_not_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(400))
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+        let result: Result<crate::sparql_table::SparqlTableVec, _> =
+            wd.load_sparql_table("SELECT").await;
+        assert!(matches!(result, Err(WikidataError::HttpStatus(_))));
+        assert_eq!(request_count(&server).await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_action_api_maxlag_is_retried() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+                "error": {"code": "maxlag", "info": "Waiting for db", "lag": 0.01}
+            })))
+            .up_to_n_times(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(API_PATH))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(json!({"entities": {
+                    "Q1": {"id": "Q1", "missing": ""}
+                }})),
+            )
+            .mount(&server)
+            .await;
+        let wd = wikidata_for(&server);
+
+        let lookup = wd.load_entity("Q1").await.unwrap();
+        assert!(lookup.entity().is_none());
+        assert_eq!(request_count(&server).await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_is_shared_by_clones() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(path(SPARQL_PATH))
+            .respond_with(ResponseTemplate::new(200).set_body_string("item\n"))
+            .mount(&server)
+            .await;
+        let mut wd = wikidata_for(&server);
+        wd.set_max_concurrent_sparql(1);
+        let clone = wd.clone();
+
+        let permit = wd.sparql_permit().await.unwrap();
+        let blocked =
+            tokio::time::timeout(Duration::from_millis(200), clone.load_sparql_csv("SELECT")).await;
+        assert!(blocked.is_err(), "the clone should wait for the slot");
+        assert_eq!(request_count(&server).await, 0);
+
+        drop(permit);
+        assert!(clone.load_sparql_csv("SELECT").await.is_ok());
+        // The Action API pool is separate.
+        assert!(wd.api_permit().await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_resizing_reaches_earlier_clones() {
+        let mut wd = Wikidata::new();
+        let clone = wd.clone();
+        let available = |wd: &Wikidata| wd.throttle.api.semaphore.available_permits();
+        assert_eq!(available(&clone), DEFAULT_API_CONCURRENCY);
+
+        wd.set_max_concurrent_api(2);
+        assert_eq!(available(&clone), 2);
+        wd.set_max_concurrent_api(6);
+        assert_eq!(available(&clone), 6);
+
+        // Shrinking while slots are in use retires them as they come back.
+        let mut held = vec![];
+        for _ in 0..3 {
+            held.push(clone.api_permit().await.unwrap());
+        }
+        wd.set_max_concurrent_api(1);
+        assert_eq!(available(&clone), 0);
+        drop(held);
+        assert_eq!(available(&clone), 3);
+        let permit = clone.api_permit().await.unwrap();
+        assert_eq!(available(&clone), 0);
+        drop(permit);
+        assert_eq!(available(&clone), 1);
+    }
+}